@@ -0,0 +1,190 @@
+//! Anomalies: a run that took, or printed, many times as much as the recent
+//! runs of its command line usually do, or only a fraction of it, by the run
+//! index. `lg list` marks them; `anomaly_notify` reports them as a run ends.
+
+use crate::index::IndexedRun;
+use crate::tools::human_size;
+use serde::Serialize;
+use std::fmt;
+
+/// Earlier runs a command line needs before its runs are compared with them.
+pub const MIN_RUNS: usize = 5;
+/// How many of the latest earlier runs the usual value is the median of.
+const WINDOW: usize = 20;
+
+/// What is compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Measure {
+    Duration,
+    Output,
+}
+
+impl Measure {
+    /// Milliseconds or bytes.
+    fn of(self, run: &IndexedRun) -> Option<i64> {
+        match self {
+            Measure::Duration => run.duration_ms,
+            Measure::Output => run.bytes,
+        }
+    }
+
+    // Runs under a second are all as quick as each other, and a few KiB of
+    // output more or less is not a change.
+    fn floor(self) -> i64 {
+        match self {
+            Measure::Duration => 1000,
+            Measure::Output => 4096,
+        }
+    }
+}
+
+/// A run that is off from the usual for its command line.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Anomaly {
+    pub measure: Measure,
+    /// The run's value over the median; above 1 for more, below for less.
+    pub ratio: f64,
+    /// Milliseconds or bytes.
+    pub value: i64,
+    pub median: i64,
+    /// Earlier runs the median is taken over.
+    pub runs: usize,
+}
+
+impl Anomaly {
+    /// For a table cell: "4.2x longer", "5.0x less output".
+    pub fn short(&self) -> String {
+        let (ratio, more) = self.ratio_and_more();
+        let what = match (self.measure, more) {
+            (Measure::Duration, true) => "longer",
+            (Measure::Duration, false) => "quicker",
+            (Measure::Output, true) => "more output",
+            (Measure::Output, false) => "less output",
+        };
+        format!("{:.1}x {}", ratio, what)
+    }
+
+    fn ratio_and_more(&self) -> (f64, bool) {
+        if self.ratio >= 1.0 {
+            (self.ratio, true)
+        } else {
+            (1.0 / self.ratio, false)
+        }
+    }
+
+    fn amount(&self, value: i64) -> String {
+        match self.measure {
+            Measure::Duration => format!("{:.1}s", value as f64 / 1000.0),
+            Measure::Output => human_size(value.max(0) as u64),
+        }
+    }
+}
+
+/// "took 4.2x as long as usual (12.0s, median 2.9s over 9 runs)"
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (ratio, more) = self.ratio_and_more();
+        let what = match (self.measure, more) {
+            (Measure::Duration, true) => format!("took {:.1}x as long as usual", ratio),
+            (Measure::Duration, false) => format!("was {:.1}x quicker than usual", ratio),
+            (Measure::Output, true) => format!("printed {:.1}x as much as usual", ratio),
+            (Measure::Output, false) => format!("printed {:.1}x less than usual", ratio),
+        };
+        write!(
+            f,
+            "{} ({}, median {} over {} runs)",
+            what,
+            self.amount(self.value),
+            self.amount(self.median),
+            self.runs
+        )
+    }
+}
+
+/// How `run` is off from the earlier runs of its command line in `earlier`
+/// (oldest first): more than `factor` times their median, or less than its
+/// share. Empty when it isn't, with fewer than `MIN_RUNS` earlier runs, or
+/// with a factor of 1 or less, which turns the check off.
+pub fn anomalies(earlier: &[&IndexedRun], run: &IndexedRun, factor: f64) -> Vec<Anomaly> {
+    if factor <= 1.0 {
+        return Vec::new();
+    }
+    let mut found = Vec::new();
+    for measure in [Measure::Duration, Measure::Output] {
+        let Some(value) = measure.of(run) else {
+            continue;
+        };
+        let mut usual: Vec<i64> = earlier.iter().rev().filter_map(|r| measure.of(r)).collect();
+        usual.truncate(WINDOW);
+        if usual.len() < MIN_RUNS {
+            continue;
+        }
+        usual.sort_unstable();
+        let median = usual[usual.len() / 2];
+        let floor = measure.floor();
+        let ratio = value.max(floor) as f64 / median.max(floor) as f64;
+        if ratio >= factor || ratio <= 1.0 / factor {
+            found.push(Anomaly {
+                measure,
+                ratio,
+                value,
+                median,
+                runs: usual.len(),
+            });
+        }
+    }
+    found
+}
+
+/// The finished runs of `run`'s command line before it in the index `runs`,
+/// oldest first; `run` is found by its run id.
+pub fn earlier_runs<'a>(runs: &'a [IndexedRun], run: &IndexedRun) -> Vec<&'a IndexedRun> {
+    runs.iter()
+        .take_while(|r| r.run_id != run.run_id)
+        .filter(|r| r.cmd == run.cmd && r.args == run.args && r.exit_code.is_some())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn run(duration_ms: i64, bytes: i64) -> IndexedRun {
+        IndexedRun {
+            run_id: None,
+            cmd: "backup".into(),
+            args: String::new(),
+            cwd: PathBuf::from("/"),
+            started: None,
+            exit_code: Some(0),
+            duration_ms: Some(duration_ms),
+            log_path: PathBuf::from("/backup.log"),
+            startup_ms: None,
+            bytes: Some(bytes),
+            lg_cpu_ms: None,
+        }
+    }
+
+    #[test]
+    fn a_run_far_from_the_median_is_flagged() {
+        let history: Vec<IndexedRun> = [60_000, 58_000, 65_000, 61_000, 59_000]
+            .into_iter()
+            .map(|ms| run(ms, 100_000))
+            .collect();
+        let earlier: Vec<&IndexedRun> = history.iter().collect();
+        let found = anomalies(&earlier, &run(240_000, 110_000), 3.0);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].to_string(),
+            "took 4.0x as long as usual (240.0s, median 60.0s over 5 runs)"
+        );
+        assert_eq!(found[0].short(), "4.0x longer");
+        let quiet = anomalies(&earlier, &run(61_000, 20_000), 3.0);
+        assert_eq!(quiet[0].short(), "5.0x less output");
+        // Too little history, or the check turned off.
+        assert!(anomalies(&earlier[1..], &run(240_000, 100_000), 3.0).is_empty());
+        assert!(anomalies(&earlier, &run(240_000, 100_000), 0.0).is_empty());
+    }
+}
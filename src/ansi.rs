@@ -0,0 +1,86 @@
+//! `strip_ansi`: terminal escape sequences (colors, cursor movement, window
+//! titles, hyperlinks) taken out of what goes into the log.
+
+use std::borrow::Cow;
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+
+/// `text` without its escape sequences; as it is when there are none.
+pub fn strip(text: &[u8]) -> Cow<'_, [u8]> {
+    if !text.contains(&ESC) {
+        return Cow::Borrowed(text);
+    }
+    let mut out = Vec::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        if text[i] == ESC {
+            i += 1 + sequence_len(&text[i + 1..]);
+        } else {
+            out.push(text[i]);
+            i += 1;
+        }
+    }
+    Cow::Owned(out)
+}
+
+// Length of the escape sequence `rest` starts, after its ESC. A cut-off
+// sequence takes the rest of the text.
+fn sequence_len(rest: &[u8]) -> usize {
+    let within = |from: usize, range: std::ops::RangeInclusive<u8>| {
+        from + rest[from..]
+            .iter()
+            .take_while(|b| range.contains(b))
+            .count()
+    };
+    match rest.first() {
+        // CSI: parameters and intermediates, then one final byte.
+        Some(b'[') => {
+            let end = within(1, 0x20..=0x3f);
+            end + usize::from(rest.get(end).is_some_and(|b| (0x40..=0x7e).contains(b)))
+        }
+        // OSC, DCS, SOS, PM, APC: a string up to BEL or ESC \.
+        Some(b']' | b'P' | b'X' | b'^' | b'_') => {
+            for (i, &b) in rest.iter().enumerate().skip(1) {
+                match b {
+                    BEL => return i + 1,
+                    ESC if rest.get(i + 1) == Some(&b'\\') => return i + 2,
+                    _ => {}
+                }
+            }
+            rest.len()
+        }
+        // Charset selection and the like: intermediates, then a final byte.
+        Some(0x20..=0x2f) => {
+            let end = within(0, 0x20..=0x2f);
+            end + usize::from(rest.get(end).is_some_and(|b| (0x30..=0x7e).contains(b)))
+        }
+        Some(0x30..=0x7e) => 1,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stripped(text: &str) -> String {
+        String::from_utf8(strip(text.as_bytes()).into_owned()).unwrap()
+    }
+
+    #[test]
+    fn strips_colors_titles_and_links_only() {
+        assert!(matches!(strip(b"plain [1m] text"), Cow::Borrowed(_)));
+        assert_eq!(
+            stripped("\x1b[1;32m   Compiling\x1b[0m serde"),
+            "   Compiling serde"
+        );
+        assert_eq!(stripped("\x1b]0;make\x07done"), "done");
+        assert_eq!(
+            stripped("see \x1b]8;;https://x.test\x1b\\docs\x1b]8;;\x1b\\ é"),
+            "see docs é"
+        );
+        assert_eq!(stripped("\x1b(B\x1b7\x1b[2K\x1b[?25lok\x1b[38;5;208"), "ok");
+        assert_eq!(stripped("cut\x1b"), "cut");
+    }
+}
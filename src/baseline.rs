@@ -0,0 +1,270 @@
+//! Baselines (`lg baseline set`): a known-good run of a command line that its
+//! later runs are compared with. The comparison is of the run's shape, not its
+//! text: exit code, duration, amount of output, the problem matchers' errors
+//! and warnings, and the kinds of line printed. A line's kind is the line with
+//! every word that contains a digit replaced by `#`, so counters, times and
+//! hashes don't make a line new.
+
+use crate::config::Config;
+use crate::matchers::Diagnostic;
+use crate::state::StateDir;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::path::PathBuf;
+
+/// Line kinds a baseline keeps; output beyond them is still counted.
+const MAX_KINDS: usize = 50_000;
+// Longest example of a new kind of line in a deviation.
+const EXAMPLE_CHARS: usize = 120;
+
+/// A pinned run, as stored in the state directory.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Baseline {
+    pub cmd: String,
+    pub args: String,
+    /// None for logs from before run ids.
+    pub run_id: Option<String>,
+    pub log: PathBuf,
+    pub exit_code: Option<i32>,
+    pub duration_secs: Option<f64>,
+    /// Output lines, of both streams.
+    pub lines: u64,
+    pub errors: usize,
+    pub warnings: usize,
+    /// Hashes of the kinds of line the run printed.
+    pub kinds: BTreeSet<u64>,
+}
+
+impl Baseline {
+    /// The command line, as `lg baseline list` shows it.
+    pub fn command(&self) -> String {
+        format!("{} {}", self.cmd, self.args).trim_end().to_string()
+    }
+
+    /// The run the baseline was taken from: its id, or else its log.
+    pub fn run(&self) -> String {
+        self.run_id
+            .clone()
+            .unwrap_or_else(|| self.log.display().to_string())
+    }
+
+    /// Add an output line of the pinned run.
+    pub fn observe(&mut self, line: &str) {
+        self.lines += 1;
+        if self.kinds.len() < MAX_KINDS {
+            if let Some(kind) = kind(line) {
+                self.kinds.insert(kind);
+            }
+        }
+    }
+}
+
+/// A run being compared with its command line's baseline, line by line.
+pub struct BaselineCheck {
+    baseline: Baseline,
+    factor: f64,
+    lines: u64,
+    new_kinds: HashSet<u64>,
+    first_new: Option<String>,
+}
+
+impl BaselineCheck {
+    /// None when `cmd args` has no baseline; a baseline that can't be read
+    /// is reported and ignored.
+    pub fn new(cfg: &Config, cmd: &str, args: &str) -> Option<Self> {
+        match StateDir::locate()?.load_baseline(cmd, args) {
+            Ok(baseline) => Some(Self::with(baseline?, cfg.baseline_factor)),
+            Err(err) => {
+                eprintln!("lg: baseline: {:#}", err);
+                None
+            }
+        }
+    }
+
+    pub fn with(baseline: Baseline, factor: f64) -> Self {
+        Self {
+            baseline,
+            factor: factor.max(1.0),
+            lines: 0,
+            new_kinds: HashSet::new(),
+            first_new: None,
+        }
+    }
+
+    pub fn baseline(&self) -> &Baseline {
+        &self.baseline
+    }
+
+    pub fn observe(&mut self, line: &str) {
+        self.lines += 1;
+        let Some(kind) = kind(line) else {
+            return;
+        };
+        // A baseline that hit MAX_KINDS can't tell which lines are new.
+        if self.baseline.kinds.len() >= MAX_KINDS || self.baseline.kinds.contains(&kind) {
+            return;
+        }
+        if self.new_kinds.insert(kind) && self.first_new.is_none() {
+            self.first_new = Some(line.trim().chars().take(EXAMPLE_CHARS).collect());
+        }
+    }
+
+    /// How the run differs from the baseline, one line each, e.g.
+    /// "exit code 2, baseline 0"; empty when it doesn't.
+    pub fn deviations(
+        &self,
+        exit_code: Option<i32>,
+        duration_secs: Option<f64>,
+        diagnostics: &[Diagnostic],
+    ) -> Vec<String> {
+        let base = &self.baseline;
+        let mut found = Vec::new();
+        if exit_code != base.exit_code {
+            let code = |c: Option<i32>| c.map_or("unknown".into(), |c| c.to_string());
+            found.push(format!(
+                "exit code {}, baseline {}",
+                code(exit_code),
+                code(base.exit_code)
+            ));
+        }
+        if let (Some(secs), Some(base_secs)) = (duration_secs, base.duration_secs) {
+            // Runs under a second are all as quick as each other.
+            if self.off(secs.max(1.0), base_secs.max(1.0)) {
+                found.push(format!("took {:.1}s, baseline {:.1}s", secs, base_secs));
+            }
+        }
+        // A handful of lines more or less is not a change in kind.
+        if self.off(self.lines.max(10) as f64, base.lines.max(10) as f64) {
+            found.push(format!(
+                "{} output lines, baseline {}",
+                self.lines, base.lines
+            ));
+        }
+        let count = |severity: &str| {
+            diagnostics
+                .iter()
+                .filter(|d| d.severity == severity)
+                .count()
+        };
+        for (severity, n, base_n) in [
+            ("error", count("error"), base.errors),
+            ("warning", count("warning"), base.warnings),
+        ] {
+            if n > base_n {
+                found.push(format!("{} {}(s), baseline {}", n, severity, base_n));
+            }
+        }
+        if let Some(example) = &self.first_new {
+            found.push(format!(
+                "{} new kind(s) of line, first: {}",
+                self.new_kinds.len(),
+                example
+            ));
+        }
+        found
+    }
+
+    // More than `factor` times the baseline, or less than its 1/factor.
+    fn off(&self, value: f64, base: f64) -> bool {
+        value > base * self.factor || value * self.factor < base
+    }
+}
+
+// The line with its numbers taken out, hashed; None for a blank line.
+fn kind(line: &str) -> Option<u64> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    let mut shape = String::with_capacity(line.len());
+    let mut word = String::new();
+    let flush = |word: &mut String, shape: &mut String| {
+        match word.chars().any(|c| c.is_ascii_digit()) {
+            true => shape.push('#'),
+            false => shape.push_str(word),
+        }
+        word.clear();
+    };
+    for c in line.chars() {
+        if c.is_alphanumeric() {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut shape);
+            shape.push(c);
+        }
+    }
+    flush(&mut word, &mut shape);
+    Some(fnv1a(shape.as_bytes()))
+}
+
+// Stable across lg versions and platforms, unlike std's hasher.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn baseline(lines: &[&str]) -> Baseline {
+        let mut baseline = Baseline {
+            cmd: "backup.sh".into(),
+            args: "--full".into(),
+            run_id: Some("01M5170180ZZZZZZZZZZZZZZZZ".into()),
+            log: PathBuf::from("/logs/backup.sh.log"),
+            exit_code: Some(0),
+            duration_secs: Some(40.0),
+            lines: 0,
+            errors: 0,
+            warnings: 1,
+            kinds: BTreeSet::new(),
+        };
+        for line in lines {
+            baseline.observe(line);
+        }
+        baseline
+    }
+
+    #[test]
+    fn numbers_do_not_make_a_line_new() {
+        assert_eq!(
+            kind("copied 120 files in 3.2s"),
+            kind("copied 7 files in 11.0s")
+        );
+        assert_eq!(kind("commit 3fa9c20b ok"), kind("commit 77ee01d4 ok"));
+        assert_ne!(kind("copied 7 files"), kind("skipped 7 files"));
+        assert_eq!(kind("   "), None);
+    }
+
+    #[test]
+    fn deviations_name_what_changed() {
+        let lines = ["snapshot 1 of 3", "snapshot 2 of 3", "done in 40s"];
+        let mut check = BaselineCheck::with(baseline(&lines), 2.0);
+        for line in lines {
+            check.observe(line);
+        }
+        assert!(check.deviations(Some(0), Some(60.0), &[]).is_empty());
+
+        check.observe("rsync: connection refused (code 10)");
+        check.observe("rsync: connection refused (code 12)");
+        let error = Diagnostic {
+            severity: "error".into(),
+            file: Path::new("backup.sh").into(),
+            line: 3,
+            col: None,
+            message: "failed".into(),
+        };
+        assert_eq!(
+            check.deviations(Some(1), Some(95.0), &[error]),
+            [
+                "exit code 1, baseline 0",
+                "took 95.0s, baseline 40.0s",
+                "1 error(s), baseline 0",
+                "1 new kind(s) of line, first: rsync: connection refused (code 10)",
+            ]
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! Sizing lg's own buffers to fit `max_memory`.
+
+use crate::config::{Compress, Config, IoBackend};
+use crate::runner::READ_CHUNK;
+use crate::sinks::parallel_gz;
+use crate::sinks::{zstd_state_bytes, GZ_STATE_BYTES};
+use anyhow::Result;
+
+// Smallest buffers `max_memory` may shrink lg to.
+pub static MIN_LINE_BYTES: usize = 1024;
+static MIN_WRITE_BUFFER: usize = 4 * 1024;
+
+/// Worst-case size of lg's own buffers for one run, by component.
+pub struct MemoryBudget {
+    limit: Option<usize>,
+    files: usize,
+    /// Per file: the write buffer, plus io_uring's two buffers of the same size.
+    write_buffer: usize,
+    /// Per file: encoder state, and chunks in flight with `compress_threads`.
+    compression: usize,
+    /// Per stream: the longest unterminated line plus one read.
+    line_buffer: usize,
+}
+
+impl MemoryBudget {
+    fn plan(cfg: &Config) -> Self {
+        let files = if cfg.split_streams { 2 } else { 1 };
+        let compression = match cfg.compress {
+            Compress::None => 0,
+            Compress::Gz if cfg.compress_threads > 0 => {
+                parallel_gz::max_buffered(cfg.compress_threads)
+            }
+            Compress::Gz => GZ_STATE_BYTES,
+            Compress::Zstd => zstd_state_bytes(cfg.compress_level().unwrap_or(0)),
+        };
+        Self {
+            limit: None,
+            files,
+            write_buffer: cfg.write_buffer_size.max(1) * Self::write_buffer_copies(cfg),
+            compression,
+            line_buffer: cfg.max_line_bytes.max(MIN_LINE_BYTES) + READ_CHUNK,
+        }
+    }
+
+    fn write_buffer_copies(cfg: &Config) -> usize {
+        if cfg.io_backend == IoBackend::Uring {
+            3
+        } else {
+            1
+        }
+    }
+
+    fn total(&self) -> usize {
+        self.files * (self.write_buffer + self.compression) + 2 * self.line_buffer
+    }
+
+    /// Shrink compression threads, the line cap and write buffers (in that
+    /// order) until the plan fits `max_memory`; without a limit, only plan.
+    pub fn fit(cfg: &mut Config) -> Result<Self> {
+        let Some(limit) = cfg.max_memory.as_deref().filter(|s| !s.is_empty()) else {
+            return Ok(Self::plan(cfg));
+        };
+        let limit = parse_size(limit).map_err(anyhow::Error::msg)?;
+        let files = Self::plan(cfg).files;
+        // Parallel gzip keeps whole chunks in flight; give it at most half the budget.
+        while cfg.compress == Compress::Gz
+            && cfg.compress_threads > 0
+            && files * parallel_gz::max_buffered(cfg.compress_threads) > limit / 2
+        {
+            cfg.compress_threads -= 1;
+        }
+        // Both streams' line buffers together get at most a quarter.
+        cfg.max_line_bytes = cfg
+            .max_line_bytes
+            .min((limit / 8).saturating_sub(READ_CHUNK))
+            .max(MIN_LINE_BYTES);
+        // Write buffers get what is left.
+        let mut plan = Self::plan(cfg);
+        let fixed = plan.total() - plan.files * plan.write_buffer;
+        let copies = Self::write_buffer_copies(cfg);
+        let per_file = limit.saturating_sub(fixed) / files / copies;
+        if per_file < MIN_WRITE_BUFFER {
+            anyhow::bail!(
+                "max_memory {} is too small for this configuration (needs at least {})",
+                fmt_size(limit),
+                fmt_size(fixed + files * copies * MIN_WRITE_BUFFER)
+            );
+        }
+        cfg.write_buffer_size = cfg.write_buffer_size.min(per_file);
+        plan = Self::plan(cfg);
+        plan.limit = Some(limit);
+        Ok(plan)
+    }
+}
+
+impl std::fmt::Display for MemoryBudget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "memory budget {}", fmt_size(self.total()))?;
+        if let Some(limit) = self.limit {
+            write!(f, " of {}", fmt_size(limit))?;
+        }
+        write!(
+            f,
+            ": write buffers {} x {}, compression {} x {}, line buffers 2 x {}",
+            self.files,
+            fmt_size(self.write_buffer),
+            self.files,
+            fmt_size(self.compression),
+            fmt_size(self.line_buffer)
+        )
+    }
+}
+
+/// Parse a byte size like `65536`, `512K`, `16M` or `1G` (binary units).
+pub fn parse_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let n: usize = num
+        .parse()
+        .map_err(|_| format!("invalid size {:?} (expected e.g. 512K, 16M)", s))?;
+    let mult = match unit.trim_end_matches(['B', 'b', 'i']) {
+        "" => 1,
+        "K" | "k" => 1 << 10,
+        "M" | "m" => 1 << 20,
+        "G" | "g" => 1 << 30,
+        _ => return Err(format!("unknown size unit {:?} in {:?}", unit, s)),
+    };
+    Ok(n.saturating_mul(mult))
+}
+
+pub fn fmt_size(bytes: usize) -> String {
+    match bytes {
+        b if b >= 1 << 20 => format!("{:.1} MiB", b as f64 / (1 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KiB", b as f64 / (1 << 10) as f64),
+        b => format!("{} B", b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_MAX_LINE_BYTES;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("65536"), Ok(65536));
+        assert_eq!(parse_size("512K"), Ok(512 << 10));
+        assert_eq!(parse_size("16MiB"), Ok(16 << 20));
+        assert_eq!(parse_size(" 1g "), Ok(1 << 30));
+        assert!(parse_size("12T").is_err());
+        assert!(parse_size("M").is_err());
+        assert_eq!(fmt_size(512), "512 B");
+        assert_eq!(fmt_size(1536), "1.5 KiB");
+    }
+
+    #[test]
+    fn fit_shrinks_to_the_limit() {
+        let mut cfg = Config {
+            max_memory: Some("256K".to_string()),
+            ..Config::default()
+        };
+        let budget = MemoryBudget::fit(&mut cfg).unwrap();
+        assert!(budget.total() <= 256 << 10);
+        assert!(cfg.max_line_bytes < DEFAULT_MAX_LINE_BYTES);
+    }
+}
@@ -0,0 +1,1136 @@
+//! Command-line definitions: the wrapper flags and the `lg <tool>` subcommands.
+
+use crate::budget::parse_size;
+use crate::config::{
+    Collision, Config, GroupBudgetAction, HeaderMode, Keep, LogFormat, LogSizeAction,
+    OutputArtifact, Tee,
+};
+use crate::sinks::Stream;
+use crate::timespec::parse_time;
+use chrono::NaiveDateTime;
+use clap::{ArgAction, Args, Parser, Subcommand};
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lg",
+    version,
+    about = "Log any command's output and metadata",
+    disable_help_subcommand = true,
+    after_help = "Listing runs:    lg list [-n 20] [--format json]\n\
+                  Recent runs:     lg history [--failed] [--cmd make] [--since 2d]\n\
+                  Viewing a run:   lg show [--cmd NAME | FILE]\n\
+                  Replaying a run: lg replay [--speed 2x] [--cmd NAME | FILE]\n\
+                  Following runs:  lg tail [--all-running]\n\
+                  Reattaching:     lg attach --last [-n 50]\n\
+                  Cancelling:      lg kill [--pid PID] [--signal INT]\n\
+                  Opening errors:  lg open --errors\n\
+                  Inspecting logs: lg stats [--runs] [--csv|--json]\n\
+                  Disk usage:      lg du [--by args|cwd] [--index]\n\
+                  Searching logs:  lg grep [-C N] [--since 2h] PATTERN\n\
+                  Daily digest:    lg report [--since 24h] [--format markdown|html]\n\
+                  Tidying logs:    lg clean --compress-older-than 3d | -i --older-than 2w\n\
+                  Grouping runs:   eval \"$(lg group start nightly)\"; ...; lg group end\n\
+                  Known-good runs: lg baseline set [RUN]; lg baseline check [RUN]\n\
+                  Config schema:   lg config schema --json\n\
+                  Config keys:     lg config doc [KEY]\n\
+                  Updating lg:     lg self-update [--check]"
+)]
+pub struct Cli {
+    /// Read this config file instead of ~/.lg and .lg.toml
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Apply the config's [profile.NAME] keys (default: $LG_PROFILE)
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Override output directory
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+
+    /// Override filename template
+    #[arg(long)]
+    pub filename_template: Option<String>,
+
+    /// What to do when the log name is taken (overrides collision)
+    #[arg(long, visible_alias = "on-conflict", value_enum, value_name = "HOW")]
+    pub collision: Option<Collision>,
+
+    /// Link NAME in the output directory to the finished log, e.g. "{cmd}.latest.log"
+    /// (sets latest_link_name and latest_symlink)
+    #[arg(long, value_name = "NAME")]
+    pub latest_link: Option<String>,
+
+    /// Log file format (overrides format)
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub format: Option<LogFormat>,
+
+    /// How much header to write (overrides header)
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub header: Option<HeaderMode>,
+
+    /// Compress logs: none|gz|zstd
+    #[arg(long)]
+    pub compress: Option<String>,
+
+    /// Child stdin: inherit|null|<file to feed>
+    #[arg(long, value_name = "SOURCE")]
+    pub stdin: Option<String>,
+
+    /// Answer prompts using the rules in this TOML file (same format as [interact])
+    #[arg(long, value_name = "FILE")]
+    pub expect_script: Option<PathBuf>,
+
+    /// Stop the command after this long, e.g. 30m: SIGTERM, then SIGKILL (overrides timeout)
+    #[arg(long, value_name = "DURATION")]
+    pub timeout: Option<String>,
+
+    /// Stop logging (or rotate, or kill, per max_log_size_action) once the log holds this
+    /// much output, e.g. 1G (overrides max_log_size)
+    #[arg(long, value_name = "SIZE")]
+    pub max_log_size: Option<String>,
+
+    /// What happens at --max-log-size (overrides max_log_size_action)
+    #[arg(long, value_enum, value_name = "ACTION")]
+    pub max_log_size_action: Option<LogSizeAction>,
+
+    /// Which logs to keep once the run is over (overrides keep)
+    #[arg(long, value_enum, value_name = "WHEN")]
+    pub keep: Option<Keep>,
+
+    /// Store stdout that is one JSON or YAML document beside the log (overrides output_artifact)
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub output_artifact: Option<OutputArtifact>,
+
+    /// Add the run to this `lg group` (overrides group and $LG_GROUP)
+    #[arg(long, value_name = "ID")]
+    pub group: Option<String>,
+
+    /// Time budget of a group started without one, e.g. 30m (overrides group_budget)
+    #[arg(long, value_name = "DURATION")]
+    pub group_budget: Option<String>,
+
+    /// What a run does once its group is over budget (overrides group_budget_action)
+    #[arg(long, value_enum, value_name = "ACTION")]
+    pub group_budget_action: Option<GroupBudgetAction>,
+
+    /// When the run was meant to start, e.g. "2026-10-16 02:00" or @1760580000; the header
+    /// records the start delay (overrides scheduled_at and what systemd or cron tell)
+    #[arg(long, value_name = "TIME")]
+    pub scheduled_at: Option<String>,
+
+    /// Cap lg's own buffer memory, e.g. 16M (overrides max_memory)
+    #[arg(long, value_name = "SIZE")]
+    pub max_memory: Option<String>,
+
+    /// Diagnostics on stderr: -v prints the memory budget, -vv also peak buffer use
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// The command and its arguments to run
+    #[arg(required = true, trailing_var_arg = true)]
+    pub cmd: Vec<OsString>,
+
+    #[command(flatten)]
+    pub switches: Switches,
+}
+
+/// A flag per boolean config key, both ways, so this run can differ from
+/// `~/.lg` in either direction. Of `--x` and `--no-x`, the last one given wins.
+#[derive(Args, Debug, Default)]
+#[command(
+    next_help_heading = "Config switches (each overrides its ~/.lg key; --no-<switch> turns it off)"
+)]
+pub struct Switches {
+    /// Include arguments in filename (include_args_in_name)
+    #[arg(long, short = 'a', overrides_with = "no_include_args")]
+    pub include_args: bool,
+    #[arg(long, overrides_with = "include_args", hide = true)]
+    pub no_include_args: bool,
+
+    /// Name flags too in {args}, not only positional arguments
+    #[arg(long, overrides_with = "no_include_full_args")]
+    pub include_full_args: bool,
+    #[arg(long, overrides_with = "include_full_args", hide = true)]
+    pub no_include_full_args: bool,
+
+    /// Replace characters unsafe in file names with _
+    #[arg(long, overrides_with = "no_sanitize_filename")]
+    pub sanitize_filename: bool,
+    #[arg(long, overrides_with = "sanitize_filename", hide = true)]
+    pub no_sanitize_filename: bool,
+
+    /// Point a latest.log symlink at the finished log
+    #[arg(long, overrides_with = "no_latest_symlink")]
+    pub latest_symlink: bool,
+    #[arg(long, overrides_with = "latest_symlink", hide = true)]
+    pub no_latest_symlink: bool,
+
+    /// Put logs of runs started under another lg in a subdirectory named after its run id
+    #[arg(long, overrides_with = "no_nested_subdir")]
+    pub nested_subdir: bool,
+    #[arg(long, overrides_with = "nested_subdir", hide = true)]
+    pub no_nested_subdir: bool,
+
+    /// Prefix every logged line with a timestamp
+    #[arg(long, overrides_with = "no_timestamp_each_line")]
+    pub timestamp_each_line: bool,
+    #[arg(long, overrides_with = "timestamp_each_line", hide = true)]
+    pub no_timestamp_each_line: bool,
+
+    /// Write logged lines without timestamps or stream markers
+    #[arg(long, overrides_with = "no_plain_lines")]
+    pub plain_lines: bool,
+    #[arg(long, overrides_with = "plain_lines", hide = true)]
+    pub no_plain_lines: bool,
+
+    /// Log the command's output as the bytes it wrote (progress bars included)
+    #[arg(long, overrides_with = "no_raw")]
+    pub raw: bool,
+    #[arg(long, overrides_with = "raw", hide = true)]
+    pub no_raw: bool,
+
+    /// Leave color codes and other escape sequences out of the log
+    #[arg(long, overrides_with = "no_strip_ansi")]
+    pub strip_ansi: bool,
+    #[arg(long, overrides_with = "strip_ansi", hide = true)]
+    pub no_strip_ansi: bool,
+
+    /// Log the input piped into the command as [STDIN] lines
+    #[arg(long, overrides_with = "no_log_stdin")]
+    pub log_stdin: bool,
+    #[arg(long, overrides_with = "log_stdin", hide = true)]
+    pub no_log_stdin: bool,
+
+    /// Split stdout/stderr into separate files (the opposite of combine_streams)
+    #[arg(long, overrides_with = "no_split_streams")]
+    pub split_streams: bool,
+    #[arg(long, overrides_with = "split_streams", hide = true)]
+    pub no_split_streams: bool,
+
+    /// Print the command's output on the terminal too (tee)
+    #[arg(long, overrides_with = "no_tee")]
+    pub tee: bool,
+    #[arg(long, overrides_with = "tee", hide = true)]
+    pub no_tee: bool,
+
+    /// Don't print the command's output when stdout is redirected or piped
+    #[arg(long, overrides_with = "no_quiet_if_redirected")]
+    pub quiet_if_redirected: bool,
+    #[arg(long, overrides_with = "quiet_if_redirected", hide = true)]
+    pub no_quiet_if_redirected: bool,
+
+    /// Show the command's stderr in dim red on the terminal
+    #[arg(long, overrides_with = "no_tee_color")]
+    pub tee_color: bool,
+    #[arg(long, overrides_with = "tee_color", hide = true)]
+    pub no_tee_color: bool,
+
+    /// Show the command's output, on stderr, only if it fails (like chronic)
+    #[arg(long, overrides_with = "no_cron")]
+    pub cron: bool,
+    #[arg(long, overrides_with = "cron", hide = true)]
+    pub no_cron: bool,
+
+    /// Record environment variables in the header
+    #[arg(long, overrides_with = "no_log_env")]
+    pub log_env: bool,
+    #[arg(long, overrides_with = "log_env", hide = true)]
+    pub no_log_env: bool,
+
+    /// With log_env, only record variables that differ from `lg env-baseline`
+    #[arg(long, overrides_with = "no_env_diff")]
+    pub env_diff: bool,
+    #[arg(long, overrides_with = "env_diff", hide = true)]
+    pub no_env_diff: bool,
+
+    /// Record umask, resource limits and locale in the header
+    #[arg(long, overrides_with = "no_log_process_env")]
+    pub log_process_env: bool,
+    #[arg(long, overrides_with = "log_process_env", hide = true)]
+    pub no_log_process_env: bool,
+
+    /// Run the command on a pseudo-terminal so it behaves as in a terminal
+    #[arg(long, overrides_with = "no_pty")]
+    pub pty: bool,
+    #[arg(long, overrides_with = "pty", hide = true)]
+    pub no_pty: bool,
+
+    /// Keep running and logging if the terminal hangs up (e.g. SSH disconnect)
+    #[arg(long, overrides_with = "no_survive_hup")]
+    pub survive_hup: bool,
+    #[arg(long, overrides_with = "survive_hup", hide = true)]
+    pub no_survive_hup: bool,
+
+    /// Record the run in the run index
+    #[arg(long, overrides_with = "no_index")]
+    pub index: bool,
+    #[arg(long, overrides_with = "index", hide = true)]
+    pub no_index: bool,
+
+    /// Turn diagnostics into GitHub Actions annotations when run there
+    #[arg(long, overrides_with = "no_ci_annotations")]
+    pub ci_annotations: bool,
+    #[arg(long, overrides_with = "ci_annotations", hide = true)]
+    pub no_ci_annotations: bool,
+
+    /// Mask the values of sensitive flags in recorded arguments
+    #[arg(long, overrides_with = "no_mask_args")]
+    pub mask_args: bool,
+    #[arg(long, overrides_with = "mask_args", hide = true)]
+    pub no_mask_args: bool,
+
+    /// Fingerprint the program, environment, cwd and args in the header
+    #[arg(long, overrides_with = "no_fingerprint")]
+    pub fingerprint: bool,
+    #[arg(long, overrides_with = "fingerprint", hide = true)]
+    pub no_fingerprint: bool,
+
+    /// Record the git branch, commit and dirty state in the header
+    #[arg(long, overrides_with = "no_git")]
+    pub git: bool,
+    #[arg(long, overrides_with = "git", hide = true)]
+    pub no_git: bool,
+
+    /// Store output that doesn't compress instead of compressing it
+    #[arg(long, overrides_with = "no_skip_incompressible")]
+    pub skip_incompressible: bool,
+    #[arg(long, overrides_with = "skip_incompressible", hide = true)]
+    pub no_skip_incompressible: bool,
+}
+
+// `--x` / `--no-x`: None when neither was given.
+fn switch(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+impl CleanArgs {
+    /// `clean_trash` as `--trash` / `--no-trash` set it for this clean.
+    pub fn clean_trash(&self, cfg: &Config) -> bool {
+        switch(self.trash, self.no_trash).unwrap_or(cfg.clean_trash)
+    }
+}
+
+impl Switches {
+    /// Override the config keys whose flag was given.
+    pub fn apply(&self, cfg: &mut Config) {
+        let keys = [
+            (
+                &mut cfg.include_args_in_name,
+                switch(self.include_args, self.no_include_args),
+            ),
+            (
+                &mut cfg.include_full_args,
+                switch(self.include_full_args, self.no_include_full_args),
+            ),
+            (
+                &mut cfg.sanitize_filename,
+                switch(self.sanitize_filename, self.no_sanitize_filename),
+            ),
+            (
+                &mut cfg.latest_symlink,
+                switch(self.latest_symlink, self.no_latest_symlink),
+            ),
+            (
+                &mut cfg.nested_subdir,
+                switch(self.nested_subdir, self.no_nested_subdir),
+            ),
+            (
+                &mut cfg.timestamp_each_line,
+                switch(self.timestamp_each_line, self.no_timestamp_each_line),
+            ),
+            (
+                &mut cfg.plain_lines,
+                switch(self.plain_lines, self.no_plain_lines),
+            ),
+            (&mut cfg.raw, switch(self.raw, self.no_raw)),
+            (
+                &mut cfg.strip_ansi,
+                switch(self.strip_ansi, self.no_strip_ansi),
+            ),
+            (
+                &mut cfg.log_stdin,
+                switch(self.log_stdin, self.no_log_stdin),
+            ),
+            (
+                &mut cfg.quiet_if_redirected,
+                switch(self.quiet_if_redirected, self.no_quiet_if_redirected),
+            ),
+            (
+                &mut cfg.tee_color,
+                switch(self.tee_color, self.no_tee_color),
+            ),
+            (&mut cfg.cron, switch(self.cron, self.no_cron)),
+            (&mut cfg.log_env, switch(self.log_env, self.no_log_env)),
+            (&mut cfg.env_diff, switch(self.env_diff, self.no_env_diff)),
+            (
+                &mut cfg.log_process_env,
+                switch(self.log_process_env, self.no_log_process_env),
+            ),
+            (&mut cfg.pty, switch(self.pty, self.no_pty)),
+            (
+                &mut cfg.survive_hup,
+                switch(self.survive_hup, self.no_survive_hup),
+            ),
+            (&mut cfg.index, switch(self.index, self.no_index)),
+            (
+                &mut cfg.ci_annotations,
+                switch(self.ci_annotations, self.no_ci_annotations),
+            ),
+            (
+                &mut cfg.mask_args,
+                switch(self.mask_args, self.no_mask_args),
+            ),
+            (
+                &mut cfg.fingerprint,
+                switch(self.fingerprint, self.no_fingerprint),
+            ),
+            (&mut cfg.git, switch(self.git, self.no_git)),
+            (
+                &mut cfg.skip_incompressible,
+                switch(self.skip_incompressible, self.no_skip_incompressible),
+            ),
+        ];
+        for (key, value) in keys {
+            if let Some(value) = value {
+                *key = value;
+            }
+        }
+        // The two stream layouts are one choice.
+        if let Some(split) = switch(self.split_streams, self.no_split_streams) {
+            cfg.split_streams = split;
+            cfg.combine_streams = !split;
+        }
+        if let Some(tee) = switch(self.tee, self.no_tee) {
+            cfg.tee = Tee::Switch(tee);
+        }
+    }
+}
+
+/// Subcommands that work on existing logs instead of running a command.
+/// To log a program that shares one of these names, use `lg -- <name> ...`.
+#[derive(Parser, Debug)]
+#[command(name = "lg", version, disable_help_subcommand = true)]
+pub struct ToolCli {
+    #[command(subcommand)]
+    pub tool: Tool,
+
+    /// Read this config file instead of ~/.lg and .lg.toml
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Apply the config's [profile.NAME] keys (default: $LG_PROFILE)
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Tool {
+    /// List past runs found in the log directory
+    List(ListArgs),
+    /// Recent runs across every directory, from the run index
+    History(HistoryArgs),
+    /// Print a past run's log (the most recent one by default)
+    Show(ShowArgs),
+    /// Print a past run's output again at the pace it was written
+    Replay(ReplayArgs),
+    /// Follow a log as it is written, or every running lg at once
+    Tail(TailArgs),
+    /// Follow the live output of a run still going in this directory
+    Attach(AttachArgs),
+    /// Stop a run still going in this directory, noting the request in its log
+    Kill(KillArgs),
+    /// Print the newest log of a directory (for editor and tmux bindings)
+    LastPath(LastPathArgs),
+    /// Print tmux key bindings that open the current pane's newest log
+    TmuxHook(TmuxHookArgs),
+    /// Open the newest log, or the file:line locations it reports, in $EDITOR
+    Open(OpenArgs),
+    /// Summarize past runs found in the log directory
+    Stats(StatsArgs),
+    /// Disk space taken by the logs, per command
+    Du(DuArgs),
+    /// Search the output of past runs (plain and gzip logs alike)
+    Grep(GrepArgs),
+    /// A digest of the runs in a time window: failures, durations, storage
+    Report(ReportArgs),
+    /// Tidy up the log directory
+    Clean(CleanArgs),
+    /// Capture the login-shell environment that `env_diff` compares against
+    EnvBaseline(EnvBaselineArgs),
+    /// Link the runs of a script into a group with a combined summary
+    #[command(subcommand)]
+    Group(GroupCommand),
+    /// Pin a known-good run of a command line and compare its later runs with it
+    #[command(subcommand)]
+    Baseline(BaselineCommand),
+    /// Inspect lg's configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Replace this lg with the newest GitHub release (builds with `self-update`)
+    SelfUpdate(SelfUpdateArgs),
+}
+
+pub static TOOL_NAMES: &[&str] = &[
+    "list",
+    "history",
+    "show",
+    "replay",
+    "tail",
+    "attach",
+    "kill",
+    "last-path",
+    "tmux-hook",
+    "open",
+    "stats",
+    "du",
+    "grep",
+    "report",
+    "clean",
+    "env-baseline",
+    "group",
+    "baseline",
+    "config",
+    "self-update",
+];
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print a JSON Schema describing every ~/.lg key (types, defaults, descriptions)
+    Schema {
+        /// Output JSON (the only format; accepted for scripts that spell it out)
+        #[arg(long, action = ArgAction::SetTrue)]
+        json: bool,
+    },
+    /// Describe each ~/.lg key: type, default, current value and where it comes from
+    Doc {
+        /// Only this key
+        key: Option<String>,
+
+        /// Write to stdout even on a terminal instead of $PAGER
+        #[arg(long, action = ArgAction::SetTrue)]
+        no_pager: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum GroupCommand {
+    /// Start a group and print `export LG_GROUP=<id>`, for `eval`: the runs
+    /// that see LG_GROUP join the group
+    Start {
+        /// What the group is, e.g. "nightly"; the id adds the start time
+        name: String,
+
+        /// Wall time the group's runs may add up to, e.g. 30m (default: group_budget)
+        #[arg(long, value_name = "DURATION")]
+        budget: Option<String>,
+    },
+    /// A group's runs so far, with their exit codes and logs
+    Show(GroupArgs),
+    /// Close a group and print its summary; exits 1 if any of its runs failed,
+    /// or it went over its budget with group_budget_action = "fail"
+    End(GroupArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct GroupArgs {
+    /// Group id (default: $LG_GROUP)
+    pub id: Option<String>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum BaselineCommand {
+    /// Pin a run as the baseline of its command line; later runs of it are compared
+    /// with it, and how they differ goes into their footer
+    Set(BaselineRunArgs),
+    /// Compare a run with the baseline of its command line; exits 1 if it differs
+    Check(BaselineRunArgs),
+    /// The pinned baselines
+    List,
+    /// Drop the baseline of a command line
+    Unset {
+        /// The command and its arguments, as the log records them
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct BaselineRunArgs {
+    /// Run id or log file (default: the most recent run)
+    pub run: Option<String>,
+
+    /// Directory to look in (default: configured output_dir or the current directory)
+    #[arg(long)]
+    pub dir: Option<PathBuf>,
+
+    /// Without RUN, the most recent run of this command
+    #[arg(long, conflicts_with = "run")]
+    pub cmd: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct EnvBaselineArgs {
+    /// Print the stored baseline instead of refreshing it
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub show: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct SelfUpdateArgs {
+    /// Only say whether a newer release is out; exit status 1 if so
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub check: bool,
+
+    /// Install this release instead of the newest, e.g. v1.1.0
+    #[arg(long)]
+    pub tag: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct CleanArgs {
+    /// Directory to clean (default: configured output_dir or the current directory)
+    #[arg(long)]
+    pub dir: Option<PathBuf>,
+
+    /// Gzip plain logs last written before this time (e.g. 12h, 3d, 2024-05-01)
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub compress_older_than: Option<NaiveDateTime>,
+
+    /// Delete the runs matched by --cmd, --older-than, --failed-only, --group,
+    /// --keep-last and --max-total-size
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub delete: bool,
+
+    /// Pick the runs to delete from a checklist of the matched runs
+    #[arg(short, long, action = ArgAction::SetTrue)]
+    pub interactive: bool,
+
+    /// Only delete runs of this command
+    #[arg(long)]
+    pub cmd: Option<String>,
+
+    /// Only delete runs started before this time
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub older_than: Option<NaiveDateTime>,
+
+    /// Only delete runs that exited non-zero
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub failed_only: bool,
+
+    /// Only delete the runs of this `lg group`
+    #[arg(long, value_name = "ID")]
+    pub group: Option<String>,
+
+    /// Spare the N most recent of the matched runs of each command
+    #[arg(long, value_name = "N")]
+    pub keep_last: Option<usize>,
+
+    /// Only delete as many of the oldest matched runs as it takes for the logs
+    /// to fit in SIZE (e.g. 500M, 1G)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max_total_size: Option<usize>,
+
+    /// List the .partial logs no running lg is writing, left when lg was
+    /// killed before it could rename them; with --delete, delete them
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with = "interactive")]
+    pub orphans: bool,
+
+    /// Put back the runs the last clean moved to .lg-trash/ (clean_trash = true)
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with_all = ["delete", "interactive", "orphans"])]
+    pub undo_last: bool,
+
+    /// Move deleted logs to .lg-trash/ (overrides clean_trash)
+    #[arg(long, overrides_with = "no_trash")]
+    pub trash: bool,
+
+    /// Delete logs for good (overrides clean_trash)
+    #[arg(long, overrides_with = "trash")]
+    pub no_trash: bool,
+
+    /// Show what would be done without changing anything
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    /// Directory to scan (default: configured output_dir or the current directory)
+    #[arg(long)]
+    pub dir: Option<PathBuf>,
+
+    /// Only include runs of this command
+    #[arg(long)]
+    pub cmd: Option<String>,
+
+    /// Only runs started at or after this time (e.g. 2h, 3d, 2024-05-01, yesterday)
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub since: Option<NaiveDateTime>,
+
+    /// Only runs started at or before this time
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub until: Option<NaiveDateTime>,
+
+    /// One row per run instead of per-command aggregates
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub runs: bool,
+
+    /// What lg itself cost per command (startup, output read, CPU), from the run index
+    #[arg(long = "self", action = ArgAction::SetTrue, conflicts_with_all = ["dir", "runs"])]
+    pub lg_self: bool,
+
+    /// Emit CSV (same as --format csv)
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with = "json")]
+    pub csv: bool,
+
+    /// Emit JSON (same as --format json)
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub json: bool,
+
+    /// Output format
+    #[arg(long, value_enum, conflicts_with_all = ["csv", "json"])]
+    pub format: Option<OutputFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct DuArgs {
+    /// Directory to scan (default: configured output_dir or the current directory)
+    #[arg(long, conflicts_with = "index")]
+    pub dir: Option<PathBuf>,
+
+    /// Read the run index (index = true) instead, covering every directory lg logged to
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub index: bool,
+
+    /// What to group the runs by
+    #[arg(long, value_enum, default_value = "cmd")]
+    pub by: DuGroup,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+/// How `lg du` groups runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DuGroup {
+    /// The command name
+    Cmd,
+    /// The command with its recorded arguments
+    Args,
+    /// The directory the command ran in
+    Cwd,
+}
+
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    /// Directory to scan (default: configured output_dir or the current directory)
+    #[arg(long)]
+    pub dir: Option<PathBuf>,
+
+    /// Only runs of this command
+    #[arg(long)]
+    pub cmd: Option<String>,
+
+    /// Only runs started at or after this time (e.g. 2h, 3d, 2024-05-01, yesterday)
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub since: Option<NaiveDateTime>,
+
+    /// Only runs started at or before this time
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub until: Option<NaiveDateTime>,
+
+    /// Only runs that exited non-zero
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub failed_only: bool,
+
+    /// Show only the N most recent runs
+    #[arg(short = 'n', long, value_name = "N")]
+    pub limit: Option<usize>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: OutputFormat,
+
+    /// Write to stdout even on a terminal instead of $PAGER
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub no_pager: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct HistoryArgs {
+    /// Scan this directory instead of reading the run index
+    #[arg(long)]
+    pub dir: Option<PathBuf>,
+
+    /// Only runs of this command
+    #[arg(long)]
+    pub cmd: Option<String>,
+
+    /// Only runs started at or after this time (e.g. 2h, 3d, 2024-05-01, yesterday)
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub since: Option<NaiveDateTime>,
+
+    /// Only runs started at or before this time
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub until: Option<NaiveDateTime>,
+
+    /// Only runs that exited non-zero
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub failed: bool,
+
+    /// Show only the N most recent runs
+    #[arg(short = 'n', long, value_name = "N", default_value_t = 20)]
+    pub limit: usize,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: OutputFormat,
+
+    /// Write to stdout even on a terminal instead of $PAGER
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub no_pager: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AttachArgs {
+    /// The most recently started run in the current directory (the default)
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub last: bool,
+
+    /// The run of this lg process, wherever it was started
+    #[arg(long, conflicts_with = "last")]
+    pub pid: Option<u32>,
+
+    /// Output lines to replay before following (default: attach_backlog from the config)
+    #[arg(long, short = 'n', value_name = "LINES")]
+    pub backlog: Option<usize>,
+
+    /// Replay the whole log so far, header included
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with = "backlog")]
+    pub from_start: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct KillArgs {
+    /// The run with this id (`run_id:` in its log header, LG_RUN_ID in its
+    /// environment)
+    #[arg(value_name = "RUN_ID", conflicts_with = "pid")]
+    pub run_id: Option<String>,
+
+    /// The run of this lg process (default: the most recently started run in
+    /// the current directory)
+    #[arg(long)]
+    pub pid: Option<u32>,
+
+    /// Signal lg passes on to the command
+    #[arg(
+        long,
+        short = 's',
+        value_enum,
+        ignore_case = true,
+        default_value = "term"
+    )]
+    pub signal: KillSignal,
+}
+
+/// What `lg kill` sends: the signals lg forwards to the command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum KillSignal {
+    /// SIGTERM
+    Term,
+    /// SIGINT, as Ctrl-C would
+    Int,
+    /// SIGHUP (ignored by runs with survive_hup)
+    Hup,
+}
+
+#[derive(Args, Debug)]
+pub struct TailArgs {
+    /// Log file to follow (default: the most recent run in the log directory)
+    #[arg(conflicts_with = "all_running")]
+    pub path: Option<PathBuf>,
+
+    /// Directory to scan (default: configured output_dir or the current directory)
+    #[arg(long)]
+    pub dir: Option<PathBuf>,
+
+    /// The newest run of this command, running or not
+    #[arg(long, conflicts_with_all = ["path", "all_running"])]
+    pub cmd: Option<String>,
+
+    /// Follow every currently running lg, prefixing lines with `cmd[pid]`
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub all_running: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct LastPathArgs {
+    /// Use the current directory of the active tmux pane
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub pane: bool,
+
+    /// Look up this directory instead of the current one
+    #[arg(long, conflicts_with = "pane")]
+    pub cwd: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct TmuxHookArgs {
+    /// Key bound after the tmux prefix
+    #[arg(long, default_value = "L")]
+    pub key: String,
+}
+
+#[derive(Args, Debug)]
+pub struct OpenArgs {
+    /// Log file (default: the newest log of the current directory, else the most recent run)
+    pub path: Option<PathBuf>,
+
+    /// Directory to scan (default: configured output_dir or the current directory)
+    #[arg(long)]
+    pub dir: Option<PathBuf>,
+
+    /// Open the `file:line[:col]` locations found in the log instead of the log
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub errors: bool,
+
+    /// With --errors, write the locations as a vim quickfix list ("-" for stdout) instead
+    #[arg(long, value_name = "FILE", requires = "errors")]
+    pub quickfix: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct ReplayArgs {
+    /// Log file to replay (default: the most recent run in the log directory)
+    pub path: Option<PathBuf>,
+
+    /// Directory to scan (default: configured output_dir or the current directory)
+    #[arg(long)]
+    pub dir: Option<PathBuf>,
+
+    /// The most recent run of this command
+    #[arg(long, conflicts_with = "path")]
+    pub cmd: Option<String>,
+
+    /// Play back this many times as fast, e.g. 2x or 0.5x
+    #[arg(long, value_name = "FACTOR", value_parser = parse_speed, default_value = "1x")]
+    pub speed: f64,
+
+    /// Cap each pause at this many seconds (default: pauses as recorded)
+    #[arg(long, value_name = "SECS")]
+    pub max_delay: Option<f64>,
+}
+
+// "2x", "0.5x" or a bare factor.
+fn parse_speed(s: &str) -> Result<f64, String> {
+    let factor = s.trim().strip_suffix('x').unwrap_or(s.trim());
+    match factor.parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!("invalid speed {:?} (expected e.g. 2x or 0.5x)", s)),
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ShowArgs {
+    /// Log file to print (default: the most recent run in the log directory)
+    pub path: Option<PathBuf>,
+
+    /// Directory to scan (default: configured output_dir or the current directory)
+    #[arg(long)]
+    pub dir: Option<PathBuf>,
+
+    /// The most recent run of this command
+    #[arg(long, conflicts_with = "path")]
+    pub cmd: Option<String>,
+
+    /// Print each [[fold]] section as one line that counts its lines
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub folded: bool,
+
+    /// Write to stdout even on a terminal instead of $PAGER
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub no_pager: bool,
+}
+
+/// How `list` and `stats` print their results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned columns with a header, colored on a terminal
+    Table,
+    /// Tab-separated fields, no header and no colors
+    Plain,
+    Csv,
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct ReportArgs {
+    /// Directory to scan (default: configured output_dir or the current directory)
+    #[arg(long)]
+    pub dir: Option<PathBuf>,
+
+    /// Only runs of this command
+    #[arg(long)]
+    pub cmd: Option<String>,
+
+    /// Only runs started at or after this time (e.g. 2h, 3d, 2024-05-01, yesterday)
+    #[arg(long, value_name = "TIME", value_parser = parse_time, default_value = "24h")]
+    pub since: NaiveDateTime,
+
+    /// Only runs started at or before this time
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub until: Option<NaiveDateTime>,
+
+    /// The runs of this `lg group`, wherever their logs are, instead of a time window
+    #[arg(long, value_name = "ID")]
+    pub group: Option<String>,
+
+    /// Report format
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: ReportFormat,
+
+    /// Output lines quoted for each failure, from its first error on
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    pub excerpt_lines: usize,
+}
+
+/// How `lg report` is rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// Plain text for a terminal or a mail body
+    Text,
+    Markdown,
+    /// A standalone page
+    Html,
+}
+
+#[derive(Args, Debug)]
+pub struct GrepArgs {
+    /// Regular expression matched against each output line (without lg's markers)
+    pub pattern: String,
+
+    /// Directory to search (default: configured output_dir or the current directory)
+    #[arg(long)]
+    pub dir: Option<PathBuf>,
+
+    /// Case-insensitive match
+    #[arg(short, long, action = ArgAction::SetTrue)]
+    pub ignore_case: bool,
+
+    /// Lines of context after each match
+    #[arg(short = 'A', long, value_name = "N")]
+    pub after_context: Option<usize>,
+
+    /// Lines of context before each match
+    #[arg(short = 'B', long, value_name = "N")]
+    pub before_context: Option<usize>,
+
+    /// Lines of context around each match
+    #[arg(short = 'C', long, value_name = "N")]
+    pub context: Option<usize>,
+
+    /// Only search lines from this stream
+    #[arg(long, value_enum)]
+    pub stream: Option<Stream>,
+
+    /// Only runs started at or after this time (e.g. 2h, 3d, 2024-05-01, yesterday)
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub since: Option<NaiveDateTime>,
+
+    /// Only runs started at or before this time
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub until: Option<NaiveDateTime>,
+
+    /// Only runs of this command
+    #[arg(long)]
+    pub cmd: Option<String>,
+
+    /// Only runs that exited non-zero
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub failed_only: bool,
+
+    /// Head each run's matches with its command, start and exit code
+    /// (default: when stdout is a terminal)
+    #[arg(long, overrides_with = "no_heading")]
+    pub heading: bool,
+    #[arg(long, overrides_with = "heading", hide = true)]
+    pub no_heading: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::CommandFactory;
+
+    #[test]
+    fn tool_names_are_subcommands() {
+        let cmd = ToolCli::command();
+        let names: Vec<&str> = cmd.get_subcommands().map(|c| c.get_name()).collect();
+        assert_eq!(names, TOOL_NAMES);
+    }
+
+    #[test]
+    fn kill_takes_signal_names_in_any_case() {
+        let cli = ToolCli::try_parse_from(["lg", "kill", "--signal", "INT"]).unwrap();
+        let Tool::Kill(args) = cli.tool else {
+            panic!("not lg kill");
+        };
+        assert_eq!((args.pid, args.signal), (None, KillSignal::Int));
+        assert!(ToolCli::try_parse_from(["lg", "kill", "-s", "kill"]).is_err());
+    }
+
+    #[test]
+    fn flags_after_the_command_belong_to_it() {
+        let cli = Cli::try_parse_from(["lg", "--no-tee", "ls", "-la", "--color"]).unwrap();
+        assert!(cli.switches.no_tee);
+        assert_eq!(cli.cmd, ["ls", "-la", "--color"]);
+    }
+
+    #[test]
+    fn switches_override_the_config_both_ways() {
+        let cli = Cli::try_parse_from([
+            "lg",
+            "--pty",
+            "--no-pty",
+            "--no-mask-args",
+            "--no-index",
+            "--index",
+            "--split-streams",
+            "make",
+        ])
+        .unwrap();
+        let mut cfg = Config {
+            pty: true,
+            log_env: true,
+            ..Config::default()
+        };
+        cli.switches.apply(&mut cfg);
+        assert!(!cfg.pty && !cfg.mask_args && cfg.index);
+        assert!(cfg.split_streams && !cfg.combine_streams);
+        // Keys without a flag on the command line keep their config value.
+        assert!(cfg.log_env && cfg.sanitize_filename);
+        assert_eq!(cfg.tee, Tee::Switch(true));
+    }
+
+    #[test]
+    fn tools_parse() {
+        let tool = ToolCli::try_parse_from(["lg", "grep", "-C", "2", "panic"]).unwrap();
+        let Tool::Grep(args) = tool.tool else {
+            panic!("not grep: {:?}", tool.tool);
+        };
+        assert_eq!((args.context, args.pattern.as_str()), (Some(2), "panic"));
+        assert!(ToolCli::try_parse_from(["lg", "stats", "--since", "soon"]).is_err());
+    }
+}
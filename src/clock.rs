@@ -0,0 +1,38 @@
+//! Wall-clock source, injectable so naming can be tested at a fixed instant.
+
+use chrono::{DateTime, Local};
+
+/// Where lg reads the current local time.
+pub trait Clock {
+    fn now(&self) -> DateTime<Local>;
+}
+
+/// The real clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+/// A clock stopped at one instant.
+#[cfg(test)]
+pub struct FixedClock(pub DateTime<Local>);
+
+#[cfg(test)]
+impl FixedClock {
+    /// `at` as local time, e.g. "2024-03-05 14:07:09".
+    pub fn at(at: &str) -> Self {
+        use chrono::TimeZone;
+        let naive = chrono::NaiveDateTime::parse_from_str(at, "%Y-%m-%d %H:%M:%S").unwrap();
+        Self(Local.from_local_datetime(&naive).earliest().unwrap())
+    }
+}
+
+#[cfg(test)]
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Local> {
+        self.0
+    }
+}
@@ -0,0 +1,1197 @@
+//! The `~/.lg` and `.lg.toml` configuration: types, defaults and loading.
+
+use crate::budget::parse_size;
+use crate::context::{INVOCATION, TTY};
+use crate::matchers::BUILTIN_MATCHERS;
+use crate::sinks::Stream;
+use crate::timespec::{parse_duration, parse_instant};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Defaults
+static DEFAULT_FILENAME_TEMPLATE: &str = "{cmd}_{date}_{time}.log";
+static DEFAULT_LATEST_LINK_NAME: &str = "latest.log";
+static DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+static DEFAULT_TIME_FORMAT: &str = "%H-%M-%S";
+static DEFAULT_TIMESTAMP_RESOLUTION_MS: u64 = 1;
+static DEFAULT_WRITE_BUFFER_SIZE: usize = 64 * 1024;
+static DEFAULT_FLUSH_INTERVAL_MS: u64 = 1000;
+static DEFAULT_RAW_STAMP_INTERVAL_MS: u64 = 1000;
+static DEFAULT_ATTACH_BACKLOG: usize = 20;
+static DEFAULT_CLEAN_TRASH_DAYS: u64 = 7;
+static DEFAULT_TIMEOUT_GRACE_SECS: u64 = 10;
+static DEFAULT_PLUGIN_TIMEOUT_SECS: u64 = 10;
+pub static DEFAULT_MAX_LINE_BYTES: usize = 1024 * 1024;
+// Variables that commonly change what a build or tool does.
+static DEFAULT_FINGERPRINT_ENV: &[&str] = &[
+    "PATH", "LD_*", "DYLD_*", "CC", "CXX", "*FLAGS", "LANG", "LC_*", "TZ",
+];
+// Flags whose value is masked in recorded arguments (the command still gets the real value).
+// Only long flags: `-p` is a port, a package or `mkdir -p` far more often than a password.
+static DEFAULT_MASK_ARG_FLAGS: &[&str] = &["--password", "--pass", "--token", "--api-key"];
+/// A repository's own config, merged over `~/.lg`.
+pub static PROJECT_CONFIG_FILE: &str = ".lg.toml";
+static DEFAULT_CONFIG_TEMPLATE: &str = include_str!("../examples/lg.example.toml");
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// Where to write logs; the current directory when unset. A leading `~`
+    /// is the home directory, and the file name placeholders work here too.
+    pub output_dir: Option<PathBuf>,
+    /// Make `{args}` in `filename_template` expand to the command's arguments.
+    pub include_args_in_name: bool,
+    /// Include every argument (true) or only positional, non-flag arguments (false).
+    pub include_full_args: bool,
+    /// Replace characters that are unsafe in file names with `_`.
+    pub sanitize_filename: bool,
+    /// Log file name template; see the README for placeholders.
+    pub filename_template: String,
+    /// What happens when a run's log name is taken, e.g. by another run in the
+    /// same second: "suffix" (`-2`, `-3`, ... before the extension),
+    /// "overwrite", "error" (the command doesn't run) or "append". Also read
+    /// as `on_conflict`.
+    pub collision: Collision,
+    /// Write the logs of runs started under another lg run (seen through
+    /// `LG_RUN_ID`) to a subdirectory of the output directory named after that run's id.
+    pub nested_subdir: bool,
+    /// Point a symlink in the output directory at each finished run's log.
+    pub latest_symlink: bool,
+    /// Name of the `latest_symlink` link; `{cmd}` gives one per command, e.g. "{cmd}.latest.log".
+    pub latest_link_name: String,
+    /// chrono format used for `{date}` and the header date.
+    pub date_format: String,
+    /// chrono format used for `{time}` and the header time.
+    pub time_format: String,
+    /// Prefix every logged line with a timestamp.
+    pub timestamp_each_line: bool,
+    /// Granularity of per-line timestamps in milliseconds.
+    pub timestamp_resolution_ms: u64,
+    /// Write lines exactly as emitted, without timestamps or stream labels.
+    pub plain_lines: bool,
+    /// Text logs: write the command's output as the bytes it wrote, `\r`
+    /// progress updates and lines without a newline included, not line by line.
+    pub raw: bool,
+    /// With `raw` and `timestamp_each_line`, a `[time]` line goes in at the
+    /// first line start after this many milliseconds; 0 for none.
+    pub raw_stamp_interval_ms: u64,
+    /// How output that isn't valid UTF-8 is logged: "verbatim" (the bytes as
+    /// written), "replace" (with U+FFFD) or "escape" (as `\xff`). JSON Lines
+    /// logs can't hold raw bytes and escape them for "verbatim".
+    pub invalid_utf8: InvalidUtf8,
+    /// Take terminal escape sequences (colors, cursor movement) out of the
+    /// log; the terminal still gets them.
+    pub strip_ansi: bool,
+    /// Log file format: "text", or "jsonl" for one JSON object per line.
+    pub format: LogFormat,
+    /// Log header: "full", "minimal" (what lg's log tools read) or "none",
+    /// for logs that start with the command's first line of output.
+    pub header: HeaderMode,
+    /// Text logs: header lines to write instead of lg's own, with the
+    /// `filename_template` placeholders known at start, e.g. "# {cmd} {args}".
+    pub header_template: Option<String>,
+    /// Write stdout and stderr into one file with stream markers.
+    pub combine_streams: bool,
+    /// Write stdout and stderr to separate `.out.log` / `.err.log` files.
+    pub split_streams: bool,
+    /// Also print the command's output to the terminal: true, false, or "auto"
+    /// (only the streams that are terminals).
+    pub tee: Tee,
+    /// Print nothing of the command's output when lg's stdout is not a
+    /// terminal, whatever `tee` says: whoever captures it already has the log.
+    pub quiet_if_redirected: bool,
+    /// Show teed stderr lines in dim red when both stdout and stderr are
+    /// terminals (and NO_COLOR is unset); the log is not affected.
+    pub tee_color: bool,
+    /// Cron mode: print nothing while the command runs, and its output and
+    /// log path on stderr only if it fails, like `chronic`.
+    pub cron: bool,
+    /// Record environment variables in the header (may expose secrets).
+    pub log_env: bool,
+    /// With `log_env`, only record variables that differ from the `lg env-baseline` snapshot.
+    pub env_diff: bool,
+    /// With `log_env`, only record variables matching one of these globs,
+    /// e.g. ["PATH", "CC", "RUST*"]; every variable when empty.
+    pub env_include: Vec<String>,
+    /// With `log_env`, leave out variables matching these globs, e.g. ["AWS_*", "*_TOKEN"].
+    pub env_exclude: Vec<String>,
+    /// Record `env_exclude` variables with the value masked instead of leaving them out.
+    pub env_mask_excluded: bool,
+    /// Record a fingerprint of the program, the `fingerprint_env` variables, cwd and args
+    /// in the header, so `lg list` can flag runs of a command under changed conditions.
+    pub fingerprint: bool,
+    /// Environment variables (globs) that go into the fingerprint.
+    pub fingerprint_env: Vec<String>,
+    /// Record the branch, commit and dirty state of the git checkout the command runs in.
+    pub git: bool,
+    /// A run deviates from its `lg baseline` when it takes, or prints, more than this many
+    /// times as much as the baseline run did, or less than its share.
+    pub baseline_factor: f64,
+    /// Bytes of output batched in memory before writing to the file.
+    pub write_buffer_size: usize,
+    /// Flush buffered output at least this often; 0 flushes only when the buffer fills.
+    pub flush_interval_ms: u64,
+    /// Lines longer than this many bytes are logged in pieces, bounding per-stream buffering.
+    pub max_line_bytes: usize,
+    /// Upper bound on lg's own buffers, e.g. "16M"; buffers and compression threads shrink to fit.
+    pub max_memory: Option<String>,
+    /// File I/O backend.
+    pub io_backend: IoBackend,
+    /// Log compression.
+    #[serde(default = "default_compress")]
+    pub compress: Compress,
+    /// Compression level: 0-9 for gz (default 6), 1-22 for zstd (default 3).
+    pub compress_level: Option<i32>,
+    /// Compress gz on this many background threads; 0 compresses inline.
+    pub compress_threads: usize,
+    /// Stop compressing when the first 64 KiB of output barely shrink (already
+    /// compressed data); the rest is stored. Not with `compress_threads`.
+    pub skip_incompressible: bool,
+    /// Where the command reads stdin from: "inherit", "null" or { file = "..." }.
+    pub stdin: StdinSource,
+    /// Copy the input piped into the command (or the `stdin` file) into the
+    /// log as `[STDIN]` lines; input typed at a terminal is not logged.
+    pub log_stdin: bool,
+    /// Run the command on a pseudo-terminal (Unix): it keeps its colors and
+    /// progress output, and stderr is logged together with stdout.
+    pub pty: bool,
+    /// Keep running and logging when the terminal hangs up (SIGHUP), e.g. when
+    /// an SSH session drops; the command is started with SIGHUP ignored (Unix).
+    pub survive_hup: bool,
+    /// Stop the command once it has run this long, e.g. "30m" or "2h": SIGTERM
+    /// first, SIGKILL after `timeout_grace_secs`. The run exits with 124.
+    pub timeout: Option<String>,
+    /// Seconds between the SIGTERM and the SIGKILL of a timed-out command.
+    pub timeout_grace_secs: u64,
+    /// Most output a run may log, e.g. "1G" (uncompressed); unlimited when unset.
+    pub max_log_size: Option<String>,
+    /// What happens at `max_log_size`.
+    pub max_log_size_action: LogSizeAction,
+    /// Which logs stay once the run is over: "always", "on-failure" (non-zero
+    /// exit) or "on-error-output" (non-zero exit or anything on stderr). The
+    /// others are deleted, or moved to `.lg-trash/` with `clean_trash`.
+    pub keep: Keep,
+    /// "auto": when a run's whole stdout is one JSON or YAML document, also
+    /// store it pretty-printed beside the log, named in the footer's `[artifact]`.
+    pub output_artifact: OutputArtifact,
+    /// Output lines `lg attach` replays before following a run live.
+    pub attach_backlog: usize,
+    /// Record every run (command, times, exit code, log path) in
+    /// `~/.local/share/lg/index.sqlite`.
+    pub index: bool,
+    /// A run is an anomaly when its duration or amount of output is more than this many
+    /// times the median of the earlier runs of its command line in the index, or less
+    /// than its share; see `crate::anomaly`. 1 or less turns it off.
+    pub anomaly_factor: f64,
+    /// Report an anomaly on stderr as the run ends, under `cron` too.
+    pub anomaly_notify: bool,
+    /// Id of the group of runs this run belongs to, normally set by `eval
+    /// "$(lg group start NAME)"` through `LG_GROUP`; see `lg group`.
+    pub group: Option<String>,
+    /// Wall time the runs of a group may add up to, e.g. "30m", for groups
+    /// that `lg group start --budget` gave none.
+    pub group_budget: Option<String>,
+    /// What happens once a group's runs have used up its budget.
+    pub group_budget_action: GroupBudgetAction,
+    /// When the run was meant to start, e.g. "2026-10-16 02:00" or
+    /// "@1760580000"; the header records how late it started. Unset, the
+    /// systemd timer or cron job that started lg tells.
+    pub scheduled_at: Option<String>,
+    /// `lg clean` moves deleted logs to `.lg-trash/` in the log directory,
+    /// where `lg clean --undo-last` can restore them.
+    pub clean_trash: bool,
+    /// Days deleted logs stay in `.lg-trash/` before `lg clean` purges them.
+    pub clean_trash_days: u64,
+    /// Scripted answers to prompts (expect mode).
+    pub interact: InteractConfig,
+    /// Credentials masked in logged lines and `env[...]` header lines.
+    pub redact: RedactConfig,
+    /// Record umask, resource limits and locale in the header.
+    pub log_process_env: bool,
+    /// Octal umask to set for the command, e.g. "077".
+    pub umask: Option<String>,
+    /// Resource limits to set for the command (soft limit; number or "unlimited").
+    pub limits: BTreeMap<String, LimitValue>,
+    /// Rules that assign a failure class to failed runs.
+    pub classify: Vec<ClassifyRule>,
+    /// Rules that put noisy stretches of output between fold markers, `[[fold]]`.
+    pub fold: Vec<FoldRule>,
+    /// Built-in problem matchers that pick diagnostics out of the output:
+    /// "rustc", "gcc", "eslint", "pytest".
+    pub problem_matchers: Vec<String>,
+    /// Additional problem matchers, `[[matcher]]`.
+    pub matcher: Vec<MatcherRule>,
+    /// Turn diagnostics into GitHub Actions annotations when run there.
+    pub ci_annotations: bool,
+    /// Command that summarizes the output of failed runs; off when unset.
+    pub post_failure_summarizer: Option<SummarizerConfig>,
+    /// External programs that get the run's output as JSON Lines, `[[plugin]]`.
+    pub plugin: Vec<PluginConfig>,
+    /// Program names that are run without any capture.
+    pub never_log: Vec<String>,
+    /// How arguments appear in file names and headers.
+    pub args_policy: ArgsPolicy,
+    /// Mask the values of sensitive flags wherever arguments are recorded.
+    pub mask_args: bool,
+    /// Flags whose following value is masked.
+    pub mask_arg_flags: Vec<String>,
+    /// Per-command overrides, keyed by program name: `[commands.curl]`.
+    pub commands: BTreeMap<String, CommandOverrides>,
+    /// Named sets of keys, `[profile.ci]`, applied over the rest of the config
+    /// by `--profile ci` or `LG_PROFILE=ci`.
+    pub profile: BTreeMap<String, serde_json::Value>,
+    /// `-v` count from the command line; not a config key.
+    #[serde(skip)]
+    pub verbosity: u8,
+}
+
+/// How a command's arguments appear in file names, headers and tools output.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ArgsPolicy {
+    #[default]
+    Full,
+    /// Replace the arguments by a short SHA-256 digest (same args, same digest).
+    Hash,
+    Omit,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Default)]
+#[serde(default)]
+pub struct CommandOverrides {
+    args_policy: Option<ArgsPolicy>,
+}
+
+/// `tee = true`, `tee = false` or `tee = "auto"`.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Tee {
+    Switch(bool),
+    Mode(TeeMode),
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TeeMode {
+    /// Tee each stream only when lg's own copy of it is a terminal.
+    Auto,
+}
+
+impl Tee {
+    fn enabled(self, stream: Stream) -> bool {
+        match self {
+            Tee::Switch(on) => on,
+            Tee::Mode(TeeMode::Auto) => match stream {
+                Stream::Stdout => TTY.stdout,
+                Stream::Stderr => TTY.stderr,
+            },
+        }
+    }
+}
+
+/// Resource limit for `[limits]`: a number or "unlimited".
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum LimitValue {
+    Value(u64),
+    Named(String),
+}
+
+/// Where the wrapped command reads its stdin from.
+/// In TOML: `stdin = "inherit"`, `stdin = "null"` or `stdin = { file = "input.txt" }`.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum StdinSource {
+    Mode(StdinMode),
+    File { file: PathBuf },
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StdinMode {
+    Inherit,
+    Null,
+}
+
+impl Default for StdinSource {
+    fn default() -> Self {
+        StdinSource::Mode(StdinMode::Inherit)
+    }
+}
+
+impl StdinSource {
+    pub fn parse_cli(value: &str) -> Self {
+        match value {
+            "inherit" => StdinSource::Mode(StdinMode::Inherit),
+            "null" => StdinSource::Mode(StdinMode::Null),
+            path => StdinSource::File {
+                file: PathBuf::from(path),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum IoBackend {
+    #[default]
+    Std,
+    Uring,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compress {
+    None,
+    Gz,
+    Zstd,
+}
+
+impl Compress {
+    /// Extension added after `.log`, e.g. "gz" for `name.log.gz`.
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Compress::None => None,
+            Compress::Gz => Some("gz"),
+            Compress::Zstd => Some("zst"),
+        }
+    }
+}
+
+/// Layout of the log file itself.
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Header, `[STDOUT]`/`[STDERR]` lines and footer, for people
+    Text,
+    /// JSON Lines: header record, one record per line, footer record
+    #[serde(alias = "json")]
+    #[value(alias = "json")]
+    Jsonl,
+}
+
+/// What a run does when its log reaches `max_log_size`.
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum LogSizeAction {
+    /// Stop logging; the command runs on and the terminal still shows its output
+    Truncate,
+    /// Move the log to `<log>.1` (replacing the one before) and start it over
+    Rotate,
+    /// Kill the command
+    Kill,
+}
+
+/// What a run of a group does once the group's time budget is used up.
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBudgetAction {
+    /// Warn on stderr after each run over the budget
+    Warn,
+    /// Warn, and don't start further runs of the group; `lg group end` exits 1
+    Fail,
+}
+
+/// What a run does when the name of its log is already taken.
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Collision {
+    /// Number the new log: `<name>-2.log`, `<name>-3.log`, ...
+    Suffix,
+    /// Replace the old log
+    Overwrite,
+    /// Refuse: don't run the command, or leave a finished log under its first name
+    Error,
+    /// Add the new run to the end of the old log
+    Append,
+}
+
+/// Which finished logs `keep` leaves in place.
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, clap::ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Keep {
+    /// Every log
+    Always,
+    /// Logs of runs that exited non-zero
+    OnFailure,
+    /// Logs of runs that exited non-zero or wrote to stderr
+    OnErrorOutput,
+}
+
+/// Whether a run's stdout may also be stored as a document (`output_artifact`).
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputArtifact {
+    /// Only the log
+    Off,
+    /// `<program>_<run id>.json` or `.yaml` beside the log when stdout is one JSON or YAML document
+    Auto,
+}
+
+/// How logged lines render bytes that aren't valid UTF-8.
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum InvalidUtf8 {
+    /// The bytes as the command wrote them
+    Verbatim,
+    /// U+FFFD for each invalid sequence
+    Replace,
+    /// `\xNN` for each invalid byte
+    Escape,
+}
+
+/// How much of a header a log gets.
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum HeaderMode {
+    /// Command, context, terminal, process environment and more
+    Full,
+    /// Command, arguments, start time and directory
+    Minimal,
+    /// No header: the log starts with the output
+    None,
+}
+
+fn default_compress() -> Compress {
+    Compress::None
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            output_dir: None,
+            include_args_in_name: false,
+            include_full_args: true,
+            sanitize_filename: true,
+            filename_template: DEFAULT_FILENAME_TEMPLATE.into(),
+            collision: Collision::Suffix,
+            nested_subdir: false,
+            latest_symlink: false,
+            latest_link_name: DEFAULT_LATEST_LINK_NAME.into(),
+            date_format: DEFAULT_DATE_FORMAT.into(),
+            time_format: DEFAULT_TIME_FORMAT.into(),
+            timestamp_each_line: true,
+            timestamp_resolution_ms: DEFAULT_TIMESTAMP_RESOLUTION_MS,
+            plain_lines: false,
+            raw: false,
+            raw_stamp_interval_ms: DEFAULT_RAW_STAMP_INTERVAL_MS,
+            invalid_utf8: InvalidUtf8::Verbatim,
+            strip_ansi: false,
+            combine_streams: true,
+            split_streams: false,
+            tee: Tee::Switch(true),
+            quiet_if_redirected: false,
+            tee_color: false,
+            cron: false,
+            format: LogFormat::Text,
+            header: HeaderMode::Full,
+            header_template: None,
+            pty: false,
+            survive_hup: false,
+            timeout: None,
+            timeout_grace_secs: DEFAULT_TIMEOUT_GRACE_SECS,
+            max_log_size: None,
+            max_log_size_action: LogSizeAction::Truncate,
+            keep: Keep::Always,
+            output_artifact: OutputArtifact::Off,
+            attach_backlog: DEFAULT_ATTACH_BACKLOG,
+            index: false,
+            anomaly_factor: 3.0,
+            anomaly_notify: false,
+            group: None,
+            group_budget: None,
+            group_budget_action: GroupBudgetAction::Warn,
+            scheduled_at: None,
+            clean_trash: false,
+            clean_trash_days: DEFAULT_CLEAN_TRASH_DAYS,
+            log_env: false,
+            env_diff: false,
+            env_include: Vec::new(),
+            env_exclude: Vec::new(),
+            env_mask_excluded: false,
+            fingerprint: true,
+            fingerprint_env: DEFAULT_FINGERPRINT_ENV
+                .iter()
+                .map(|v| v.to_string())
+                .collect(),
+            git: true,
+            baseline_factor: 2.0,
+            write_buffer_size: DEFAULT_WRITE_BUFFER_SIZE,
+            flush_interval_ms: DEFAULT_FLUSH_INTERVAL_MS,
+            max_line_bytes: DEFAULT_MAX_LINE_BYTES,
+            max_memory: None,
+            io_backend: IoBackend::Std,
+            compress: Compress::None,
+            compress_level: None,
+            compress_threads: 0,
+            skip_incompressible: true,
+            stdin: StdinSource::default(),
+            log_stdin: false,
+            interact: InteractConfig::default(),
+            redact: RedactConfig::default(),
+            log_process_env: true,
+            umask: None,
+            limits: BTreeMap::new(),
+            classify: Vec::new(),
+            fold: Vec::new(),
+            problem_matchers: BUILTIN_MATCHERS
+                .iter()
+                .map(|m| m.name.to_string())
+                .collect(),
+            matcher: Vec::new(),
+            ci_annotations: true,
+            post_failure_summarizer: None,
+            plugin: Vec::new(),
+            never_log: Vec::new(),
+            args_policy: ArgsPolicy::Full,
+            mask_args: true,
+            mask_arg_flags: DEFAULT_MASK_ARG_FLAGS
+                .iter()
+                .map(|f| f.to_string())
+                .collect(),
+            commands: BTreeMap::new(),
+            profile: BTreeMap::new(),
+            verbosity: 0,
+        }
+    }
+}
+
+// Name config entries refer to a command by, e.g. "gpg" for /usr/bin/gpg.
+pub fn program_name(cmd: &OsString) -> Cow<'_, str> {
+    Path::new(cmd)
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default()
+}
+
+impl Config {
+    /// True when `quiet_if_redirected` applies to this run.
+    pub fn quiet(&self) -> bool {
+        self.cron || (self.quiet_if_redirected && !TTY.stdout)
+    }
+
+    pub fn tees(&self, stream: Stream) -> bool {
+        !self.quiet() && self.tee.enabled(stream)
+    }
+
+    /// `compress_level`, or the compressor's default, checked against its range.
+    pub fn compress_level(&self) -> Result<i32> {
+        let (name, default, range) = match self.compress {
+            Compress::None => return Ok(0),
+            Compress::Gz => ("gz", 6, 0..=9),
+            Compress::Zstd => (
+                "zstd",
+                zstd::DEFAULT_COMPRESSION_LEVEL,
+                zstd::compression_level_range(),
+            ),
+        };
+        let level = self.compress_level.unwrap_or(default);
+        if !range.contains(&level) {
+            anyhow::bail!(
+                "compress_level {} is out of range for {} ({} to {})",
+                level,
+                name,
+                range.start(),
+                range.end()
+            );
+        }
+        Ok(level)
+    }
+
+    /// `timeout` as a duration; None when unset or zero.
+    pub fn timeout(&self) -> Result<Option<Duration>> {
+        let Some(spec) = self.timeout.as_deref().filter(|s| !s.trim().is_empty()) else {
+            return Ok(None);
+        };
+        let limit = parse_duration(spec)
+            .map_err(anyhow::Error::msg)
+            .context("timeout")?;
+        Ok(Some(limit).filter(|d| !d.is_zero()))
+    }
+
+    /// `group_budget` as a duration; None when unset or zero.
+    pub fn group_budget(&self) -> Result<Option<Duration>> {
+        let Some(spec) = self
+            .group_budget
+            .as_deref()
+            .filter(|s| !s.trim().is_empty())
+        else {
+            return Ok(None);
+        };
+        let budget = parse_duration(spec)
+            .map_err(anyhow::Error::msg)
+            .context("group_budget")?;
+        Ok(Some(budget).filter(|d| !d.is_zero()))
+    }
+
+    /// When the run was scheduled: `scheduled_at`, else when the systemd
+    /// timer fired or cron started the job; None for runs started by hand.
+    pub fn scheduled_at(&self) -> Result<Option<DateTime<Local>>> {
+        let Some(spec) = self
+            .scheduled_at
+            .as_deref()
+            .filter(|s| !s.trim().is_empty())
+        else {
+            return Ok(INVOCATION.scheduled);
+        };
+        let at = parse_instant(spec)
+            .map_err(anyhow::Error::msg)
+            .context("scheduled_at")?;
+        Ok(Some(at))
+    }
+
+    /// `max_log_size` in bytes; None when unset or zero.
+    pub fn max_log_size(&self) -> Result<Option<u64>> {
+        let Some(spec) = self
+            .max_log_size
+            .as_deref()
+            .filter(|s| !s.trim().is_empty())
+        else {
+            return Ok(None);
+        };
+        let limit = parse_size(spec)
+            .map_err(anyhow::Error::msg)
+            .context("max_log_size")?;
+        Ok(Some(limit as u64).filter(|n| *n > 0))
+    }
+
+    pub fn args_policy_for(&self, cmd: &OsString) -> ArgsPolicy {
+        self.commands
+            .get(program_name(cmd).as_ref())
+            .and_then(|c| c.args_policy)
+            .unwrap_or(self.args_policy)
+    }
+}
+
+/// `~/.lg`, whether or not it exists yet.
+pub fn config_file() -> Option<PathBuf> {
+    simple_home_dir().map(|home| home.join(".lg"))
+}
+
+fn ensure_config_file() -> Option<PathBuf> {
+    let path = config_file()?;
+    if !path.exists() {
+        if let Err(err) = fs::write(&path, DEFAULT_CONFIG_TEMPLATE) {
+            eprintln!("lg: failed to create default config at {:?}: {}", path, err);
+            return Some(path);
+        }
+    }
+    Some(path)
+}
+
+pub fn simple_home_dir() -> Option<PathBuf> {
+    // Unix-like: $HOME
+    if let Ok(h) = std::env::var("HOME") {
+        if !h.is_empty() {
+            return Some(PathBuf::from(h));
+        }
+    }
+    // Windows fallbacks
+    if cfg!(windows) {
+        if let Ok(p) = std::env::var("USERPROFILE") {
+            if !p.is_empty() {
+                return Some(PathBuf::from(p));
+            }
+        }
+        let drive = std::env::var("HOMEDRIVE").unwrap_or_default();
+        let path = std::env::var("HOMEPATH").unwrap_or_default();
+        if !drive.is_empty() && !path.is_empty() {
+            return Some(PathBuf::from(format!("{}{}", drive, path)));
+        }
+    }
+    None
+}
+
+/// `path` with a leading `~` component taken from the home directory.
+pub fn expand_tilde(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), simple_home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// The files the configuration is read from, lowest precedence first: `path`
+/// alone when given (`--config`), otherwise `~/.lg` and the nearest
+/// `.lg.toml` in or above the working directory.
+pub fn config_sources(path: Option<&Path>) -> Vec<PathBuf> {
+    if let Some(path) = path {
+        return vec![path.to_path_buf()];
+    }
+    let home = ensure_config_file().filter(|p| p.exists());
+    let project = std::env::current_dir()
+        .ok()
+        .and_then(|dir| find_project_config(&dir));
+    home.into_iter().chain(project).collect()
+}
+
+fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|d| d.join(PROJECT_CONFIG_FILE))
+        .find(|p| p.is_file())
+}
+
+/// A config file's keys. A relative `output_dir` in a `.lg.toml` is taken
+/// from the file's directory, so a repository's logs land in one place
+/// whichever subdirectory lg runs in.
+pub fn read_config_table(path: &Path) -> Result<toml::Table> {
+    let data = fs::read_to_string(path).with_context(|| format!("reading config {:?}", path))?;
+    let mut table: toml::Table =
+        toml::from_str(&data).with_context(|| format!("parsing config TOML {:?}", path))?;
+    resolve_aliases(&mut table);
+    if let Some(toml::Value::Table(profiles)) = table.get_mut("profile") {
+        for (_, keys) in profiles.iter_mut() {
+            if let toml::Value::Table(keys) = keys {
+                resolve_aliases(keys);
+            }
+        }
+    }
+    // Type errors are reported against the file that has them.
+    check_keys(&table).with_context(|| format!("parsing config TOML {:?}", path))?;
+    for (name, keys) in profiles(&table) {
+        check_keys(keys).with_context(|| format!("parsing [profile.{}] in {:?}", name, path))?;
+    }
+    if path.file_name() == Some(PROJECT_CONFIG_FILE.as_ref()) {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        anchor_output_dir(&mut table, dir);
+        if let Some(toml::Value::Table(profiles)) = table.get_mut("profile") {
+            for (_, keys) in profiles.iter_mut() {
+                if let toml::Value::Table(keys) = keys {
+                    anchor_output_dir(keys, dir);
+                }
+            }
+        }
+    }
+    Ok(table)
+}
+
+// Other names keys are known by, and the key each one sets.
+static KEY_ALIASES: &[(&str, &str)] = &[("on_conflict", "collision")];
+
+// Replace aliases by their keys; where a file has both, the key wins.
+fn resolve_aliases(table: &mut toml::Table) {
+    for (alias, key) in KEY_ALIASES {
+        if let Some(value) = table.remove(*alias) {
+            table.entry(key.to_string()).or_insert(value);
+        }
+    }
+}
+
+fn check_keys(table: &toml::Table) -> Result<()> {
+    toml::Value::Table(table.clone()).try_into::<Config>()?;
+    Ok(())
+}
+
+/// The `[profile.NAME]` tables of a config file.
+pub fn profiles(table: &toml::Table) -> impl Iterator<Item = (&String, &toml::Table)> {
+    let profiles = match table.get("profile") {
+        Some(toml::Value::Table(profiles)) => Some(profiles),
+        _ => None,
+    };
+    profiles
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, keys)| keys.as_table().map(|keys| (name, keys)))
+}
+
+fn anchor_output_dir(table: &mut toml::Table, dir: &Path) {
+    if let Some(toml::Value::String(out)) = table.get_mut("output_dir") {
+        if !out.is_empty() && Path::new(out).is_relative() && !out.starts_with('~') {
+            *out = dir.join(&*out).to_string_lossy().into_owned();
+        }
+    }
+}
+
+/// The profile to apply: `flag` (`--profile`), else `$LG_PROFILE`.
+pub fn selected_profile(flag: Option<&str>) -> Option<String> {
+    flag.map(str::to_string)
+        .or_else(|| std::env::var("LG_PROFILE").ok())
+        .filter(|p| !p.is_empty())
+}
+
+/// The merged configuration of `sources`, with `profile`'s keys and then the
+/// `LG_<KEY>` environment variables on top.
+pub fn load_config(sources: &[PathBuf], profile: Option<&str>) -> Result<Config> {
+    let mut table = toml::Table::new();
+    for path in sources {
+        merge_tables(&mut table, read_config_table(path)?);
+    }
+    if let Some(name) = profile {
+        let keys = profiles(&table)
+            .find(|(n, _)| *n == name)
+            .map(|(_, keys)| keys.clone())
+            .with_context(|| format!("no [profile.{}] in the config", name))?;
+        merge_tables(&mut table, keys);
+    }
+    for (key, var, raw) in env_keys(std::env::vars_os()) {
+        let value = env_value(&key, &raw).with_context(|| format!("{}={:?}", var, raw))?;
+        table.insert(key, value);
+    }
+    toml::Value::Table(table)
+        .try_into()
+        .context("merging config files")
+}
+
+/// The config keys that `LG_<KEY>` variables among `vars` set, as (key,
+/// variable, value); e.g. ("compress", "LG_COMPRESS", "gz"). LG_PROFILE picks
+/// a profile instead.
+pub fn env_keys(vars: impl Iterator<Item = (OsString, OsString)>) -> Vec<(String, String, String)> {
+    static KEYS: Lazy<Vec<String>> = Lazy::new(|| match serde_json::to_value(Config::default()) {
+        Ok(serde_json::Value::Object(keys)) => keys.into_iter().map(|(k, _)| k).collect(),
+        _ => Vec::new(),
+    });
+    let mut found: Vec<_> = vars
+        .filter_map(|(var, raw)| {
+            let (var, raw) = (var.into_string().ok()?, raw.into_string().ok()?);
+            let key = var.strip_prefix("LG_")?.to_ascii_lowercase();
+            (key != "profile" && KEYS.contains(&key)).then_some((key, var, raw))
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+// An environment value as the TOML value its key takes: `false`, `3`,
+// `["ssh", "gpg"]` or `{ enabled = false }` as written, anything else as a
+// string (`LG_COMPRESS=gz`, `LG_OUTPUT_DIR=/var/log/lg`).
+fn env_value(key: &str, raw: &str) -> Result<toml::Value> {
+    let fits = |value: &toml::Value| {
+        let table: toml::Table = [(key.to_string(), value.clone())].into_iter().collect();
+        check_keys(&table)
+    };
+    let written = toml::from_str::<toml::Table>(&format!("v = {}", raw))
+        .ok()
+        .and_then(|mut t| t.remove("v"));
+    if let Some(value) = written.filter(|v| fits(v).is_ok()) {
+        return Ok(value);
+    }
+    let value = toml::Value::String(raw.to_string());
+    fits(&value)?;
+    Ok(value)
+}
+
+// Keys of `over` replace those of `base`; tables such as `[redact]` or
+// `[commands.make]` are merged key by key.
+fn merge_tables(base: &mut toml::Table, over: toml::Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(over)) => merge_tables(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// A `[[classify]]` rule: failed runs whose output matches `pattern` get class `name`.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct ClassifyRule {
+    pub name: String,
+    pub pattern: String,
+}
+
+/// A `[[fold]]` rule: output from a line matching `start` through the next
+/// one matching `end` is logged between fold markers; without `end`, each run
+/// of lines matching `start` is.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct FoldRule {
+    pub name: String,
+    pub start: String,
+    #[serde(default)]
+    pub end: Option<String>,
+}
+
+/// A `[[matcher]]` problem matcher. `pattern` names its captures `file`,
+/// `line` and optionally `col`, `severity` and `message`; captures of the
+/// optional `context` pattern (say a file header, or an "error: ..." line
+/// printed before the location) carry over to later matches of `pattern`.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct MatcherRule {
+    pub name: String,
+    pub pattern: String,
+    #[serde(default)]
+    pub context: Option<String>,
+    /// Severity when the pattern doesn't capture one.
+    #[serde(default = "default_severity")]
+    pub severity: String,
+}
+
+pub fn default_severity() -> String {
+    "error".to_string()
+}
+
+/// `[post_failure_summarizer]`: a command (say, an LLM CLI) that is given the
+/// redacted last lines of a failed run on stdin and prints a short summary.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(default)]
+pub struct SummarizerConfig {
+    /// Run with `sh -c`; LG_CMD, LG_RUN_ID, LG_EXIT_CODE and LG_FAILURE_CLASS are set.
+    pub command: String,
+    /// Lines from the end of the output to send.
+    pub excerpt_lines: usize,
+    /// Seconds to wait for the summary.
+    pub timeout_secs: u64,
+}
+
+impl Default for SummarizerConfig {
+    fn default() -> Self {
+        Self {
+            command: String::new(),
+            excerpt_lines: 100,
+            timeout_secs: 60,
+        }
+    }
+}
+
+/// A `[[plugin]]`: a program fed the run's output records (the `jsonl` line
+/// records, then the footer) on stdin while the command runs, or a WebAssembly
+/// filter run inside lg.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct PluginConfig {
+    /// Name in footer notes; the command or module when unset.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Run with `sh -c`; LG_CMD, LG_ARGS, LG_RUN_ID and LG_LOG are set.
+    #[serde(default)]
+    pub command: String,
+    /// A WebAssembly module to filter the records with instead of a command
+    /// (builds with the `wasm` feature). It runs sandboxed in lg's process and
+    /// is always a filter.
+    #[serde(default)]
+    pub wasm: Option<PathBuf>,
+    /// "sink" only reads the records; a "filter" writes records to its stdout,
+    /// and the plugins after it get those instead.
+    #[serde(default)]
+    pub kind: PluginKind,
+    /// Seconds to wait for the plugin once the command is done, then it is killed.
+    #[serde(default = "default_plugin_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_plugin_timeout_secs() -> u64 {
+    DEFAULT_PLUGIN_TIMEOUT_SECS
+}
+
+impl PluginConfig {
+    pub fn name(&self) -> &str {
+        let module = self.wasm.as_deref().and_then(|p| p.to_str());
+        self.name.as_deref().or(module).unwrap_or(&self.command)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginKind {
+    #[default]
+    Sink,
+    Filter,
+}
+
+/// `[redact]`: what is masked as `****` before it reaches the log. The
+/// terminal still shows the command's output as it is.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(default)]
+pub struct RedactConfig {
+    pub enabled: bool,
+    /// Mask passwords and tokens in `key=value` form, bearer tokens,
+    /// passwords in URLs and AWS access key ids.
+    pub builtin: bool,
+    /// More regexes to mask; only the `secret` group when a pattern has one.
+    pub patterns: Vec<String>,
+}
+
+impl Default for RedactConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            builtin: true,
+            patterns: Vec::new(),
+        }
+    }
+}
+
+/// Scripted responses for `[interact]` rules (or `--expect-script`).
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, Default)]
+#[serde(default)]
+pub struct InteractConfig {
+    pub rules: Vec<InteractRule>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+pub struct InteractRule {
+    /// Regex matched against each output line and the pending unterminated line.
+    pub expect: String,
+    /// Text written to the command's stdin when `expect` matches.
+    pub send: String,
+    #[serde(default = "default_true")]
+    pub newline: bool,
+    /// Log the response as `****` instead of its contents.
+    #[serde(default)]
+    pub secret: bool,
+    /// Only fire the first time the pattern matches.
+    #[serde(default)]
+    pub once: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example_config_parses() {
+        let cfg: Config = toml::from_str(DEFAULT_CONFIG_TEMPLATE).unwrap();
+        assert_eq!(cfg.filename_template, DEFAULT_FILENAME_TEMPLATE);
+        assert_eq!(cfg.problem_matchers.len(), 4);
+    }
+
+    #[test]
+    fn tee_accepts_bool_or_auto() {
+        let tee = |s: &str| toml::from_str::<Config>(s).unwrap().tee;
+        assert_eq!(tee("tee = false"), Tee::Switch(false));
+        assert_eq!(tee("tee = \"auto\""), Tee::Mode(TeeMode::Auto));
+        assert!(toml::from_str::<Config>("tee = \"sometimes\"").is_err());
+    }
+
+    #[test]
+    fn project_config_and_profiles_merge_over_home() {
+        let root = std::env::temp_dir().join(format!("lg-config-test-{}", std::process::id()));
+        let sub = root.join("src/deep");
+        fs::create_dir_all(&sub).unwrap();
+        let home = root.join("home.lg");
+        fs::write(
+            &home,
+            "compress = \"gz\"\nsplit_streams = true\n[redact]\nbuiltin = false\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join(PROJECT_CONFIG_FILE),
+            "output_dir = \"logs\"\ncompress = \"zstd\"\n[redact]\npatterns = [\"x\"]\n",
+        )
+        .unwrap();
+
+        let project = find_project_config(&sub).unwrap();
+        assert_eq!(project, root.join(PROJECT_CONFIG_FILE));
+        let cfg = load_config(&[home.clone(), project.clone()], None).unwrap();
+        assert_eq!(cfg.output_dir, Some(root.join("logs")));
+        assert_eq!(cfg.compress, Compress::Zstd);
+        assert!(cfg.split_streams);
+        assert!(!cfg.redact.builtin);
+        assert_eq!(cfg.redact.patterns, ["x"]);
+
+        fs::write(&home, "compress = 3\n").unwrap();
+        let err = load_config(std::slice::from_ref(&home), None).unwrap_err();
+        assert!(format!("{:#}", err).contains("home.lg"));
+
+        // Profiles merge too, and apply on top of both files.
+        fs::write(&home, "[profile.ci]\ncompress = \"gz\"\ntee = false\n").unwrap();
+        fs::write(
+            &project,
+            "[profile.ci]\noutput_dir = \"ci-logs\"\n[profile.bad]\ntee = 3\n",
+        )
+        .unwrap();
+        let cfg = load_config(std::slice::from_ref(&home), Some("ci")).unwrap();
+        assert_eq!(cfg.tee, Tee::Switch(false));
+        let err = load_config(&[home.clone(), project.clone()], Some("ci")).unwrap_err();
+        assert!(format!("{:#}", err).contains("[profile.bad]"));
+        fs::write(&project, "[profile.ci]\noutput_dir = \"ci-logs\"\n").unwrap();
+        let cfg = load_config(&[home.clone(), project.clone()], Some("ci")).unwrap();
+        assert_eq!(cfg.output_dir, Some(root.join("ci-logs")));
+        assert_eq!((cfg.compress, cfg.tee), (Compress::Gz, Tee::Switch(false)));
+        let cfg = load_config(&[home.clone(), project.clone()], None).unwrap();
+        assert_eq!((cfg.compress, cfg.output_dir), (Compress::None, None));
+
+        // `on_conflict` is another name for `collision`, in profiles too.
+        fs::write(&home, "collision = \"append\"\n").unwrap();
+        fs::write(
+            &project,
+            "on_conflict = \"error\"\n[profile.ci]\non_conflict = \"overwrite\"\n",
+        )
+        .unwrap();
+        let cfg = load_config(&[home.clone(), project.clone()], None).unwrap();
+        assert_eq!(cfg.collision, Collision::Error);
+        let cfg = load_config(&[home.clone(), project.clone()], Some("ci")).unwrap();
+        assert_eq!(cfg.collision, Collision::Overwrite);
+        assert!(load_config(&[home], Some("nightly")).is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn env_vars_set_keys_in_their_type() {
+        let vars = [
+            ("LG_COMPRESS", "gz"),
+            ("LG_TEE", "auto"),
+            ("LG_SPLIT_STREAMS", "true"),
+            ("LG_NEVER_LOG", r#"["ssh", "gpg"]"#),
+            ("LG_OUTPUT_DIR", "2024"),
+            ("LG_PROFILE", "ci"),
+            ("LG_PAGER", "less"),
+            ("PATH", "/bin"),
+        ]
+        .map(|(k, v)| (OsString::from(k), OsString::from(v)));
+        let found = env_keys(vars.into_iter());
+        let keys: Vec<&str> = found.iter().map(|(k, _, _)| k.as_str()).collect();
+        assert_eq!(
+            keys,
+            [
+                "compress",
+                "never_log",
+                "output_dir",
+                "split_streams",
+                "tee"
+            ]
+        );
+        let mut table = toml::Table::new();
+        for (key, _, raw) in found {
+            let value = env_value(&key, &raw).unwrap();
+            table.insert(key, value);
+        }
+        let cfg: Config = toml::Value::Table(table).try_into().unwrap();
+        assert_eq!(cfg.compress, Compress::Gz);
+        assert_eq!(cfg.tee, Tee::Mode(TeeMode::Auto));
+        assert!(cfg.split_streams);
+        assert_eq!(cfg.never_log, ["ssh", "gpg"]);
+        assert_eq!(cfg.output_dir, Some(PathBuf::from("2024")));
+        assert!(env_value("tee", "sometimes").is_err());
+        assert!(env_value("compress_level", "high").is_err());
+    }
+
+    #[test]
+    fn per_command_args_policy() {
+        let cfg: Config =
+            toml::from_str("args_policy = \"omit\"\n[commands.curl]\nargs_policy = \"hash\"\n")
+                .unwrap();
+        assert_eq!(
+            cfg.args_policy_for(&"/usr/bin/curl".into()),
+            ArgsPolicy::Hash
+        );
+        assert_eq!(cfg.args_policy_for(&"wget".into()), ArgsPolicy::Omit);
+    }
+}
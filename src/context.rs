@@ -0,0 +1,259 @@
+//! What lg was started from: terminals, CI systems, systemd units, cron.
+
+use chrono::{DateTime, Local, Timelike};
+use hostname::get as get_hostname;
+use once_cell::sync::Lazy;
+use std::fs;
+use std::io::{self, IsTerminal};
+
+// Cache hostname once
+pub static HOSTNAME: Lazy<String> = Lazy::new(|| {
+    get_hostname()
+        .ok()
+        .and_then(|o| o.into_string().ok())
+        .unwrap_or_else(|| "unknown".into())
+});
+
+// The user running lg, for `{user}`: from the environment, else the password
+// database (cron and systemd may not set USER).
+pub static USER: Lazy<String> = Lazy::new(|| {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .ok()
+        .filter(|u| !u.is_empty())
+        .or_else(passwd_user)
+        .unwrap_or_else(|| "unknown".into())
+});
+
+#[cfg(unix)]
+fn passwd_user() -> Option<String> {
+    let pw = unsafe { libc::getpwuid(libc::geteuid()) };
+    if pw.is_null() {
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr((*pw).pw_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(not(unix))]
+fn passwd_user() -> Option<String> {
+    std::env::var("USERNAME").ok()
+}
+
+// Terminal detection for `tee = "auto"`, colors and the header's `tty:` line
+pub static TTY: Lazy<TtyState> = Lazy::new(TtyState::detect);
+// Detect once what started lg (CI job, systemd unit, cron)
+pub static INVOCATION: Lazy<InvocationContext> = Lazy::new(InvocationContext::detect);
+
+/// Which of lg's standard streams are terminals, detected once at startup.
+#[derive(Debug, Clone, Copy)]
+pub struct TtyState {
+    pub stdin: bool,
+    pub stdout: bool,
+    pub stderr: bool,
+}
+
+impl TtyState {
+    fn detect() -> Self {
+        Self {
+            stdin: io::stdin().is_terminal(),
+            stdout: io::stdout().is_terminal(),
+            stderr: io::stderr().is_terminal(),
+        }
+    }
+}
+
+impl std::fmt::Display for TtyState {
+    /// "stdin stdout stderr" for the streams that are terminals, or "none".
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ttys: Vec<&str> = [
+            ("stdin", self.stdin),
+            ("stdout", self.stdout),
+            ("stderr", self.stderr),
+        ]
+        .iter()
+        .filter(|(_, tty)| *tty)
+        .map(|(name, _)| *name)
+        .collect();
+        if ttys.is_empty() {
+            f.write_str("none")
+        } else {
+            f.write_str(&ttys.join(" "))
+        }
+    }
+}
+
+/// What started this lg invocation, so logs can be tied back to a CI run,
+/// systemd unit or cron job.
+#[derive(Debug, Default)]
+pub struct InvocationContext {
+    /// "github-actions", "gitlab-ci", "jenkins", "systemd", "cron", ...; None when interactive
+    pub kind: Option<&'static str>,
+    /// CI run/pipeline identifier, exposed as `{ci_run}`
+    pub run_id: Option<String>,
+    /// When the systemd timer fired, or the minute cron started the job in.
+    pub scheduled: Option<DateTime<Local>>,
+    details: Vec<(&'static str, String)>,
+}
+
+/// A CI system recognised by its marker variable.
+struct CiSystem {
+    kind: &'static str,
+    marker: &'static str,
+    run_id: &'static str,
+    /// (label, variable) pairs worth recording in the header
+    extra: &'static [(&'static str, &'static str)],
+}
+
+static CI_SYSTEMS: &[CiSystem] = &[
+    CiSystem {
+        kind: "github-actions",
+        marker: "GITHUB_ACTIONS",
+        run_id: "GITHUB_RUN_ID",
+        extra: &[
+            ("attempt", "GITHUB_RUN_ATTEMPT"),
+            ("workflow", "GITHUB_WORKFLOW"),
+            ("job", "GITHUB_JOB"),
+            ("repo", "GITHUB_REPOSITORY"),
+        ],
+    },
+    CiSystem {
+        kind: "gitlab-ci",
+        marker: "GITLAB_CI",
+        run_id: "CI_PIPELINE_ID",
+        extra: &[
+            ("job", "CI_JOB_ID"),
+            ("job_name", "CI_JOB_NAME"),
+            ("url", "CI_JOB_URL"),
+        ],
+    },
+    CiSystem {
+        kind: "jenkins",
+        marker: "JENKINS_URL",
+        run_id: "BUILD_NUMBER",
+        extra: &[("job", "JOB_NAME"), ("url", "BUILD_URL")],
+    },
+    CiSystem {
+        kind: "circleci",
+        marker: "CIRCLECI",
+        run_id: "CIRCLE_BUILD_NUM",
+        extra: &[("job", "CIRCLE_JOB"), ("url", "CIRCLE_BUILD_URL")],
+    },
+    CiSystem {
+        kind: "buildkite",
+        marker: "BUILDKITE",
+        run_id: "BUILDKITE_BUILD_ID",
+        extra: &[("job", "BUILDKITE_JOB_ID"), ("url", "BUILDKITE_BUILD_URL")],
+    },
+    CiSystem {
+        kind: "azure-pipelines",
+        marker: "TF_BUILD",
+        run_id: "BUILD_BUILDID",
+        extra: &[("job", "SYSTEM_JOBID")],
+    },
+    CiSystem {
+        kind: "travis",
+        marker: "TRAVIS",
+        run_id: "TRAVIS_BUILD_ID",
+        extra: &[("job", "TRAVIS_JOB_ID")],
+    },
+];
+
+impl InvocationContext {
+    fn detect() -> Self {
+        let var = |k: &str| std::env::var(k).ok().filter(|v| !v.is_empty());
+        for ci in CI_SYSTEMS {
+            if var(ci.marker).is_none() {
+                continue;
+            }
+            let details = ci
+                .extra
+                .iter()
+                .filter_map(|(label, k)| var(k).map(|v| (*label, v)))
+                .collect();
+            return Self {
+                kind: Some(ci.kind),
+                run_id: var(ci.run_id),
+                details,
+                ..Self::default()
+            };
+        }
+        if var("CI").is_some() {
+            return Self {
+                kind: Some("ci"),
+                ..Self::default()
+            };
+        }
+        if let Some(invocation) = var("INVOCATION_ID") {
+            let mut details = vec![("invocation", invocation)];
+            if let Some(unit) = systemd_unit() {
+                details.insert(0, ("unit", unit));
+            }
+            // Set by systemd 251 and later for a unit a timer started.
+            let scheduled = var("TRIGGER_TIMER_REALTIME_USEC")
+                .and_then(|usec| usec.parse::<i64>().ok())
+                .and_then(DateTime::from_timestamp_micros)
+                .map(|t| t.with_timezone(&Local));
+            return Self {
+                kind: Some("systemd"),
+                run_id: None,
+                scheduled,
+                details,
+            };
+        }
+        if started_by_cron() {
+            // cron starts jobs on the minute.
+            let now = Local::now();
+            return Self {
+                kind: Some("cron"),
+                scheduled: now.with_second(0).and_then(|t| t.with_nanosecond(0)),
+                ..Self::default()
+            };
+        }
+        Self::default()
+    }
+
+    pub fn describe(&self) -> Option<String> {
+        let mut s = self.kind?.to_string();
+        if let Some(run) = &self.run_id {
+            s.push_str(&format!(" run={}", run));
+        }
+        for (k, v) in &self.details {
+            s.push_str(&format!(" {}={}", k, v));
+        }
+        Some(s)
+    }
+}
+
+// Unit name from our cgroup path, e.g. ".../backup.service".
+fn systemd_unit() -> Option<String> {
+    let cgroup = fs::read_to_string("/proc/self/cgroup").ok()?;
+    cgroup
+        .lines()
+        .flat_map(|l| l.rsplit('/'))
+        .find(|seg| seg.ends_with(".service") || seg.ends_with(".scope"))
+        .map(str::to_string)
+}
+
+// Walk up the process tree (Linux /proc) looking for a cron daemon.
+fn started_by_cron() -> bool {
+    let mut pid = std::process::id();
+    for _ in 0..16 {
+        let Ok(stat) = fs::read_to_string(format!("/proc/{}/stat", pid)) else {
+            return false;
+        };
+        // Format: pid (comm) state ppid ...; comm may itself contain spaces or parens.
+        let (Some(open), Some(close)) = (stat.find('('), stat.rfind(')')) else {
+            return false;
+        };
+        let comm = &stat[open + 1..close];
+        if matches!(comm, "cron" | "crond" | "anacron" | "fcron") {
+            return true;
+        }
+        match stat[close + 1..].split_whitespace().nth(1).map(str::parse) {
+            Some(Ok(ppid)) if ppid > 1 => pid = ppid,
+            _ => return false,
+        }
+    }
+    false
+}
@@ -0,0 +1,212 @@
+//! The run fingerprint: one hash over what decides how a command line behaves,
+//! so runs of "the same command" under different conditions can be told apart.
+
+use crate::config::Config;
+use crate::state::{replace_file, StateDir};
+use crate::template::glob_match;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long a program must have been left alone before its digest is cached.
+const SETTLED: Duration = Duration::from_secs(2);
+
+/// Digest of the program's contents, the `fingerprint_env` variables, the
+/// working directory and the arguments, e.g. "sha256-3fa9c20b17de".
+pub fn fingerprint(cfg: &Config, cmd: &OsStr, args: &[OsString], cwd: &Path) -> String {
+    fingerprint_in(StateDir::locate().as_ref(), cfg, cmd, args, cwd)
+}
+
+// `fingerprint`, with the program digests cached in `state`.
+fn fingerprint_in(
+    state: Option<&StateDir>,
+    cfg: &Config,
+    cmd: &OsStr,
+    args: &[OsString],
+    cwd: &Path,
+) -> String {
+    let env: BTreeMap<OsString, OsString> = std::env::vars_os()
+        .filter(|(k, _)| {
+            let name = k.to_string_lossy();
+            cfg.fingerprint_env.iter().any(|g| glob_match(g, &name))
+        })
+        .collect();
+    let mut hasher = Sha256::new();
+    let mut field = |value: &[u8]| {
+        hasher.update(value);
+        hasher.update([0u8]);
+    };
+    // A program lg can't find or read fails to start; its name is all there is.
+    match program_digest(state, cmd, cwd) {
+        Some(digest) => field(&digest),
+        None => field(cmd.as_encoded_bytes()),
+    }
+    for (k, v) in &env {
+        field(k.as_encoded_bytes());
+        field(v.as_encoded_bytes());
+    }
+    field(cwd.as_os_str().as_encoded_bytes());
+    for a in args {
+        field(a.as_encoded_bytes());
+    }
+    let digest = hasher.finalize();
+    let hex: String = digest[..6].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256-{}", hex)
+}
+
+// Reading a large program on every run costs more than the run of a small
+// command, so the digest is kept in the state directory until the file
+// changes: a different file, size or modification time. A file modified in
+// the last few seconds could change again within the timestamp's
+// granularity, so its digest is not kept.
+fn program_digest(state: Option<&StateDir>, cmd: &OsStr, cwd: &Path) -> Option<Vec<u8>> {
+    let program = resolve_program(cmd, cwd)?;
+    let meta = fs::metadata(&program).ok()?;
+    // A fifo or device would never finish hashing; it fails to run anyway.
+    if !meta.is_file() {
+        return None;
+    }
+    let identity = file_identity(&meta);
+    let cache = state.map(|s| s.program_digest(&program));
+    let cached = cache
+        .as_ref()
+        .and_then(|c| fs::read_to_string(c).ok())
+        .and_then(|c| {
+            let (id, hex) = c.trim_end().split_once('\n')?;
+            (id == identity).then(|| from_hex(hex)).flatten()
+        });
+    if cached.is_some() {
+        return cached;
+    }
+    let mut file = File::open(&program).ok()?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).ok()?;
+    let digest = hasher.finalize().to_vec();
+    let settled = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.elapsed().ok())
+        .is_some_and(|age| age >= SETTLED);
+    // Best effort: without the cache the next run hashes the program again.
+    if let Some(cache) = cache.filter(|_| settled) {
+        let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        let _ = cache
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| replace_file(&cache, format!("{}\n{}\n", identity, hex).as_bytes()));
+    }
+    Some(digest)
+}
+
+// Which file, and which version of it: device, inode, size and modification
+// and change times.
+#[cfg(unix)]
+fn file_identity(meta: &fs::Metadata) -> String {
+    use std::os::unix::fs::MetadataExt;
+    format!(
+        "{} {} {} {}.{} {}.{}",
+        meta.dev(),
+        meta.ino(),
+        meta.size(),
+        meta.mtime(),
+        meta.mtime_nsec(),
+        meta.ctime(),
+        meta.ctime_nsec()
+    )
+}
+
+#[cfg(not(unix))]
+fn file_identity(meta: &fs::Metadata) -> String {
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    format!("{} {}", meta.len(), modified.as_nanos())
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// The file `cmd` runs, found the way execvp finds it.
+fn resolve_program(cmd: &OsStr, cwd: &Path) -> Option<PathBuf> {
+    let cmd = Path::new(cmd);
+    if cmd.components().count() > 1 {
+        return Some(cwd.join(cmd));
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| cwd.join(dir).join(cmd))
+        .find(|p| is_executable(p))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| m.is_file())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_with_program_cwd_and_args_only() {
+        let cfg = Config {
+            fingerprint_env: vec!["LG_FINGERPRINT_TEST_*".into()],
+            ..Config::default()
+        };
+        let dir = std::env::temp_dir().join(format!("lg-fingerprint-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("build.sh");
+        fs::write(&script, "#!/bin/sh\necho v1\n").unwrap();
+        let an_hour_ago = std::time::SystemTime::now() - Duration::from_secs(3600);
+        File::options()
+            .write(true)
+            .open(&script)
+            .and_then(|f| f.set_modified(an_hour_ago))
+            .unwrap();
+        let state = StateDir::new(dir.join("state"));
+        let print = |args: &[&str], cwd: &Path| {
+            let args: Vec<OsString> = args.iter().map(OsString::from).collect();
+            fingerprint_in(Some(&state), &cfg, script.as_os_str(), &args, cwd)
+        };
+        let first = print(&["-j4"], &dir);
+        assert!(first.starts_with("sha256-") && first.len() == 19);
+        assert_eq!(print(&["-j4"], &dir), first);
+        assert_ne!(print(&["-j8"], &dir), first);
+        assert_ne!(print(&["-j4"], Path::new("/")), first);
+        std::env::set_var("LG_FINGERPRINT_TEST_CC", "clang");
+        let with_cc = print(&["-j4"], &dir);
+        std::env::remove_var("LG_FINGERPRINT_TEST_CC");
+        assert_ne!(with_cc, first);
+        // The cached digest goes with the old file.
+        assert!(state.program_digest(&script).is_file());
+        fs::write(&script, "#!/bin/sh\necho v2\n").unwrap();
+        assert_ne!(print(&["-j4"], &dir), first);
+        // Not a program to hash.
+        assert_eq!(program_digest(None, OsStr::new("/dev/zero"), &dir), None);
+
+        assert_eq!(
+            resolve_program(OsStr::new("sh"), &dir).map(|p| p.ends_with("sh")),
+            Some(true)
+        );
+        assert_eq!(
+            resolve_program(OsStr::new("no-such-lg-program"), &dir),
+            None
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+//! The git checkout a command runs in: branch, commit and whether tracked
+//! files were changed, so a log says which tree produced it.
+
+use serde::Serialize;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GitInfo {
+    /// "HEAD" when detached.
+    pub branch: String,
+    /// The commit checked out; None before the first commit.
+    pub sha: Option<String>,
+    /// Tracked files differ from the commit (untracked ones don't count).
+    pub dirty: bool,
+}
+
+impl GitInfo {
+    /// The checkout `cwd` is in; None outside one, or without git.
+    pub fn detect(cwd: &Path) -> Option<Self> {
+        // Without optional locks, status doesn't refresh the index behind a
+        // build's back.
+        let output = Command::new("git")
+            .arg("--no-optional-locks")
+            .arg("-C")
+            .arg(cwd)
+            .args([
+                "status",
+                "--porcelain=v2",
+                "--branch",
+                "--untracked-files=no",
+            ])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        Self::parse(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    fn parse(status: &str) -> Option<Self> {
+        let (mut branch, mut sha, mut dirty) = (None, None, false);
+        for line in status.lines() {
+            if let Some(oid) = line.strip_prefix("# branch.oid ") {
+                sha = (oid != "(initial)").then(|| oid.to_string());
+            } else if let Some(head) = line.strip_prefix("# branch.head ") {
+                branch = Some(match head {
+                    "(detached)" => "HEAD".to_string(),
+                    head => head.to_string(),
+                });
+            } else if !line.starts_with('#') {
+                dirty = true;
+            }
+        }
+        Some(Self {
+            branch: branch?,
+            sha,
+            dirty,
+        })
+    }
+
+    /// The commit's first 12 hex digits, as `{git_sha}` gives it.
+    pub fn short_sha(&self) -> Option<&str> {
+        self.sha.as_deref().map(|sha| &sha[..sha.len().min(12)])
+    }
+
+    /// The `git:` header line's value: "main 3fa9c20b17de... (dirty)".
+    pub fn describe(&self) -> String {
+        let mut s = format!("{} {}", self.branch, self.sha.as_deref().unwrap_or("NA"));
+        if self.dirty {
+            s.push_str(" (dirty)");
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_gives_branch_commit_and_dirty_state() {
+        let sha = "3fa9c20b17de4a1f0c2b9e8d7a6f5e4d3c2b1a09";
+        let clean = format!(
+            "# branch.oid {}\n# branch.head main\n# branch.ab +0 -0\n",
+            sha
+        );
+        let info = GitInfo::parse(&clean).unwrap();
+        assert_eq!(info.describe(), format!("main {}", sha));
+        assert_eq!(info.short_sha(), Some("3fa9c20b17de"));
+        let detached = format!(
+            "# branch.oid {}\n# branch.head (detached)\n1 .M N... 100644 100644 100644 a b src/x.rs\n",
+            sha
+        );
+        let info = GitInfo::parse(&detached).unwrap();
+        assert_eq!((info.branch.as_str(), info.dirty), ("HEAD", true));
+        let initial = GitInfo::parse("# branch.oid (initial)\n# branch.head main\n").unwrap();
+        assert_eq!(initial.describe(), "main NA");
+        assert_eq!(GitInfo::parse(""), None);
+    }
+}
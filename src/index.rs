@@ -0,0 +1,478 @@
+//! The run index (`index = true`): one row per run in `<state dir>/index.sqlite`.
+
+use crate::runner::Overhead;
+use crate::state::StateDir;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDateTime};
+use rusqlite::{params, Connection, Params, Transaction, TransactionBehavior};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Times are RFC 3339 with milliseconds, which SQLite's date functions read.
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3f%:z";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id          INTEGER PRIMARY KEY,
+    run_id      TEXT,
+    parent_run_id TEXT,
+    cmd         TEXT NOT NULL,
+    args        TEXT NOT NULL,
+    cwd         TEXT NOT NULL,
+    host        TEXT NOT NULL,
+    started     TEXT NOT NULL,
+    ended       TEXT,
+    exit_code   INTEGER,
+    duration_ms INTEGER,
+    log_path    TEXT NOT NULL,
+    startup_ms  INTEGER,
+    bytes       INTEGER,
+    lg_cpu_ms   INTEGER
+);
+CREATE INDEX IF NOT EXISTS runs_cmd_started ON runs (cmd, started);
+";
+
+/// An open index database.
+pub struct RunIndex {
+    conn: Connection,
+}
+
+/// What is known about a run when it starts.
+pub struct RunStart<'a> {
+    pub run_id: &'a str,
+    /// The run of the lg this one runs under.
+    pub parent_run_id: Option<&'a str>,
+    pub cmd: &'a str,
+    /// Arguments as recorded in the log (after masking and `args_policy`).
+    pub args: &'a str,
+    pub cwd: &'a Path,
+    pub host: &'a str,
+    pub started: DateTime<Local>,
+    pub log_path: &'a Path,
+}
+
+/// A row of the index, as `lg du` and `lg history` read it back.
+pub struct IndexedRun {
+    /// None for runs from before run ids.
+    pub run_id: Option<String>,
+    pub cmd: String,
+    pub args: String,
+    pub cwd: PathBuf,
+    pub started: Option<NaiveDateTime>,
+    /// None while the run is going, or if lg was killed.
+    pub exit_code: Option<i32>,
+    pub duration_ms: Option<i64>,
+    pub log_path: PathBuf,
+    /// lg's own cost, see `Overhead`; None for runs from before it was recorded.
+    pub startup_ms: Option<i64>,
+    pub bytes: Option<i64>,
+    pub lg_cpu_ms: Option<i64>,
+}
+
+impl RunIndex {
+    pub fn open(state: &StateDir) -> Result<Self> {
+        let path = state.index();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("create {:?}", dir))?;
+        }
+        let conn = Connection::open(&path).with_context(|| format!("opening {:?}", path))?;
+        // Several lg runs may finish at the same moment.
+        conn.busy_timeout(Duration::from_secs(5))?;
+        // In WAL mode readers (`lg history`) and the one writer don't block each other.
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))
+            .with_context(|| format!("opening {:?}", path))?;
+        conn.execute_batch(SCHEMA)
+            .with_context(|| format!("creating tables in {:?}", path))?;
+        // Indexes from before run ids and lg's overhead lack their columns.
+        for (column, kind) in [
+            ("run_id", "TEXT"),
+            ("parent_run_id", "TEXT"),
+            ("startup_ms", "INTEGER"),
+            ("bytes", "INTEGER"),
+            ("lg_cpu_ms", "INTEGER"),
+        ] {
+            let exists = conn
+                .prepare("SELECT 1 FROM pragma_table_info('runs') WHERE name = ?1")?
+                .exists([column])?;
+            if !exists {
+                conn.execute_batch(&format!("ALTER TABLE runs ADD COLUMN {} {}", column, kind))
+                    .with_context(|| format!("adding {} to {:?}", column, path))?;
+            }
+        }
+        Ok(Self { conn })
+    }
+
+    /// The index in lg's state directory, if runs have been recorded there.
+    pub fn existing() -> Result<Option<Self>> {
+        match StateDir::locate() {
+            Some(state) if state.index().exists() => Self::open(&state).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Adds the run with no end yet, so a killed lg still leaves its row; returns its id.
+    pub fn start(&self, run: &RunStart) -> Result<i64> {
+        self.conn
+            .execute(
+                "INSERT INTO runs (run_id, parent_run_id, cmd, args, cwd, host, started, log_path)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    run.run_id,
+                    run.parent_run_id,
+                    run.cmd,
+                    run.args,
+                    run.cwd.to_string_lossy(),
+                    run.host,
+                    run.started.format(TIME_FORMAT).to_string(),
+                    run.log_path.to_string_lossy(),
+                ],
+            )
+            .context("recording run start")?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Completes row `id`; `log_path` is the final name, after any rename.
+    pub fn finish(
+        &self,
+        id: i64,
+        started: DateTime<Local>,
+        ended: DateTime<Local>,
+        exit_code: i32,
+        log_path: &Path,
+        overhead: &Overhead,
+    ) -> Result<()> {
+        let duration_ms = (ended - started).num_milliseconds().max(0);
+        self.conn
+            .execute(
+                "UPDATE runs SET ended = ?2, exit_code = ?3, duration_ms = ?4, log_path = ?5,
+                 startup_ms = ?6, bytes = ?7, lg_cpu_ms = ?8
+                 WHERE id = ?1",
+                params![
+                    id,
+                    ended.format(TIME_FORMAT).to_string(),
+                    exit_code,
+                    duration_ms,
+                    log_path.to_string_lossy(),
+                    overhead.startup_ms.map(|ms| ms as i64),
+                    overhead.bytes as i64,
+                    overhead.cpu_ms.map(|ms| ms as i64),
+                ],
+            )
+            .context("recording run end")?;
+        Ok(())
+    }
+
+    /// Drop row `id`, a run whose log `keep` discarded.
+    pub fn discard(&self, id: i64) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM runs WHERE id = ?1", [id])
+            .context("removing the run from the index")?;
+        Ok(())
+    }
+
+    /// Drop the rows of runs whose log was one of `logs` (absolute paths), also
+    /// when the row still has the name from before `lg clean` compressed it
+    /// (`x.log` for `x.log.gz`); returns how many.
+    pub fn forget(&self, logs: &[PathBuf]) -> Result<usize> {
+        // Take the write lock up front: a read transaction that later writes
+        // fails at once when another lg holds it, whatever busy_timeout says.
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)
+            .context("locking the run index")?;
+        // With the default output_dir, log paths are relative to the run's cwd.
+        let mut stmt = tx.prepare("SELECT id, cwd, log_path FROM runs")?;
+        let rows = stmt.query_map([], |r| {
+            let (cwd, log_path): (String, String) = (r.get(1)?, r.get(2)?);
+            Ok((r.get::<_, i64>(0)?, Path::new(&cwd).join(log_path)))
+        })?;
+        let mut forgotten = 0;
+        for row in rows {
+            let (id, log) = row.context("reading the run index")?;
+            if logs.contains(&log) || logs.contains(&gz_name(&log)) {
+                forgotten += tx
+                    .execute("DELETE FROM runs WHERE id = ?1", [id])
+                    .context("removing runs from the index")?;
+            }
+        }
+        drop(stmt);
+        tx.commit().context("removing runs from the index")?;
+        Ok(forgotten)
+    }
+
+    /// Point the rows of runs whose log was the first path of a pair (absolute
+    /// paths) at the second, after the log was moved; returns how many.
+    pub fn rename_logs(&self, moved: &[(PathBuf, PathBuf)]) -> Result<usize> {
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)
+            .context("locking the run index")?;
+        let mut stmt = tx.prepare("SELECT id, cwd, log_path FROM runs")?;
+        let rows = stmt.query_map([], |r| {
+            let (cwd, log_path): (String, String) = (r.get(1)?, r.get(2)?);
+            Ok((r.get::<_, i64>(0)?, Path::new(&cwd).join(log_path)))
+        })?;
+        let mut renamed = 0;
+        for row in rows {
+            let (id, log) = row.context("reading the run index")?;
+            if let Some((_, to)) = moved.iter().find(|(from, _)| *from == log) {
+                renamed += tx
+                    .execute(
+                        "UPDATE runs SET log_path = ?2 WHERE id = ?1",
+                        params![id, to.to_string_lossy()],
+                    )
+                    .context("renaming logs in the index")?;
+            }
+        }
+        drop(stmt);
+        tx.commit().context("renaming logs in the index")?;
+        Ok(renamed)
+    }
+
+    /// Every recorded run, oldest first; `started` is local time.
+    pub fn runs(&self) -> Result<Vec<IndexedRun>> {
+        self.select_runs("", [])
+    }
+
+    /// The recorded runs of one command line, oldest first.
+    pub fn runs_of(&self, cmd: &str, args: &str) -> Result<Vec<IndexedRun>> {
+        self.select_runs("WHERE cmd = ?1 AND args = ?2", [cmd, args])
+    }
+
+    fn select_runs(&self, filter: &str, params: impl Params) -> Result<Vec<IndexedRun>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT cmd, args, cwd, started, exit_code, duration_ms, log_path,
+                    startup_ms, bytes, lg_cpu_ms, run_id
+             FROM runs {} ORDER BY id",
+            filter
+        ))?;
+        let rows = stmt.query_map(params, |r| {
+            Ok(IndexedRun {
+                run_id: r.get(10)?,
+                cmd: r.get(0)?,
+                args: r.get(1)?,
+                cwd: PathBuf::from(r.get::<_, String>(2)?),
+                started: DateTime::parse_from_rfc3339(&r.get::<_, String>(3)?)
+                    .ok()
+                    .map(|t| t.with_timezone(&Local).naive_local()),
+                exit_code: r.get(4)?,
+                duration_ms: r.get(5)?,
+                log_path: PathBuf::from(r.get::<_, String>(6)?),
+                startup_ms: r.get(7)?,
+                bytes: r.get(8)?,
+                lg_cpu_ms: r.get(9)?,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<_>>()
+            .context("reading the run index")
+    }
+}
+
+// `x.log.gz` for `x.log`, the name `lg clean --compress-older-than` gives it.
+fn gz_name(log: &Path) -> PathBuf {
+    let mut name = log.as_os_str().to_owned();
+    name.push(".gz");
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, FixedClock};
+
+    #[test]
+    fn run_is_recorded_at_start_and_completed_at_end() {
+        let root = std::env::temp_dir().join(format!("lg-index-test-{}", std::process::id()));
+        let index = RunIndex::open(&StateDir::new(&root)).unwrap();
+        let started = FixedClock::at("2024-03-05 14:07:09").now();
+        let ended = started + chrono::Duration::milliseconds(1500);
+        let overhead = Overhead {
+            startup_ms: Some(4),
+            bytes: 2048,
+            cpu_ms: None,
+        };
+        let id = index
+            .start(&RunStart {
+                run_id: "01M5170180ZZZZZZZZZZZZZZZZ",
+                parent_run_id: None,
+                cmd: "make",
+                args: "test",
+                cwd: Path::new("/src"),
+                host: "box",
+                started,
+                log_path: Path::new("/src/.make.partial.log"),
+            })
+            .unwrap();
+        let row = |index: &RunIndex| -> (Option<i32>, Option<i64>, String) {
+            index
+                .conn
+                .query_row(
+                    "SELECT exit_code, duration_ms, log_path FROM runs WHERE id = ?1",
+                    [id],
+                    |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+                )
+                .unwrap()
+        };
+        assert_eq!(row(&index), (None, None, "/src/.make.partial.log".into()));
+
+        index
+            .finish(
+                id,
+                started,
+                ended,
+                2,
+                &PathBuf::from("/src/make_2.log"),
+                &overhead,
+            )
+            .unwrap();
+        assert_eq!(row(&index), (Some(2), Some(1500), "/src/make_2.log".into()));
+        let runs = index.runs().unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(
+            (runs[0].startup_ms, runs[0].bytes, runs[0].lg_cpu_ms),
+            (Some(4), Some(2048), None)
+        );
+        assert_eq!(runs[0].started, Some(started.naive_local()));
+        assert_eq!(
+            (runs[0].exit_code, runs[0].duration_ms),
+            (Some(2), Some(1500))
+        );
+        let gz = PathBuf::from("/src/make_2.log.gz");
+        let moved = [(PathBuf::from("/src/make_2.log"), gz.clone())];
+        assert_eq!(index.rename_logs(&moved).unwrap(), 1);
+        assert_eq!(row(&index).2, "/src/make_2.log.gz");
+        let forget = [PathBuf::from("/src/make_1.log"), gz];
+        assert_eq!(index.forget(&forget).unwrap(), 1);
+        assert!(index.runs().unwrap().is_empty());
+        let id = index
+            .start(&RunStart {
+                run_id: "01M5170190ZZZZZZZZZZZZZZZZ",
+                parent_run_id: None,
+                cmd: "make",
+                args: "",
+                cwd: Path::new("/src"),
+                host: "box",
+                started,
+                log_path: Path::new("/src/.make.log.partial"),
+            })
+            .unwrap();
+        index.discard(id).unwrap();
+        assert!(index.runs().unwrap().is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn a_log_compressed_behind_the_index_is_still_forgotten() {
+        let root = std::env::temp_dir().join(format!("lg-index-gz-test-{}", std::process::id()));
+        let index = RunIndex::open(&StateDir::new(&root)).unwrap();
+        let started = FixedClock::at("2024-03-05 14:07:09").now();
+        // Relative, as with the default output_dir.
+        let log = Path::new("make.log");
+        let id = index
+            .start(&RunStart {
+                run_id: "01M5170180ZZZZZZZZZZZZZZZZ",
+                parent_run_id: None,
+                cmd: "make",
+                args: "",
+                cwd: Path::new("/src"),
+                host: "box",
+                started,
+                log_path: log,
+            })
+            .unwrap();
+        index
+            .finish(id, started, started, 0, log, &Overhead::default())
+            .unwrap();
+        let forget = [PathBuf::from("/src/make.log.gz")];
+        assert_eq!(index.forget(&forget).unwrap(), 1);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn indexes_from_before_run_ids_get_the_columns() {
+        let root = std::env::temp_dir().join(format!("lg-index-old-test-{}", std::process::id()));
+        let state = StateDir::new(&root);
+        fs::create_dir_all(state.index().parent().unwrap()).unwrap();
+        let old = SCHEMA
+            .replace("    run_id      TEXT,\n    parent_run_id TEXT,\n", "")
+            .replace(
+                ",\n    startup_ms  INTEGER,\n    bytes       INTEGER,\n    lg_cpu_ms   INTEGER",
+                "",
+            );
+        assert!(!old.contains("run_id") && !old.contains("lg_cpu_ms"));
+        Connection::open(state.index())
+            .unwrap()
+            .execute_batch(&old)
+            .unwrap();
+        let index = RunIndex::open(&state).unwrap();
+        let run = RunStart {
+            run_id: "01M5170180ZZZZZZZZZZZZZZZZ",
+            parent_run_id: Some("01M5170100ZZZZZZZZZZZZZZZZ"),
+            cmd: "make",
+            args: "",
+            cwd: Path::new("/src"),
+            host: "box",
+            started: FixedClock::at("2024-03-05 14:07:09").now(),
+            log_path: Path::new("/src/make.log"),
+        };
+        let id = index.start(&run).unwrap();
+        let ids: (String, Option<String>) = index
+            .conn
+            .query_row(
+                "SELECT run_id, parent_run_id FROM runs WHERE id = ?1",
+                [id],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(ids, (run.run_id.into(), run.parent_run_id.map(Into::into)));
+        let overhead = Overhead {
+            startup_ms: Some(3),
+            bytes: 10,
+            cpu_ms: Some(7),
+        };
+        index
+            .finish(id, run.started, run.started, 0, run.log_path, &overhead)
+            .unwrap();
+        assert_eq!(index.runs().unwrap()[0].lg_cpu_ms, Some(7));
+        // Opening it again finds the columns there.
+        RunIndex::open(&state).unwrap();
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn concurrent_runs_all_land_in_the_index() {
+        let root = std::env::temp_dir().join(format!("lg-index-race-test-{}", std::process::id()));
+        let started = FixedClock::at("2024-03-05 14:07:09").now();
+        // One connection per worker, as one per lg process.
+        std::thread::scope(|s| {
+            for worker in 0..8 {
+                let root = &root;
+                s.spawn(move || {
+                    let index = RunIndex::open(&StateDir::new(root)).unwrap();
+                    for run in 0..25 {
+                        let log = PathBuf::from(format!("/src/make_{}_{}.log", worker, run));
+                        let id = index
+                            .start(&RunStart {
+                                run_id: "01M5170180ZZZZZZZZZZZZZZZZ",
+                                parent_run_id: None,
+                                cmd: "make",
+                                args: "",
+                                cwd: Path::new("/src"),
+                                host: "box",
+                                started,
+                                log_path: &log,
+                            })
+                            .unwrap();
+                        index
+                            .finish(id, started, started, 0, &log, &Overhead::default())
+                            .unwrap();
+                    }
+                });
+            }
+        });
+        let runs = RunIndex::open(&StateDir::new(&root))
+            .unwrap()
+            .runs()
+            .unwrap();
+        assert_eq!(runs.len(), 200);
+        assert!(runs.iter().all(|r| r.exit_code == Some(0)));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,210 @@
+//! JSON Lines logs (`format = "jsonl"`): the records lg writes and reads back.
+//!
+//! A log is a `header` record, one record per output line, and a `footer`
+//! record. Output lines carry no `type` so they stay small:
+//! `{"ts":"2025-01-02T03:04:05.678+01:00","stream":"stdout","line":"..."}`.
+
+use crate::git::GitInfo;
+use crate::matchers::Diagnostic;
+use crate::runner::{Received, Usage};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// chrono format of the `ts` fields.
+pub static TS_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3f%:z";
+
+/// What `type` a record has; `None` for output lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Header,
+    Footer,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Header<'a> {
+    #[serde(rename = "type")]
+    pub kind: Kind,
+    pub ts: &'a str,
+    pub run_id: &'a str,
+    /// The run of the lg this one runs under.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_run_id: Option<&'a str>,
+    pub cmd: &'a str,
+    pub args: &'a str,
+    pub cwd: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git: Option<&'a GitInfo>,
+    pub host: &'a str,
+    /// The `lg group` the run belongs to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<&'a str>,
+    /// When the run was meant to start; see `scheduled_at`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheduled: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_delay_secs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tty: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub pty: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Line<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ts: Option<&'a str>,
+    /// "stdout", "stderr", "stdin" for `log_stdin` input, "expect" for
+    /// scripted answers, or "fold" for `[[fold]]` markers.
+    pub stream: &'a str,
+    pub line: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Footer<'a> {
+    #[serde(rename = "type")]
+    pub kind: Kind,
+    pub exit_code: i32,
+    /// The command's process, which every line of the log came from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// Why the command could not be started, if it wasn't.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spawn_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure_class: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub probable_cause: Option<&'a str>,
+    /// How lg stopped a command that overran `timeout`, e.g. "30m, SIGTERM".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<&'a str>,
+    /// What `max_log_size` did, e.g. "1.0 GiB, rotated 2 time(s)".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_log_size: Option<&'a str>,
+    /// Who stopped the run with `lg kill`, e.g. "SIGTERM by lg kill from alice@box".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cancelled: Option<&'a str>,
+    /// Why lg stopped compressing the log (`skip_incompressible`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<&'a str>,
+    /// The `output_artifact` beside the log, e.g. "curl_01M5295QJ6NM46MXDVT0Q7YA6G.json".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact: Option<&'a str>,
+    /// Wall-clock time, CPU time and peak memory of the command.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<&'a Usage>,
+    /// Signals lg received while the command ran.
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub signals: &'a [Received],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub diagnostics: &'a [Diagnostic],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<&'a str>,
+    /// How the run differs from its command line's `lg baseline`.
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub baseline: &'a [String],
+    /// `[[plugin]]` programs that failed, fell behind or had to be killed.
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub plugins: &'a [String],
+}
+
+/// Any record, as read back by the log tools. Fields a record doesn't have stay empty.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Record {
+    #[serde(rename = "type")]
+    pub kind: Option<Kind>,
+    pub ts: Option<String>,
+    pub run_id: Option<String>,
+    pub parent_run_id: Option<String>,
+    pub cmd: String,
+    pub args: String,
+    pub cwd: Option<PathBuf>,
+    pub group: Option<String>,
+    pub start_delay_secs: Option<f64>,
+    pub fingerprint: Option<String>,
+    pub exit_code: Option<i32>,
+    pub failure_class: Option<String>,
+    pub probable_cause: Option<String>,
+    pub usage: Option<RecordUsage>,
+    pub diagnostics: Vec<Diagnostic>,
+    pub summary: Option<String>,
+    pub artifact: Option<String>,
+    pub baseline: Vec<String>,
+}
+
+/// The part of a footer's `usage` the log tools read back.
+#[derive(Debug, Default, Deserialize)]
+pub struct RecordUsage {
+    pub duration_ms: u64,
+}
+
+pub fn write<W: Write + ?Sized>(w: &mut W, record: &impl Serialize) -> Result<()> {
+    serde_json::to_writer(&mut *w, record)?;
+    w.write_all(b"\n")?;
+    Ok(())
+}
+
+/// True for the first line of a JSON Lines log.
+pub fn is_header(line: &str) -> bool {
+    line.starts_with(r#"{"type":"header""#)
+}
+
+pub fn is_footer(line: &[u8]) -> bool {
+    line.starts_with(br#"{"type":"footer""#)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_round_trip() {
+        let mut out = Vec::new();
+        let line = Line {
+            ts: None,
+            stream: "stderr",
+            line: "say \"hi\"",
+        };
+        write(&mut out, &line).unwrap();
+        let diagnostics = [Diagnostic::parse("error src/main.rs:3:5: oops").unwrap()];
+        let footer = Footer {
+            kind: Kind::Footer,
+            exit_code: 2,
+            pid: Some(4242),
+            spawn_error: None,
+            failure_class: Some("test"),
+            probable_cause: None,
+            timeout: None,
+            max_log_size: None,
+            cancelled: None,
+            compression: None,
+            artifact: None,
+            usage: None,
+            signals: &[],
+            diagnostics: &diagnostics,
+            summary: None,
+            baseline: &[],
+            plugins: &[],
+        };
+        write(&mut out, &footer).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            r#"{"stream":"stderr","line":"say \"hi\""}"#
+        );
+        let footer: Record = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(footer.kind, Some(Kind::Footer));
+        assert_eq!(footer.exit_code, Some(2));
+        assert!(text.contains(r#""pid":4242"#));
+        assert_eq!(footer.failure_class.as_deref(), Some("test"));
+        assert_eq!(footer.diagnostics, diagnostics);
+    }
+}
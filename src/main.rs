@@ -4,134 +4,80 @@
 // - Configurable via ~/.lg (TOML): output dir, filename template, include args, gzip, split streams, etc.
 // - English comments throughout for clarity and maintenance.
 
+mod anomaly;
+mod ansi;
+mod baseline;
+mod budget;
+mod cli;
+mod clock;
+mod config;
+mod context;
+mod fingerprint;
+mod git;
+mod index;
+mod jsonl;
+mod matchers;
+mod naming;
+mod redact;
+mod run_id;
+mod runner;
+mod sinks;
+mod state;
+mod template;
+mod timespec;
+mod tools;
+
+use crate::anomaly::{anomalies, earlier_runs};
+use crate::budget::MemoryBudget;
+use crate::cli::{Cli, ToolCli, TOOL_NAMES};
+use crate::clock::{Clock, SystemClock};
+use crate::config::{
+    config_sources, load_config, selected_profile, ArgsPolicy, Compress, Config, GroupBudgetAction,
+    StdinSource,
+};
+use crate::context::{HOSTNAME, INVOCATION, USER};
+use crate::git::GitInfo;
+use crate::index::{RunIndex, RunStart};
+use crate::naming::{collision, link_latest, render_output_dir, LogPlan, Stamp};
+use crate::redact::Redactor;
+use crate::runner::{
+    exec_uncaptured, is_never_logged, run_and_log_combined, run_and_log_split, Overhead, LG_STARTED,
+};
+use crate::state::{record_group_run, record_latest, GroupRun, StateDir};
+use crate::template::{hash_args, join_args, mask_sensitive_args, TemplateVars};
+use crate::tools::{discard_run, group_budget, run_tool};
 use anyhow::{Context, Result};
-use chrono::Local;
-use clap::{ArgAction, Parser};
-use flate2::write::GzEncoder;
-use flate2::Compression;
-use hostname::get as get_hostname;
+use chrono::{DateTime, Local, SubsecRound};
+use clap::Parser;
 use once_cell::sync::Lazy;
-use serde::Deserialize;
-use std::borrow::Cow;
 use std::ffi::OsString;
-use std::fs::{self, File};
-use std::io::{self, Write};
+use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::Stdio;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
-
-// Defaults
-static DEFAULT_FILENAME_TEMPLATE: &str = "{cmd}_{date}_{time}.log";
-static DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
-static DEFAULT_TIME_FORMAT: &str = "%H-%M-%S";
-static DEFAULT_LINE_TIME_FORMAT: &str = "%H:%M:%S%.3f";
-static DEFAULT_CONFIG_TEMPLATE: &str = include_str!("../examples/lg.example.toml");
-
-// Cache hostname once
-static HOSTNAME: Lazy<String> = Lazy::new(|| {
-    get_hostname()
-        .ok()
-        .and_then(|o| o.into_string().ok())
-        .unwrap_or_else(|| "unknown".into())
-});
-
-#[derive(Debug, Deserialize, Clone)]
-#[serde(default)]
-struct Config {
-    output_dir: Option<PathBuf>,
-    include_args_in_name: bool,
-    include_full_args: bool,
-    sanitize_filename: bool,
-    filename_template: String,
-    date_format: String,
-    time_format: String,
-    timestamp_each_line: bool,
-    plain_lines: bool,
-    combine_streams: bool,
-    split_streams: bool,
-    tee: bool,
-    log_env: bool,
-    #[serde(default = "default_compress")]
-    compress: Compress,
-}
-
-#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
-#[serde(rename_all = "lowercase")]
-enum Compress {
-    None,
-    Gz,
-}
-
-fn default_compress() -> Compress {
-    Compress::None
-}
-
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            output_dir: None,
-            include_args_in_name: false,
-            include_full_args: true,
-            sanitize_filename: true,
-            filename_template: DEFAULT_FILENAME_TEMPLATE.into(),
-            date_format: DEFAULT_DATE_FORMAT.into(),
-            time_format: DEFAULT_TIME_FORMAT.into(),
-            timestamp_each_line: true,
-            plain_lines: false,
-            combine_streams: true,
-            split_streams: false,
-            tee: true,
-            log_env: false,
-            compress: Compress::None,
-        }
-    }
-}
-
-#[derive(Parser, Debug)]
-#[command(
-    name = "lg",
-    version,
-    about = "Log any command's output and metadata",
-    disable_help_subcommand = true
-)]
-struct Cli {
-    /// Override output directory
-    #[arg(long)]
-    output: Option<PathBuf>,
-
-    /// Override filename template
-    #[arg(long)]
-    filename_template: Option<String>,
-
-    /// Include arguments in filename
-    #[arg(long, short = 'a', action = ArgAction::SetTrue)]
-    include_args: bool,
-
-    /// Split stdout/stderr into separate files
-    #[arg(long, action = ArgAction::SetTrue)]
-    split_streams: bool,
-
-    /// Write logged lines without timestamps or stream markers
-    #[arg(long, action = ArgAction::SetTrue)]
-    plain_lines: bool,
-
-    /// Compress logs: none|gz
-    #[arg(long)]
-    compress: Option<String>,
-
-    /// Disable tee to terminal
-    #[arg(long, action = ArgAction::SetTrue)]
-    no_tee: bool,
-
-    /// The command and its arguments to run
-    #[arg(required = true, trailing_var_arg = true)]
-    cmd: Vec<OsString>,
-}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let (exit_code, _) = run().await.unwrap_or((1, PathBuf::new()));
+    Lazy::force(&LG_STARTED);
+    let is_tool = std::env::args_os()
+        .nth(1)
+        .is_some_and(|a| TOOL_NAMES.iter().any(|t| a == *t));
+    if is_tool {
+        let code = match run_tool(ToolCli::parse()) {
+            Ok(code) => code,
+            Err(err) => {
+                eprintln!("lg: {:#}", err);
+                1
+            }
+        };
+        std::process::exit(code);
+    }
+
+    let (exit_code, _) = match run().await {
+        Ok(res) => res,
+        Err(err) => {
+            eprintln!("lg: {:#}", err);
+            (1, PathBuf::new())
+        }
+    };
     // Exit with the wrapped command's status code
     std::process::exit(exit_code);
 }
@@ -139,29 +85,35 @@ async fn main() -> Result<()> {
 async fn run() -> Result<(i32, PathBuf)> {
     let cli = Cli::parse();
 
-    // Read config from ~/.lg (TOML)
-    let mut cfg = load_config()?;
+    // Read config from ~/.lg and .lg.toml (TOML)
+    let profile = selected_profile(cli.profile.as_deref());
+    let mut cfg = load_config(&config_sources(cli.config.as_deref()), profile.as_deref())?;
 
     // Apply CLI overrides
+    cli.switches.apply(&mut cfg);
     if let Some(out) = cli.output {
         cfg.output_dir = Some(out);
     }
     if let Some(tpl) = cli.filename_template {
         cfg.filename_template = tpl;
     }
-    if cli.include_args {
-        cfg.include_args_in_name = true;
+    if let Some(collision) = cli.collision {
+        cfg.collision = collision;
     }
-    if cli.split_streams {
-        cfg.split_streams = true;
-        cfg.combine_streams = false;
+    if let Some(name) = cli.latest_link {
+        cfg.latest_link_name = name;
+        cfg.latest_symlink = true;
     }
-    if cli.plain_lines {
-        cfg.plain_lines = true;
+    if let Some(format) = cli.format {
+        cfg.format = format;
+    }
+    if let Some(header) = cli.header {
+        cfg.header = header;
     }
     if let Some(c) = cli.compress.as_deref() {
         cfg.compress = match c {
             "gz" => Compress::Gz,
+            "zstd" => Compress::Zstd,
             "none" | "" => Compress::None,
             other => {
                 eprintln!("Unknown --compress value '{}', using 'none'", other);
@@ -169,545 +121,375 @@ async fn run() -> Result<(i32, PathBuf)> {
             }
         };
     }
-    if cli.no_tee {
-        cfg.tee = false;
+    if cli.timeout.is_some() {
+        cfg.timeout = cli.timeout;
+    }
+    if let Some(src) = cli.stdin.as_deref() {
+        cfg.stdin = StdinSource::parse_cli(src);
+    }
+    if let Some(script) = cli.expect_script.as_deref() {
+        let data = fs::read_to_string(script)
+            .with_context(|| format!("reading expect script {:?}", script))?;
+        cfg.interact =
+            toml::from_str(&data).with_context(|| format!("parsing expect script {:?}", script))?;
+    }
+    if cli.max_log_size.is_some() {
+        cfg.max_log_size = cli.max_log_size;
+    }
+    if let Some(action) = cli.max_log_size_action {
+        cfg.max_log_size_action = action;
+    }
+    if let Some(keep) = cli.keep {
+        cfg.keep = keep;
+    }
+    if let Some(artifact) = cli.output_artifact {
+        cfg.output_artifact = artifact;
+    }
+    if cli.group.is_some() {
+        cfg.group = cli.group;
+    }
+    if cli.group_budget.is_some() {
+        cfg.group_budget = cli.group_budget;
+    }
+    if let Some(action) = cli.group_budget_action {
+        cfg.group_budget_action = action;
+    }
+    if cli.scheduled_at.is_some() {
+        cfg.scheduled_at = cli.scheduled_at;
+    }
+    if cli.max_memory.is_some() {
+        cfg.max_memory = cli.max_memory;
+    }
+    cfg.verbosity = cli.verbose;
+    // A bad timeout, size limit or time stops lg before any log is written.
+    cfg.timeout()?;
+    cfg.max_log_size()?;
+    cfg.group_budget()?;
+    cfg.scheduled_at()?;
+    let budget = MemoryBudget::fit(&mut cfg)?;
+    if cfg.verbosity >= 1 {
+        eprintln!("lg: {}", budget);
     }
 
     // Command + args
     let cmd = cli.cmd.first().unwrap().clone();
     let args: Vec<OsString> = cli.cmd.iter().skip(1).cloned().collect();
+    if is_never_logged(&cfg, &cmd) {
+        return exec_uncaptured(&cmd, &args).map(|code| (code, PathBuf::new()));
+    }
     let cmd_str = cmd.to_string_lossy().to_string();
-    let args_str = join_args(&args, cfg.include_full_args);
+    if cfg.group_budget_action == GroupBudgetAction::Fail {
+        if let Some(budget) = over_group_budget(&cfg) {
+            eprintln!("lg: not running {}: group {}", cmd_str, budget);
+            return Ok((1, PathBuf::new()));
+        }
+    }
+    // Recorded form of the arguments; `args` itself is passed to the command untouched.
+    let shown_args = if cfg.mask_args {
+        mask_sensitive_args(&args, &cfg.mask_arg_flags)
+    } else {
+        args.clone()
+    };
+    let args_str = match cfg.args_policy_for(&cmd) {
+        ArgsPolicy::Full => join_args(&shown_args, cfg.include_full_args),
+        ArgsPolicy::Hash => hash_args(&shown_args),
+        ArgsPolicy::Omit => String::new(),
+    };
+    // Secrets passed other than as flag values, e.g. in a URL.
+    let args_str = match Redactor::new(&cfg.redact)? {
+        Some(redactor) => redactor.redact(&args_str).into_owned(),
+        None => args_str,
+    };
+    let command_line = format!("{} {}", cmd_str, args_str).trim_end().to_string();
 
     let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-    let now = Local::now();
-    let date_s = now.format(&cfg.date_format).to_string();
-    let time_s = now.format(&cfg.time_format).to_string();
-    let ts_s = now.timestamp().to_string();
+    let stamp = Stamp::new(&cfg, &SystemClock);
     let cwd_s = cwd.to_string_lossy().to_string();
-
-    // Prepare filename (may include exit_code which we don't know yet)
-    let mut base_name = render_template(
-        &cfg.filename_template,
-        &cmd_str,
-        &args_str,
-        &date_s,
-        &time_s,
-        &ts_s,
-        None,
-        &HOSTNAME,
-        &cwd_s,
-        cfg.sanitize_filename,
-        cfg.include_args_in_name,
-    );
+    let fingerprint = cfg
+        .fingerprint
+        .then(|| fingerprint::fingerprint(&cfg, &cmd, &args, &cwd));
+    let git = cfg.git.then(|| GitInfo::detect(&cwd)).flatten();
+    let run_id = run_id::new_run_id();
+    let parent_run_id = run_id::parent_run_id();
+
+    let mut vars = TemplateVars {
+        cmd: &cmd_str,
+        args: &args_str,
+        date: &stamp.date,
+        time: &stamp.time,
+        ts: &stamp.ts,
+        hostname: &HOSTNAME,
+        user: &USER,
+        ci_run: INVOCATION.run_id.as_deref().unwrap_or("NA"),
+        run_id: &run_id,
+        parent_run_id: parent_run_id.as_deref(),
+        fingerprint: fingerprint.as_deref(),
+        git: git.as_ref(),
+        seq: None,
+        cwd: &cwd_s,
+        exit_code: None,
+        failure_class: None,
+        duration_secs: None,
+        pid: None,
+    };
 
     // Output directory
-    let out_dir = cfg.output_dir.clone().unwrap_or_else(|| cwd.clone());
+    let mut out_dir = render_output_dir(&cfg, &vars)?.unwrap_or_else(|| cwd.clone());
+    if let Some(parent) = parent_run_id.as_deref().filter(|_| cfg.nested_subdir) {
+        out_dir.push(parent);
+    }
     fs::create_dir_all(&out_dir).with_context(|| format!("create output dir {:?}", out_dir))?;
-
-    // Temp path if {exit_code} is present
-    let needs_rename = cfg.filename_template.contains("{exit_code}");
-    let (mut log_path, final_template) = if needs_rename {
-        // Use a hidden temp file to avoid partial-file confusion
-        let tmp_name = format!(".{}.partial", base_name);
-        (out_dir.join(tmp_name), Some(cfg.filename_template.clone()))
+    vars.seq = next_seq(&cfg, &out_dir);
+
+    // Logs are written under a `.partial` name, renamed when the run is over
+    let plan = LogPlan::new(&cfg, &vars, &out_dir);
+    let finals = plan.known_final_paths(&cfg, &vars);
+    let start_paths = collision::claim(&cfg, &plan.start_paths(&cfg), finals.as_deref())?;
+    let indexed = if cfg.index {
+        // Split runs are indexed by their stdout log, as in the latest-log pointer.
+        index_start(&RunStart {
+            run_id: &run_id,
+            parent_run_id: parent_run_id.as_deref(),
+            cmd: &cmd_str,
+            args: &args_str,
+            cwd: &cwd,
+            host: &HOSTNAME,
+            started: stamp.at,
+            log_path: &start_paths[0],
+        })
     } else {
-        (out_dir.join(&base_name), None)
+        None
     };
 
-    // Ensure extension for split/combined
-    if cfg.split_streams {
-        // We'll append .out.log and .err.log later
-    } else {
-        // Ensure it ends with .log (or .log.gz if compressed and user didn't set another extension)
-        if std::path::Path::new(&base_name).extension().is_none() {
-            base_name.push_str(".log");
-            log_path = out_dir.join(&base_name);
-        }
-        if cfg.compress == Compress::Gz && !log_path.to_string_lossy().ends_with(".gz") {
-            log_path.set_extension(format!(
-                "{}gz",
-                log_path
-                    .extension()
-                    .and_then(|e| e.to_str())
-                    .unwrap_or("log.")
-            ));
-        }
-    }
-
     let exit_code: i32;
+    let kept: bool;
+    let duration_secs: Option<f64>;
+    let overhead: Overhead;
+    let artifact: Option<PathBuf>;
+    let mut log_path;
 
     // Write header and run process
     if cfg.split_streams {
-        let (exit, out_path, err_path) = run_and_log_split(
-            &cfg, &cmd, &args, &cwd, &log_path, &cmd_str, &args_str, &date_s, &time_s,
-        )
-        .await?;
-        exit_code = exit;
-        if let Some(tpl) = final_template {
-            // We need to rename both files to include exit_code if requested.
-            let out_final = out_dir.join(
-                render_template(
-                    &tpl,
-                    &cmd_str,
-                    &args_str,
-                    &date_s,
-                    &time_s,
-                    &ts_s,
-                    Some(exit_code),
-                    &HOSTNAME,
-                    &cwd_s,
-                    cfg.sanitize_filename,
-                    cfg.include_args_in_name,
-                ) + ".out.log"
-                    + if cfg.compress == Compress::Gz {
-                        ".gz"
-                    } else {
-                        ""
-                    },
-            );
-            let err_final = out_dir.join(
-                render_template(
-                    &tpl,
-                    &cmd_str,
-                    &args_str,
-                    &date_s,
-                    &time_s,
-                    &ts_s,
-                    Some(exit_code),
-                    &HOSTNAME,
-                    &cwd_s,
-                    cfg.sanitize_filename,
-                    cfg.include_args_in_name,
-                ) + ".err.log"
-                    + if cfg.compress == Compress::Gz {
-                        ".gz"
-                    } else {
-                        ""
-                    },
-            );
-
-            let _ = fs::rename(out_path, out_final);
-            let _ = fs::rename(err_path, err_final);
+        let (out_path, err_path) = (&start_paths[0], &start_paths[1]);
+        let res = run_and_log_split(&cfg, &cmd, &args, &cwd, out_path, err_path, &vars).await;
+        let (mut outcome, out_path, err_path) = match res {
+            Ok(res) => res,
+            Err(err) => {
+                return Err(abandon_run(
+                    &cfg,
+                    &plan,
+                    &vars,
+                    &start_paths,
+                    indexed,
+                    stamp.at,
+                    err,
+                ))
+            }
+        };
+        exit_code = outcome.code;
+        kept = outcome.kept(cfg.keep);
+        duration_secs = outcome.usage.map(|u| u.duration().as_secs_f64());
+        overhead = outcome.overhead;
+        artifact = outcome.artifact.take();
+        log_path = out_path.clone();
+        let final_vars = TemplateVars {
+            exit_code: Some(outcome.code),
+            failure_class: Some(outcome.class_label()),
+            duration_secs: outcome.usage.map(|u| u.duration().as_secs()),
+            pid: outcome.pid,
+            ..vars
+        };
+        let finals = plan.final_paths(&cfg, &final_vars);
+        match collision::finish(&cfg, &[out_path, err_path], &finals) {
+            Ok(paths) => log_path = paths[0].clone(),
+            Err(err) => eprintln!("lg: {:#}; the log stays at {:?}", err, log_path),
         }
+        outcome.report_failure(&command_line, &log_path);
     } else {
-        let (exit, path_written) = run_and_log_combined(
-            &cfg, &cmd, &args, &cwd, &log_path, &cmd_str, &args_str, &date_s, &time_s,
-        )
-        .await?;
-        exit_code = exit;
-        if let Some(tpl) = final_template {
-            // Compute final name with exit code and rename
-            let final_name = render_template(
-                &tpl,
-                &cmd_str,
-                &args_str,
-                &date_s,
-                &time_s,
-                &ts_s,
-                Some(exit_code),
-                &HOSTNAME,
-                &cwd_s,
-                cfg.sanitize_filename,
-                cfg.include_args_in_name,
-            );
-            let mut final_path = out_dir.join(final_name);
-            // Preserve compression extension
-            if path_written.to_string_lossy().ends_with(".gz")
-                && !final_path.to_string_lossy().ends_with(".gz")
-            {
-                final_path.set_extension("log.gz");
-            } else if std::path::Path::new(&final_path).extension().is_none() {
-                final_path.set_extension("log");
+        let res = run_and_log_combined(&cfg, &cmd, &args, &cwd, &start_paths[0], &vars).await;
+        let (mut outcome, path_written) = match res {
+            Ok(res) => res,
+            Err(err) => {
+                return Err(abandon_run(
+                    &cfg,
+                    &plan,
+                    &vars,
+                    &start_paths,
+                    indexed,
+                    stamp.at,
+                    err,
+                ))
             }
-            let _ = fs::rename(path_written, final_path);
-        }
-    }
-
-    Ok((exit_code, log_path))
-}
-
-fn ensure_config_file() -> Option<PathBuf> {
-    let home = simple_home_dir()?;
-    let path = home.join(".lg");
-    if !path.exists() {
-        if let Err(err) = fs::write(&path, DEFAULT_CONFIG_TEMPLATE) {
-            eprintln!("lg: failed to create default config at {:?}: {}", path, err);
-            return Some(path);
-        }
-    }
-    Some(path)
-}
-
-fn simple_home_dir() -> Option<PathBuf> {
-    // Unix-like: $HOME
-    if let Ok(h) = std::env::var("HOME") {
-        if !h.is_empty() {
-            return Some(PathBuf::from(h));
+        };
+        exit_code = outcome.code;
+        kept = outcome.kept(cfg.keep);
+        duration_secs = outcome.usage.map(|u| u.duration().as_secs_f64());
+        overhead = outcome.overhead;
+        artifact = outcome.artifact.take();
+        log_path = path_written.clone();
+        let final_vars = TemplateVars {
+            exit_code: Some(outcome.code),
+            failure_class: Some(outcome.class_label()),
+            duration_secs: outcome.usage.map(|u| u.duration().as_secs()),
+            pid: outcome.pid,
+            ..vars
+        };
+        let finals = plan.final_paths(&cfg, &final_vars);
+        match collision::finish(&cfg, &[path_written], &finals) {
+            Ok(paths) => log_path = paths[0].clone(),
+            Err(err) => eprintln!("lg: {:#}; the log stays at {:?}", err, log_path),
         }
+        outcome.report_failure(&command_line, &log_path);
     }
-    // Windows fallbacks
-    if cfg!(windows) {
-        if let Ok(p) = std::env::var("USERPROFILE") {
-            if !p.is_empty() {
-                return Some(PathBuf::from(p));
+    if !kept {
+        match discard_run(&cfg, &log_path, artifact.as_deref()) {
+            Ok(trashed) if cfg.verbosity >= 1 => {
+                let to = trashed.map_or("deleted".into(), |b| format!("moved to {:?}", b));
+                eprintln!("lg: keep: log {}", to);
             }
+            Ok(_) => {}
+            Err(err) => eprintln!("lg: keep: {:#}", err),
         }
-        let drive = std::env::var("HOMEDRIVE").unwrap_or_default();
-        let path = std::env::var("HOMEPATH").unwrap_or_default();
-        if !drive.is_empty() && !path.is_empty() {
-            return Some(PathBuf::from(format!("{}{}", drive, path)));
+    } else {
+        if cfg.latest_symlink {
+            if let Err(err) = link_latest(&cfg, &vars, &out_dir, &log_path) {
+                eprintln!("lg: latest_symlink: {:#}", err);
+            }
         }
+        record_latest(&cwd, &log_path);
     }
-    None
-}
-
-fn load_config() -> Result<Config> {
-    let mut cfg = Config::default();
-    if let Some(p) = ensure_config_file() {
-        if p.exists() {
-            let data = fs::read_to_string(&p).with_context(|| format!("reading config {:?}", p))?;
-            let file_cfg: Config =
-                toml::from_str(&data).with_context(|| format!("parsing config TOML {:?}", p))?;
-            cfg = Config { ..file_cfg };
+    if let Some(group) = &cfg.group {
+        let run = GroupRun {
+            cmd: cmd_str.clone(),
+            args: args_str.clone(),
+            started: stamp.at.naive_local().trunc_subsecs(0),
+            duration_secs,
+            exit_code,
+            log: kept.then(|| fs::canonicalize(&log_path).unwrap_or_else(|_| log_path.clone())),
+        };
+        record_group_run(group, &run);
+        if let Some(budget) = over_group_budget(&cfg) {
+            eprintln!("lg: group {}", budget);
         }
     }
-    Ok(cfg)
-}
-
-fn join_args(args: &[OsString], include_full: bool) -> String {
-    let mut out = Vec::new();
-    for a in args {
-        let s = a.to_string_lossy().to_string();
-        if include_full {
-            out.push(s);
+    if let Some((index, id)) = indexed {
+        let ended = SystemClock.now();
+        let overhead = overhead.with_cpu_time();
+        // A log `keep` discarded is gone from the index too, as after `lg clean`.
+        let res = if kept {
+            index.finish(id, stamp.at, ended, exit_code, &log_path, &overhead)
         } else {
-            if !s.starts_with('-') {
-                out.push(s);
-            }
+            index.discard(id)
+        };
+        if let Err(err) = res {
+            eprintln!("lg: index: {:#}", err);
+        } else if kept && cfg.anomaly_notify {
+            notify_anomalies(&cfg, &index, &run_id, &cmd_str, &args_str);
         }
     }
-    out.join(" ")
-}
 
-fn sanitize_component(s: &str) -> String {
-    let mut out = String::with_capacity(s.len());
-    for ch in s.chars() {
-        if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' || ch == '.' {
-            out.push(ch);
-        } else {
-            out.push('_');
-        }
-    }
-    while out.contains("__") {
-        out = out.replace("__", "_");
-    }
-    out.trim_matches('_').to_string()
+    Ok((exit_code, log_path))
 }
 
-fn maybe_sanitize_component<'a>(input: &'a str, sanitize: bool) -> Cow<'a, str> {
-    if sanitize {
-        Cow::Owned(sanitize_component(input))
-    } else {
-        Cow::Borrowed(input)
+// A run that failed in lg itself (a log write error, say) still leaves its log
+// under the final name, `{exit_code}` as "NA", and its index row ended with
+// lg's own exit code 1. Returns `err` for the caller to report.
+fn abandon_run(
+    cfg: &Config,
+    plan: &LogPlan,
+    vars: &TemplateVars,
+    start_paths: &[PathBuf],
+    indexed: Option<(RunIndex, i64)>,
+    started: DateTime<Local>,
+    err: anyhow::Error,
+) -> anyhow::Error {
+    let finals = plan.final_paths(cfg, vars);
+    // A split run may not have created its stderr log yet.
+    let (from, to): (Vec<_>, Vec<_>) = start_paths
+        .iter()
+        .cloned()
+        .zip(finals)
+        .filter(|(from, _)| from.exists())
+        .unzip();
+    let mut log_path = start_paths[0].clone();
+    match collision::finish(cfg, &from, &to) {
+        Ok(paths) => log_path = paths.first().cloned().unwrap_or(log_path),
+        Err(err) => eprintln!("lg: {:#}; the log stays at {:?}", err, log_path),
+    }
+    if let Some((index, id)) = indexed {
+        let overhead = Overhead::default().with_cpu_time();
+        if let Err(err) = index.finish(id, started, SystemClock.now(), 1, &log_path, &overhead) {
+            eprintln!("lg: index: {:#}", err);
+        }
     }
+    err
 }
 
-fn render_template(
-    tpl: &str,
-    cmd: &str,
-    args: &str,
-    date: &str,
-    time: &str,
-    ts: &str,
-    exit_code: Option<i32>,
-    hostname: &str,
-    cwd: &str,
-    sanitize: bool,
-    include_args_in_name: bool,
-) -> String {
-    let mut args_used = if include_args_in_name {
-        args.to_string()
-    } else {
-        String::new()
+// Report how the run just indexed is off from the usual for its command line.
+// Best effort, like the index.
+fn notify_anomalies(cfg: &Config, index: &RunIndex, run_id: &str, cmd: &str, args: &str) {
+    let runs = match index.runs_of(cmd, args) {
+        Ok(runs) => runs,
+        Err(err) => {
+            eprintln!("lg: index: {:#}", err);
+            return;
+        }
     };
-    if sanitize {
-        args_used = sanitize_component(&args_used);
-    }
-    let cmd_fragment = maybe_sanitize_component(cmd, sanitize);
-    let hostname_fragment = maybe_sanitize_component(hostname, sanitize);
-    let cwd_fragment = maybe_sanitize_component(cwd, sanitize);
-    let mut s = tpl
-        .replace("{cmd}", cmd_fragment.as_ref())
-        .replace("{args}", &args_used)
-        .replace("{date}", date)
-        .replace("{time}", time)
-        .replace("{ts}", ts)
-        .replace("{hostname}", hostname_fragment.as_ref())
-        .replace("{cwd}", cwd_fragment.as_ref());
-    if let Some(code) = exit_code {
-        s = s.replace("{exit_code}", &code.to_string());
-    } else {
-        s = s.replace("{exit_code}", "NA");
-    }
-    s = s.replace("..", ".");
-    while s.contains("__") {
-        s = s.replace("__", "_");
+    let Some(run) = runs.iter().rfind(|r| r.run_id.as_deref() == Some(run_id)) else {
+        return;
+    };
+    for anomaly in anomalies(&earlier_runs(&runs, run), run, cfg.anomaly_factor) {
+        eprintln!("lg: anomaly: {}", anomaly);
     }
-    s.trim_matches(|c| c == '_' || c == '.').to_string()
 }
 
-async fn run_and_log_combined(
-    cfg: &Config,
-    cmd: &OsString,
-    args: &[OsString],
-    cwd: &Path,
-    log_path: &Path,
-    cmd_str: &str,
-    args_str: &str,
-    date_s: &str,
-    time_s: &str,
-) -> Result<(i32, PathBuf)> {
-    // Open writer (plain or gz)
-    let (mut writer_box, final_path) = open_writer(cfg, log_path)?;
-
-    // Header
-    write_header(
-        &mut *writer_box,
-        cfg,
-        cmd_str,
-        args_str,
-        cwd,
-        date_s,
-        time_s,
-    )?;
-
-    // Spawn process
-    let mut child = Command::new(cmd)
-        .args(args)
-        .stdin(Stdio::inherit())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .with_context(|| "spawning child")?;
-
-    let stdout = child.stdout.take().unwrap();
-    let stderr = child.stderr.take().unwrap();
-
-    let mut r_out = BufReader::new(stdout).lines();
-    let mut r_err = BufReader::new(stderr).lines();
-
-    let tee = cfg.tee;
-    let ts_each = cfg.timestamp_each_line;
-    let plain_lines = cfg.plain_lines;
-
-    let mut out_done = false;
-    let mut err_done = false;
-
-    // Interleave lines with markers based on whichever channel yields first.
-    loop {
-        tokio::select! {
-            line = r_out.next_line(), if !out_done => {
-                match line? {
-                    Some(l) => {
-                        if tee { println!("{}", l); }
-                        write_line(&mut *writer_box, "STDOUT", &l, ts_each, plain_lines)?;
-                    }
-                    None => { out_done = true; }
-                }
-            }
-            line = r_err.next_line(), if !err_done => {
-                match line? {
-                    Some(l) => {
-                        if tee { eprintln!("{}", l); }
-                        write_line(&mut *writer_box, "STDERR", &l, ts_each, plain_lines)?;
-                    }
-                    None => { err_done = true; }
-                }
-            }
-            else => { break; }
+// "<id>: 31m02s used, over its 30m00s budget" once the run's group has used
+// up its budget. Best effort, like recording the run.
+fn over_group_budget(cfg: &Config) -> Option<String> {
+    let id = cfg.group.as_deref()?;
+    match group_budget(cfg, id) {
+        Ok(budget) => budget
+            .filter(|b| b.over())
+            .map(|b| format!("{}: {}", id, b)),
+        Err(err) => {
+            eprintln!("lg: group {}: {:#}", id, err);
+            None
         }
     }
-
-    let status = child.wait().await?;
-    let code = status.code().unwrap_or(1);
-    writeln!(
-        &mut *writer_box,
-        "
-[exit_code] {}",
-        code
-    )?;
-    writer_box.flush()?;
-
-    Ok((code, final_path))
 }
 
-async fn run_and_log_split(
-    cfg: &Config,
-    cmd: &OsString,
-    args: &[OsString],
-    cwd: &Path,
-    base_path: &Path,
-    cmd_str: &str,
-    args_str: &str,
-    date_s: &str,
-    time_s: &str,
-) -> Result<(i32, PathBuf, PathBuf)> {
-    // Paths
-    let mut out_path = base_path.with_extension("out.log");
-    let mut err_path = base_path.with_extension("err.log");
-    if cfg.compress == Compress::Gz {
-        out_path = out_path.with_extension("out.log.gz");
-        err_path = err_path.with_extension("err.log.gz");
+// The `{seq}` of this run, when a template uses it. Best effort: without it
+// the name still goes through `collision`.
+fn next_seq(cfg: &Config, out_dir: &Path) -> Option<u64> {
+    let templates = [Some(&cfg.filename_template), cfg.header_template.as_ref()];
+    if !templates.iter().flatten().any(|t| t.contains("{seq}")) {
+        return None;
     }
-
-    let (mut out_writer, out_final) = open_writer(cfg, &out_path)?;
-    let (mut err_writer, err_final) = open_writer(cfg, &err_path)?;
-
-    // Header
-    write_header(
-        &mut *out_writer,
-        cfg,
-        cmd_str,
-        args_str,
-        cwd,
-        date_s,
-        time_s,
-    )?;
-    write_header(
-        &mut *err_writer,
-        cfg,
-        cmd_str,
-        args_str,
-        cwd,
-        date_s,
-        time_s,
-    )?;
-
-    let mut child = Command::new(cmd)
-        .args(args)
-        .stdin(Stdio::inherit())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .with_context(|| "spawning child")?;
-
-    let stdout = child.stdout.take().unwrap();
-    let stderr = child.stderr.take().unwrap();
-
-    let mut r_out = BufReader::new(stdout).lines();
-    let mut r_err = BufReader::new(stderr).lines();
-
-    let tee = cfg.tee;
-    let ts_each = cfg.timestamp_each_line;
-    let plain_lines = cfg.plain_lines;
-
-    let mut out_done = false;
-    let mut err_done = false;
-
-    loop {
-        tokio::select! {
-            line = r_out.next_line(), if !out_done => {
-                match line? {
-                    Some(l) => {
-                        if tee { println!("{}", l); }
-                        write_line(&mut *out_writer, "STDOUT", &l, ts_each, plain_lines)?;
-                    }
-                    None => { out_done = true; }
-                }
-            }
-            line = r_err.next_line(), if !err_done => {
-                match line? {
-                    Some(l) => {
-                        if tee { eprintln!("{}", l); }
-                        write_line(&mut *err_writer, "STDERR", &l, ts_each, plain_lines)?;
-                    }
-                    None => { err_done = true; }
-                }
-            }
-            else => { break; }
+    // One counter however the directory is spelled.
+    let out_dir = fs::canonicalize(out_dir).unwrap_or_else(|_| out_dir.to_path_buf());
+    let res = StateDir::locate()
+        .context("cannot determine lg state directory (HOME unset)")
+        .and_then(|dir| dir.next_seq(&out_dir));
+    match res {
+        Ok(seq) => Some(seq),
+        Err(err) => {
+            eprintln!("lg: seq: {:#}", err);
+            None
         }
     }
-
-    let status = child.wait().await?;
-    let code = status.code().unwrap_or(1);
-    writeln!(
-        &mut *out_writer,
-        "
-[exit_code] {}",
-        code
-    )?;
-    writeln!(
-        &mut *err_writer,
-        "
-[exit_code] {}",
-        code
-    )?;
-    out_writer.flush()?;
-    err_writer.flush()?;
-
-    Ok((code, out_final, err_final))
 }
 
-fn write_header<W: Write>(
-    mut w: W,
-    cfg: &Config,
-    cmd: &str,
-    args: &str,
-    cwd: &Path,
-    date_s: &str,
-    time_s: &str,
-) -> Result<()> {
-    writeln!(w, "# lg log")?;
-    writeln!(w, "cmd: {}", cmd)?;
-    if !args.is_empty() {
-        writeln!(w, "args: {}", args)?;
-    }
-    writeln!(w, "date: {} {}", date_s, time_s)?;
-    writeln!(w, "cwd: {}", cwd.display())?;
-    writeln!(w, "host: {}", *HOSTNAME)?;
-    if cfg.log_env {
-        for (k, v) in std::env::vars() {
-            writeln!(w, "env[{}]={}", k, v)?;
+// Best effort: a broken index must not stop the command from running.
+fn index_start(run: &RunStart) -> Option<(RunIndex, i64)> {
+    let res = StateDir::locate()
+        .context("cannot determine lg state directory (HOME unset)")
+        .and_then(|dir| RunIndex::open(&dir))
+        .and_then(|index| Ok((index.start(run)?, index)));
+    match res {
+        Ok((id, index)) => Some((index, id)),
+        Err(err) => {
+            eprintln!("lg: index: {:#}", err);
+            None
         }
     }
-    writeln!(w, "----- BEGIN OUTPUT -----")?;
-    Ok(())
-}
-
-fn write_line<W: Write>(
-    mut w: W,
-    stream: &str,
-    line: &str,
-    ts_each: bool,
-    plain_lines: bool,
-) -> Result<()> {
-    if plain_lines {
-        writeln!(w, "{}", line)?;
-        return Ok(());
-    }
-    if ts_each {
-        let ts = Local::now().format(DEFAULT_LINE_TIME_FORMAT);
-        writeln!(w, "[{}][{}] {}", ts, stream, line)?;
-    } else {
-        writeln!(w, "[{}] {}", stream, line)?;
-    }
-    Ok(())
-}
-
-fn open_writer(cfg: &Config, final_path: &Path) -> Result<(Box<dyn Write + Send>, PathBuf)> {
-    let boxed: Box<dyn Write + Send> = match cfg.compress {
-        Compress::None => {
-            let file = File::create(&final_path)
-                .with_context(|| format!("create file {:?}", final_path))?;
-            Box::new(io::BufWriter::new(file))
-        }
-        Compress::Gz => {
-            let file = File::create(&final_path)
-                .with_context(|| format!("create file {:?}", final_path))?;
-            let enc = GzEncoder::new(file, Compression::default());
-            Box::new(enc)
-        }
-    };
-    Ok((boxed, final_path.to_path_buf()))
 }
@@ -0,0 +1,316 @@
+//! Reading the output as it goes by: `[[classify]]` rules and problem matchers.
+
+use crate::config::{default_severity, ClassifyRule, Config};
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use regex::{Regex, RegexSet};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// A source location reported in a log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// "error", "warning", "note", ...
+    pub severity: String,
+    pub file: PathBuf,
+    pub line: u32,
+    pub col: Option<u32>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Parse the value of a `[diagnostic]` footer line.
+    pub fn parse(v: &str) -> Option<Self> {
+        let (severity, rest) = v.split_once(' ')?;
+        let caps = FOOTER_LOCATION.captures(rest)?;
+        Some(Self {
+            severity: severity.to_string(),
+            file: PathBuf::from(&caps["file"]),
+            line: caps["line"].parse().ok()?,
+            col: caps.name("col").and_then(|c| c.as_str().parse().ok()),
+            message: caps["message"].to_string(),
+        })
+    }
+
+    pub fn same_place(&self, other: &Self) -> bool {
+        self.file == other.file && self.line == other.line && self.col == other.col
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    /// `file:line[:col]: message`, the form compilers print and vim's quickfix reads.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file.display(), self.line)?;
+        if let Some(col) = self.col {
+            write!(f, ":{}", col)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+static FOOTER_LOCATION: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?P<file>.+?):(?P<line>\d+)(?::(?P<col>\d+))?: (?P<message>.*)$").unwrap()
+});
+
+/// Tracks the highest-priority (earliest declared) rule matched by any output line.
+pub struct Classifier {
+    names: Vec<String>,
+    set: RegexSet,
+    best: Option<usize>,
+}
+
+impl Classifier {
+    pub fn new(rules: &[ClassifyRule]) -> Result<Option<Self>> {
+        if rules.is_empty() {
+            return Ok(None);
+        }
+        let set = RegexSet::new(rules.iter().map(|r| r.pattern.as_str()))
+            .context("invalid [[classify]] pattern")?;
+        Ok(Some(Self {
+            names: rules.iter().map(|r| r.name.clone()).collect(),
+            set,
+            best: None,
+        }))
+    }
+
+    pub fn observe(&mut self, line: &str) {
+        if self.best == Some(0) {
+            return;
+        }
+        if let Some(idx) = self.set.matches(line).iter().next() {
+            self.best = Some(self.best.map_or(idx, |best| best.min(idx)));
+        }
+    }
+
+    pub fn class(&self) -> Option<&str> {
+        self.best.map(|idx| self.names[idx].as_str())
+    }
+}
+
+/// A problem matcher shipped with lg, enabled by name in `problem_matchers`.
+pub struct BuiltinMatcher {
+    pub name: &'static str,
+    pattern: &'static str,
+    context: Option<&'static str>,
+}
+
+pub static BUILTIN_MATCHERS: &[BuiltinMatcher] = &[
+    // error[E0308]: mismatched types
+    //   --> src/main.rs:4:18
+    BuiltinMatcher {
+        name: "rustc",
+        pattern: r"^\s*--> (?P<file>[^:\s]+):(?P<line>\d+):(?P<col>\d+)",
+        context: Some(r"^(?P<severity>error|warning|note|help)(?:\[\w+\])?: (?P<message>.+)$"),
+    },
+    // src/x.c:3:5: error: expected ';' before '}' token
+    BuiltinMatcher {
+        name: "gcc",
+        pattern: r"^(?P<file>[^:\s]+):(?P<line>\d+):(?:(?P<col>\d+):)? (?:fatal )?(?P<severity>error|warning|note): (?P<message>.+)$",
+        context: None,
+    },
+    // /app/src/index.js
+    //   3:10  error  'x' is defined but never used  no-unused-vars
+    BuiltinMatcher {
+        name: "eslint",
+        pattern: r"^\s+(?P<line>\d+):(?P<col>\d+)\s+(?P<severity>error|warning)\s+(?P<message>.+?)(?:\s{2,}[\w@/-]+)?$",
+        context: Some(r"^(?P<file>\S.*\.(?:[cm]?[jt]sx?|vue|svelte))$"),
+    },
+    // tests/test_app.py:12: AssertionError
+    BuiltinMatcher {
+        name: "pytest",
+        pattern: r"^(?P<file>[^:\s]+\.py):(?P<line>\d+): (?P<message>\w*(?:Error|Exception|Failed|Warning)\b.*)$",
+        context: None,
+    },
+];
+
+// Diagnostics recorded per run; a build that fails everywhere shouldn't
+// bloat the footer.
+static MAX_DIAGNOSTICS: usize = 200;
+
+struct CompiledMatcher {
+    pattern: Regex,
+    context: Option<Regex>,
+    severity: String,
+    /// Captures of the last `context` line.
+    carried: BTreeMap<&'static str, String>,
+}
+
+/// Runs the enabled problem matchers over the output lines.
+pub struct Matchers {
+    matchers: Vec<CompiledMatcher>,
+    pub found: Vec<Diagnostic>,
+}
+
+impl Matchers {
+    pub fn new(cfg: &Config) -> Result<Option<Self>> {
+        let mut matchers = Vec::new();
+        for name in &cfg.problem_matchers {
+            let builtin = BUILTIN_MATCHERS
+                .iter()
+                .find(|m| m.name == name)
+                .with_context(|| {
+                    format!(
+                        "unknown problem matcher {:?} (built in: {})",
+                        name,
+                        BUILTIN_MATCHERS
+                            .iter()
+                            .map(|m| m.name)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                })?;
+            matchers.push(CompiledMatcher {
+                pattern: Regex::new(builtin.pattern).unwrap(),
+                context: builtin.context.map(|c| Regex::new(c).unwrap()),
+                severity: default_severity(),
+                carried: BTreeMap::new(),
+            });
+        }
+        for rule in &cfg.matcher {
+            let invalid = || format!("invalid [[matcher]] {:?}", rule.name);
+            let pattern = Regex::new(&rule.pattern).with_context(invalid)?;
+            anyhow::ensure!(
+                pattern.capture_names().any(|n| n == Some("line")),
+                "[[matcher]] {:?} has no (?P<line>...) capture",
+                rule.name
+            );
+            matchers.push(CompiledMatcher {
+                pattern,
+                context: rule
+                    .context
+                    .as_deref()
+                    .map(Regex::new)
+                    .transpose()
+                    .with_context(invalid)?,
+                severity: rule.severity.clone(),
+                carried: BTreeMap::new(),
+            });
+        }
+        if matchers.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            matchers,
+            found: Vec::new(),
+        }))
+    }
+
+    pub fn observe(&mut self, line: &str) {
+        if self.found.len() >= MAX_DIAGNOSTICS {
+            return;
+        }
+        const FIELDS: [&str; 5] = ["file", "line", "col", "severity", "message"];
+        for m in &mut self.matchers {
+            if let Some(caps) = m.context.as_ref().and_then(|c| c.captures(line)) {
+                m.carried = FIELDS
+                    .iter()
+                    .filter_map(|&f| caps.name(f).map(|v| (f, v.as_str().to_string())))
+                    .collect();
+                continue;
+            }
+            let Some(caps) = m.pattern.captures(line) else {
+                continue;
+            };
+            let get = |f: &str| {
+                caps.name(f)
+                    .map(|v| v.as_str().to_string())
+                    .or_else(|| m.carried.get(f).cloned())
+            };
+            let (Some(file), Some(line_no)) = (get("file"), get("line")) else {
+                continue;
+            };
+            let Ok(line_no) = line_no.parse() else {
+                continue;
+            };
+            let diag = Diagnostic {
+                severity: get("severity")
+                    .map(|s| s.to_lowercase())
+                    .unwrap_or_else(|| m.severity.clone()),
+                file: PathBuf::from(file),
+                line: line_no,
+                col: get("col").and_then(|c| c.parse().ok()),
+                message: get("message").unwrap_or_default().trim().to_string(),
+            };
+            if !self.found.iter().any(|d| d.same_place(&diag)) {
+                self.found.push(diag);
+            }
+            // One matcher per line; the packs don't overlap.
+            break;
+        }
+    }
+}
+
+// `::error file=...::message` lines that GitHub Actions turns into
+// annotations on the run and the pull request.
+pub fn github_annotation(d: &Diagnostic) -> String {
+    let level = match d.severity.as_str() {
+        "error" | "fatal" => "error",
+        "warning" => "warning",
+        _ => "notice",
+    };
+    let prop = |s: String| {
+        s.replace('%', "%25")
+            .replace(',', "%2C")
+            .replace(':', "%3A")
+    };
+    let mut out = format!(
+        "::{} file={},line={}",
+        level,
+        prop(d.file.display().to_string()),
+        d.line
+    );
+    if let Some(col) = d.col {
+        out.push_str(&format!(",col={}", col));
+    }
+    out.push_str("::");
+    out.push_str(&d.message.replace('%', "%25"));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matched(lines: &[&str]) -> Vec<String> {
+        let mut m = Matchers::new(&Config::default()).unwrap().unwrap();
+        for l in lines {
+            m.observe(l);
+        }
+        m.found
+            .iter()
+            .map(|d| format!("{} {}", d.severity, d))
+            .collect()
+    }
+
+    #[test]
+    fn builtin_matchers() {
+        assert_eq!(
+            matched(&[
+                "error[E0308]: mismatched types",
+                "  --> src/main.rs:4:18",
+                "src/x.c:3:5: fatal error: foo.h: No such file or directory",
+                "/app/src/index.js",
+                "  3:10  error  'x' is defined but never used  no-unused-vars",
+                "tests/test_app.py:12: AssertionError",
+            ]),
+            [
+                "error src/main.rs:4:18: mismatched types",
+                "error src/x.c:3:5: foo.h: No such file or directory",
+                "error /app/src/index.js:3:10: 'x' is defined but never used",
+                "error tests/test_app.py:12: AssertionError",
+            ]
+        );
+    }
+
+    #[test]
+    fn diagnostic_footer_round_trip() {
+        let d = Diagnostic::parse("warning src/a b.rs:7: unused: `x`").unwrap();
+        assert_eq!(d.file, PathBuf::from("src/a b.rs"));
+        assert_eq!((d.line, d.col), (7, None));
+        assert_eq!(
+            format!("{} {}", d.severity, d),
+            "warning src/a b.rs:7: unused: `x`"
+        );
+    }
+}
@@ -0,0 +1,455 @@
+//! Log file names: the name rendered at start and the rename once the outcome is known.
+
+pub mod collision;
+
+use crate::clock::Clock;
+use crate::config::{expand_tilde, Config};
+use crate::context::{HOSTNAME, USER};
+use crate::state::temp_path;
+use crate::template::{render_template, TemplateVars, POST_RUN_PLACEHOLDERS};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The `{date}`, `{time}` and `{ts}` values of one run, read once from `clock`.
+pub struct Stamp {
+    pub date: String,
+    pub time: String,
+    pub ts: String,
+    /// The instant itself, for the run index.
+    pub at: DateTime<Local>,
+}
+
+impl Stamp {
+    pub fn new(cfg: &Config, clock: &dyn Clock) -> Self {
+        let now = clock.now();
+        Self {
+            date: now.format(&cfg.date_format).to_string(),
+            time: now.format(&cfg.time_format).to_string(),
+            ts: now.timestamp().to_string(),
+            at: now,
+        }
+    }
+}
+
+/// Where a run writes its log, decided before the command starts. Nothing
+/// here touches the filesystem; the caller creates, writes and renames.
+///
+/// A run writes to a hidden `.partial` file and renames it once it is over,
+/// so a log under its final name is always complete (log shippers and the
+/// log tools skip hidden files). The rename also fills in the post-run
+/// placeholders ({exit_code}, {duration}, ...).
+pub struct LogPlan {
+    out_dir: PathBuf,
+    /// The combined `.partial` log, or the base the split logs add
+    /// `.out.log`/`.err.log` to.
+    pub path: PathBuf,
+    // Whether the final name is known at start: no post-run placeholders.
+    final_known: bool,
+}
+
+impl LogPlan {
+    pub fn new(cfg: &Config, vars: &TemplateVars, out_dir: &Path) -> Self {
+        let base_name = render_template(
+            &cfg.filename_template,
+            vars,
+            cfg.sanitize_filename,
+            cfg.include_args_in_name,
+        );
+        let path = if cfg.split_streams {
+            // Split logs get .out.log and .err.log in place of the extension,
+            // see `split_paths`
+            let stem = Path::new(&base_name).with_extension("");
+            out_dir.join(format!(".{}.partial", stem.display()))
+        } else {
+            with_compress_ext(out_dir.join(format!(".{}.partial", base_name)), cfg)
+        };
+        Self {
+            out_dir: out_dir.to_path_buf(),
+            path,
+            final_known: !POST_RUN_PLACEHOLDERS
+                .iter()
+                .any(|p| cfg.filename_template.contains(p)),
+        }
+    }
+
+    /// The logs the run starts writing: the combined log or the split pair.
+    pub fn start_paths(&self, cfg: &Config) -> Vec<PathBuf> {
+        if cfg.split_streams {
+            let (out, err) = self.split_paths(cfg);
+            vec![out, err]
+        } else {
+            vec![self.path.clone()]
+        }
+    }
+
+    /// Stdout and stderr logs of a split run: `<base>.out.log` and `<base>.err.log`.
+    pub fn split_paths(&self, cfg: &Config) -> (PathBuf, PathBuf) {
+        let half = |ext: &str| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(ext);
+            with_compress_ext(PathBuf::from(name), cfg)
+        };
+        (half(".out.log"), half(".err.log"))
+    }
+
+    /// The final names of the logs of `start_paths`, with the exit code and
+    /// failure class from `vars`.
+    pub fn final_paths(&self, cfg: &Config, vars: &TemplateVars) -> Vec<PathBuf> {
+        let name = render_template(
+            &cfg.filename_template,
+            vars,
+            cfg.sanitize_filename,
+            cfg.include_args_in_name,
+        );
+        let mut path = self.out_dir.join(name);
+        if cfg.split_streams {
+            return ["out.log", "err.log"]
+                .iter()
+                .map(|ext| with_compress_ext(path.with_extension(ext), cfg))
+                .collect();
+        }
+        if path.extension().is_none() {
+            path.set_extension("log");
+        }
+        vec![with_compress_ext(path, cfg)]
+    }
+
+    /// `final_paths` as far as they can be told before the run, for
+    /// `collision = "error"`: None when the name has post-run placeholders.
+    pub fn known_final_paths(&self, cfg: &Config, vars: &TemplateVars) -> Option<Vec<PathBuf>> {
+        self.final_known.then(|| self.final_paths(cfg, vars))
+    }
+}
+
+/// `output_dir` with `~` expanded and the placeholders in its components
+/// filled in, e.g. "~/logs/{cmd}/{date}"; None when unset.
+pub fn render_output_dir(cfg: &Config, vars: &TemplateVars) -> Result<Option<PathBuf>> {
+    let Some(dir) = &cfg.output_dir else {
+        return Ok(None);
+    };
+    let raw = dir.to_string_lossy();
+    // The directory is made before the command runs, and holds the `{seq}` counter.
+    if let Some(p) = POST_RUN_PLACEHOLDERS
+        .iter()
+        .chain(&["{seq}"])
+        .find(|p| raw.contains(*p))
+    {
+        bail!(
+            "output_dir can't use {}: it is made before the command runs",
+            p
+        );
+    }
+    let mut out = PathBuf::new();
+    for component in expand_tilde(dir).components() {
+        let part = component.as_os_str().to_string_lossy();
+        match part.contains('{') {
+            true => out.push(render_dir_component(cfg, &part, vars)),
+            false => out.push(component),
+        }
+    }
+    Ok(Some(out))
+}
+
+/// Where the log tools look for a templated `output_dir`: the directories
+/// before its first placeholder, and how many levels the placeholders add.
+/// `{hostname}` and `{user}` are the same for every run here, so they are
+/// filled in: on a log root shared by several machines, each looks at its own.
+pub fn output_dir_root(cfg: &Config) -> Option<(PathBuf, usize)> {
+    let dir = expand_tilde(cfg.output_dir.as_deref()?);
+    let vars = TemplateVars {
+        cmd: "",
+        args: "",
+        date: "",
+        time: "",
+        ts: "",
+        hostname: &HOSTNAME,
+        user: &USER,
+        cwd: "",
+        ci_run: "",
+        run_id: "",
+        parent_run_id: None,
+        fingerprint: None,
+        git: None,
+        seq: None,
+        exit_code: None,
+        failure_class: None,
+        duration_secs: None,
+        pid: None,
+    };
+    let mut root = PathBuf::new();
+    let mut depth = 0;
+    for component in dir.components() {
+        let part = component.as_os_str().to_string_lossy();
+        let unfixed = ["{hostname}", "{user}"]
+            .iter()
+            .fold(part.to_string(), |s, p| s.replace(p, ""));
+        if depth > 0 || unfixed.contains('{') {
+            depth += 1;
+        } else if part.contains('{') {
+            root.push(render_dir_component(cfg, &part, &vars));
+        } else {
+            root.push(component);
+        }
+    }
+    Some((root, depth))
+}
+
+// One directory of `output_dir` with its placeholders filled in.
+fn render_dir_component(cfg: &Config, part: &str, vars: &TemplateVars) -> PathBuf {
+    let rendered = render_template(part, vars, cfg.sanitize_filename, cfg.include_args_in_name);
+    // An unsanitized `{cwd}` nests rather than replacing the path.
+    let rendered = rendered.trim_start_matches(['/', '\\']);
+    PathBuf::from(if rendered.is_empty() { "NA" } else { rendered })
+}
+
+/// Point the `latest_link_name` symlink in `out_dir` at `log`, replacing the
+/// previous link in one rename so readers never find it missing.
+pub fn link_latest(cfg: &Config, vars: &TemplateVars, out_dir: &Path, log: &Path) -> Result<()> {
+    let name = render_template(&cfg.latest_link_name, vars, cfg.sanitize_filename, false);
+    if name.is_empty() || name.contains(['/', '\\']) {
+        bail!(
+            "latest_link_name {:?} is not a file name",
+            cfg.latest_link_name
+        );
+    }
+    let link = out_dir.join(&name);
+    // Relative when the log sits next to the link, so the directory can move.
+    let target = match log.parent() {
+        Some(dir) if dir == out_dir => Path::new(log.file_name().unwrap_or_default()),
+        _ => log,
+    };
+    if link.as_path() == log {
+        bail!("{:?} would replace the log itself", link);
+    }
+    let tmp = temp_path(&link);
+    symlink(target, &tmp).with_context(|| format!("creating {:?}", tmp))?;
+    fs::rename(&tmp, &link).with_context(|| {
+        let _ = fs::remove_file(&tmp);
+        format!("replacing {:?}", link)
+    })
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+fn has_ext(path: &Path, ext: &str) -> bool {
+    path.extension().is_some_and(|e| e == ext)
+}
+
+// `name.log` -> `name.log.gz` when compressing, unless it already ends that way.
+fn with_compress_ext(path: PathBuf, cfg: &Config) -> PathBuf {
+    match cfg.compress.extension() {
+        Some(ext) if !has_ext(&path, ext) => {
+            let mut name = path.into_os_string();
+            name.push(".");
+            name.push(ext);
+            PathBuf::from(name)
+        }
+        _ => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+    use crate::template::sanitize_component;
+
+    fn config(extra: &str) -> Config {
+        toml::from_str(extra).unwrap()
+    }
+
+    fn vars<'a>(stamp: &'a Stamp) -> TemplateVars<'a> {
+        TemplateVars {
+            cmd: "make",
+            args: "all",
+            date: &stamp.date,
+            time: &stamp.time,
+            ts: &stamp.ts,
+            hostname: "box",
+            user: "dev",
+            cwd: "/src",
+            ci_run: "NA",
+            run_id: "01M5170180ZZZZZZZZZZZZZZZZ",
+            parent_run_id: None,
+            fingerprint: None,
+            git: None,
+            seq: None,
+            exit_code: None,
+            failure_class: None,
+            duration_secs: None,
+            pid: None,
+        }
+    }
+
+    #[test]
+    fn names_come_from_the_clock() {
+        let cfg = config("");
+        let stamp = Stamp::new(&cfg, &FixedClock::at("2024-03-05 14:07:09"));
+        assert_eq!(stamp.date, "2024-03-05");
+        assert_eq!(stamp.time, "14-07-09");
+        let plan = LogPlan::new(&cfg, &vars(&stamp), Path::new("/logs"));
+        assert_eq!(
+            plan.path,
+            Path::new("/logs/.make_2024-03-05_14-07-09.log.partial")
+        );
+        let done = [PathBuf::from("/logs/make_2024-03-05_14-07-09.log")];
+        assert_eq!(plan.final_paths(&cfg, &vars(&stamp)), done);
+        assert_eq!(
+            plan.known_final_paths(&cfg, &vars(&stamp)).as_deref(),
+            Some(&done[..])
+        );
+    }
+
+    #[test]
+    fn exit_code_names_are_renamed_after_the_run() {
+        let cfg = config(r#"filename_template = "{cmd}_{date}_{exit_code}""#);
+        let stamp = Stamp::new(&cfg, &FixedClock::at("2024-03-05 14:07:09"));
+        let plan = LogPlan::new(&cfg, &vars(&stamp), Path::new("/logs"));
+        let done = TemplateVars {
+            exit_code: Some(2),
+            ..vars(&stamp)
+        };
+        assert_eq!(
+            plan.final_paths(&cfg, &done),
+            [PathBuf::from("/logs/make_2024-03-05_2.log")]
+        );
+        assert_eq!(plan.known_final_paths(&cfg, &done), None);
+
+        let cfg = config(
+            r#"filename_template = "{cmd}_{exit_code}"
+split_streams = true
+compress = "gz""#,
+        );
+        let plan = LogPlan::new(&cfg, &vars(&stamp), Path::new("/logs"));
+        assert_eq!(
+            plan.start_paths(&cfg),
+            [
+                PathBuf::from("/logs/.make_NA.partial.out.log.gz"),
+                PathBuf::from("/logs/.make_NA.partial.err.log.gz")
+            ]
+        );
+        assert_eq!(
+            plan.final_paths(&cfg, &done),
+            [
+                PathBuf::from("/logs/make_2.out.log.gz"),
+                PathBuf::from("/logs/make_2.err.log.gz")
+            ]
+        );
+    }
+
+    #[test]
+    fn output_dir_is_rendered_per_component() {
+        let stamp = Stamp::new(&config(""), &FixedClock::at("2024-03-05 14:07:09"));
+        let cfg =
+            config(r#"output_dir = "/var/log/lg/{hostname}/{cmd}_{env.LG_TEST_UNSET:-x}/{date}""#);
+        assert_eq!(
+            render_output_dir(&cfg, &vars(&stamp)).unwrap(),
+            Some(PathBuf::from("/var/log/lg/box/make_x/2024-03-05"))
+        );
+        // This machine's directory, as the tools see it.
+        assert_eq!(
+            output_dir_root(&cfg),
+            Some((
+                PathBuf::from("/var/log/lg").join(sanitize_component(&HOSTNAME)),
+                2
+            ))
+        );
+        // Relative parts and dots outside placeholders stay as written.
+        let cfg = config(r#"output_dir = "../logs/{user}""#);
+        assert_eq!(
+            render_output_dir(&cfg, &vars(&stamp)).unwrap(),
+            Some(PathBuf::from("../logs/dev"))
+        );
+        let cfg = config(r#"output_dir = "logs/{exit_code}""#);
+        assert!(render_output_dir(&cfg, &vars(&stamp)).is_err());
+        assert_eq!(render_output_dir(&config(""), &vars(&stamp)).unwrap(), None);
+    }
+
+    #[test]
+    fn compressed_logs_keep_the_log_extension() {
+        let stamp = Stamp::new(&config(""), &FixedClock::at("2024-03-05 14:07:09"));
+        let cfg = config(r#"compress = "gz""#);
+        let plan = LogPlan::new(&cfg, &vars(&stamp), Path::new("/logs"));
+        assert_eq!(
+            plan.final_paths(&cfg, &vars(&stamp)),
+            [PathBuf::from("/logs/make_2024-03-05_14-07-09.log.gz")]
+        );
+
+        let cfg = config(
+            r#"compress = "zstd"
+split_streams = true"#,
+        );
+        let plan = LogPlan::new(&cfg, &vars(&stamp), Path::new("/logs"));
+        assert_eq!(
+            plan.final_paths(&cfg, &vars(&stamp)),
+            [
+                PathBuf::from("/logs/make_2024-03-05_14-07-09.out.log.zst"),
+                PathBuf::from("/logs/make_2024-03-05_14-07-09.err.log.zst")
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn latest_link_follows_the_newest_log() {
+        let dir = std::env::temp_dir().join(format!("lg-latest-link-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let stamp = Stamp::new(&config(""), &FixedClock::at("2024-03-05 14:07:09"));
+        let cfg = config(r#"latest_link_name = "{cmd}.latest.log""#);
+        for log in ["make_1.log", "make_2.log"] {
+            fs::write(dir.join(log), log).unwrap();
+            link_latest(&cfg, &vars(&stamp), &dir, &dir.join(log)).unwrap();
+        }
+        let link = dir.join("make.latest.log");
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new("make_2.log"));
+        assert_eq!(fs::read_to_string(&link).unwrap(), "make_2.log");
+
+        let cfg = config(r#"latest_link_name = "make_2.log""#);
+        assert!(link_latest(&cfg, &vars(&stamp), &dir, &dir.join("make_2.log")).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn latest_link_survives_concurrent_runs() {
+        let dir = std::env::temp_dir().join(format!("lg-latest-race-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let stamp = Stamp::new(&config(""), &FixedClock::at("2024-03-05 14:07:09"));
+        let cfg = config("");
+        let logs: Vec<PathBuf> = (0..8)
+            .map(|i| dir.join(format!("make_{}.log", i)))
+            .collect();
+        for log in &logs {
+            fs::write(log, "").unwrap();
+        }
+        std::thread::scope(|s| {
+            for log in &logs {
+                let (cfg, stamp, dir) = (&cfg, &stamp, &dir);
+                s.spawn(move || {
+                    for _ in 0..25 {
+                        link_latest(cfg, &vars(stamp), dir, log).unwrap();
+                    }
+                });
+            }
+            for _ in 0..200 {
+                // Missing only before the first link is in place.
+                if let Ok(target) = fs::read_link(dir.join("latest.log")) {
+                    assert!(dir.join(target).exists());
+                }
+            }
+        });
+        let entries = fs::read_dir(&dir).unwrap().count();
+        assert_eq!(entries, logs.len() + 1, "temporary links left behind");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,318 @@
+//! `collision`: what a run does when the name of its log is taken. A run
+//! writes under a hidden `.partial` name that is always its own, so the rules
+//! apply when it is renamed to its final name; a split pair is resolved as
+//! one, so its halves keep matching names.
+
+use crate::config::{Collision, Config};
+use crate::runner::{rename_log, rotated_path};
+use anyhow::{Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Take the `.partial` names `paths` (one log, or a split pair) for a run
+/// about to start, and return the ones to write; they are numbered rather
+/// than shared whatever the strategy. With `collision = "error"` the run
+/// doesn't start when its final names are known (`finals`) and taken.
+pub fn claim(cfg: &Config, paths: &[PathBuf], finals: Option<&[PathBuf]>) -> Result<Vec<PathBuf>> {
+    if let (Collision::Error, Some(finals)) = (cfg.collision, finals) {
+        if let Some(taken) = finals.iter().find(|p| p.exists()) {
+            return Err(taken_error(taken));
+        }
+    }
+    claim_numbered(paths)
+}
+
+/// Move the finished logs `from` to the names `to` and return where they
+/// ended up. All of them move or none does: on an error (with `collision =
+/// "error"`, a taken name) every log is left where it was written.
+pub fn finish(cfg: &Config, from: &[PathBuf], to: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut moves = Moves::default();
+    let res = match cfg.collision {
+        Collision::Suffix => claim_numbered(to).and_then(|to| {
+            moves.claimed = to.clone();
+            move_all(&mut moves, from, &to).map(|()| to)
+        }),
+        Collision::Error => create_new(to).and_then(|created| {
+            created.map_err(|taken| taken_error(&taken))?;
+            moves.claimed = to.to_vec();
+            move_all(&mut moves, from, to).map(|()| to.to_vec())
+        }),
+        Collision::Overwrite => replace_all(&mut moves, from, to).map(|()| to.to_vec()),
+        Collision::Append => return append_all(from, to).map(|()| to.to_vec()),
+    };
+    match res {
+        Ok(to) => {
+            moves.commit();
+            Ok(to)
+        }
+        Err(err) => {
+            moves.undo();
+            Err(err)
+        }
+    }
+}
+
+/// The renames of a `finish` so far, undone if a later step fails.
+#[derive(Default)]
+struct Moves {
+    done: Vec<(PathBuf, PathBuf)>,
+    /// Empty files created to hold the new names.
+    claimed: Vec<PathBuf>,
+    /// Old logs moved aside for `collision = "overwrite"`.
+    replaced: Vec<PathBuf>,
+}
+
+impl Moves {
+    fn rename(&mut self, from: &Path, to: &Path) -> Result<()> {
+        rename_log(from, to).with_context(|| format!("renaming {:?} to {:?}", from, to))?;
+        self.done.push((from.to_path_buf(), to.to_path_buf()));
+        Ok(())
+    }
+
+    fn commit(self) {
+        for old in &self.replaced {
+            let _ = fs::remove_file(old);
+            let _ = fs::remove_file(rotated_path(old));
+        }
+    }
+
+    fn undo(self) {
+        for (from, to) in self.done.iter().rev() {
+            let _ = rename_log(to, from);
+        }
+        for path in &self.claimed {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn move_all(moves: &mut Moves, from: &[PathBuf], to: &[PathBuf]) -> Result<()> {
+    for (from, to) in from.iter().zip(to) {
+        moves.rename(from, to)?;
+    }
+    Ok(())
+}
+
+// Move the old logs at `to` aside first, so a failed rename can bring them back.
+fn replace_all(moves: &mut Moves, from: &[PathBuf], to: &[PathBuf]) -> Result<()> {
+    for to in to.iter().filter(|to| to.exists()) {
+        let name = to.file_name().unwrap_or_default().to_string_lossy();
+        let aside = to.with_file_name(format!(".{}.replaced", name));
+        moves.rename(to, &aside)?;
+        moves.replaced.push(aside);
+    }
+    move_all(moves, from, to)
+}
+
+/// `path` with `-n` before the extensions lg gives logs: `make_1.log.gz`
+/// becomes `make_1-2.log.gz`, `.make_1.log.partial` `.make_1-2.log.partial`.
+pub fn numbered(path: &Path, n: u32) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut stem: &str = &name;
+    // `.partial` comes before or after the log extension.
+    for exts in [
+        &[".gz", ".zst"][..],
+        &[".partial"],
+        &[".out.log", ".err.log", ".log"],
+        &[".partial"],
+    ] {
+        if let Some(s) = exts.iter().find_map(|ext| stem.strip_suffix(ext)) {
+            stem = s;
+        }
+    }
+    path.with_file_name(format!("{}-{}{}", stem, n, &name[stem.len()..]))
+}
+
+// The first of `paths`, `paths` numbered 2, 3, ... whose names are all free,
+// created empty so no other run takes them.
+fn claim_numbered(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    for n in 1.. {
+        let names: Vec<PathBuf> = match n {
+            1 => paths.to_vec(),
+            n => paths.iter().map(|p| numbered(p, n)).collect(),
+        };
+        if create_new(&names)?.is_ok() {
+            return Ok(names);
+        }
+    }
+    unreachable!("ran out of numbers")
+}
+
+// Create every one of `paths`; the one already taken, if any, with the ones
+// created before it removed again.
+fn create_new(paths: &[PathBuf]) -> Result<Result<(), PathBuf>> {
+    for (i, path) in paths.iter().enumerate() {
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(_) => {}
+            Err(err) => {
+                for created in &paths[..i] {
+                    let _ = fs::remove_file(created);
+                }
+                if err.kind() == io::ErrorKind::AlreadyExists {
+                    return Ok(Err(path.clone()));
+                }
+                return Err(err).with_context(|| format!("creating {:?}", path));
+            }
+        }
+    }
+    Ok(Ok(()))
+}
+
+fn taken_error(path: &Path) -> anyhow::Error {
+    anyhow::anyhow!("{:?} already exists (collision = \"error\")", path)
+}
+
+// Add each log of `from` to the end of its `to`, or move it there if `to` is
+// new. Compressed logs are a sequence of gzip members or zstd frames either
+// way. The old logs are cut back to their length if one of them fails.
+fn append_all(from: &[PathBuf], to: &[PathBuf]) -> Result<()> {
+    let mut moves = Moves::default();
+    let mut appended = Vec::new();
+    let res = from.iter().zip(to).try_for_each(|(from, to)| {
+        let Ok(meta) = fs::metadata(to) else {
+            return moves.rename(from, to);
+        };
+        appended.push((from, to, meta.len()));
+        append_log(from, to)
+    });
+    if let Err(err) = res {
+        for (_, to, len) in &appended {
+            if let Ok(file) = OpenOptions::new().write(true).open(to) {
+                let _ = file.set_len(*len);
+            }
+        }
+        moves.undo();
+        return Err(err);
+    }
+    for (from, to, _) in appended {
+        fs::remove_file(from).with_context(|| format!("removing {:?}", from))?;
+        match fs::rename(rotated_path(from), rotated_path(to)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                return Err(err).with_context(|| format!("renaming {:?}", rotated_path(from)));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn append_log(from: &Path, to: &Path) -> Result<()> {
+    let mut src = fs::File::open(from).with_context(|| format!("open {:?}", from))?;
+    let mut dst = OpenOptions::new()
+        .append(true)
+        .open(to)
+        .with_context(|| format!("open {:?}", to))?;
+    io::copy(&mut src, &mut dst).with_context(|| format!("appending to {:?}", to))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lg-{}-test-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn numbers_go_before_the_log_extensions() {
+        let n = |name: &str| numbered(Path::new(name), 2);
+        assert_eq!(n("/l/make_1.log"), Path::new("/l/make_1-2.log"));
+        assert_eq!(
+            n("/l/make_1.out.log.gz"),
+            Path::new("/l/make_1-2.out.log.gz")
+        );
+        assert_eq!(
+            n("/l/.make_NA.partial.zst"),
+            Path::new("/l/.make_NA-2.partial.zst")
+        );
+        assert_eq!(
+            n("/l/.make_NA.partial.err.log"),
+            Path::new("/l/.make_NA-2.partial.err.log")
+        );
+        assert_eq!(
+            n("/l/.make_1.log.partial.gz"),
+            Path::new("/l/.make_1-2.log.partial.gz")
+        );
+        assert_eq!(n("/l/sleep_1.5.txt"), Path::new("/l/sleep_1.5.txt-2"));
+    }
+
+    #[test]
+    fn each_strategy_on_a_taken_name() {
+        let dir = scratch("collision");
+        let pair = [dir.join("make.out.log"), dir.join("make.err.log")];
+        // Only the stderr half is taken; both halves move on to -2.
+        fs::write(&pair[1], "old").unwrap();
+        let suffix = Config::default();
+        let claimed = claim(&suffix, &pair, None).unwrap();
+        assert_eq!(
+            claimed,
+            [dir.join("make-2.out.log"), dir.join("make-2.err.log")]
+        );
+        assert!(!pair[0].exists());
+
+        let error = Config {
+            collision: Collision::Error,
+            ..Config::default()
+        };
+        let partial = [
+            dir.join(".make.partial.out.log"),
+            dir.join(".make.partial.err.log"),
+        ];
+        let err = claim(&error, &partial, Some(&pair))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("make.err.log"), "{}", err);
+        assert!(!partial[0].exists());
+        // The `.partial` name is numbered whatever the strategy.
+        assert_eq!(
+            claim(&error, &pair, None).unwrap()[0],
+            dir.join("make-3.out.log")
+        );
+
+        fs::write(&claimed[1], "new").unwrap();
+        let append = Config {
+            collision: Collision::Append,
+            ..Config::default()
+        };
+        let to = [pair[1].clone()];
+        assert_eq!(finish(&append, &claimed[1..], &to).unwrap(), to);
+        assert_eq!(fs::read_to_string(&pair[1]).unwrap(), "oldnew");
+        assert!(!claimed[1].exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_failed_rename_leaves_every_log_in_place() {
+        let dir = scratch("finish");
+        let from = [dir.join(".make_NA.out.log"), dir.join(".make_NA.err.log")];
+        fs::write(&from[0], "out").unwrap();
+        fs::write(&from[1], "err").unwrap();
+        // The stderr log's new name is in a directory that isn't there.
+        let to = [dir.join("make_1.out.log"), dir.join("gone/make_1.err.log")];
+        fs::write(&to[0], "old").unwrap();
+        for collision in [Collision::Overwrite, Collision::Append] {
+            let cfg = Config {
+                collision,
+                ..Config::default()
+            };
+            assert!(finish(&cfg, &from, &to).is_err());
+            assert_eq!(fs::read_to_string(&from[0]).unwrap(), "out");
+            assert_eq!(fs::read_to_string(&from[1]).unwrap(), "err");
+            assert_eq!(fs::read_to_string(&to[0]).unwrap(), "old");
+        }
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [".make_NA.err.log", ".make_NA.out.log", "make_1.out.log"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,76 @@
+//! The run id: a ULID per invocation, e.g. "01JA8X3V9QK2M7T4R6W0ZBCDEF", that
+//! ties a run's logs, its index row and whatever the command itself records
+//! (through `LG_RUN_ID`) together. Ids sort by the time the run started.
+
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::Read;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Crockford's base32, as ULIDs use it.
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// The id of the lg run this one was started under: lg gives the commands it
+/// runs their run's id in `LG_RUN_ID`.
+pub fn parent_run_id() -> Option<String> {
+    std::env::var("LG_RUN_ID").ok().filter(|id| is_run_id(id))
+}
+
+// 26 characters of the ULID alphabet, so the id is safe in a path.
+fn is_run_id(s: &str) -> bool {
+    s.len() == 26 && s.bytes().all(|b| ALPHABET.contains(&b))
+}
+
+/// A new id for a run starting now.
+pub fn new_run_id() -> String {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    encode(since_epoch.as_millis() as u64, random_bits())
+}
+
+// 48 bits of milliseconds, then 80 random bits.
+fn encode(millis: u64, random: u128) -> String {
+    let value = (u128::from(millis & 0xffff_ffff_ffff) << 80) | (random & ((1 << 80) - 1));
+    (0..26)
+        .rev()
+        .map(|i| ALPHABET[(value >> (i * 5)) as usize & 31] as char)
+        .collect()
+}
+
+// From the system's random source, or else a hash of what tells runs apart.
+fn random_bits() -> u128 {
+    let mut bytes = [0u8; 16];
+    let read = File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut bytes));
+    if read.is_err() {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let mut hasher = Sha256::new();
+        hasher.update(nanos.to_le_bytes());
+        hasher.update(std::process::id().to_le_bytes());
+        bytes.copy_from_slice(&hasher.finalize()[..16]);
+    }
+    u128::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_ulids_in_start_order() {
+        assert_eq!(encode(0, 0), "00000000000000000000000000");
+        assert_eq!(
+            encode(1_792_116_000_000, u128::MAX),
+            "01M5170180ZZZZZZZZZZZZZZZZ"
+        );
+        let (a, b) = (new_run_id(), new_run_id());
+        assert_eq!(a.len(), 26);
+        assert_ne!(a, b);
+        assert!(encode(1, u128::MAX) < encode(2, 0));
+        assert!(is_run_id(&a));
+        assert!(!is_run_id("../../etc/passwd") && !is_run_id("01m5295qj6nm46mxdvt0q7ya6g"));
+    }
+}
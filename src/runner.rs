@@ -0,0 +1,1337 @@
+//! Running the wrapped command and streaming its output into the log.
+
+mod artifact;
+mod cron;
+mod fold;
+#[cfg(unix)]
+mod hangup;
+mod header;
+mod interact;
+mod log_size;
+#[cfg(target_os = "linux")]
+mod oom;
+mod plugins;
+#[cfg(unix)]
+mod process_env;
+#[cfg(unix)]
+mod pty;
+mod raw;
+mod signals;
+mod stdin_log;
+mod summarize;
+mod timeout;
+mod usage;
+
+use crate::ansi;
+use crate::baseline::BaselineCheck;
+use crate::budget::{fmt_size, MIN_LINE_BYTES};
+use crate::config::{
+    program_name, Config, InvalidUtf8, Keep, LogFormat, LogSizeAction, StdinMode, StdinSource,
+};
+use crate::context::{INVOCATION, TTY};
+use crate::jsonl;
+use crate::matchers::{github_annotation, Classifier, Diagnostic, Matchers};
+use crate::redact::Redactor;
+use crate::runner::artifact::ArtifactCapture;
+use crate::runner::cron::HeldOutput;
+use crate::runner::fold::Folds;
+use crate::runner::header::{write_header, write_spawn_error};
+use crate::runner::interact::Interactor;
+pub use crate::runner::log_size::{rename_log, rotated_path};
+use crate::runner::log_size::{rotate, SizeLimit};
+use crate::runner::plugins::{line_record, Plugins};
+use crate::runner::raw::RawLog;
+use crate::runner::signals::Forwarder;
+pub use crate::runner::signals::{name as signal_name, Received};
+use crate::runner::summarize::{summarize_failure, FailureContext};
+use crate::runner::timeout::{Deadline, TIMEOUT_EXIT_CODE};
+pub use crate::runner::usage::{parse_duration_secs, Overhead, Usage, LG_STARTED};
+use crate::sinks::incompressible::CompressionNote;
+use crate::sinks::{open_writer, LogWriters, Stream};
+use crate::state::RunningEntry;
+use crate::template::TemplateVars;
+use crate::tools::{use_color, DIM_RED};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+
+static DEFAULT_LINE_TIME_FORMAT: &str = "%H:%M:%S%.3f";
+// Bytes read from a child pipe per call.
+pub static READ_CHUNK: usize = 8 * 1024;
+// Logged when the terminal hangs up during a `survive_hup` run.
+static HANGUP_MARKER: &str = "SIGHUP: terminal gone, detached; still logging, tee off";
+// How often the per-line clock re-reads the wall clock to pick up drift and DST changes
+static LINE_CLOCK_RESYNC: Duration = Duration::from_secs(60);
+// Longest `first_error` in a failure report.
+static FIRST_ERROR_CHARS: usize = 240;
+
+// Commands listed in `never_log` (matched by program name).
+pub fn is_never_logged(cfg: &Config, cmd: &OsString) -> bool {
+    let name = program_name(cmd);
+    cfg.never_log.iter().any(|n| *n == name)
+}
+
+// Run the command with no capture at all: nothing is read, written or recorded.
+// On Unix lg replaces itself with the command, so it isn't even a parent process.
+pub fn exec_uncaptured(cmd: &OsString, args: &[OsString]) -> Result<i32> {
+    let mut command = std::process::Command::new(cmd);
+    command.args(args);
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        let err = command.exec();
+        eprintln!("lg: failed to start '{}': {}", cmd.to_string_lossy(), err);
+        Ok(spawn_failure_code(&err))
+    }
+    #[cfg(not(unix))]
+    match command.status() {
+        Ok(status) => Ok(status.code().unwrap_or(1)),
+        Err(err) => {
+            eprintln!("lg: failed to start '{}': {}", cmd.to_string_lossy(), err);
+            Ok(spawn_failure_code(&err))
+        }
+    }
+}
+
+pub async fn run_and_log_combined(
+    cfg: &Config,
+    cmd: &OsString,
+    args: &[OsString],
+    cwd: &Path,
+    log_path: &Path,
+    vars: &TemplateVars<'_>,
+) -> Result<(RunOutcome, PathBuf)> {
+    let (cmd_str, args_str) = (vars.cmd, vars.args);
+    // Open writer (plain or gz)
+    let compression = CompressionNote::default();
+    let (mut writer_box, final_path) = open_writer(cfg, log_path, &compression)?;
+
+    // Header
+    let mut header = Vec::new();
+    write_header(&mut header, cfg, vars, cwd)?;
+    writer_box.write_all(&header)?;
+
+    let mut files = LogFiles {
+        writers: LogWriters::Combined(writer_box),
+        paths: vec![final_path.clone()],
+        header,
+        compression,
+    };
+    // Make the run visible to `lg tail` and the other tools right away.
+    files.writers.flush()?;
+    let _running = RunningEntry::register(
+        vars.run_id,
+        cmd_str,
+        args_str,
+        cwd,
+        vec![final_path.clone()],
+    );
+    let outcome = run_child(cfg, cmd, args, vars, &mut files).await?;
+    Ok((outcome, final_path))
+}
+
+pub async fn run_and_log_split(
+    cfg: &Config,
+    cmd: &OsString,
+    args: &[OsString],
+    cwd: &Path,
+    out_path: &Path,
+    err_path: &Path,
+    vars: &TemplateVars<'_>,
+) -> Result<(RunOutcome, PathBuf, PathBuf)> {
+    let (cmd_str, args_str) = (vars.cmd, vars.args);
+    let compression = CompressionNote::default();
+    let (mut out_writer, out_final) = open_writer(cfg, out_path, &compression)?;
+    let (mut err_writer, err_final) = open_writer(cfg, err_path, &compression)?;
+
+    // Header
+    let mut header = Vec::new();
+    write_header(&mut header, cfg, vars, cwd)?;
+    out_writer.write_all(&header)?;
+    err_writer.write_all(&header)?;
+
+    let mut files = LogFiles {
+        writers: LogWriters::Split {
+            out: out_writer,
+            err: err_writer,
+        },
+        paths: vec![out_final.clone(), err_final.clone()],
+        header,
+        compression,
+    };
+    files.writers.flush()?;
+    let _running = RunningEntry::register(
+        vars.run_id,
+        cmd_str,
+        args_str,
+        cwd,
+        vec![out_final.clone(), err_final.clone()],
+    );
+    let outcome = run_child(cfg, cmd, args, vars, &mut files).await?;
+    Ok((outcome, out_final, err_final))
+}
+
+// A run's open logs, and what `max_log_size_action = "rotate"` needs to start
+// them over.
+struct LogFiles {
+    writers: LogWriters,
+    /// The files behind `writers`, stdout's first.
+    paths: Vec<PathBuf>,
+    header: Vec<u8>,
+    compression: CompressionNote,
+}
+
+// Spawn the command, stream its output into `writers` and write the footer.
+// A failed start still leaves a log documenting the attempt.
+async fn run_child(
+    cfg: &Config,
+    cmd: &OsString,
+    args: &[OsString],
+    vars: &TemplateVars<'_>,
+    files: &mut LogFiles,
+) -> Result<RunOutcome> {
+    let cmd_str = vars.cmd;
+    let LogFiles {
+        writers,
+        paths,
+        header,
+        compression,
+    } = files;
+    let mut interactor = Interactor::new(&cfg.interact)?;
+    let mut classifier = Classifier::new(&cfg.classify)?;
+    let mut matchers = Matchers::new(cfg)?;
+    let mut baseline = BaselineCheck::new(cfg, cmd_str, vars.args);
+    let mut artifact = ArtifactCapture::new(cfg);
+    let mut folds = Folds::new(&cfg.fold)?;
+    let redactor = Redactor::new(&cfg.redact)?;
+    let summarizer = cfg
+        .post_failure_summarizer
+        .as_ref()
+        .filter(|s| !s.command.trim().is_empty());
+    // The last lines of output, for the summarizer.
+    let mut excerpt: VecDeque<String> = VecDeque::new();
+    #[cfg(target_os = "linux")]
+    let mut oom_watch = oom::OomWatch::start();
+    #[cfg(unix)]
+    if cfg.survive_hup {
+        hangup::install()?;
+    }
+    let mut forwarder = Forwarder::install(!cfg.survive_hup)?;
+    let mut signals = Vec::new();
+    // Set when the signal came from `lg kill`.
+    let mut cancelled = None;
+    let limit = cfg.timeout()?;
+    let mut size_limit = SizeLimit::new(cfg)?;
+    let mut held = match cfg.cron {
+        true => Some(HeldOutput::new().context("creating the cron output spool")?),
+        false => None,
+    };
+
+    let own_group = signals::own_group();
+    let spawned = stdin_log::open(cfg).and_then(|input| {
+        let (child, pty_master) =
+            spawn_child(cfg, cmd, args, vars.run_id, input.is_some(), own_group)?;
+        Ok((child, pty_master, input))
+    });
+    let (mut child, pty_master, input) = match spawned {
+        Ok(spawned) => spawned,
+        Err(err) => {
+            let code = spawn_failure_code(&err);
+            eprintln!("lg: failed to start '{}': {}", cmd_str, err);
+            writers.for_each(|w| write_spawn_error(w, cfg, &err, code))?;
+            writers.flush()?;
+            return Ok(RunOutcome {
+                code,
+                failure_class: None,
+                usage: None,
+                pid: None,
+                overhead: Overhead::default(),
+                stderr_lines: 0,
+                first_error: Some(err.to_string()),
+                artifact: None,
+                report: !(cfg.tees(Stream::Stdout) || cfg.tees(Stream::Stderr)),
+                held,
+            });
+        }
+    };
+    // Signals for the command go to its process group, when it has one.
+    let group = child.id().filter(|_| own_group);
+    let spawned_at = Instant::now();
+    let mut overhead = Overhead::spawned(spawned_at);
+    let log = paths[0].to_string_lossy();
+    let plugin_env = [
+        ("LG_CMD", cmd_str),
+        ("LG_ARGS", vars.args),
+        ("LG_RUN_ID", vars.run_id),
+        ("LG_LOG", &log),
+    ];
+    let plugins = Plugins::start(&cfg.plugin, &plugin_env);
+    let mut plugin_clock = LineClock::new(cfg.timestamp_resolution_ms, jsonl::TS_FORMAT);
+    if let Some(interactor) = interactor.as_mut() {
+        interactor.stdin = child.stdin.take();
+    }
+    // `log_stdin`: lg passes the input on and logs its copy.
+    let mut in_done = input.is_none();
+    let mut r_in = match input {
+        Some(input) => LineReader::new(
+            stdin_log::feed(input, child.stdin.take()),
+            cfg.max_line_bytes,
+        ),
+        None => LineReader::new(tokio::io::empty(), cfg.max_line_bytes),
+    };
+    let grace = Duration::from_secs(cfg.timeout_grace_secs);
+    let mut deadline = Deadline::start(limit, grace);
+
+    // On a pty both streams arrive on the master and are logged as stdout.
+    // Its output is teed as raw bytes so progress bars and cursor movement
+    // still work; lines only exist in the log.
+    let mut raw_tee = pty_master.is_some() && cfg.tees(Stream::Stdout);
+    let mut r_out = match pty_master {
+        #[cfg(unix)]
+        Some(master) => LineReader::new(pty::PtyReader::new(master), cfg.max_line_bytes),
+        _ => LineReader::new(child.stdout.take().unwrap(), cfg.max_line_bytes),
+    };
+    let mut r_err = match child.stderr.take() {
+        Some(stderr) => LineReader::new(stderr, cfg.max_line_bytes),
+        None => LineReader::new(tokio::io::empty(), cfg.max_line_bytes),
+    };
+
+    let mut tee_out = cfg.tees(Stream::Stdout);
+    let mut tee_err = cfg.tees(Stream::Stderr);
+    // `tee_color`: only when both streams reach a terminal, where they mix.
+    let err_color = (cfg.tee_color && use_color() && TTY.stderr).then_some(DIM_RED);
+    let mut detached = false;
+    // A SIGHUP without `survive_hup`: the terminal is gone while the command finishes.
+    let mut hup = false;
+    let ts_each = cfg.timestamp_each_line;
+    let style = LineStyle::of(cfg);
+    let line_time_format = match style {
+        LineStyle::Jsonl => jsonl::TS_FORMAT,
+        _ => DEFAULT_LINE_TIME_FORMAT,
+    };
+    let mut clock = LineClock::new(cfg.timestamp_resolution_ms, line_time_format);
+    let invalid_utf8 = match (style, cfg.invalid_utf8) {
+        (LineStyle::Jsonl, InvalidUtf8::Verbatim) => InvalidUtf8::Escape,
+        (_, mode) => mode,
+    };
+    let flush_enabled = cfg.flush_interval_ms > 0;
+    let mut flush_tick = flush_timer(cfg.flush_interval_ms);
+    // `raw` logs (and tees) the bytes as they come; lines are still split
+    // out for everything else that reads the output.
+    let mut raw_log = match style {
+        LineStyle::Jsonl => None,
+        _ if cfg.raw => Some(RawLog::new(cfg)),
+        _ => None,
+    };
+    // Bare lines and bytes as they come have nowhere to put fold markers.
+    if style == LineStyle::Plain || raw_log.is_some() {
+        folds = None;
+    }
+
+    let mut out_done = false;
+    let mut err_done = false;
+    let mut stderr_lines = 0;
+    let mut first_stderr: Option<String> = None;
+
+    // Interleave lines with markers based on whichever channel yields first.
+    loop {
+        let (stream, reader, n) = tokio::select! {
+            res = r_out.fill(), if !out_done => {
+                let n = res?;
+                out_done = n == 0;
+                (Stream::Stdout, &mut r_out, n)
+            }
+            res = r_err.fill(), if !err_done => {
+                let n = res?;
+                err_done = n == 0;
+                (Stream::Stderr, &mut r_err, n)
+            }
+            res = r_in.fill(), if !in_done && (!out_done || !err_done) => {
+                in_done = res? == 0;
+                // Output a `raw` log holds back came before this input.
+                if let Some(raw) = raw_log.as_mut().filter(|_| logging(&size_limit)) {
+                    raw.flush(writers, redactor.as_ref(), &mut clock)?;
+                }
+                while let Some(line) = r_in.next_line() {
+                    let l = String::from_utf8_lossy(&line);
+                    let masked = redactor.as_ref().map(|r| r.redact(&l));
+                    let rendered = match &masked {
+                        Some(Cow::Owned(masked)) => Cow::Borrowed(masked.as_bytes()),
+                        _ => render_invalid(&line, invalid_utf8),
+                    };
+                    if size_limit.as_mut().map_or(true, |s| s.take(rendered.len() + 1)) {
+                        let stamp = if ts_each { Some(clock.stamp()) } else { None };
+                        let w = writers.for_stream(Stream::Stdout);
+                        write_line(w, "STDIN", &rendered, stamp, style)?;
+                    }
+                }
+                continue;
+            }
+            _ = flush_tick.tick(), if flush_enabled && !(out_done && err_done) => {
+                if let Some(raw) = raw_log.as_mut().filter(|_| logging(&size_limit)) {
+                    raw.flush(writers, redactor.as_ref(), &mut clock)?;
+                }
+                writers.flush()?;
+                continue;
+            }
+            signal = forwarder.recv(), if !(out_done && err_done) => {
+                hup |= signals::is_hangup(signal);
+                let stamp = if ts_each { Some(clock.stamp()) } else { None };
+                let (received, by) = forward_signal(signal, &child, group, writers, stamp, style)?;
+                signals.push(received);
+                cancelled = by.or(cancelled);
+                continue;
+            }
+            _ = deadline.expired(), if deadline.armed() && !(out_done && err_done) => {
+                let note = deadline.fire(&mut child, group);
+                let stamp = if ts_each { Some(clock.stamp()) } else { None };
+                writers.for_each(|w| write_line(w, "LG", &note, stamp, style))?;
+                // Whatever still holds the pipes open (say a background job of
+                // the command) is not waited for once the command is killed.
+                if deadline.killed() {
+                    break;
+                }
+                continue;
+            }
+            else => { break; }
+        };
+        overhead.bytes += n as u64;
+        if let Some(artifact) = artifact.as_mut().filter(|_| stream == Stream::Stdout) {
+            artifact.push(reader.fresh(n));
+        }
+        // Checked before anything is printed: the terminal may be gone.
+        if !detached && (hup || hung_up(cfg)) {
+            detached = true;
+            (tee_out, tee_err, raw_tee) = (false, false, false);
+            #[cfg(unix)]
+            let _ = hangup::detach();
+            let stamp = if ts_each { Some(clock.stamp()) } else { None };
+            writers.for_each(|w| write_line(w, "LG", HANGUP_MARKER, stamp, style))?;
+        }
+        let tee_raw = match stream {
+            Stream::Stdout => raw_tee || (raw_log.is_some() && tee_out),
+            Stream::Stderr => raw_log.is_some() && tee_err,
+        };
+        let teed = match stream {
+            Stream::Stdout if tee_raw => {
+                let mut stdout = io::stdout().lock();
+                stdout
+                    .write_all(reader.fresh(n))
+                    .and_then(|()| stdout.flush())
+            }
+            Stream::Stderr if tee_raw => tee(&mut io::stderr().lock(), reader.fresh(n), err_color),
+            _ => Ok(()),
+        };
+        if let Err(err) = teed {
+            let note = stop_tee(stream, &err, &mut tee_out, &mut tee_err, &mut raw_tee);
+            let stamp = if ts_each { Some(clock.stamp()) } else { None };
+            writers.for_each(|w| write_line(w, "LG", &note, stamp, style))?;
+        }
+        while let Some(line) = reader.next_line() {
+            if stream == Stream::Stderr {
+                stderr_lines += 1;
+            }
+            if let Some(held) = held.as_mut() {
+                held.hold(&line)?;
+            }
+            let teed = match stream {
+                Stream::Stdout if tee_out && !tee_raw => {
+                    let mut stdout = io::stdout().lock();
+                    stdout
+                        .write_all(&line)
+                        .and_then(|()| stdout.write_all(b"\n"))
+                }
+                Stream::Stderr if tee_err && !tee_raw => {
+                    let mut stderr = io::stderr().lock();
+                    tee(&mut stderr, &line, err_color).and_then(|()| stderr.write_all(b"\n"))
+                }
+                _ => Ok(()),
+            };
+            if let Err(err) = teed {
+                let note = stop_tee(stream, &err, &mut tee_out, &mut tee_err, &mut raw_tee);
+                let stamp = if ts_each { Some(clock.stamp()) } else { None };
+                writers.for_each(|w| write_line(w, "LG", &note, stamp, style))?;
+            }
+            let line = match cfg.strip_ansi {
+                true => ansi::strip(&line),
+                false => Cow::Borrowed(&line[..]),
+            };
+            let l = String::from_utf8_lossy(&line);
+            // The log, and whatever lg derives from it, only sees the masked line.
+            let logged = match &redactor {
+                Some(redactor) => redactor.redact(&l),
+                None => Cow::Borrowed(&*l),
+            };
+            // Bytes the log gets: as written, unless something was masked.
+            let rendered = match &logged {
+                Cow::Owned(masked) => Cow::Borrowed(masked.as_bytes()),
+                Cow::Borrowed(_) => render_invalid(&line, invalid_utf8),
+            };
+            if stream == Stream::Stderr && first_stderr.is_none() && !logged.trim().is_empty() {
+                first_stderr = Some(logged.trim().chars().take(FIRST_ERROR_CHARS).collect());
+            }
+            let bytes = rendered.len() + 1;
+            if let Some(size) = size_limit.as_mut().filter(|s| s.full(bytes)) {
+                let note = size.reached();
+                match size.action() {
+                    LogSizeAction::Truncate => {}
+                    LogSizeAction::Rotate => rotate(cfg, writers, paths, header, compression)?,
+                    LogSizeAction::Kill => {
+                        let _ = child.start_kill();
+                    }
+                }
+                let stamp = if ts_each { Some(clock.stamp()) } else { None };
+                writers.for_each(|w| write_line(w, "LG", &note, stamp, style))?;
+            }
+            let markers = folds
+                .as_mut()
+                .map(|f| f.observe(stream, &logged))
+                .unwrap_or_default();
+            if size_limit.as_mut().map_or(true, |s| s.take(bytes)) && raw_log.is_none() {
+                let stamp = if ts_each { Some(clock.stamp()) } else { None };
+                let w = writers.for_stream(stream);
+                for marker in &markers.before {
+                    write_line(&mut *w, "FOLD", marker, stamp, style)?;
+                }
+                write_line(&mut *w, stream.label(), &rendered, stamp, style)?;
+                if let Some(marker) = &markers.after {
+                    write_line(w, "FOLD", marker, stamp, style)?;
+                }
+            }
+            if let Some(plugins) = &plugins {
+                plugins.send(&line_record(plugin_clock.stamp(), stream.name(), &logged));
+            }
+            if let Some(classifier) = classifier.as_mut() {
+                classifier.observe(&logged);
+            }
+            if let Some(matchers) = matchers.as_mut() {
+                matchers.observe(&logged);
+            }
+            if let Some(baseline) = baseline.as_mut() {
+                baseline.observe(&logged);
+            }
+            if let Some(summarizer) = summarizer {
+                if excerpt.len() >= summarizer.excerpt_lines {
+                    excerpt.pop_front();
+                }
+                if summarizer.excerpt_lines > 0 {
+                    excerpt.push_back(logged.to_string());
+                }
+            }
+            if let Some(interactor) = interactor.as_mut() {
+                if let Some(note) = interactor.respond(stream, &l, true).await? {
+                    let stamp = if ts_each { Some(clock.stamp()) } else { None };
+                    write_line(writers.for_stream(stream), "EXPECT", &note, stamp, style)?;
+                }
+            }
+        }
+        if let Some(raw) = raw_log.as_mut().filter(|_| logging(&size_limit)) {
+            let (bytes, end) = (reader.fresh(n), n == 0);
+            raw.write(writers, stream, bytes, end, redactor.as_ref(), &mut clock)?;
+        }
+        // Prompts usually wait on an unterminated line, so match the pending tail too.
+        if let Some(interactor) = interactor.as_mut() {
+            let tail = reader.partial();
+            if !tail.is_empty() {
+                if let Some(note) = interactor.respond(stream, &tail, false).await? {
+                    let stamp = if ts_each { Some(clock.stamp()) } else { None };
+                    write_line(writers.for_stream(stream), "EXPECT", &note, stamp, style)?;
+                }
+            }
+        }
+    }
+
+    // A killed command may leave output short of a line end.
+    if let Some(raw) = raw_log.as_mut().filter(|_| logging(&size_limit)) {
+        raw.flush(writers, redactor.as_ref(), &mut clock)?;
+    }
+    if let Some((stream, marker)) = folds.as_mut().and_then(Folds::finish) {
+        let stamp = if ts_each { Some(clock.stamp()) } else { None };
+        write_line(writers.for_stream(stream), "FOLD", &marker, stamp, style)?;
+    }
+    let pid = child.id();
+    // The command may have closed its output and still be running.
+    let status = loop {
+        tokio::select! {
+            status = child.wait() => break status?,
+            signal = forwarder.recv() => {
+                let stamp = if ts_each { Some(clock.stamp()) } else { None };
+                let (received, by) = forward_signal(signal, &child, group, writers, stamp, style)?;
+                signals.push(received);
+                cancelled = by.or(cancelled);
+            }
+            _ = deadline.expired(), if deadline.armed() => {
+                let note = deadline.fire(&mut child, group);
+                let stamp = if ts_each { Some(clock.stamp()) } else { None };
+                writers.for_each(|w| write_line(w, "LG", &note, stamp, style))?;
+            }
+        }
+    };
+    let usage = Usage::collect(spawned_at);
+    let code = if deadline.fired() {
+        TIMEOUT_EXIT_CODE
+    } else {
+        status.code().unwrap_or(1)
+    };
+    let timed_out = deadline.describe();
+    let size_note = size_limit.as_ref().and_then(SizeLimit::describe);
+    #[cfg(target_os = "linux")]
+    let probable_cause = oom_watch.probable_cause(status, pid);
+    #[cfg(not(target_os = "linux"))]
+    let probable_cause: Option<&str> = pid.and(None);
+    if cfg.verbosity >= 2 {
+        eprintln!(
+            "lg: peak line buffer: stdout {}, stderr {}; {} long line(s) split",
+            fmt_size(r_out.peak),
+            fmt_size(r_err.peak),
+            r_out.splits + r_err.splits
+        );
+    }
+    let failure_class = match classifier {
+        Some(classifier) if code != 0 => classifier.class().map(str::to_string),
+        _ => None,
+    };
+    let diagnostics: &[Diagnostic] = matchers.as_ref().map_or(&[], |m| &m.found);
+    let first_error = diagnostics
+        .iter()
+        .find(|d| d.severity == "error")
+        .map(Diagnostic::to_string)
+        .or(first_stderr);
+    let summary = match summarizer {
+        Some(summarizer) if code != 0 => {
+            let failure = FailureContext {
+                cmd: cmd_str,
+                run_id: vars.run_id,
+                code,
+                failure_class: failure_class.as_deref(),
+            };
+            match summarize_failure(summarizer, &failure, excerpt.make_contiguous()).await {
+                Ok(summary) => summary,
+                Err(err) => {
+                    eprintln!("lg: post_failure_summarizer: {:#}", err);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+    let dir = paths[0].parent().unwrap_or(Path::new("."));
+    let artifact = artifact.and_then(|artifact| {
+        let program = program_name(cmd);
+        match artifact.finish(dir, &program, vars.run_id, redactor.as_ref()) {
+            Ok(name) => name,
+            Err(err) => {
+                eprintln!("lg: output_artifact: {:#}", err);
+                None
+            }
+        }
+    });
+    let deviations = baseline.as_ref().map_or_else(Vec::new, |b| {
+        b.deviations(
+            Some(code),
+            Some(usage.duration().as_secs_f64()),
+            diagnostics,
+        )
+    });
+    let mut footer = jsonl::Footer {
+        kind: jsonl::Kind::Footer,
+        exit_code: code,
+        pid,
+        spawn_error: None,
+        failure_class: failure_class.as_deref(),
+        probable_cause,
+        timeout: timed_out.as_deref(),
+        max_log_size: size_note.as_deref(),
+        cancelled: cancelled.as_deref(),
+        compression: compression.get().map(String::as_str),
+        artifact: artifact.as_deref(),
+        usage: Some(&usage),
+        signals: &signals,
+        diagnostics,
+        summary: summary.as_deref(),
+        baseline: &deviations,
+        plugins: &[],
+    };
+    // Plugins get the footer too, before lg notes how they did in it.
+    let plugin_notes = match plugins {
+        Some(plugins) => {
+            plugins.send(&footer);
+            tokio::task::spawn_blocking(move || plugins.finish())
+                .await
+                .unwrap_or_default()
+        }
+        None => Vec::new(),
+    };
+    footer.plugins = &plugin_notes;
+    writers.for_each(|w| {
+        if style == LineStyle::Jsonl {
+            return jsonl::write(w, &footer);
+        }
+        writeln!(
+            w,
+            "
+[exit_code] {}",
+            code
+        )?;
+        if let Some(pid) = pid {
+            writeln!(w, "[pid] {}", pid)?;
+        }
+        if let Some(class) = &failure_class {
+            writeln!(w, "[failure_class] {}", class)?;
+        }
+        if let Some(cause) = probable_cause {
+            writeln!(w, "[probable_cause] {}", cause)?;
+        }
+        if let Some(timeout) = &timed_out {
+            writeln!(w, "[timeout] {}", timeout)?;
+        }
+        if let Some(size) = &size_note {
+            writeln!(w, "[max_log_size] {}", size)?;
+        }
+        if let Some(compression) = compression.get() {
+            writeln!(w, "[compression] {}", compression)?;
+        }
+        if let Some(artifact) = &artifact {
+            writeln!(w, "[artifact] {}", artifact)?;
+        }
+        for (key, value) in usage.footer_lines() {
+            writeln!(w, "[{}] {}", key, value)?;
+        }
+        for received in &signals {
+            writeln!(w, "[signal] {}", received.describe())?;
+        }
+        if let Some(cancelled) = &cancelled {
+            writeln!(w, "[cancelled] {}", cancelled)?;
+        }
+        for note in &plugin_notes {
+            writeln!(w, "[plugin] {}", note)?;
+        }
+        for d in diagnostics {
+            writeln!(w, "[diagnostic] {} {}", d.severity, d)?;
+        }
+        for line in summary.iter().flat_map(|s| s.lines()) {
+            writeln!(w, "[summary] {}", line)?;
+        }
+        for deviation in &deviations {
+            writeln!(w, "[baseline] {}", deviation)?;
+        }
+        Ok(())
+    })?;
+    writers.flush()?;
+    if detached || hung_up(cfg) {
+        // Nothing below may reach the terminal that went away.
+        return Ok(RunOutcome {
+            code,
+            failure_class,
+            usage: Some(usage),
+            pid,
+            overhead,
+            stderr_lines,
+            first_error,
+            artifact: artifact.map(|name| dir.join(name)),
+            report: false,
+            held,
+        });
+    }
+    if let Some(summary) = summary.as_ref().filter(|_| !cfg.quiet()) {
+        eprintln!("lg: summary: {}", summary.replace('\n', "\nlg:          "));
+    }
+    if let Some(baseline) = baseline.filter(|_| !deviations.is_empty() && !cfg.quiet()) {
+        eprintln!(
+            "lg: baseline: differs from run {}: {}",
+            baseline.baseline().run(),
+            deviations.join("; ")
+        );
+    }
+    if cfg.ci_annotations && INVOCATION.kind == Some("github-actions") {
+        for d in diagnostics {
+            println!("{}", github_annotation(d));
+        }
+    }
+
+    Ok(RunOutcome {
+        code,
+        failure_class,
+        usage: Some(usage),
+        pid,
+        overhead,
+        stderr_lines,
+        first_error,
+        artifact: artifact.map(|name| dir.join(name)),
+        report: !(cfg.tees(Stream::Stdout) || cfg.tees(Stream::Stderr)),
+        held,
+    })
+}
+
+/// Result of running the wrapped command.
+pub struct RunOutcome {
+    pub code: i32,
+    /// Name of the matching `[[classify]]` rule, for failed runs only.
+    failure_class: Option<String>,
+    /// None when the command could not be started.
+    pub usage: Option<Usage>,
+    /// The command's process id, for `{pid}`.
+    pub pid: Option<u32>,
+    pub overhead: Overhead,
+    /// Lines the command wrote to stderr (none on a pty, where it shares stdout).
+    stderr_lines: u64,
+    /// The first error diagnostic, else the first line on stderr.
+    first_error: Option<String>,
+    /// The `output_artifact` the run left beside its log.
+    pub artifact: Option<PathBuf>,
+    /// Whether a failure gets a report: none of the output reached the
+    /// terminal, and it is still there.
+    report: bool,
+    /// The output a `cron` run kept off the terminal.
+    held: Option<HeldOutput>,
+}
+
+impl RunOutcome {
+    /// Value of `{failure_class}`: "none" on success, "unknown" if no rule matched.
+    pub fn class_label(&self) -> &str {
+        match &self.failure_class {
+            _ if self.code == 0 => "none",
+            Some(class) => class,
+            None => "unknown",
+        }
+    }
+
+    /// For a failed run whose output wasn't shown, print the output a `cron`
+    /// run held back and then the failure report on stderr; nothing otherwise.
+    pub fn report_failure(&mut self, command: &str, log: &Path) {
+        if self.code == 0 || !self.report {
+            return;
+        }
+        let mut stderr = io::stderr().lock();
+        if let Some(held) = self.held.take() {
+            let _ = held.release(&mut stderr);
+        }
+        let _ = self.write_report(&mut stderr, command, log);
+    }
+
+    // `lg: key: value` lines, always in this order; `failure_class` and
+    // `first_error` only when known.
+    fn write_report(&self, w: &mut dyn Write, command: &str, log: &Path) -> io::Result<()> {
+        writeln!(w, "lg: command: {}", command)?;
+        writeln!(w, "lg: exit_code: {}", self.code)?;
+        if let Some(class) = &self.failure_class {
+            writeln!(w, "lg: failure_class: {}", class)?;
+        }
+        let duration = self.usage.map_or(Duration::ZERO, |u| u.duration());
+        writeln!(w, "lg: duration: {:.3}s", duration.as_secs_f64())?;
+        writeln!(w, "lg: log: {}", log.display())?;
+        if let Some(error) = &self.first_error {
+            writeln!(w, "lg: first_error: {}", error)?;
+        }
+        Ok(())
+    }
+
+    /// Whether `keep` leaves this run's log in place.
+    pub fn kept(&self, keep: Keep) -> bool {
+        match keep {
+            Keep::Always => true,
+            Keep::OnFailure => self.code != 0,
+            Keep::OnErrorOutput => self.code != 0 || self.stderr_lines > 0,
+        }
+    }
+}
+
+/// Splits a child pipe into lines while keeping the unterminated tail
+/// visible, so prompts that don't end in a newline can still be matched.
+/// Lines longer than `max_line` are cut into pieces, so the buffer stays
+/// below `max_line` plus one read.
+struct LineReader {
+    inner: Box<dyn AsyncRead + Unpin + Send>,
+    buf: Vec<u8>,
+    start: usize,
+    eof: bool,
+    max_line: usize,
+    /// Largest buffer size seen, for `-vv` accounting.
+    peak: usize,
+    /// Number of pieces cut from over-long lines.
+    splits: usize,
+}
+
+impl LineReader {
+    fn new(inner: impl AsyncRead + Unpin + Send + 'static, max_line: usize) -> Self {
+        Self {
+            inner: Box::new(inner),
+            buf: Vec::with_capacity(READ_CHUNK),
+            start: 0,
+            eof: false,
+            max_line: max_line.max(MIN_LINE_BYTES),
+            peak: 0,
+            splits: 0,
+        }
+    }
+
+    // Cancel-safe: bytes are only appended once `read` has completed.
+    async fn fill(&mut self) -> io::Result<usize> {
+        if self.start > 0 {
+            self.buf.drain(..self.start);
+            self.start = 0;
+        }
+        let mut chunk = [0u8; READ_CHUNK];
+        let n = self.inner.read(&mut chunk).await?;
+        self.buf.extend_from_slice(&chunk[..n]);
+        self.peak = self.peak.max(self.buf.len());
+        if n == 0 {
+            self.eof = true;
+        }
+        Ok(n)
+    }
+
+    /// The `n` bytes the last `fill` read.
+    fn fresh(&self, n: usize) -> &[u8] {
+        &self.buf[self.buf.len() - n..]
+    }
+
+    fn next_line(&mut self) -> Option<Vec<u8>> {
+        let pending = &self.buf[self.start..];
+        let (mut line, consumed) = match pending.iter().position(|&b| b == b'\n') {
+            Some(pos) if pos <= self.max_line => (&pending[..pos], pos + 1),
+            // Over-long line: emit a piece, cut before a UTF-8 continuation byte
+            _ if pending.len() > self.max_line => {
+                let cut = (1..=self.max_line)
+                    .rev()
+                    .find(|&i| pending[i] & 0xC0 != 0x80)
+                    .unwrap_or(self.max_line);
+                self.splits += 1;
+                (&pending[..cut], cut)
+            }
+            // Last line without a trailing newline
+            None if self.eof && !pending.is_empty() => (pending, pending.len()),
+            _ => return None,
+        };
+        if line.last() == Some(&b'\r') {
+            line = &line[..line.len() - 1];
+        }
+        let line = line.to_vec();
+        self.start += consumed;
+        Some(line)
+    }
+
+    fn partial(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.buf[self.start..])
+    }
+}
+
+// True once SIGHUP arrived in a `survive_hup` run.
+fn hung_up(cfg: &Config) -> bool {
+    #[cfg(unix)]
+    {
+        cfg.survive_hup && hangup::received()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = cfg;
+        false
+    }
+}
+
+// Spawn the command; with `pty` also return the pty master its output arrives on.
+// `log_stdin` gives it a pipe for stdin, which lg feeds.
+fn spawn_child(
+    cfg: &Config,
+    cmd: &OsString,
+    args: &[OsString],
+    run_id: &str,
+    log_stdin: bool,
+    own_group: bool,
+) -> io::Result<(tokio::process::Child, Option<File>)> {
+    let stdin = match &cfg.stdin {
+        // Scripted interaction owns the child's stdin, as lg does when it logs the input.
+        _ if !cfg.interact.rules.is_empty() || log_stdin => Stdio::piped(),
+        StdinSource::Mode(StdinMode::Inherit) => Stdio::inherit(),
+        StdinSource::Mode(StdinMode::Null) => Stdio::null(),
+        StdinSource::File { file } => File::open(file)
+            .map(Stdio::from)
+            .map_err(|err| io::Error::new(err.kind(), format!("stdin file {:?}: {}", file, err)))?,
+    };
+    let mut command = Command::new(cmd);
+    command.args(args).stdin(stdin).env("LG_RUN_ID", run_id);
+    #[cfg(unix)]
+    process_env::apply(&mut command, cfg)?;
+    #[cfg(unix)]
+    if cfg.survive_hup {
+        hangup::ignore_in_child(&mut command);
+    }
+    #[cfg(unix)]
+    if own_group {
+        command.process_group(0);
+    }
+    #[cfg(unix)]
+    if cfg.pty {
+        let pty = pty::Pty::open()?;
+        command.stdout(pty.slave()?).stderr(pty.slave()?);
+        let child = command.spawn()?;
+        // Our copies of the slave close here, so the master sees EOF with the child.
+        return Ok((child, Some(pty.master)));
+    }
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    command.spawn().map(|child| (child, None))
+}
+
+// Turn the tee of `stream` off once writing to it failed (a closed pipe, as
+// with `lg seq 1 1000000 | head`, or a terminal that is gone), as a hangup
+// does: the output is still logged. Returns the `[LG]` line to log.
+fn stop_tee(
+    stream: Stream,
+    err: &io::Error,
+    tee_out: &mut bool,
+    tee_err: &mut bool,
+    raw_tee: &mut bool,
+) -> String {
+    match stream {
+        Stream::Stdout => (*tee_out, *raw_tee) = (false, false),
+        Stream::Stderr => *tee_err = false,
+    }
+    format!("{} tee off: {}; still logging", stream.name(), err)
+}
+
+// Pass a signal lg caught on to the command and note it in the log. One that
+// `lg kill` sent also returns the `[cancelled]` value, e.g. "SIGTERM by alice".
+fn forward_signal(
+    signal: i32,
+    child: &tokio::process::Child,
+    group: Option<u32>,
+    writers: &mut LogWriters,
+    stamp: Option<&str>,
+    style: LineStyle,
+) -> Result<(Received, Option<String>)> {
+    let received = signals::deliver(child, group, signal);
+    writers.for_each(|w| write_line(w, "LG", received.note(), stamp, style))?;
+    let mut cancelled = None;
+    if let Some(by) = RunningEntry::take_kill_request() {
+        let note = format!("cancelled by {}", by);
+        writers.for_each(|w| write_line(w, "LG", &note, stamp, style))?;
+        cancelled = Some(format!("{} by {}", signals::name(signal), by));
+    }
+    Ok((received, cancelled))
+}
+
+// Mirror the shell's conventions: 127 for "not found", 126 for "not executable".
+fn spawn_failure_code(err: &io::Error) -> i32 {
+    match err.kind() {
+        io::ErrorKind::NotFound => 127,
+        io::ErrorKind::PermissionDenied => 126,
+        _ => 1,
+    }
+}
+
+/// Per-line timestamp source that avoids a wall-clock lookup and a chrono
+/// format call for every line.
+///
+/// The wall clock is read once and then advanced with a monotonic offset,
+/// re-synchronising every `LINE_CLOCK_RESYNC`. The formatted string is cached
+/// and only re-rendered when the offset enters a new `resolution` bucket.
+struct LineClock {
+    format: &'static str,
+    base_wall: DateTime<Local>,
+    base_mono: Instant,
+    resolution_ms: u64,
+    bucket: Option<u64>,
+    cached: String,
+}
+
+impl LineClock {
+    fn new(resolution_ms: u64, format: &'static str) -> Self {
+        Self {
+            format,
+            base_wall: Local::now(),
+            base_mono: Instant::now(),
+            resolution_ms: resolution_ms.max(1),
+            bucket: None,
+            cached: String::new(),
+        }
+    }
+
+    fn stamp(&mut self) -> &str {
+        let mut elapsed = self.base_mono.elapsed();
+        if elapsed >= LINE_CLOCK_RESYNC {
+            self.base_wall = Local::now();
+            self.base_mono = Instant::now();
+            self.bucket = None;
+            elapsed = Duration::ZERO;
+        }
+        let bucket = elapsed.as_millis() as u64 / self.resolution_ms;
+        if self.bucket != Some(bucket) {
+            let offset = chrono::Duration::milliseconds((bucket * self.resolution_ms) as i64);
+            self.cached = (self.base_wall + offset).format(self.format).to_string();
+            self.bucket = Some(bucket);
+        }
+        &self.cached
+    }
+}
+
+/// How output lines are written: `[ts][STDOUT] line`, bare, or as JSON records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineStyle {
+    Marked,
+    Plain,
+    Jsonl,
+}
+
+impl LineStyle {
+    fn of(cfg: &Config) -> Self {
+        match cfg.format {
+            LogFormat::Jsonl => LineStyle::Jsonl,
+            LogFormat::Text if cfg.plain_lines => LineStyle::Plain,
+            LogFormat::Text => LineStyle::Marked,
+        }
+    }
+}
+
+fn write_line<W: Write>(
+    mut w: W,
+    stream: &str,
+    line: impl AsRef<[u8]>,
+    stamp: Option<&str>,
+    style: LineStyle,
+) -> Result<()> {
+    let line = line.as_ref();
+    match style {
+        LineStyle::Plain => {}
+        LineStyle::Jsonl => {
+            let record = jsonl::Line {
+                ts: stamp,
+                stream: &stream.to_ascii_lowercase(),
+                line: &String::from_utf8_lossy(line),
+            };
+            return jsonl::write(&mut w, &record);
+        }
+        LineStyle::Marked => match stamp {
+            Some(ts) => write!(w, "[{}][{}] ", ts, stream)?,
+            None => write!(w, "[{}] ", stream)?,
+        },
+    }
+    w.write_all(line)?;
+    w.write_all(b"\n")?;
+    Ok(())
+}
+
+// `line` as `invalid_utf8` renders it; valid UTF-8 always comes back as is.
+fn render_invalid(line: &[u8], mode: InvalidUtf8) -> Cow<'_, [u8]> {
+    if mode == InvalidUtf8::Verbatim || std::str::from_utf8(line).is_ok() {
+        return Cow::Borrowed(line);
+    }
+    if mode == InvalidUtf8::Replace {
+        return Cow::Owned(String::from_utf8_lossy(line).into_owned().into_bytes());
+    }
+    let mut out = Vec::with_capacity(line.len() + 16);
+    let mut rest = line;
+    while !rest.is_empty() {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                out.extend_from_slice(valid.as_bytes());
+                break;
+            }
+            Err(err) => {
+                let (valid, after) = rest.split_at(err.valid_up_to());
+                out.extend_from_slice(valid);
+                let bad = err.error_len().unwrap_or(after.len());
+                for b in &after[..bad] {
+                    out.extend_from_slice(format!("\\x{:02x}", b).as_bytes());
+                }
+                rest = &after[bad..];
+            }
+        }
+    }
+    Cow::Owned(out)
+}
+
+// Output for the terminal, in `sgr` colors when set; the colors end before a
+// line break so they don't bleed into whatever prints next.
+fn tee(w: &mut dyn Write, output: &[u8], sgr: Option<&str>) -> io::Result<()> {
+    let Some(sgr) = sgr else {
+        return w.write_all(output);
+    };
+    let end = output.len() - output.iter().rev().take_while(|&&b| b == b'\n').count();
+    write!(w, "\x1b[{}m", sgr)?;
+    w.write_all(&output[..end])?;
+    w.write_all(b"\x1b[0m")?;
+    w.write_all(&output[end..])
+}
+
+// Whether output still goes into the log; `max_log_size` may have stopped it.
+fn logging(size_limit: &Option<SizeLimit>) -> bool {
+    size_limit.as_ref().map_or(true, SizeLimit::logging)
+}
+
+// Periodic flush so buffered lines still reach the file during quiet periods.
+fn flush_timer(interval_ms: u64) -> tokio::time::Interval {
+    let period = Duration::from_millis(interval_ms.max(1));
+    let mut tick = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    tick
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn byte_lines(input: &'static [u8], max_line: usize) -> Vec<Vec<u8>> {
+        let mut reader = LineReader::new(input, max_line);
+        let mut out = Vec::new();
+        while reader.fill().await.unwrap() > 0 {
+            out.extend(std::iter::from_fn(|| reader.next_line()));
+        }
+        out.extend(std::iter::from_fn(|| reader.next_line()));
+        out
+    }
+
+    async fn lines(input: &'static [u8], max_line: usize) -> Vec<String> {
+        let lines = byte_lines(input, max_line).await;
+        lines
+            .into_iter()
+            .map(|l| String::from_utf8(l).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn reads_lines() {
+        assert_eq!(lines(b"a\r\nb\n\nc", 4096).await, ["a", "b", "", "c"]);
+    }
+
+    #[tokio::test]
+    async fn invalid_utf8_is_kept_and_rendered_as_configured() {
+        let got = byte_lines(b"caf\xe9 ok\n\xff\n", 4096).await;
+        assert_eq!(got, [&b"caf\xe9 ok"[..], b"\xff"]);
+        let line = &got[0][..];
+        assert_eq!(render_invalid(line, InvalidUtf8::Verbatim), line);
+        assert_eq!(
+            render_invalid(line, InvalidUtf8::Replace),
+            "caf\u{fffd} ok".as_bytes()
+        );
+        assert_eq!(
+            render_invalid(line, InvalidUtf8::Escape),
+            &b"caf\\xe9 ok"[..]
+        );
+        assert_eq!(
+            render_invalid(b"\xe2\x82", InvalidUtf8::Escape),
+            &b"\\xe2\\x82"[..]
+        );
+        assert!(matches!(
+            render_invalid("é".as_bytes(), InvalidUtf8::Escape),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn splits_long_lines_on_char_boundaries() {
+        let long = "é".repeat(MIN_LINE_BYTES);
+        let input: &'static [u8] = Box::leak(format!("{}\nok\n", long).into_bytes().into());
+        let got = lines(input, MIN_LINE_BYTES).await;
+        assert_eq!(got.concat(), format!("{}ok", long));
+        assert!(got.len() > 2);
+        assert!(got.iter().all(|l| l.len() <= MIN_LINE_BYTES));
+    }
+
+    #[test]
+    fn keep_decides_on_exit_code_and_stderr() {
+        let outcome = |code, stderr_lines| RunOutcome {
+            code,
+            failure_class: None,
+            usage: None,
+            pid: None,
+            overhead: Overhead::default(),
+            stderr_lines,
+            first_error: None,
+            artifact: None,
+            report: true,
+            held: None,
+        };
+        assert!(outcome(0, 0).kept(Keep::Always));
+        assert!(!outcome(0, 3).kept(Keep::OnFailure));
+        assert!(outcome(2, 0).kept(Keep::OnFailure));
+        assert!(!outcome(0, 0).kept(Keep::OnErrorOutput));
+        assert!(outcome(0, 1).kept(Keep::OnErrorOutput));
+        assert!(outcome(1, 0).kept(Keep::OnErrorOutput));
+    }
+
+    #[test]
+    fn failure_report_keys_come_in_a_fixed_order() {
+        let mut outcome = RunOutcome {
+            code: 2,
+            failure_class: Some("compile-error".into()),
+            usage: Some(Usage {
+                duration_ms: 3214,
+                user_cpu_ms: None,
+                sys_cpu_ms: None,
+                max_rss_bytes: None,
+            }),
+            pid: Some(4242),
+            overhead: Overhead::default(),
+            stderr_lines: 1,
+            first_error: Some("src/main.rs:3:5: cannot find value `x`".into()),
+            artifact: None,
+            report: true,
+            held: None,
+        };
+        let mut out = Vec::new();
+        let log = Path::new("/var/log/lg/make.log");
+        outcome.write_report(&mut out, "make -j4", log).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "lg: command: make -j4\n\
+             lg: exit_code: 2\n\
+             lg: failure_class: compile-error\n\
+             lg: duration: 3.214s\n\
+             lg: log: /var/log/lg/make.log\n\
+             lg: first_error: src/main.rs:3:5: cannot find value `x`\n"
+        );
+        (outcome.failure_class, outcome.first_error) = (None, None);
+        let mut out = Vec::new();
+        outcome.write_report(&mut out, "make", log).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 4);
+    }
+
+    #[test]
+    fn teed_stderr_is_colored_up_to_the_line_break() {
+        let mut out = Vec::new();
+        tee(&mut out, b"error: boom\n", Some(DIM_RED)).unwrap();
+        tee(&mut out, b"50%\r", Some(DIM_RED)).unwrap();
+        tee(&mut out, b"plain\n", None).unwrap();
+        assert_eq!(
+            out,
+            b"\x1b[2;31merror: boom\x1b[0m\n\x1b[2;31m50%\r\x1b[0mplain\n"
+        );
+    }
+
+    #[test]
+    fn line_prefixes() {
+        let mut out = Vec::new();
+        write_line(
+            &mut out,
+            "STDERR",
+            "boom",
+            Some("12:00:00.000"),
+            LineStyle::Marked,
+        )
+        .unwrap();
+        write_line(&mut out, "STDOUT", "ok", None, LineStyle::Marked).unwrap();
+        write_line(&mut out, "STDOUT", "raw", Some("ignored"), LineStyle::Plain).unwrap();
+        write_line(
+            &mut out,
+            "EXPECT",
+            "y",
+            Some("12:00:01.000"),
+            LineStyle::Jsonl,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "[12:00:00.000][STDERR] boom\n[STDOUT] ok\nraw\n\
+             {\"ts\":\"12:00:01.000\",\"stream\":\"expect\",\"line\":\"y\"}\n"
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! `output_artifact = "auto"`: when a run's whole stdout is one JSON or YAML
+//! document, such as the payload of an API call, lg also stores it
+//! pretty-printed beside the log, for jq and friends. The log is written as
+//! always; its footer names the artifact (`[artifact]`).
+
+use crate::config::{Config, OutputArtifact};
+use crate::redact::Redactor;
+use crate::template::sanitize_component;
+use anyhow::{Context, Result};
+use serde::de::IgnoredAny;
+use std::fs;
+use std::path::Path;
+
+/// Stdout beyond this is not kept, and the run gets no artifact.
+const MAX_ARTIFACT_BYTES: usize = 16 << 20;
+
+/// The stdout of a run, as the command wrote it.
+pub struct ArtifactCapture {
+    stdout: Vec<u8>,
+    overflowed: bool,
+}
+
+impl ArtifactCapture {
+    pub fn new(cfg: &Config) -> Option<Self> {
+        (cfg.output_artifact == OutputArtifact::Auto).then(|| Self {
+            stdout: Vec::new(),
+            overflowed: false,
+        })
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        if self.overflowed {
+            return;
+        }
+        if self.stdout.len() + bytes.len() > MAX_ARTIFACT_BYTES {
+            self.overflowed = true;
+            self.stdout = Vec::new();
+            return;
+        }
+        self.stdout.extend_from_slice(bytes);
+    }
+
+    /// Write stdout to `dir` as `<program>_<run id>.json` (or `.yaml`) if it
+    /// was one document, masked like the log; returns the file's name.
+    pub fn finish(
+        self,
+        dir: &Path,
+        program: &str,
+        run_id: &str,
+        redactor: Option<&Redactor>,
+    ) -> Result<Option<String>> {
+        if self.overflowed {
+            return Ok(None);
+        }
+        let Ok(text) = String::from_utf8(self.stdout) else {
+            return Ok(None);
+        };
+        let text = match redactor {
+            Some(redactor) => redactor.redact_quoted(&text).into_owned(),
+            None => text,
+        };
+        let Some((ext, document)) = document(&text) else {
+            return Ok(None);
+        };
+        let name = format!("{}_{}.{}", sanitize_component(program), run_id, ext);
+        let path = dir.join(&name);
+        fs::write(&path, document).with_context(|| format!("writing {:?}", path))?;
+        Ok(Some(name))
+    }
+}
+
+// The extension and pretty-printed form of `text` when it is a single JSON or
+// YAML object or array.
+fn document(text: &str) -> Option<(&'static str, String)> {
+    let text = text.trim();
+    if text.starts_with(['{', '[']) && serde_json::from_str::<IgnoredAny>(text).is_ok() {
+        return Some(("json", pretty_json(text)));
+    }
+    // Nearly any text parses as YAML; a single line ("error: not found") is
+    // not taken for a document.
+    if !text.starts_with("---") && !text.contains('\n') {
+        return None;
+    }
+    match serde_yaml::from_str(text) {
+        Ok(value @ (serde_yaml::Value::Mapping(_) | serde_yaml::Value::Sequence(_))) => {
+            serde_yaml::to_string(&value)
+                .ok()
+                .map(|yaml| ("yaml", yaml))
+        }
+        _ => None,
+    }
+}
+
+// Valid JSON indented by two spaces. Keys keep their order, and numbers and
+// strings stay exactly as written, which a round trip through
+// `serde_json::Value` wouldn't guarantee.
+fn pretty_json(json: &str) -> String {
+    let mut out = String::with_capacity(json.len() * 2);
+    let newline = |out: &mut String, depth: usize| {
+        out.push('\n');
+        out.extend(std::iter::repeat("  ").take(depth));
+    };
+    let (mut depth, mut in_string, mut escaped) = (0, false, false);
+    let mut chars = json.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                out.push(c);
+            }
+            '{' | '[' => {
+                out.push(c);
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                // An empty object or array stays on its line.
+                match chars.next_if(|c| matches!(c, '}' | ']')) {
+                    Some(close) => out.push(close),
+                    None => {
+                        depth += 1;
+                        newline(&mut out, depth);
+                    }
+                }
+            }
+            '}' | ']' => {
+                depth -= 1;
+                newline(&mut out, depth);
+                out.push(c);
+            }
+            ',' => {
+                out.push(c);
+                newline(&mut out, depth);
+            }
+            ':' => out.push_str(": "),
+            c if c.is_whitespace() => {}
+            c => out.push(c),
+        }
+    }
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_documents_become_artifacts() {
+        let payload =
+            r#"{"id":7,"tags":[],"price":1.50,"note":"a, \"b\": {c}","a":[1,{"x":null}]}"#;
+        let (ext, json) = document(&format!("{}\n", payload)).unwrap();
+        assert_eq!(ext, "json");
+        assert_eq!(
+            json,
+            "{\n  \"id\": 7,\n  \"tags\": [],\n  \"price\": 1.50,\n  \"note\": \"a, \\\"b\\\": {c}\",\n  \
+             \"a\": [\n    1,\n    {\n      \"x\": null\n    }\n  ]\n}\n"
+        );
+        let (ext, yaml) = document("kind: Pod\nmetadata:\n    name: web\n").unwrap();
+        assert_eq!(
+            (ext, yaml.as_str()),
+            ("yaml", "kind: Pod\nmetadata:\n  name: web\n")
+        );
+
+        // Not one document: JSON Lines, plain output, a lone "key: value" line.
+        assert_eq!(document("{\"a\":1}\n{\"a\":2}\n"), None);
+        assert_eq!(document("Compiling lg\nFinished in 3.2s\n"), None);
+        assert_eq!(document("error: not found\n"), None);
+        assert_eq!(document("\"just a string\"\n"), None);
+        assert_eq!(document(""), None);
+    }
+
+    #[test]
+    fn output_past_the_limit_gives_no_artifact() {
+        let mut capture = ArtifactCapture {
+            stdout: Vec::new(),
+            overflowed: false,
+        };
+        capture.push(b"[");
+        capture.push(&vec![b' '; MAX_ARTIFACT_BYTES]);
+        capture.push(b"]");
+        let dir = std::env::temp_dir();
+        assert_eq!(capture.finish(&dir, "curl", "01M5", None).unwrap(), None);
+    }
+}
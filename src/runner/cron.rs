@@ -0,0 +1,66 @@
+//! `cron`: keep the command's output off the terminal and show it, with the
+//! log path, only when the run fails, like moreutils' `chronic`.
+
+use crate::state::temp_path;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+/// Output held back during a `cron` run, spooled to a temporary file so a
+/// chatty command doesn't grow lg's memory.
+pub struct HeldOutput {
+    file: BufWriter<File>,
+    path: PathBuf,
+}
+
+impl HeldOutput {
+    pub fn new() -> io::Result<Self> {
+        let path = temp_path(&std::env::temp_dir().join("lg-cron"));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Self {
+            file: BufWriter::new(file),
+            path,
+        })
+    }
+
+    pub fn hold(&mut self, line: &[u8]) -> io::Result<()> {
+        self.file.write_all(line)?;
+        self.file.write_all(b"\n")
+    }
+
+    /// Copy everything held, in the order it came, to `out`.
+    pub fn release(mut self, out: &mut dyn Write) -> io::Result<()> {
+        self.file.flush()?;
+        let file = self.file.get_mut();
+        file.seek(SeekFrom::Start(0))?;
+        io::copy(file, out)?;
+        Ok(())
+    }
+}
+
+impl Drop for HeldOutput {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn held_lines_come_back_in_order() {
+        let mut held = HeldOutput::new().unwrap();
+        let path = held.path.clone();
+        held.hold(b"compiling").unwrap();
+        held.hold(b"error: \xffboom").unwrap();
+        let mut out = Vec::new();
+        held.release(&mut out).unwrap();
+        assert_eq!(out, b"compiling\nerror: \xffboom\n");
+        assert!(!path.exists());
+    }
+}
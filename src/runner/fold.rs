@@ -0,0 +1,174 @@
+//! `[[fold]]` rules: known-noisy stretches of output (download lists, the
+//! tests of passing suites) are logged between `[FOLD] start NAME` and
+//! `[FOLD] end NAME (N lines)` markers, which `lg show --folded` and HTML
+//! reports collapse. The lines themselves are logged as always.
+
+use crate::config::FoldRule;
+use crate::sinks::Stream;
+use anyhow::{Context, Result};
+use regex::Regex;
+
+struct CompiledFold {
+    name: String,
+    start: Regex,
+    end: Option<Regex>,
+}
+
+// The fold being written, on the stream it started on.
+struct OpenFold {
+    rule: usize,
+    stream: Stream,
+    lines: u64,
+}
+
+/// Marker lines to log around an output line.
+#[derive(Debug, Default, PartialEq)]
+pub struct FoldMarkers {
+    pub before: Vec<String>,
+    pub after: Option<String>,
+}
+
+/// Tracks which fold, if any, the output is in. One fold is open at a time,
+/// so the markers nest in a log that has both streams.
+pub struct Folds {
+    rules: Vec<CompiledFold>,
+    open: Option<OpenFold>,
+}
+
+impl Folds {
+    pub fn new(rules: &[FoldRule]) -> Result<Option<Self>> {
+        if rules.is_empty() {
+            return Ok(None);
+        }
+        let mut compiled = Vec::new();
+        for rule in rules {
+            let invalid = || format!("invalid [[fold]] {:?}", rule.name);
+            compiled.push(CompiledFold {
+                name: rule.name.clone(),
+                start: Regex::new(&rule.start).with_context(invalid)?,
+                end: rule
+                    .end
+                    .as_deref()
+                    .map(Regex::new)
+                    .transpose()
+                    .with_context(invalid)?,
+            });
+        }
+        Ok(Some(Self {
+            rules: compiled,
+            open: None,
+        }))
+    }
+
+    /// The markers that go before and after `line` of `stream`.
+    pub fn observe(&mut self, stream: Stream, line: &str) -> FoldMarkers {
+        let mut markers = FoldMarkers::default();
+        if let Some(open) = self.open.as_mut().filter(|o| o.stream == stream) {
+            let rule = &self.rules[open.rule];
+            match &rule.end {
+                Some(end) => {
+                    open.lines += 1;
+                    if end.is_match(line) {
+                        markers.after = self.close();
+                    }
+                    return markers;
+                }
+                // Without `end`, the fold is the lines that match `start`.
+                None if rule.start.is_match(line) => {
+                    open.lines += 1;
+                    return markers;
+                }
+                None => markers.before.extend(self.close()),
+            }
+        }
+        if self.open.is_some() {
+            return markers;
+        }
+        if let Some(rule) = self.rules.iter().position(|r| r.start.is_match(line)) {
+            markers
+                .before
+                .push(format!("start {}", self.rules[rule].name));
+            self.open = Some(OpenFold {
+                rule,
+                stream,
+                lines: 1,
+            });
+        }
+        markers
+    }
+
+    /// The end marker of a fold still open when the output ends, and the
+    /// stream it goes to.
+    pub fn finish(&mut self) -> Option<(Stream, String)> {
+        let stream = self.open.as_ref()?.stream;
+        Some((stream, self.close()?))
+    }
+
+    fn close(&mut self) -> Option<String> {
+        let open = self.open.take()?;
+        let unit = if open.lines == 1 { "line" } else { "lines" };
+        Some(format!(
+            "end {} ({} {})",
+            self.rules[open.rule].name, open.lines, unit
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folds() -> Folds {
+        let rule = |name: &str, start: &str, end: Option<&str>| FoldRule {
+            name: name.into(),
+            start: start.into(),
+            end: end.map(Into::into),
+        };
+        Folds::new(&[
+            rule("downloads", "^\\s+Downloaded ", None),
+            rule("tests", "^running \\d+ tests", Some("^test result: ok")),
+        ])
+        .unwrap()
+        .unwrap()
+    }
+
+    #[test]
+    fn markers_surround_the_folded_lines() {
+        let mut folds = folds();
+        let mut log = Vec::new();
+        let output = [
+            (Stream::Stdout, "  Downloaded serde v1.0"),
+            (Stream::Stdout, "  Downloaded regex v1.10"),
+            (Stream::Stdout, "running 2 tests"),
+            (Stream::Stderr, "warning: unused variable"),
+            (Stream::Stdout, "test a ... ok"),
+            (Stream::Stdout, "test result: ok. 2 passed"),
+            (Stream::Stdout, "  Downloaded libc v0.2"),
+        ];
+        for (stream, line) in output {
+            let markers = folds.observe(stream, line);
+            log.extend(markers.before.into_iter().map(|m| format!("[FOLD] {}", m)));
+            log.push(line.to_string());
+            log.extend(markers.after.map(|m| format!("[FOLD] {}", m)));
+        }
+        log.extend(folds.finish().map(|(_, m)| format!("[FOLD] {}", m)));
+        assert_eq!(
+            log,
+            [
+                "[FOLD] start downloads",
+                "  Downloaded serde v1.0",
+                "  Downloaded regex v1.10",
+                "[FOLD] end downloads (2 lines)",
+                "[FOLD] start tests",
+                "running 2 tests",
+                "warning: unused variable",
+                "test a ... ok",
+                "test result: ok. 2 passed",
+                "[FOLD] end tests (3 lines)",
+                "[FOLD] start downloads",
+                "  Downloaded libc v0.2",
+                "[FOLD] end downloads (1 line)",
+            ]
+        );
+    }
+}
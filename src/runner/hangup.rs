@@ -0,0 +1,72 @@
+//! `survive_hup`: keep the command running and logging after the terminal hangs up.
+
+use std::io;
+use std::os::fd::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::process::Command;
+
+static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_hup(_: libc::c_int) {
+    RECEIVED.store(true, Ordering::Relaxed);
+}
+
+/// Catch SIGHUP instead of dying from it.
+pub fn install() -> io::Result<()> {
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe.
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_hup as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(libc::SIGHUP, &action, std::ptr::null_mut()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+pub fn received() -> bool {
+    RECEIVED.load(Ordering::Relaxed)
+}
+
+/// Start the command with SIGHUP ignored, as `nohup` does. Unlike a handler,
+/// the ignored disposition survives exec.
+pub fn ignore_in_child(command: &mut Command) {
+    // SAFETY: signal() is async-signal-safe.
+    unsafe {
+        command.pre_exec(|| {
+            libc::signal(libc::SIGHUP, libc::SIG_IGN);
+            Ok(())
+        });
+    }
+}
+
+/// Point lg's stdin, stdout and stderr at /dev/null, so nothing more is
+/// written to (or read from) the terminal that went away.
+pub fn detach() -> io::Result<()> {
+    let null = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")?;
+    for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        // SAFETY: dup2 onto the standard descriptors; `null` stays open until it returns.
+        if unsafe { libc::dup2(null.as_raw_fd(), fd) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sighup_is_caught() {
+        install().unwrap();
+        // SAFETY: raise only delivers the signal to this thread; the handler is installed.
+        assert_eq!(unsafe { libc::raise(libc::SIGHUP) }, 0);
+        assert!(received());
+    }
+}
@@ -0,0 +1,385 @@
+//! The log header: command, invocation context and environment.
+
+#[cfg(unix)]
+use super::process_env;
+use super::stdin_log;
+use crate::config::{Config, HeaderMode, LimitValue, LogFormat, StdinMode, StdinSource};
+use crate::context::{HOSTNAME, INVOCATION, TTY};
+use crate::jsonl;
+use crate::redact::{Redactor, MASK};
+use crate::state::load_env_baseline;
+use crate::template::{glob_match, render_header, TemplateVars};
+use anyhow::Result;
+use chrono::Local;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+
+pub fn write_header<W: Write>(
+    mut w: W,
+    cfg: &Config,
+    vars: &TemplateVars,
+    cwd: &Path,
+) -> Result<()> {
+    let (cmd, args) = (vars.cmd, vars.args);
+    let full = match cfg.header {
+        HeaderMode::None => return Ok(()),
+        mode => mode == HeaderMode::Full,
+    };
+    let now = Local::now();
+    let scheduled = cfg.scheduled_at().ok().flatten();
+    // Seconds the run started after it was scheduled; negative when early.
+    let start_delay = scheduled.map(|at| (now - at).num_milliseconds() as f64 / 1000.0);
+    if cfg.format == LogFormat::Jsonl {
+        let header = jsonl::Header {
+            kind: jsonl::Kind::Header,
+            ts: &now.format(jsonl::TS_FORMAT).to_string(),
+            run_id: vars.run_id,
+            parent_run_id: vars.parent_run_id,
+            cmd,
+            args,
+            cwd: &cwd.to_string_lossy(),
+            git: vars.git,
+            host: &HOSTNAME,
+            group: cfg.group.as_deref(),
+            scheduled: scheduled.map(|at| at.format(jsonl::TS_FORMAT).to_string()),
+            start_delay_secs: start_delay,
+            fingerprint: vars.fingerprint.filter(|_| full),
+            context: INVOCATION.describe().filter(|_| full),
+            tty: full.then(|| TTY.to_string()),
+            pty: full && cfg.pty && cfg!(unix),
+        };
+        return jsonl::write(&mut w, &header);
+    }
+    if let Some(tpl) = &cfg.header_template {
+        let header = render_header(tpl, vars);
+        if !header.is_empty() {
+            writeln!(w, "{}", header.trim_end_matches('\n'))?;
+        }
+        return Ok(());
+    }
+    writeln!(w, "# lg log")?;
+    writeln!(w, "cmd: {}", cmd)?;
+    if !args.is_empty() {
+        writeln!(w, "args: {}", args)?;
+    }
+    writeln!(w, "date: {} {}", vars.date, vars.time)?;
+    writeln!(w, "run_id: {}", vars.run_id)?;
+    if let Some(parent) = vars.parent_run_id {
+        writeln!(w, "parent_run_id: {}", parent)?;
+    }
+    writeln!(w, "cwd: {}", cwd.display())?;
+    if let Some(git) = vars.git {
+        writeln!(w, "git: {}", git.describe())?;
+    }
+    if let Some(group) = &cfg.group {
+        writeln!(w, "group: {}", group)?;
+    }
+    if let (Some(at), Some(delay)) = (scheduled, start_delay) {
+        let at = at.format("%Y-%m-%d %H:%M:%S");
+        writeln!(w, "scheduled: {} (start delay {:.3}s)", at, delay)?;
+    }
+    if !full {
+        writeln!(w, "----- BEGIN OUTPUT -----")?;
+        return Ok(());
+    }
+    writeln!(w, "host: {}", *HOSTNAME)?;
+    if let Some(fingerprint) = vars.fingerprint {
+        writeln!(w, "fingerprint: {}", fingerprint)?;
+    }
+    if let Some(context) = INVOCATION.describe() {
+        writeln!(w, "context: {}", context)?;
+    }
+    // Tells a reader why output has no color codes: most tools drop them
+    // when their stdout is not a terminal, which it only is with `pty`.
+    writeln!(w, "tty: {}", *TTY)?;
+    if cfg.pty && cfg!(unix) {
+        writeln!(w, "pty: yes (stderr logged as stdout)")?;
+    }
+    if cfg.cron {
+        writeln!(w, "tee: off (cron: output shown on failure)")?;
+    } else if cfg.quiet() {
+        writeln!(w, "tee: off (stdout redirected)")?;
+    }
+    let logged = match stdin_log::logged(cfg) {
+        true => " (logged as [STDIN] lines)",
+        false => "",
+    };
+    match &cfg.stdin {
+        StdinSource::Mode(StdinMode::Inherit) if logged.is_empty() => {}
+        StdinSource::Mode(StdinMode::Inherit) => writeln!(w, "stdin: pipe{}", logged)?,
+        StdinSource::Mode(StdinMode::Null) => writeln!(w, "stdin: null")?,
+        StdinSource::File { file } => writeln!(w, "stdin: file {}{}", file.display(), logged)?,
+    }
+    if !cfg.interact.rules.is_empty() {
+        writeln!(w, "interact: {} rule(s)", cfg.interact.rules.len())?;
+    }
+    if cfg.log_process_env {
+        write_process_env(&mut w, cfg)?;
+    }
+    if cfg.log_env {
+        write_env(&mut w, cfg)?;
+    }
+    writeln!(w, "----- BEGIN OUTPUT -----")?;
+    Ok(())
+}
+
+// Environment for the header: everything, or with `env_diff` only what differs from
+// the baseline captured by `lg env-baseline`.
+fn write_env<W: Write>(mut w: W, cfg: &Config) -> Result<()> {
+    let baseline = if cfg.env_diff {
+        match load_env_baseline() {
+            Ok(baseline) => Some(baseline),
+            Err(_) => {
+                writeln!(
+                    w,
+                    "env_baseline: missing (run `lg env-baseline`), logging full environment"
+                )?;
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let filter = EnvFilter {
+        cfg,
+        redactor: Redactor::new(&cfg.redact)?,
+    };
+    let Some(baseline) = baseline else {
+        for (k, v) in std::env::vars() {
+            if let Some(v) = filter.value(&k, &v) {
+                writeln!(w, "env[{}]={}", k, v)?;
+            }
+        }
+        return Ok(());
+    };
+    writeln!(w, "env_baseline: {}", baseline.captured)?;
+    let current: BTreeMap<String, String> = std::env::vars().collect();
+    for (k, v) in &current {
+        if baseline.vars.get(k) != Some(v) {
+            if let Some(v) = filter.value(k, v) {
+                writeln!(w, "env[{}]={}", k, v)?;
+            }
+        }
+    }
+    for k in baseline.vars.keys() {
+        if !current.contains_key(k) && filter.listed(k).is_some() {
+            writeln!(w, "env[{}] (unset)", k)?;
+        }
+    }
+    Ok(())
+}
+
+/// Which variables `env_include`/`env_exclude` let into the header, and how.
+struct EnvFilter<'a> {
+    cfg: &'a Config,
+    redactor: Option<Redactor>,
+}
+
+impl EnvFilter<'_> {
+    // Some(true) to record the value, Some(false) to record it masked, None to leave it out.
+    fn listed(&self, name: &str) -> Option<bool> {
+        let matches = |globs: &[String]| globs.iter().any(|g| glob_match(g, name));
+        if !self.cfg.env_include.is_empty() && !matches(&self.cfg.env_include) {
+            return None;
+        }
+        if !matches(&self.cfg.env_exclude) {
+            Some(true)
+        } else {
+            self.cfg.env_mask_excluded.then_some(false)
+        }
+    }
+
+    fn value<'v>(&self, name: &str, value: &'v str) -> Option<Cow<'v, str>> {
+        Some(match (self.listed(name)?, &self.redactor) {
+            (false, _) => Cow::Borrowed(MASK),
+            (true, Some(redactor)) => redactor.env_value(name, value),
+            (true, None) => Cow::Borrowed(value),
+        })
+    }
+}
+
+// Snapshot of umask, resource limits and locale as inherited by lg, plus what
+// `umask`/`[limits]` will change for the child.
+fn write_process_env<W: Write>(mut w: W, cfg: &Config) -> Result<()> {
+    #[cfg(unix)]
+    {
+        writeln!(w, "umask: {:04o}", process_env::current_umask())?;
+        let limits: Vec<String> = process_env::RESOURCES
+            .iter()
+            .filter_map(|&(name, res)| {
+                let (soft, hard) = process_env::get_limit(res)?;
+                Some(format!(
+                    "{}={}/{}",
+                    name,
+                    process_env::fmt_limit(soft),
+                    process_env::fmt_limit(hard)
+                ))
+            })
+            .collect();
+        writeln!(w, "limits: {}", limits.join(" "))?;
+    }
+    let mut locale: Vec<String> = std::env::vars()
+        .filter(|(k, _)| k == "LANG" || k == "LANGUAGE" || k.starts_with("LC_"))
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect();
+    locale.sort();
+    if !locale.is_empty() {
+        writeln!(w, "locale: {}", locale.join(" "))?;
+    }
+    if let Some(mask) = &cfg.umask {
+        writeln!(w, "set umask: {}", mask)?;
+    }
+    if !cfg.limits.is_empty() {
+        let set: Vec<String> = cfg
+            .limits
+            .iter()
+            .map(|(k, v)| {
+                let set = match v {
+                    LimitValue::Value(n) => format!("{}={}", k, n),
+                    LimitValue::Named(s) => format!("{}={}", k, s),
+                };
+                let value = match v {
+                    LimitValue::Value(n) => Some(*n as libc::rlim_t),
+                    LimitValue::Named(s) => (s == "unlimited").then_some(libc::RLIM_INFINITY),
+                };
+                let resource = process_env::RESOURCES.iter().find(|(n, _)| n == k);
+                match resource
+                    .zip(value)
+                    .and_then(|(&(_, r), v)| process_env::over_hard_limit(r, v))
+                {
+                    Some(hard) => format!("{} (hard limit {})", set, hard),
+                    None => set,
+                }
+            })
+            .collect();
+        writeln!(w, "set limits: {}", set.join(" "))?;
+    }
+    Ok(())
+}
+
+pub fn write_spawn_error<W: Write>(
+    mut w: W,
+    cfg: &Config,
+    err: &io::Error,
+    code: i32,
+) -> Result<()> {
+    if cfg.format == LogFormat::Jsonl {
+        let footer = jsonl::Footer {
+            kind: jsonl::Kind::Footer,
+            exit_code: code,
+            pid: None,
+            spawn_error: Some(err.to_string()),
+            failure_class: None,
+            probable_cause: None,
+            timeout: None,
+            max_log_size: None,
+            cancelled: None,
+            compression: None,
+            artifact: None,
+            usage: None,
+            signals: &[],
+            diagnostics: &[],
+            summary: None,
+            baseline: &[],
+            plugins: &[],
+        };
+        return jsonl::write(&mut w, &footer);
+    }
+    writeln!(w, "[spawn_error] {}", err)?;
+    writeln!(
+        w,
+        "
+[exit_code] {}",
+        code
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(cfg: &Config) -> String {
+        let vars = TemplateVars {
+            cmd: "make",
+            args: "-j4 all",
+            date: "2025-01-02",
+            time: "03-04-05",
+            ts: "1735787045",
+            hostname: "box",
+            user: "dev",
+            cwd: "/src",
+            ci_run: "NA",
+            run_id: "01M5170180ZZZZZZZZZZZZZZZZ",
+            parent_run_id: None,
+            fingerprint: None,
+            git: None,
+            seq: None,
+            exit_code: None,
+            failure_class: None,
+            duration_secs: None,
+            pid: None,
+        };
+        let mut out = Vec::new();
+        write_header(&mut out, cfg, &vars, Path::new("/src")).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn env_globs_include_exclude_and_mask() {
+        assert!(glob_match("AWS_*", "AWS_SECRET_ACCESS_KEY"));
+        assert!(glob_match("*_TOKEN", "GH_TOKEN") && !glob_match("*_TOKEN", "GH_TOKENS"));
+        assert!(glob_match("RUST?LAGS", "RUSTFLAGS") && glob_match("*", ""));
+        assert!(!glob_match("CC", "CXX") && glob_match("C*X*", "CXX"));
+
+        let mut cfg = Config {
+            env_include: vec!["PATH".into(), "CC".into(), "AWS_*".into()],
+            env_exclude: vec!["AWS_*".into(), "*_TOKEN".into()],
+            ..Config::default()
+        };
+        let filter = |cfg: &Config| {
+            let filter = EnvFilter {
+                cfg,
+                redactor: None,
+            };
+            ["PATH", "HOME", "AWS_REGION"].map(|name| filter.value(name, "v").map(String::from))
+        };
+        assert_eq!(filter(&cfg), [Some("v".into()), None, None]);
+        cfg.env_mask_excluded = true;
+        assert_eq!(filter(&cfg), [Some("v".into()), None, Some("****".into())]);
+    }
+
+    #[test]
+    fn header_modes_and_template() {
+        let minimal = Config {
+            header: HeaderMode::Minimal,
+            ..Config::default()
+        };
+        assert_eq!(
+            header(&minimal),
+            "# lg log\ncmd: make\nargs: -j4 all\ndate: 2025-01-02 03-04-05\n\
+             run_id: 01M5170180ZZZZZZZZZZZZZZZZ\ncwd: /src\n----- BEGIN OUTPUT -----\n"
+        );
+        let scheduled = Config {
+            scheduled_at: Some("2025-01-02 03:04".into()),
+            ..minimal.clone()
+        };
+        let scheduled = header(&scheduled);
+        let line = scheduled.lines().nth(6).unwrap();
+        assert!(line.starts_with("scheduled: 2025-01-02 03:04:00 (start delay "));
+        assert!(line.ends_with("s)"), "{}", line);
+        let none = Config {
+            header: HeaderMode::None,
+            header_template: Some("# {cmd}".into()),
+            ..Config::default()
+        };
+        assert_eq!(header(&none), "");
+        let templated = Config {
+            header_template: Some("# {cmd} {args} ({date})\n".into()),
+            ..Config::default()
+        };
+        assert_eq!(header(&templated), "# make -j4 all (2025-01-02)\n");
+    }
+}
@@ -0,0 +1,85 @@
+//! Scripted answers to prompts (`[interact]`, `--expect-script`).
+
+use crate::config::{InteractConfig, InteractRule};
+use crate::sinks::Stream;
+use anyhow::{Context, Result};
+use regex::Regex;
+use tokio::io::AsyncWriteExt;
+use tokio::process::ChildStdin;
+
+pub struct Interactor {
+    rules: Vec<(Regex, InteractRule, bool)>,
+    pub stdin: Option<ChildStdin>,
+    // Whether a rule already answered the line currently pending on each stream
+    answered: [bool; 2],
+}
+
+impl Interactor {
+    pub fn new(cfg: &InteractConfig) -> Result<Option<Self>> {
+        if cfg.rules.is_empty() {
+            return Ok(None);
+        }
+        let rules = cfg
+            .rules
+            .iter()
+            .map(|rule| {
+                let re = Regex::new(&rule.expect)
+                    .with_context(|| format!("invalid interact pattern {:?}", rule.expect))?;
+                Ok((re, rule.clone(), false))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(Self {
+            rules,
+            stdin: None,
+            answered: [false; 2],
+        }))
+    }
+
+    /// Answer `text` if a rule matches it; returns the note to log for the exchange.
+    /// `complete` is false for the pending tail of a line that may still grow.
+    pub async fn respond(
+        &mut self,
+        stream: Stream,
+        text: &str,
+        complete: bool,
+    ) -> Result<Option<String>> {
+        let slot = stream as usize;
+        let already_answered = self.answered[slot];
+        // A finished line starts a fresh prompt; a tail stays answered until its newline arrives.
+        self.answered[slot] = !complete && already_answered;
+        if already_answered {
+            return Ok(None);
+        }
+        let Some((re, rule, fired)) = self
+            .rules
+            .iter_mut()
+            .find(|(re, rule, fired)| !(rule.once && *fired) && re.is_match(text))
+        else {
+            return Ok(None);
+        };
+        *fired = true;
+        if !complete {
+            self.answered[slot] = true;
+        }
+        let mut payload = rule.send.clone();
+        if rule.newline {
+            payload.push('\n');
+        }
+        let shown = if rule.secret {
+            "****".to_string()
+        } else {
+            format!("{:?}", rule.send)
+        };
+        let note = match self.stdin.as_mut() {
+            Some(stdin) => match stdin.write_all(payload.as_bytes()).await {
+                Ok(()) => {
+                    stdin.flush().await?;
+                    format!("/{}/ -> sent {}", re.as_str(), shown)
+                }
+                Err(err) => format!("/{}/ -> could not send {}: {}", re.as_str(), shown, err),
+            },
+            None => format!("/{}/ -> stdin unavailable", re.as_str()),
+        };
+        Ok(Some(note))
+    }
+}
@@ -0,0 +1,195 @@
+//! `max_log_size`: what a run does once its log has taken in that much output.
+
+use crate::budget::fmt_size;
+use crate::config::{Config, LogSizeAction};
+use crate::sinks::incompressible::CompressionNote;
+use crate::sinks::{open_writer, LogWriters};
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The size limit of one run's log and what came of it.
+pub struct SizeLimit {
+    limit: u64,
+    action: LogSizeAction,
+    /// Output bytes in the current log file.
+    logged: u64,
+    /// Set once logging has stopped (truncate, kill).
+    stopped: bool,
+    /// Lines not logged since.
+    dropped: u64,
+    rotations: u32,
+}
+
+impl SizeLimit {
+    /// The limit `max_log_size` sets; None without one.
+    pub fn new(cfg: &Config) -> Result<Option<Self>> {
+        Ok(cfg.max_log_size()?.map(|limit| Self {
+            limit,
+            action: cfg.max_log_size_action,
+            logged: 0,
+            stopped: false,
+            dropped: 0,
+            rotations: 0,
+        }))
+    }
+
+    pub fn action(&self) -> LogSizeAction {
+        self.action
+    }
+
+    /// Whether a line of `bytes` would take the log past the limit. A fresh
+    /// rotated log takes any line, so one huge line can't rotate forever.
+    pub fn full(&self, bytes: usize) -> bool {
+        let fresh = self.action == LogSizeAction::Rotate && self.logged == 0;
+        !self.stopped && !fresh && self.logged + bytes as u64 > self.limit
+    }
+
+    /// Mark the limit reached; returns the `[LG]` line to log.
+    pub fn reached(&mut self) -> String {
+        let size = fmt_size(self.limit as usize);
+        match self.action {
+            LogSizeAction::Truncate => {
+                self.stopped = true;
+                format!(
+                    "max_log_size: {} logged, the rest of the output is not",
+                    size
+                )
+            }
+            LogSizeAction::Kill => {
+                self.stopped = true;
+                format!("max_log_size: {} logged, SIGKILL sent", size)
+            }
+            LogSizeAction::Rotate => {
+                self.rotations += 1;
+                self.logged = 0;
+                format!(
+                    "max_log_size: rotated after {}; the output before is in the .1 file",
+                    size
+                )
+            }
+        }
+    }
+
+    /// False once logging has stopped.
+    pub fn logging(&self) -> bool {
+        !self.stopped
+    }
+
+    /// Count a line of output; false when it is not to be logged.
+    pub fn take(&mut self, bytes: usize) -> bool {
+        if self.stopped {
+            self.dropped += 1;
+            return false;
+        }
+        self.logged += bytes as u64;
+        true
+    }
+
+    /// `[max_log_size]` footer value, e.g. "1024.0 MiB, truncated (5120 lines not
+    /// logged)"; None if the limit was never reached.
+    pub fn describe(&self) -> Option<String> {
+        let size = fmt_size(self.limit as usize);
+        match self.action {
+            _ if !self.stopped && self.rotations == 0 => None,
+            LogSizeAction::Truncate => Some(format!(
+                "{}, truncated ({} lines not logged)",
+                size, self.dropped
+            )),
+            LogSizeAction::Kill => Some(format!("{}, command killed", size)),
+            LogSizeAction::Rotate => Some(format!("{}, rotated {} time(s)", size, self.rotations)),
+        }
+    }
+}
+
+/// Where a rotated log keeps its previous part: `<log>.1`.
+pub fn rotated_path(log: &Path) -> PathBuf {
+    let mut name = log.as_os_str().to_os_string();
+    name.push(".1");
+    PathBuf::from(name)
+}
+
+/// Rename a finished log, and the part rotated out of it if there is one.
+pub fn rename_log(from: &Path, to: &Path) -> io::Result<()> {
+    fs::rename(from, to)?;
+    match fs::rename(rotated_path(from), rotated_path(to)) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        done => done,
+    }
+}
+
+/// Close the files of `writers` (one per path, stdout first), keep each as
+/// `<log>.1` and start them over with `header`.
+pub fn rotate(
+    cfg: &Config,
+    writers: &mut LogWriters,
+    paths: &[PathBuf],
+    header: &[u8],
+    compression: &CompressionNote,
+) -> Result<()> {
+    writers.flush()?;
+    // Dropping the writers finishes any compressed stream.
+    *writers = LogWriters::Combined(Box::new(io::sink()));
+    let mut fresh = Vec::new();
+    for path in paths {
+        let kept = rotated_path(path);
+        fs::rename(path, &kept).with_context(|| format!("rotating {:?}", path))?;
+        let (mut w, _) = open_writer(cfg, path, compression)?;
+        w.write_all(header)?;
+        fresh.push(w);
+    }
+    let mut fresh = fresh.into_iter();
+    *writers = match (fresh.next(), fresh.next()) {
+        (Some(out), Some(err)) => LogWriters::Split { out, err },
+        (Some(w), None) => LogWriters::Combined(w),
+        _ => anyhow::bail!("no log file to rotate"),
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(action: LogSizeAction) -> SizeLimit {
+        let cfg = Config {
+            max_log_size: Some("100".into()),
+            max_log_size_action: action,
+            ..Config::default()
+        };
+        SizeLimit::new(&cfg).unwrap().unwrap()
+    }
+
+    #[test]
+    fn truncate_stops_and_rotate_starts_over() {
+        let mut size = limit(LogSizeAction::Truncate);
+        assert!(!size.full(60) && size.take(60));
+        assert!(size.full(60));
+        assert!(size.reached().contains("the rest of the output is not"));
+        assert!(!size.full(60) && !size.take(60) && !size.take(1));
+        assert_eq!(
+            size.describe().unwrap(),
+            "100 B, truncated (2 lines not logged)"
+        );
+
+        let mut size = limit(LogSizeAction::Rotate);
+        assert_eq!(size.describe(), None);
+        assert!(size.take(60) && size.full(60));
+        size.reached();
+        // A line bigger than the limit still fits in a fresh log.
+        assert!(!size.full(500) && size.take(500));
+        assert!(size.full(1));
+        assert_eq!(size.describe().unwrap(), "100 B, rotated 1 time(s)");
+        assert_eq!(
+            rotated_path(Path::new("logs/make.log.gz")),
+            Path::new("logs/make.log.gz.1")
+        );
+
+        let cfg = Config {
+            max_log_size: Some("lots".into()),
+            ..Config::default()
+        };
+        assert!(SizeLimit::new(&cfg).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+//! Evidence that a SIGKILLed child was picked by the OOM killer: the
+//! cgroup's `oom_kill` counter moving during the run, or a kernel log
+//! record naming the child's pid.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+
+pub struct OomWatch {
+    counter: Option<PathBuf>,
+    kills_before: u64,
+    kmsg: Option<File>,
+}
+
+impl OomWatch {
+    /// Snapshot the counter and kernel log position before the child starts.
+    pub fn start() -> Self {
+        let counter = counter_file();
+        let kills_before = counter.as_deref().and_then(read_oom_kills).unwrap_or(0);
+        // Needs CAP_SYSLOG when kernel.dmesg_restrict is set; the counter still works.
+        let kmsg = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open("/dev/kmsg")
+            .ok()
+            .and_then(|mut f| f.seek(SeekFrom::End(0)).ok().map(|_| f));
+        Self {
+            counter,
+            kills_before,
+            kmsg,
+        }
+    }
+
+    /// "oom-killed" if the child died of SIGKILL (or a shell reported 137)
+    /// and the kernel recorded an OOM kill since `start`.
+    pub fn probable_cause(&mut self, status: ExitStatus, pid: Option<u32>) -> Option<&'static str> {
+        let direct = status.signal() == Some(libc::SIGKILL);
+        if !direct && status.code() != Some(128 + libc::SIGKILL) {
+            return None;
+        }
+        let kills = self.counter.as_deref().and_then(read_oom_kills);
+        if kills.is_some_and(|k| k > self.kills_before) {
+            return Some("oom-killed");
+        }
+        // A grandchild's pid is unknown, so the log is only trusted for direct kills.
+        let pid = pid.filter(|_| direct)?;
+        let kmsg = self.kmsg.as_mut()?;
+        kmsg_names_oom_kill(kmsg, pid).then_some("oom-killed")
+    }
+}
+
+// The memory cgroup lg (and so the child) runs in. Inside a container the
+// cgroup namespace hides the host path, so fall back to the mount root.
+fn counter_file() -> Option<PathBuf> {
+    let cgroups = fs::read_to_string("/proc/self/cgroup").ok()?;
+    for line in cgroups.lines() {
+        let mut parts = line.splitn(3, ':');
+        let (_, controllers, path) = (parts.next()?, parts.next()?, parts.next()?);
+        let (mounts, file): (&[&str], _) = if controllers.is_empty() {
+            (
+                &["/sys/fs/cgroup", "/sys/fs/cgroup/unified"],
+                "memory.events",
+            )
+        } else if controllers.split(',').any(|c| c == "memory") {
+            (&["/sys/fs/cgroup/memory"], "memory.oom_control")
+        } else {
+            continue;
+        };
+        let rel = path.trim_start_matches('/');
+        for mount in mounts {
+            for dir in [Path::new(mount).join(rel), PathBuf::from(mount)] {
+                let candidate = dir.join(file);
+                if read_oom_kills(&candidate).is_some() {
+                    return Some(candidate);
+                }
+            }
+        }
+    }
+    None
+}
+
+// cgroup v2 `memory.events` and v1 `memory.oom_control` both carry "oom_kill N".
+fn read_oom_kills(path: &Path) -> Option<u64> {
+    fs::read_to_string(path)
+        .ok()?
+        .lines()
+        .find_map(|l| l.strip_prefix("oom_kill ")?.trim().parse().ok())
+}
+
+// Each read returns one record; EAGAIN marks the end of the log.
+fn kmsg_names_oom_kill(kmsg: &mut File, pid: u32) -> bool {
+    let needle = format!("Killed process {} ", pid);
+    let mut buf = vec![0u8; 8192];
+    loop {
+        match kmsg.read(&mut buf) {
+            Ok(0) => return false,
+            Ok(n) => {
+                if String::from_utf8_lossy(&buf[..n]).contains(&needle) {
+                    return true;
+                }
+            }
+            // Records were overwritten under us; the next read resumes at the oldest.
+            Err(err) if err.raw_os_error() == Some(libc::EPIPE) => continue,
+            Err(_) => return false,
+        }
+    }
+}
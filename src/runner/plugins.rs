@@ -0,0 +1,361 @@
+//! `[[plugin]]`: external programs fed the run's output as JSON Lines.
+//!
+//! Plugins form a chain in config order. A sink reads the records and passes
+//! them on untouched; a filter's stdout replaces them for the plugins after
+//! it. The log itself never goes through plugins. A plugin can't hold up the
+//! command: records that don't fit a slow plugin's queue are dropped, and one
+//! that fails or hangs only gets a `[plugin]` line in the footer.
+
+#[cfg(feature = "wasm")]
+mod wasm;
+
+use crate::config::{PluginConfig, PluginKind};
+use crate::jsonl;
+use serde::Serialize;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TryRecvError, TrySendError};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Records waiting for a plugin before further ones are dropped.
+const QUEUE_RECORDS: usize = 4096;
+const POLL: Duration = Duration::from_millis(10);
+
+// The input of one plugin, counting what it had to drop.
+#[derive(Clone)]
+struct Queue {
+    tx: SyncSender<String>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Queue {
+    fn send(&self, record: String) {
+        // A plugin that is gone gets nothing more; that is noted when it's waited for.
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(record) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+struct Stage {
+    name: String,
+    timeout: Duration,
+    child: Option<Child>,
+    start_error: Option<String>,
+    /// Why a WebAssembly filter stopped.
+    wasm_error: Arc<OnceLock<String>>,
+    dropped: Arc<AtomicU64>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+/// The running plugins of one run.
+pub struct Plugins {
+    input: Option<Queue>,
+    stages: Vec<Stage>,
+}
+
+impl Plugins {
+    /// Start the configured plugins; None without any. `env` is what each gets
+    /// on top of lg's environment.
+    pub fn start(plugins: &[PluginConfig], env: &[(&str, &str)]) -> Option<Self> {
+        if plugins.is_empty() {
+            return None;
+        }
+        // Built from the end, so each stage knows the queue of the next one.
+        let mut next: Option<Queue> = None;
+        let mut stages = Vec::new();
+        for plugin in plugins.iter().rev() {
+            let (tx, rx) = sync_channel(QUEUE_RECORDS);
+            let dropped = Arc::new(AtomicU64::new(0));
+            stages.push(Stage::start(plugin, env, rx, next.take(), dropped.clone()));
+            next = Some(Queue { tx, dropped });
+        }
+        stages.reverse();
+        Some(Self {
+            input: next,
+            stages,
+        })
+    }
+
+    pub fn send(&self, record: &impl Serialize) {
+        if let (Some(input), Ok(json)) = (&self.input, serde_json::to_string(record)) {
+            input.send(json);
+        }
+    }
+
+    /// Close the chain and wait for each plugin, up to its `timeout_secs`;
+    /// returns a note for each one that failed, dropped records or was killed.
+    pub fn finish(mut self) -> Vec<String> {
+        drop(self.input.take());
+        let started = Instant::now();
+        let mut notes = Vec::new();
+        // In order: a stage's input ends once the one before it is done.
+        for stage in &mut self.stages {
+            let deadline = started + stage.timeout;
+            notes.extend(stage.finish(deadline));
+        }
+        notes
+    }
+}
+
+impl Stage {
+    fn start(
+        plugin: &PluginConfig,
+        env: &[(&str, &str)],
+        rx: Receiver<String>,
+        next: Option<Queue>,
+        dropped: Arc<AtomicU64>,
+    ) -> Self {
+        let mut stage = Self {
+            name: plugin.name().to_string(),
+            timeout: Duration::from_secs(plugin.timeout_secs),
+            child: None,
+            start_error: None,
+            wasm_error: Arc::default(),
+            dropped,
+            threads: Vec::new(),
+        };
+        if let Some(module) = plugin.wasm.clone() {
+            let error = stage.wasm_error.clone();
+            stage
+                .threads
+                .push(thread::spawn(move || run_wasm(&module, rx, next, &error)));
+            return stage;
+        }
+        if plugin.command.trim().is_empty() {
+            stage.start_error = Some("neither command nor wasm is set".into());
+            return stage;
+        }
+        let filter = plugin.kind == PluginKind::Filter;
+        let spawned = Command::new("sh")
+            .arg("-c")
+            .arg(&plugin.command)
+            .envs(env.iter().copied())
+            .stdin(Stdio::piped())
+            .stdout(if filter {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .spawn();
+        let (stdin, stdout) = match spawned {
+            Ok(mut child) => {
+                let pipes = (child.stdin.take(), child.stdout.take());
+                stage.child = Some(child);
+                pipes
+            }
+            Err(err) => {
+                stage.start_error = Some(err.to_string());
+                (None, None)
+            }
+        };
+        // A filter's output is what goes on, whatever it read.
+        let (forward, filtered) = match filter {
+            true => (None, next),
+            false => (next, None),
+        };
+        stage
+            .threads
+            .push(thread::spawn(move || feed(rx, stdin, forward)));
+        if let (Some(stdout), Some(next)) = (stdout, filtered) {
+            stage
+                .threads
+                .push(thread::spawn(move || read_filtered(stdout, next)));
+        }
+        stage
+    }
+
+    fn finish(&mut self, deadline: Instant) -> Vec<String> {
+        let mut notes = Vec::new();
+        if let Some(err) = &self.start_error {
+            notes.push(format!("{}: failed to start: {}", self.name, err));
+        }
+        let mut killed = false;
+        if let Some(child) = self.child.as_mut() {
+            let status = loop {
+                match child.try_wait() {
+                    Ok(None) if Instant::now() < deadline => thread::sleep(POLL),
+                    Ok(None) => {
+                        killed = true;
+                        let _ = child.kill();
+                        break child.wait().ok();
+                    }
+                    Ok(status) => break status,
+                    Err(_) => break None,
+                }
+            };
+            match status {
+                _ if killed => notes.push(format!(
+                    "{}: still running after {}s, killed",
+                    self.name,
+                    self.timeout.as_secs()
+                )),
+                Some(status) if !status.success() => {
+                    notes.push(format!("{}: exited with {}", self.name, status))
+                }
+                _ => {}
+            }
+        }
+        // Threads still stuck on a pipe some leftover process holds are left behind.
+        while !self.threads.iter().all(JoinHandle::is_finished) && Instant::now() < deadline {
+            thread::sleep(POLL);
+        }
+        if let Some(err) = self.wasm_error.get() {
+            notes.push(format!("{}: {}", self.name, err));
+        }
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        if dropped > 0 {
+            notes.push(format!(
+                "{}: {} record(s) dropped, the plugin fell behind",
+                self.name, dropped
+            ));
+        }
+        notes
+    }
+}
+
+// Write the records of `rx` to the plugin (until it stops reading) and pass
+// them on to `forward`.
+fn feed(rx: Receiver<String>, stdin: Option<ChildStdin>, forward: Option<Queue>) {
+    let mut stdin = stdin.map(BufWriter::new);
+    loop {
+        let record = match rx.try_recv() {
+            Ok(record) => record,
+            Err(TryRecvError::Empty) => {
+                // Nothing waiting: hand the plugin what it has so far.
+                if stdin.as_mut().is_some_and(|w| w.flush().is_err()) {
+                    stdin = None;
+                }
+                match rx.recv() {
+                    Ok(record) => record,
+                    Err(_) => break,
+                }
+            }
+            Err(TryRecvError::Disconnected) => break,
+        };
+        if let Some(w) = stdin.as_mut() {
+            if writeln!(w, "{}", record).is_err() {
+                stdin = None;
+            }
+        }
+        if let Some(forward) = &forward {
+            forward.send(record);
+        }
+    }
+    // Dropping stdin is the plugin's end of input.
+    if let Some(mut w) = stdin {
+        let _ = w.flush();
+    }
+}
+
+// Filter the records of `rx` through a WebAssembly module; the first error
+// stops it.
+fn run_wasm(module: &Path, rx: Receiver<String>, next: Option<Queue>, error: &OnceLock<String>) {
+    #[cfg(feature = "wasm")]
+    {
+        let mut filter = match wasm::WasmFilter::load(module) {
+            Ok(filter) => filter,
+            Err(err) => {
+                let _ = error.set(format!("failed to start: {:#}", err));
+                return;
+            }
+        };
+        for record in rx {
+            let passed = match filter.process(&record) {
+                Ok(passed) => passed,
+                Err(err) => {
+                    let _ = error.set(format!("stopped: {:#}", err));
+                    return;
+                }
+            };
+            let Some(next) = &next else {
+                continue;
+            };
+            for record in passed.split(|&b| b == b'\n').filter(|r| !r.is_empty()) {
+                next.send(String::from_utf8_lossy(record).into_owned());
+            }
+        }
+    }
+    #[cfg(not(feature = "wasm"))]
+    {
+        let _ = (module, rx, next);
+        let _ = error.set("failed to start: lg was built without the `wasm` feature".into());
+    }
+}
+
+fn read_filtered(stdout: ChildStdout, next: Queue) {
+    for line in BufReader::new(stdout).split(b'\n') {
+        let Ok(line) = line else {
+            break;
+        };
+        next.send(String::from_utf8_lossy(&line).into_owned());
+    }
+}
+
+/// An output line as plugins get it: a `jsonl` line record, always timestamped.
+pub fn line_record<'a>(ts: &'a str, stream: &'a str, line: &'a str) -> jsonl::Line<'a> {
+    jsonl::Line {
+        ts: Some(ts),
+        stream,
+        line,
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn plugin(command: String, kind: PluginKind) -> PluginConfig {
+        PluginConfig {
+            name: None,
+            command,
+            wasm: None,
+            kind,
+            timeout_secs: 5,
+        }
+    }
+
+    #[test]
+    fn filters_feed_the_sinks_after_them() {
+        let dir = std::env::temp_dir().join(format!("lg-plugins-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (all, errors) = (dir.join("all.jsonl"), dir.join("errors.jsonl"));
+        let chain = [
+            plugin(format!("cat > {}", all.display()), PluginKind::Sink),
+            plugin("grep --line-buffered stderr".into(), PluginKind::Filter),
+            plugin(format!("cat > {}", errors.display()), PluginKind::Sink),
+            plugin("exit 3".into(), PluginKind::Sink),
+        ];
+        let plugins = Plugins::start(&chain, &[("LG_CMD", "make")]).unwrap();
+        plugins.send(&line_record("t1", "stdout", "compiling"));
+        plugins.send(&line_record("t2", "stderr", "error: boom"));
+        let notes = plugins.finish();
+        assert_eq!(notes, ["exit 3: exited with exit status: 3"]);
+        assert_eq!(fs::read_to_string(&all).unwrap().lines().count(), 2);
+        assert_eq!(
+            fs::read_to_string(&errors).unwrap(),
+            "{\"ts\":\"t2\",\"stream\":\"stderr\",\"line\":\"error: boom\"}\n"
+        );
+        assert!(Plugins::start(&[], &[]).is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_hung_plugin_is_killed_and_a_slow_one_drops_records() {
+        let mut hung = plugin("sleep 30".into(), PluginKind::Sink);
+        hung.timeout_secs = 0;
+        hung.name = Some("hung".into());
+        let plugins = Plugins::start(&[hung], &[]).unwrap();
+        for i in 0..QUEUE_RECORDS * 4 {
+            plugins.send(&line_record("t", "stdout", &"x".repeat(i % 100)));
+        }
+        let notes = plugins.finish();
+        assert_eq!(notes[0], "hung: still running after 0s, killed");
+        assert!(notes[1].starts_with("hung: ") && notes[1].ends_with("the plugin fell behind"));
+    }
+}
@@ -0,0 +1,135 @@
+//! `[[plugin]]` filters compiled to WebAssembly, run inside lg by the wasmi
+//! interpreter. A module gets no imports, so it can't reach files, the network
+//! or the environment; fuel and a memory cap bound what a record may cost.
+//!
+//! The LineProcessor interface: the module exports its `memory`,
+//! `lg_alloc(len: i32) -> i32`, where lg may write a record of `len` bytes,
+//! and `lg_process(ptr: i32, len: i32) -> i64`, the records to pass on as
+//! `(ptr << 32) | len`: none for 0 bytes, several separated by newlines.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+use wasmi::{Engine, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+
+/// Instructions a module may run per record.
+const FUEL_PER_RECORD: u64 = 10_000_000;
+/// Linear memory a module may grow to.
+const MEMORY_BYTES: usize = 64 * 1024 * 1024;
+
+pub struct WasmFilter {
+    store: Store<StoreLimits>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    process: TypedFunc<(i32, i32), i64>,
+    /// Fuel handed out so far.
+    fuel: u64,
+}
+
+impl WasmFilter {
+    pub fn load(path: &Path) -> Result<Self> {
+        let wasm = std::fs::read(path).with_context(|| format!("reading {:?}", path))?;
+        let mut config = wasmi::Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module =
+            Module::new(&engine, &wasm[..]).with_context(|| format!("loading {:?}", path))?;
+        let limits = StoreLimitsBuilder::new().memory_size(MEMORY_BYTES).build();
+        let mut store = Store::new(&engine, limits);
+        store.limiter(|limits| limits);
+        let instance = Linker::new(&engine)
+            .instantiate(&mut store, &module)
+            .and_then(|pre| pre.start(&mut store))
+            .context("instantiating (a module gets no imports)")?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .context("the module exports no memory")?;
+        let alloc = instance
+            .get_typed_func(&store, "lg_alloc")
+            .context("lg_alloc")?;
+        let process = instance
+            .get_typed_func(&store, "lg_process")
+            .context("lg_process")?;
+        Ok(Self {
+            store,
+            memory,
+            alloc,
+            process,
+            fuel: 0,
+        })
+    }
+
+    /// Run `record` through the module; returns the records it passes on.
+    pub fn process(&mut self, record: &str) -> Result<Vec<u8>> {
+        // Top the fuel up to one record's worth.
+        let used = self.store.fuel_consumed().unwrap_or(0);
+        let top_up = FUEL_PER_RECORD.saturating_sub(self.fuel - used);
+        self.store
+            .add_fuel(top_up)
+            .map_err(|err| anyhow::anyhow!("{}", err))?;
+        self.fuel += top_up;
+        let len = i32::try_from(record.len()).context("record too large")?;
+        let ptr = self.alloc.call(&mut self.store, len).context("lg_alloc")?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, record.as_bytes())
+            .map_err(|err| anyhow::anyhow!("lg_alloc gave {}: {}", ptr, err))?;
+        let packed = self
+            .process
+            .call(&mut self.store, (ptr, len))
+            .context("lg_process")? as u64;
+        let (ptr, len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+        let mut out = vec![0; len];
+        self.memory
+            .read(&self.store, ptr, &mut out)
+            .map_err(|err| anyhow::anyhow!("lg_process returned {}+{}: {}", ptr, len, err))?;
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // A module exporting one page of memory, `lg_alloc` always answering 1024
+    // and an `lg_process` with the given body (no locals).
+    fn module(process: &[u8]) -> Vec<u8> {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        // (i32) -> i32, (i32, i32) -> i64
+        wasm.extend(b"\x01\x0c\x02\x60\x01\x7f\x01\x7f\x60\x02\x7f\x7f\x01\x7e");
+        wasm.extend(b"\x03\x03\x02\x00\x01");
+        wasm.extend(b"\x05\x03\x01\x00\x01");
+        wasm.extend(b"\x07\x22\x03\x06memory\x02\x00\x08lg_alloc\x00\x00\x0alg_process\x00\x01");
+        let alloc = b"\x05\x00\x41\x80\x08\x0b";
+        wasm.extend([0x0a, (1 + alloc.len() + 2 + process.len()) as u8, 0x02]);
+        wasm.extend(alloc);
+        wasm.extend([(process.len() + 1) as u8, 0x00]);
+        wasm.extend(process);
+        wasm
+    }
+
+    fn load(name: &str, process: &[u8]) -> Result<WasmFilter> {
+        let path =
+            std::env::temp_dir().join(format!("lg-wasm-test-{}-{}", std::process::id(), name));
+        fs::write(&path, module(process)).unwrap();
+        let filter = WasmFilter::load(&path);
+        fs::remove_file(path).unwrap();
+        filter
+    }
+
+    #[test]
+    fn records_pass_through_or_get_dropped_and_runaways_stop() {
+        // (i64.extend_i32_u ptr << 32) | i64.extend_i32_u len
+        let echo = load("echo.wasm", b"\x20\x00\xad\x42\x20\x86\x20\x01\xad\x84\x0b");
+        let mut echo = echo.unwrap();
+        for record in ["{\"line\":\"a\"}", "{\"line\":\"bb\"}"] {
+            assert_eq!(echo.process(record).unwrap(), record.as_bytes());
+        }
+        let mut drop = load("drop.wasm", b"\x42\x00\x0b").unwrap();
+        assert!(drop.process("{}").unwrap().is_empty());
+        // loop br 0 end unreachable
+        let mut spin = load("spin.wasm", b"\x03\x40\x0c\x00\x0b\x00\x0b").unwrap();
+        let err = format!("{:#}", spin.process("{}").unwrap_err());
+        assert!(err.contains("fuel"), "{}", err);
+        assert!(load("bad.wasm", b"\x0b").is_err());
+    }
+}
@@ -0,0 +1,136 @@
+//! The process environment lg sets up for the child (umask, resource limits)
+//! and reports in the header.
+
+use crate::config::{Config, LimitValue};
+use std::io;
+use tokio::process::Command;
+
+pub const RESOURCES: &[(&str, i32)] = &[
+    ("core", libc::RLIMIT_CORE as i32),
+    ("cpu", libc::RLIMIT_CPU as i32),
+    ("data", libc::RLIMIT_DATA as i32),
+    ("fsize", libc::RLIMIT_FSIZE as i32),
+    ("nofile", libc::RLIMIT_NOFILE as i32),
+    ("nproc", libc::RLIMIT_NPROC as i32),
+    ("stack", libc::RLIMIT_STACK as i32),
+    ("as", libc::RLIMIT_AS as i32),
+    ("memlock", libc::RLIMIT_MEMLOCK as i32),
+];
+
+pub fn current_umask() -> u32 {
+    // There is no read-only getter; set and immediately restore.
+    // SAFETY: umask only swaps the process file mode mask.
+    unsafe {
+        let mask = libc::umask(0o022);
+        libc::umask(mask);
+        mask as u32
+    }
+}
+
+pub fn get_limit(resource: i32) -> Option<(libc::rlim_t, libc::rlim_t)> {
+    let mut lim = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: getrlimit only writes into the provided struct.
+    let rc = unsafe { libc::getrlimit(resource as _, &mut lim) };
+    (rc == 0).then_some((lim.rlim_cur, lim.rlim_max))
+}
+
+pub fn fmt_limit(value: libc::rlim_t) -> String {
+    if value == libc::RLIM_INFINITY {
+        "unlimited".into()
+    } else {
+        value.to_string()
+    }
+}
+
+/// The hard limit a `[limits]` value above it is cut down to, as only root
+/// may raise one: anyone else's command would fail to start with EPERM.
+pub fn over_hard_limit(resource: i32, value: libc::rlim_t) -> Option<libc::rlim_t> {
+    // SAFETY: geteuid has no preconditions.
+    if unsafe { libc::geteuid() } == 0 {
+        return None;
+    }
+    let (_, hard) = get_limit(resource)?;
+    (hard != libc::RLIM_INFINITY && value > hard).then_some(hard)
+}
+
+/// Validate `umask`/`[limits]` and install them in the child before exec.
+pub fn apply(command: &mut Command, cfg: &Config) -> io::Result<()> {
+    let umask = match &cfg.umask {
+        Some(s) => Some(
+            libc::mode_t::from_str_radix(s.trim_start_matches("0o"), 8).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid umask {:?}", s),
+                )
+            })?,
+        ),
+        None => None,
+    };
+    let mut limits = Vec::new();
+    for (name, value) in &cfg.limits {
+        let resource = RESOURCES
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|&(_, r)| r)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unknown resource limit {:?}", name),
+                )
+            })?;
+        let mut value = match value {
+            LimitValue::Value(n) => *n as libc::rlim_t,
+            LimitValue::Named(s) if s == "unlimited" => libc::RLIM_INFINITY,
+            LimitValue::Named(s) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid value {:?} for limit {}", s, name),
+                ))
+            }
+        };
+        if let Some(hard) = over_hard_limit(resource, value) {
+            eprintln!(
+                "lg: limits: {} = {} is above the hard limit, using {}",
+                name,
+                fmt_limit(value),
+                hard
+            );
+            value = hard;
+        }
+        limits.push((resource, value));
+    }
+    if umask.is_none() && limits.is_empty() {
+        return Ok(());
+    }
+    // SAFETY: the closure only calls async-signal-safe functions (umask,
+    // getrlimit, setrlimit) between fork and exec.
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(mask) = umask {
+                libc::umask(mask);
+            }
+            for &(resource, value) in &limits {
+                let mut lim = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                if libc::getrlimit(resource as _, &mut lim) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                lim.rlim_cur = value;
+                // Only as root: the value was clamped to the hard limit otherwise.
+                if lim.rlim_max != libc::RLIM_INFINITY && value > lim.rlim_max {
+                    lim.rlim_max = value;
+                }
+                if libc::setrlimit(resource as _, &lim) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    Ok(())
+}
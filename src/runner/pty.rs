@@ -0,0 +1,110 @@
+//! Running the child on a pseudo-terminal (`pty = true`), so it colors its
+//! output and draws progress as it would in a terminal.
+
+use std::fs::File;
+use std::io;
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::pin::Pin;
+use std::process::Stdio;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// A fresh pseudo-terminal: lg reads the master, the child gets the slave
+/// as stdout and stderr.
+pub struct Pty {
+    pub master: File,
+    slave: OwnedFd,
+}
+
+impl Pty {
+    /// Open a pty sized like lg's own terminal (80x24 when there is none).
+    pub fn open() -> io::Result<Self> {
+        let mut size = libc::winsize {
+            ws_row: 24,
+            ws_col: 80,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let mut current = size;
+        // SAFETY: TIOCGWINSZ only writes into the provided struct.
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut current) } == 0
+            && current.ws_col > 0
+        {
+            size = current;
+        }
+        let (mut master, mut slave) = (-1, -1);
+        // SAFETY: openpty writes two new descriptors; name and termios are optional.
+        let rc = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::addr_of_mut!(size),
+            )
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        for fd in [master, slave] {
+            // SAFETY: keeps both descriptors out of other children; the slave
+            // reaches the command through dup2, which clears the flag.
+            unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+        }
+        // SAFETY: both descriptors were just opened and are owned by nobody else.
+        let (master, slave) = unsafe { (File::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+        Ok(Self { master, slave })
+    }
+
+    /// A `Stdio` connected to the slave side.
+    pub fn slave(&self) -> io::Result<Stdio> {
+        Ok(Stdio::from(self.slave.try_clone()?))
+    }
+}
+
+/// The master side as an async reader. Once every slave descriptor is
+/// closed, Linux reports EIO instead of end of file; that is mapped to EOF.
+pub struct PtyReader(tokio::fs::File);
+
+impl PtyReader {
+    pub fn new(master: File) -> Self {
+        Self(tokio::fs::File::from_std(master))
+    }
+}
+
+impl AsyncRead for PtyReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match Pin::new(&mut self.0).poll_read(cx, buf) {
+            Poll::Ready(Err(err)) if err.raw_os_error() == Some(libc::EIO) => Poll::Ready(Ok(())),
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn child_sees_a_terminal_and_reader_ends_at_exit() {
+        let pty = Pty::open().unwrap();
+        let mut child = tokio::process::Command::new("sh")
+            .args(["-c", "test -t 1 && echo tty; test -t 2 && echo tty >&2"])
+            .stdout(pty.slave().unwrap())
+            .stderr(pty.slave().unwrap())
+            .spawn()
+            .unwrap();
+        let Pty { master, slave } = pty;
+        drop(slave);
+        let mut reader = PtyReader::new(master);
+        let mut out = String::new();
+        reader.read_to_string(&mut out).await.unwrap();
+        assert_eq!(out, "tty\r\ntty\r\n");
+        assert!(child.wait().await.unwrap().success());
+    }
+}
@@ -0,0 +1,192 @@
+//! `raw`: text logs that keep the command's output as the bytes it wrote, so
+//! `\r` progress bars and lines without a newline read as on a terminal.
+//! Output is logged up to its last `\n` or `\r`; the rest waits for more
+//! output, the next flush or the end of the stream. `[redact]` masks within
+//! those pieces.
+
+use super::LineClock;
+use crate::ansi;
+use crate::budget::MIN_LINE_BYTES;
+use crate::config::Config;
+use crate::redact::Redactor;
+use crate::sinks::{LogWriters, Stream};
+use std::borrow::Cow;
+use std::io;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct FileState {
+    /// The last byte written did not end a line.
+    mid_line: bool,
+    stamped: Option<Instant>,
+}
+
+/// The unwritten output of a `raw` run and where its log files stand.
+pub struct RawLog {
+    /// Per stream, stdout's first.
+    pending: [Vec<u8>; 2],
+    /// Per log file; a combined log only uses the first.
+    files: [FileState; 2],
+    stamp_every: Option<Duration>,
+    max_pending: usize,
+    strip_ansi: bool,
+}
+
+impl RawLog {
+    pub fn new(cfg: &Config) -> Self {
+        let stamps = cfg.timestamp_each_line && !cfg.plain_lines && cfg.raw_stamp_interval_ms > 0;
+        Self {
+            pending: Default::default(),
+            files: Default::default(),
+            stamp_every: stamps.then(|| Duration::from_millis(cfg.raw_stamp_interval_ms)),
+            max_pending: cfg.max_line_bytes.max(MIN_LINE_BYTES),
+            strip_ansi: cfg.strip_ansi,
+        }
+    }
+
+    /// Take `bytes` the command wrote to `stream` and log them up to the last
+    /// line end or carriage return; all of them once `end` says the stream closed.
+    pub fn write(
+        &mut self,
+        writers: &mut LogWriters,
+        stream: Stream,
+        bytes: &[u8],
+        end: bool,
+        redactor: Option<&Redactor>,
+        clock: &mut LineClock,
+    ) -> io::Result<()> {
+        let pending = &mut self.pending[index(stream)];
+        pending.extend_from_slice(bytes);
+        let ready = match pending.iter().rposition(|&b| b == b'\n' || b == b'\r') {
+            _ if end || pending.len() > self.max_pending => pending.len(),
+            Some(pos) => pos + 1,
+            None => 0,
+        };
+        if ready == 0 {
+            return Ok(());
+        }
+        let piece: Vec<u8> = pending.drain(..ready).collect();
+        self.log(writers, stream, &piece, redactor, clock)
+    }
+
+    /// Log what waits of both streams, say a prompt, when output is flushed.
+    pub fn flush(
+        &mut self,
+        writers: &mut LogWriters,
+        redactor: Option<&Redactor>,
+        clock: &mut LineClock,
+    ) -> io::Result<()> {
+        for stream in [Stream::Stdout, Stream::Stderr] {
+            let piece = std::mem::take(&mut self.pending[index(stream)]);
+            if !piece.is_empty() {
+                self.log(writers, stream, &piece, redactor, clock)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn log(
+        &mut self,
+        writers: &mut LogWriters,
+        stream: Stream,
+        piece: &[u8],
+        redactor: Option<&Redactor>,
+        clock: &mut LineClock,
+    ) -> io::Result<()> {
+        let file = match writers {
+            LogWriters::Split { .. } => &mut self.files[index(stream)],
+            LogWriters::Combined(_) => &mut self.files[0],
+        };
+        let w = writers.for_stream(stream);
+        if let Some(every) = self.stamp_every.filter(|_| !file.mid_line) {
+            if file.stamped.map_or(true, |at| at.elapsed() >= every) {
+                writeln!(w, "[{}]", clock.stamp())?;
+                file.stamped = Some(Instant::now());
+            }
+        }
+        let piece = match self.strip_ansi {
+            true => ansi::strip(piece),
+            false => Cow::Borrowed(piece),
+        };
+        match redactor {
+            Some(redactor) => w.write_all(&redacted(redactor, &piece))?,
+            None => w.write_all(&piece)?,
+        }
+        file.mid_line = piece.last() != Some(&b'\n');
+        Ok(())
+    }
+}
+
+fn index(stream: Stream) -> usize {
+    match stream {
+        Stream::Stdout => 0,
+        Stream::Stderr => 1,
+    }
+}
+
+// `piece` with its secrets masked; the bytes as written unless one matched.
+fn redacted<'a>(redactor: &Redactor, piece: &'a [u8]) -> Cow<'a, [u8]> {
+    let text = String::from_utf8_lossy(piece);
+    match redactor.redact(&text) {
+        Cow::Borrowed(_) => Cow::Borrowed(piece),
+        Cow::Owned(masked) => Cow::Owned(masked.into_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RedactConfig;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn progress_bars_pass_through_with_a_stamp_per_interval() {
+        let cfg = Config {
+            raw: true,
+            raw_stamp_interval_ms: 60_000,
+            ..Config::default()
+        };
+        let log = Shared::default();
+        let mut writers = LogWriters::Combined(Box::new(log.clone()));
+        let mut clock = LineClock::new(1, "T");
+        let redactor = Redactor::new(&RedactConfig::default()).unwrap();
+        let mut raw = RawLog::new(&cfg);
+        let mut write = |stream, bytes: &[u8], end| {
+            raw.write(
+                &mut writers,
+                stream,
+                bytes,
+                end,
+                redactor.as_ref(),
+                &mut clock,
+            )
+            .unwrap();
+        };
+        write(Stream::Stdout, b" 10%\r 5", false);
+        write(Stream::Stdout, b"0%\r100%\n", false);
+        write(Stream::Stderr, b"password=hunter2\n", false);
+        write(Stream::Stdout, b"\xff\xfeno newline", false);
+        write(Stream::Stdout, b"", true);
+        let logged = log.0.lock().unwrap().clone();
+        let masked = String::from_utf8_lossy(&logged);
+        assert!(logged.starts_with(b"[T]\n 10%\r 50%\r100%\n"));
+        assert!(!masked.contains("hunter2"));
+        assert!(logged.ends_with(b"\n\xff\xfeno newline"));
+        assert_eq!(masked.matches("[T]").count(), 1);
+    }
+}
@@ -0,0 +1,224 @@
+//! SIGINT, SIGTERM and SIGHUP sent to lg while the command runs: passed on
+//! to the command and noted in the log, instead of killing lg and orphaning it.
+
+use serde::Serialize;
+use std::io;
+use tokio::process::Child;
+
+/// A signal lg received during the run.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Received {
+    #[serde(serialize_with = "serialize_name")]
+    pub signal: i32,
+    /// False when the terminal sent it to the whole job, so the command
+    /// already had it and lg did not send it again.
+    pub forwarded: bool,
+}
+
+impl Received {
+    /// `[signal]` footer value, e.g. "SIGTERM forwarded".
+    pub fn describe(&self) -> String {
+        let how = if self.forwarded {
+            "forwarded"
+        } else {
+            "from terminal"
+        };
+        format!("{} {}", name(self.signal), how)
+    }
+
+    /// The `[LG]` line written when it arrives.
+    pub fn note(&self) -> String {
+        if self.forwarded {
+            format!("{} received, forwarded to the command", name(self.signal))
+        } else {
+            format!(
+                "{} from the terminal, which sent it to the command too",
+                name(self.signal)
+            )
+        }
+    }
+}
+
+fn serialize_name<S: serde::Serializer>(signal: &i32, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(name(*signal))
+}
+
+#[cfg(unix)]
+pub fn name(signal: i32) -> &'static str {
+    match signal {
+        libc::SIGINT => "SIGINT",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGHUP => "SIGHUP",
+        _ => "signal",
+    }
+}
+
+#[cfg(not(unix))]
+pub fn name(_signal: i32) -> &'static str {
+    "signal"
+}
+
+#[cfg(unix)]
+pub fn is_hangup(signal: i32) -> bool {
+    signal == libc::SIGHUP
+}
+
+#[cfg(not(unix))]
+pub fn is_hangup(_signal: i32) -> bool {
+    false
+}
+
+/// The signals lg catches for the rest of its life once this exists.
+pub struct Forwarder {
+    #[cfg(unix)]
+    streams: Vec<(i32, tokio::signal::unix::Signal)>,
+}
+
+impl Forwarder {
+    /// Catch SIGINT and SIGTERM, and SIGHUP too unless `survive_hup` handles it.
+    #[cfg(unix)]
+    pub fn install(hangup: bool) -> io::Result<Self> {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut kinds = vec![
+            (libc::SIGINT, SignalKind::interrupt()),
+            (libc::SIGTERM, SignalKind::terminate()),
+        ];
+        if hangup {
+            kinds.push((libc::SIGHUP, SignalKind::hangup()));
+        }
+        let streams = kinds
+            .into_iter()
+            .map(|(number, kind)| Ok((number, signal(kind)?)))
+            .collect::<io::Result<_>>()?;
+        Ok(Self { streams })
+    }
+
+    #[cfg(not(unix))]
+    pub fn install(_hangup: bool) -> io::Result<Self> {
+        Ok(Self {})
+    }
+
+    /// The next signal; never resolves where signals aren't caught.
+    pub async fn recv(&mut self) -> i32 {
+        #[cfg(unix)]
+        {
+            std::future::poll_fn(|cx| {
+                for (number, stream) in &mut self.streams {
+                    if stream.poll_recv(cx).is_ready() {
+                        return std::task::Poll::Ready(*number);
+                    }
+                }
+                std::task::Poll::Pending
+            })
+            .await
+        }
+        #[cfg(not(unix))]
+        std::future::pending().await
+    }
+}
+
+/// Whether the command is started in a process group of its own, so that
+/// signals reach whatever it starts too (the children of `sh -c`, `make` or
+/// `npm`). Not while lg is the foreground job of a terminal: there the command
+/// stays in lg's job, which Ctrl-C reaches as a whole, and can use the terminal.
+#[cfg(unix)]
+pub fn own_group() -> bool {
+    !in_foreground()
+}
+
+#[cfg(not(unix))]
+pub fn own_group() -> bool {
+    false
+}
+
+/// Send `signal` to the command: to its process group when it leads one
+/// (`group`, its pid at spawn), which outlives the command itself, else to
+/// the command while it hasn't been reaped.
+#[cfg(unix)]
+pub fn send(child: &Child, group: Option<u32>, signal: i32) -> bool {
+    // SAFETY: killpg and kill only signal the child we spawned and its group.
+    match group {
+        Some(group) => unsafe { libc::killpg(group as libc::pid_t, signal) == 0 },
+        None => child
+            .id()
+            .is_some_and(|pid| unsafe { libc::kill(pid as libc::pid_t, signal) == 0 }),
+    }
+}
+
+/// Send `signal` on to the command. Ctrl-C and a hangup reach the whole
+/// foreground job, the command included unless it has a `group` of its own,
+/// so then they are not sent a second time.
+#[cfg(unix)]
+pub fn deliver(child: &Child, group: Option<u32>, signal: i32) -> Received {
+    let from_terminal =
+        group.is_none() && matches!(signal, libc::SIGINT | libc::SIGHUP) && in_foreground();
+    let forwarded = !from_terminal && send(child, group, signal);
+    Received { signal, forwarded }
+}
+
+#[cfg(not(unix))]
+pub fn deliver(_child: &Child, _group: Option<u32>, signal: i32) -> Received {
+    Received {
+        signal,
+        forwarded: false,
+    }
+}
+
+// Whether lg's process group is the foreground group of its terminal.
+#[cfg(unix)]
+fn in_foreground() -> bool {
+    // SAFETY: getpgrp and tcgetpgrp only query process state.
+    unsafe {
+        let group = libc::getpgrp();
+        [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO]
+            .iter()
+            .map(|fd| libc::tcgetpgrp(*fd))
+            .find(|pg| *pg > 0)
+            .is_some_and(|pg| pg == group)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn sigterm_is_forwarded_to_the_child() {
+        let mut forwarder = Forwarder::install(false).unwrap();
+        let mut child = tokio::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        // SAFETY: sends SIGTERM to this test process, where the forwarder catches it.
+        unsafe { libc::kill(libc::getpid(), libc::SIGTERM) };
+        let signal = forwarder.recv().await;
+        let received = deliver(&child, None, signal);
+        assert_eq!(received.describe(), "SIGTERM forwarded");
+        let status = child.wait().await.unwrap();
+        use std::os::unix::process::ExitStatusExt;
+        assert_eq!(status.signal(), Some(libc::SIGTERM));
+    }
+
+    #[tokio::test]
+    async fn a_group_of_its_own_takes_the_commands_children_along() {
+        use tokio::io::AsyncReadExt;
+        // The shell's `sleep` holds stdout open until it is gone too.
+        let mut child = tokio::process::Command::new("sh")
+            .args(["-c", "sleep 30; echo done"])
+            .stdout(std::process::Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let group = child.id();
+        let mut stdout = child.stdout.take().unwrap();
+        assert!(deliver(&child, group, libc::SIGTERM).forwarded);
+        let mut out = Vec::new();
+        let read = stdout.read_to_end(&mut out);
+        tokio::time::timeout(std::time::Duration::from_secs(5), read)
+            .await
+            .expect("the sleep outlived its shell")
+            .unwrap();
+        child.wait().await.unwrap();
+        assert!(out.is_empty());
+    }
+}
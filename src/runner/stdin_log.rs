@@ -0,0 +1,122 @@
+//! `log_stdin`: what is piped into the command also goes into the log, as
+//! `[STDIN]` lines. lg reads the input itself, hands each piece on to the
+//! command and keeps a copy for the log.
+
+use super::READ_CHUNK;
+use crate::config::{Config, StdinMode, StdinSource};
+use crate::context::TTY;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio::process::ChildStdin;
+
+/// Input copied but not logged yet before lg stops reading more of it.
+const COPY_BUFFER: usize = 64 * 1024;
+
+pub type Input = Box<dyn AsyncRead + Unpin + Send>;
+
+/// Whether the run's input is logged: with `log_stdin`, unless lg's stdin is
+/// a terminal (typing stays interactive), there is none, or `[interact]`
+/// answers prompts on it.
+pub fn logged(cfg: &Config) -> bool {
+    let piped = match &cfg.stdin {
+        StdinSource::Mode(StdinMode::Inherit) => !TTY.stdin,
+        StdinSource::Mode(StdinMode::Null) => false,
+        StdinSource::File { .. } => true,
+    };
+    cfg.log_stdin && piped && cfg.interact.rules.is_empty()
+}
+
+/// The input to log, lg's stdin or the `stdin` file; None when it isn't logged.
+pub fn open(cfg: &Config) -> io::Result<Option<Input>> {
+    if !logged(cfg) {
+        return Ok(None);
+    }
+    match &cfg.stdin {
+        StdinSource::File { file } => std::fs::File::open(file)
+            .map(|file| Some(Box::new(tokio::fs::File::from_std(file)) as Input))
+            .map_err(|err| io::Error::new(err.kind(), format!("stdin file {:?}: {}", file, err))),
+        _ => Ok(Some(Box::new(tokio::io::stdin()))),
+    }
+}
+
+/// Feed `input` to the command's `stdin` and return a copy of it to log. The
+/// command's stdin closes at the end of the input; once the command stops
+/// reading, so does lg.
+pub fn feed(mut input: Input, stdin: Option<ChildStdin>) -> DuplexStream {
+    let (mut copy, logged) = tokio::io::duplex(COPY_BUFFER);
+    tokio::spawn(async move {
+        let Some(mut stdin) = stdin else {
+            return;
+        };
+        let mut copying = true;
+        let mut chunk = vec![0u8; READ_CHUNK];
+        loop {
+            let n = match input.read(&mut chunk).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            // The copy first, so input tends to be logged before the output it causes.
+            if copying {
+                copying = copy.write_all(&chunk[..n]).await.is_ok();
+            }
+            if stdin.write_all(&chunk[..n]).await.is_err() {
+                break;
+            }
+        }
+    });
+    logged
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::config::{InteractConfig, InteractRule};
+    use std::process::Stdio;
+
+    #[tokio::test]
+    async fn input_reaches_the_command_and_the_log() {
+        let mut child = tokio::process::Command::new("cat")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let input: Input = Box::new(&b"select 1;\n\\q\n"[..]);
+        let mut copy = feed(input, child.stdin.take());
+        let mut logged = Vec::new();
+        copy.read_to_end(&mut logged).await.unwrap();
+        let output = child.wait_with_output().await.unwrap();
+        assert_eq!(logged, b"select 1;\n\\q\n");
+        assert_eq!(output.stdout, logged);
+    }
+
+    #[test]
+    fn only_piped_input_is_logged() {
+        let file = Config {
+            log_stdin: true,
+            stdin: StdinSource::File {
+                file: "answers.txt".into(),
+            },
+            ..Config::default()
+        };
+        assert!(logged(&file));
+        let null = Config {
+            stdin: StdinSource::Mode(StdinMode::Null),
+            ..file.clone()
+        };
+        assert!(!logged(&null));
+        let scripted = Config {
+            interact: InteractConfig {
+                rules: vec![InteractRule {
+                    expect: "Password:".into(),
+                    send: "x".into(),
+                    newline: true,
+                    secret: true,
+                    once: true,
+                }],
+            },
+            ..file.clone()
+        };
+        assert!(!logged(&scripted));
+        assert!(!logged(&Config::default()));
+    }
+}
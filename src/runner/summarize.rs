@@ -0,0 +1,75 @@
+//! The opt-in `post_failure_summarizer` hook.
+
+use crate::config::SummarizerConfig;
+use crate::redact::BUILTIN;
+use anyhow::{Context, Result};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+
+/// The failed run, as described to the summarizer.
+pub struct FailureContext<'a> {
+    pub cmd: &'a str,
+    pub run_id: &'a str,
+    pub code: i32,
+    pub failure_class: Option<&'a str>,
+}
+
+// Summaries longer than this are cut; they end up in the footer and on the terminal.
+static MAX_SUMMARY_BYTES: usize = 4096;
+
+// Pipe the redacted excerpt through the summarizer; Ok(None) if it printed nothing.
+pub async fn summarize_failure(
+    summarizer: &SummarizerConfig,
+    failure: &FailureContext<'_>,
+    excerpt: &[String],
+) -> Result<Option<String>> {
+    let mut input = String::new();
+    for line in excerpt {
+        input.push_str(&BUILTIN.redact(line));
+        input.push('\n');
+    }
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&summarizer.command)
+        .env("LG_CMD", failure.cmd)
+        .env("LG_RUN_ID", failure.run_id)
+        .env("LG_EXIT_CODE", failure.code.to_string())
+        .env(
+            "LG_FAILURE_CLASS",
+            failure.failure_class.unwrap_or("unknown"),
+        )
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("starting {:?}", summarizer.command))?;
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap().take(MAX_SUMMARY_BYTES as u64);
+    let work = async {
+        // A summarizer may not read all of its input; that's its business.
+        let feed = async {
+            let _ = stdin.write_all(input.as_bytes()).await;
+            drop(stdin);
+        };
+        let mut out = Vec::new();
+        let ((), read) = tokio::join!(feed, stdout.read_to_end(&mut out));
+        read?;
+        // Past the limit, the summarizer gets EPIPE rather than a reader.
+        drop(stdout);
+        let status = child.wait().await?;
+        anyhow::ensure!(status.success(), "exited with {}", status);
+        Ok(out)
+    };
+    let out = tokio::time::timeout(Duration::from_secs(summarizer.timeout_secs), work)
+        .await
+        .map_err(|_| anyhow::anyhow!("no summary after {}s", summarizer.timeout_secs))??;
+    let summary = String::from_utf8_lossy(&out);
+    let lines: Vec<&str> = summary
+        .lines()
+        .map(str::trim_end)
+        .filter(|l| !l.is_empty())
+        .collect();
+    Ok((!lines.is_empty()).then(|| lines.join("\n")))
+}
@@ -0,0 +1,205 @@
+//! `timeout`: stop a command that runs too long, SIGTERM first and SIGKILL
+//! once the grace period is over. Both go to the command's process group when
+//! it has one (see `signals::own_group`), so nothing it started outlives it.
+
+#[cfg(unix)]
+use crate::runner::signals;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::process::Child;
+use tokio::time::{sleep, Sleep};
+
+/// lg's exit status for a run it stopped, as with coreutils `timeout`.
+pub static TIMEOUT_EXIT_CODE: i32 = 124;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Running,
+    Terminated,
+    /// Whether a SIGTERM went first.
+    Killed {
+        after_term: bool,
+    },
+}
+
+/// The time limit of one run and how far lg got in enforcing it.
+pub struct Deadline {
+    limit: Duration,
+    grace: Duration,
+    /// Until the next step; None without a limit.
+    sleep: Option<Pin<Box<Sleep>>>,
+    stage: Stage,
+}
+
+impl Deadline {
+    /// Start counting `limit` from now; no deadline at all when it is None.
+    pub fn start(limit: Option<Duration>, grace: Duration) -> Self {
+        Self {
+            limit: limit.unwrap_or_default(),
+            grace,
+            sleep: limit.map(|limit| Box::pin(sleep(limit))),
+            stage: Stage::Running,
+        }
+    }
+
+    /// Whether a step is still to come.
+    pub fn armed(&self) -> bool {
+        self.sleep.is_some() && !self.killed()
+    }
+
+    /// Whether the command overran its limit.
+    pub fn fired(&self) -> bool {
+        self.stage != Stage::Running
+    }
+
+    pub fn killed(&self) -> bool {
+        matches!(self.stage, Stage::Killed { .. })
+    }
+
+    /// Resolves when the next step is due; never without a limit.
+    pub async fn expired(&mut self) {
+        match self.sleep.as_mut() {
+            Some(sleep) => sleep.as_mut().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Take the step that is due: SIGTERM, or SIGKILL once the grace period
+    /// is over (or when there is none). `group` is the command's process
+    /// group, if it has one. Returns the `[LG]` line to log.
+    pub fn fire(&mut self, child: &mut Child, group: Option<u32>) -> String {
+        if self.stage == Stage::Running && !self.grace.is_zero() && terminate(child, group) {
+            self.stage = Stage::Terminated;
+            if let Some(sleep) = self.sleep.as_mut() {
+                let at = sleep.deadline() + self.grace;
+                sleep.as_mut().reset(at);
+            }
+            return format!(
+                "timeout: still running after {}, SIGTERM sent (SIGKILL in {})",
+                human(self.limit),
+                human(self.grace)
+            );
+        }
+        let after_term = self.stage == Stage::Terminated;
+        self.stage = Stage::Killed { after_term };
+        kill(child, group);
+        if after_term {
+            format!(
+                "timeout: still running {} after SIGTERM, SIGKILL sent",
+                human(self.grace)
+            )
+        } else {
+            format!(
+                "timeout: still running after {}, SIGKILL sent",
+                human(self.limit)
+            )
+        }
+    }
+
+    /// `[timeout]` footer value, e.g. "30m, SIGTERM"; None if it never fired.
+    pub fn describe(&self) -> Option<String> {
+        let how = match self.stage {
+            Stage::Running => return None,
+            Stage::Terminated => "SIGTERM".to_string(),
+            Stage::Killed { after_term: false } => "SIGKILL".to_string(),
+            Stage::Killed { after_term: true } => {
+                format!("SIGKILL after {} grace", human(self.grace))
+            }
+        };
+        Some(format!("{}, {}", human(self.limit), how))
+    }
+}
+
+// The child's pid is gone once it has been reaped, and its group once all of
+// it has exited; a SIGKILL is all that is left then.
+#[cfg(unix)]
+fn terminate(child: &Child, group: Option<u32>) -> bool {
+    signals::send(child, group, libc::SIGTERM)
+}
+
+#[cfg(not(unix))]
+fn terminate(_child: &Child, _group: Option<u32>) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn kill(child: &mut Child, group: Option<u32>) {
+    if !signals::send(child, group, libc::SIGKILL) {
+        let _ = child.start_kill();
+    }
+}
+
+#[cfg(not(unix))]
+fn kill(child: &mut Child, _group: Option<u32>) {
+    let _ = child.start_kill();
+}
+
+// "90s", "30m", "1h30m": the units `timeout` is written in.
+fn human(d: Duration) -> String {
+    let secs = d.as_secs();
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    let mut out = String::new();
+    for (n, unit) in [(h, "h"), (m, "m"), (s, "s")] {
+        if n > 0 {
+            out.push_str(&format!("{}{}", n, unit));
+        }
+    }
+    if out.is_empty() {
+        out = format!("{}ms", d.as_millis());
+    }
+    out
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stubborn_command_is_killed_after_the_grace_period() {
+        let mut child = tokio::process::Command::new("sh")
+            .args(["-c", "trap '' TERM; exec sleep 30"])
+            .spawn()
+            .unwrap();
+        let mut deadline =
+            Deadline::start(Some(Duration::from_millis(100)), Duration::from_millis(100));
+        deadline.expired().await;
+        let note = deadline.fire(&mut child, None);
+        assert!(note.contains("SIGTERM sent"), "{}", note);
+        assert!(deadline.armed() && deadline.fired());
+        deadline.expired().await;
+        assert!(deadline.fire(&mut child, None).contains("SIGKILL sent"));
+        assert!(!deadline.armed());
+        use std::os::unix::process::ExitStatusExt;
+        assert_eq!(child.wait().await.unwrap().signal(), Some(libc::SIGKILL));
+        assert_eq!(
+            deadline.describe().unwrap(),
+            "100ms, SIGKILL after 100ms grace"
+        );
+        assert_eq!(human(Duration::from_secs(5400)), "1h30m");
+    }
+
+    #[tokio::test]
+    async fn what_the_command_started_is_stopped_with_it() {
+        use tokio::io::AsyncReadExt;
+        let mut child = tokio::process::Command::new("sh")
+            .args(["-c", "sleep 30; echo done"])
+            .stdout(std::process::Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let group = child.id();
+        let mut deadline =
+            Deadline::start(Some(Duration::from_millis(100)), Duration::from_secs(30));
+        deadline.expired().await;
+        assert!(deadline.fire(&mut child, group).contains("SIGTERM sent"));
+        // The `sleep` holds stdout open as long as it runs.
+        let mut out = Vec::new();
+        let mut stdout = child.stdout.take().unwrap();
+        tokio::time::timeout(Duration::from_secs(5), stdout.read_to_end(&mut out))
+            .await
+            .expect("the sleep outlived its shell")
+            .unwrap();
+        child.wait().await.unwrap();
+        assert!(out.is_empty());
+    }
+}
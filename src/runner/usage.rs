@@ -0,0 +1,146 @@
+//! What the command cost: wall-clock time, CPU time and peak memory, as
+//! `/usr/bin/time` would report them. Also what lg itself cost, for
+//! `lg stats --self`.
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Resource use of one run, written to the footer.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Usage {
+    pub duration_ms: u64,
+    /// CPU time of the command and the children it waited for (Unix).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_cpu_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sys_cpu_ms: Option<u64>,
+    /// Peak resident set size of the largest of those processes (Unix).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_rss_bytes: Option<u64>,
+}
+
+impl Usage {
+    /// Usage of the command spawned at `started`, read right after it was
+    /// waited for. lg has no other children at that point, so its children's
+    /// totals are the command's.
+    pub fn collect(started: Instant) -> Self {
+        let mut usage = Self {
+            duration_ms: started.elapsed().as_millis() as u64,
+            user_cpu_ms: None,
+            sys_cpu_ms: None,
+            max_rss_bytes: None,
+        };
+        #[cfg(unix)]
+        if let Some(ru) = rusage(libc::RUSAGE_CHILDREN) {
+            usage.user_cpu_ms = Some(ms(ru.ru_utime));
+            usage.sys_cpu_ms = Some(ms(ru.ru_stime));
+            usage.max_rss_bytes = Some(max_rss_bytes(ru.ru_maxrss as u64));
+        }
+        usage
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms)
+    }
+
+    /// The `[duration]`, `[cpu]` and `[max_rss]` footer lines, as (key, value).
+    pub fn footer_lines(&self) -> Vec<(&'static str, String)> {
+        let secs = |ms: u64| format!("{:.3}s", ms as f64 / 1000.0);
+        let mut lines = vec![("duration", secs(self.duration_ms))];
+        if let (Some(user), Some(sys)) = (self.user_cpu_ms, self.sys_cpu_ms) {
+            lines.push(("cpu", format!("user {}, sys {}", secs(user), secs(sys))));
+        }
+        if let Some(rss) = self.max_rss_bytes {
+            lines.push(("max_rss", crate::budget::fmt_size(rss as usize)));
+        }
+        lines
+    }
+}
+
+/// When lg started; `main` reads it before anything else.
+pub static LG_STARTED: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// What wrapping the command cost lg, recorded in the run index.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Overhead {
+    /// From lg starting to the command being spawned.
+    pub startup_ms: Option<u64>,
+    /// Output lg read from the command.
+    pub bytes: u64,
+    /// CPU time lg used, user and system together (Unix).
+    pub cpu_ms: Option<u64>,
+}
+
+impl Overhead {
+    /// The overhead of a command spawned at `spawned_at`.
+    pub fn spawned(spawned_at: Instant) -> Self {
+        Self {
+            startup_ms: Some(spawned_at.duration_since(*LG_STARTED).as_millis() as u64),
+            ..Self::default()
+        }
+    }
+
+    /// With lg's CPU time so far, read when the run is over.
+    pub fn with_cpu_time(self) -> Self {
+        #[cfg(unix)]
+        if let Some(ru) = rusage(libc::RUSAGE_SELF) {
+            return Self {
+                cpu_ms: Some(ms(ru.ru_utime) + ms(ru.ru_stime)),
+                ..self
+            };
+        }
+        self
+    }
+}
+
+#[cfg(unix)]
+fn rusage(who: libc::c_int) -> Option<libc::rusage> {
+    // SAFETY: getrusage only writes into the provided struct.
+    unsafe {
+        let mut ru: libc::rusage = std::mem::zeroed();
+        (libc::getrusage(who, &mut ru) == 0).then_some(ru)
+    }
+}
+
+#[cfg(unix)]
+fn ms(t: libc::timeval) -> u64 {
+    t.tv_sec as u64 * 1000 + t.tv_usec as u64 / 1000
+}
+
+// macOS reports ru_maxrss in bytes, Linux and the BSDs in kilobytes.
+#[cfg(unix)]
+fn max_rss_bytes(maxrss: u64) -> u64 {
+    if cfg!(target_os = "macos") {
+        maxrss
+    } else {
+        maxrss * 1024
+    }
+}
+
+/// Seconds from a `[duration]` footer value such as "12.345s".
+pub fn parse_duration_secs(value: &str) -> Option<f64> {
+    value.trim().strip_suffix('s')?.parse().ok()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn busy_child_shows_cpu_time_and_memory() {
+        let started = Instant::now();
+        let status = std::process::Command::new("sh")
+            .args(["-c", "i=0; while [ $i -lt 20000 ]; do i=$((i+1)); done"])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let usage = Usage::collect(started);
+        assert!(usage.user_cpu_ms.unwrap() + usage.sys_cpu_ms.unwrap() > 0);
+        assert!(usage.max_rss_bytes.unwrap() > 100 * 1024);
+        let lines = usage.footer_lines();
+        assert_eq!(lines[0].0, "duration");
+        let secs = parse_duration_secs(&lines[0].1).unwrap();
+        assert!((secs - usage.duration().as_secs_f64()).abs() < 0.001);
+    }
+}
@@ -0,0 +1,231 @@
+//! Where log lines end up: the writer stack behind each log file.
+
+pub mod incompressible;
+pub mod parallel_gz;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+mod uring;
+
+use crate::config::{Collision, Compress, Config, IoBackend};
+use anyhow::{Context, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use incompressible::{CompressionNote, SkipIncompressible};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Deflate window and hash tables at the default compression level.
+pub static GZ_STATE_BYTES: usize = 256 * 1024;
+
+/// Rough size of a zstd compression stream: its window (which grows with the
+/// level) plus match-finder tables of about half that.
+pub fn zstd_state_bytes(level: i32) -> usize {
+    let window_log = match level {
+        ..=1 => 19,
+        2 => 20,
+        3..=5 => 21,
+        6..=15 => 22,
+        16..=19 => 23,
+        _ => 25,
+    };
+    (1usize << window_log) * 3 / 2
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    pub fn label(self) -> &'static str {
+        match self {
+            Stream::Stdout => "STDOUT",
+            Stream::Stderr => "STDERR",
+        }
+    }
+
+    /// As `jsonl` records name it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+}
+
+/// Open log file(s) for one run: a single combined file or a stdout/stderr pair.
+pub enum LogWriters {
+    Combined(Box<dyn Write + Send>),
+    Split {
+        out: Box<dyn Write + Send>,
+        err: Box<dyn Write + Send>,
+    },
+}
+
+impl LogWriters {
+    pub fn for_stream(&mut self, stream: Stream) -> &mut (dyn Write + Send) {
+        match self {
+            LogWriters::Combined(w) => &mut **w,
+            LogWriters::Split { out, .. } if stream == Stream::Stdout => &mut **out,
+            LogWriters::Split { err, .. } => &mut **err,
+        }
+    }
+
+    // Apply `f` to every open file (headers, footers and markers go to all of them).
+    pub fn for_each(
+        &mut self,
+        mut f: impl FnMut(&mut (dyn Write + Send)) -> Result<()>,
+    ) -> Result<()> {
+        match self {
+            LogWriters::Combined(w) => f(&mut **w),
+            LogWriters::Split { out, err } => {
+                f(&mut **out)?;
+                f(&mut **err)
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.for_each(|w| Ok(w.flush()?))
+    }
+}
+
+/// `note` learns whether the writer stopped compressing (`skip_incompressible`).
+pub fn open_writer(
+    cfg: &Config,
+    final_path: &Path,
+    note: &CompressionNote,
+) -> Result<(Box<dyn Write + Send>, PathBuf)> {
+    // Lines are batched in a large buffer in front of the file (or encoder)
+    // so verbose children don't cost a syscall per line.
+    let capacity = cfg.write_buffer_size.max(1);
+    let level = cfg.compress_level()?;
+    let file = open_file_sink(cfg, final_path)?;
+    let boxed: Box<dyn Write + Send> = match cfg.compress {
+        Compress::None => Box::new(io::BufWriter::with_capacity(capacity, file)),
+        Compress::Gz if cfg.compress_threads > 0 => {
+            let level = Compression::new(level as u32);
+            let enc = parallel_gz::ParallelGzWriter::new(file, cfg.compress_threads, level);
+            Box::new(io::BufWriter::with_capacity(capacity, enc))
+        }
+        Compress::Gz if cfg.skip_incompressible => {
+            let enc = SkipIncompressible::gz(file, Compression::new(level as u32), note.clone());
+            Box::new(io::BufWriter::with_capacity(capacity, enc))
+        }
+        Compress::Gz => {
+            let enc = GzEncoder::new(file, Compression::new(level as u32));
+            Box::new(io::BufWriter::with_capacity(capacity, enc))
+        }
+        Compress::Zstd if cfg.skip_incompressible => {
+            let enc = SkipIncompressible::zstd(file, level, note.clone())
+                .context("starting zstd encoder")?;
+            Box::new(io::BufWriter::with_capacity(capacity, enc))
+        }
+        Compress::Zstd => {
+            // Like the gzip encoder, finish the frame when the writer is dropped.
+            let enc = zstd::Encoder::new(file, level)
+                .context("starting zstd encoder")?
+                .on_finish(|_| ());
+            Box::new(io::BufWriter::with_capacity(capacity, enc))
+        }
+    };
+    Ok((boxed, final_path.to_path_buf()))
+}
+
+// Create the file at the bottom of the writer stack using the configured I/O backend.
+fn open_file_sink(cfg: &Config, path: &Path) -> Result<Box<dyn Write + Send>> {
+    let file = match cfg.collision {
+        // A compressed log gets another gzip member or zstd frame.
+        Collision::Append => OpenOptions::new().append(true).create(true).open(path),
+        _ => File::create(path),
+    };
+    let file = file.with_context(|| format!("create file {:?}", path))?;
+    if cfg.io_backend == IoBackend::Uring {
+        #[cfg(all(feature = "uring", target_os = "linux"))]
+        match uring::UringFile::new(file.try_clone()?, cfg.write_buffer_size) {
+            Ok(w) => return Ok(Box::new(w)),
+            Err(err) => eprintln!("lg: io_uring unavailable ({}), using standard writes", err),
+        }
+        #[cfg(not(all(feature = "uring", target_os = "linux")))]
+        eprintln!(
+            "lg: io_backend = \"uring\" needs a Linux build with the `uring` feature; using standard writes"
+        );
+    }
+    Ok(Box::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lg-test-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn gz_writers_produce_gzip() {
+        let dir = scratch("sinks-gz");
+        for threads in [0, 2] {
+            let cfg = Config {
+                compress: Compress::Gz,
+                compress_threads: threads,
+                ..Config::default()
+            };
+            let path = dir.join(format!("t{}.log.gz", threads));
+            let (mut w, _) = open_writer(&cfg, &path, &CompressionNote::default()).unwrap();
+            for i in 0..10_000 {
+                writeln!(w, "line {}", i).unwrap();
+            }
+            drop(w);
+            let mut text = String::new();
+            MultiGzDecoder::new(File::open(&path).unwrap())
+                .read_to_string(&mut text)
+                .unwrap();
+            assert_eq!(text.lines().count(), 10_000);
+            assert_eq!(text.lines().last(), Some("line 9999"));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn zstd_writer_finishes_the_frame_on_drop() {
+        let dir = scratch("sinks-zstd");
+        let cfg = Config {
+            compress: Compress::Zstd,
+            compress_level: Some(19),
+            ..Config::default()
+        };
+        let path = dir.join("t.log.zst");
+        let (mut w, _) = open_writer(&cfg, &path, &CompressionNote::default()).unwrap();
+        writeln!(w, "only line").unwrap();
+        drop(w);
+        let data = zstd::decode_all(File::open(&path).unwrap()).unwrap();
+        assert_eq!(data, b"only line\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn split_writers_route_by_stream() {
+        let dir = scratch("sinks-split");
+        let cfg = Config::default();
+        let (out, _) =
+            open_writer(&cfg, &dir.join("x.out.log"), &CompressionNote::default()).unwrap();
+        let (err, _) =
+            open_writer(&cfg, &dir.join("x.err.log"), &CompressionNote::default()).unwrap();
+        let mut writers = LogWriters::Split { out, err };
+        writers.for_each(|w| Ok(writeln!(w, "header")?)).unwrap();
+        writeln!(writers.for_stream(Stream::Stdout), "to out").unwrap();
+        writeln!(writers.for_stream(Stream::Stderr), "to err").unwrap();
+        writers.flush().unwrap();
+        let read = |name| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("x.out.log"), "header\nto out\n");
+        assert_eq!(read("x.err.log"), "header\nto err\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
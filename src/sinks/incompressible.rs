@@ -0,0 +1,214 @@
+//! `skip_incompressible`: stop compressing output that doesn't compress
+//! (archives, media, encrypted dumps). If the encoder saved next to nothing on
+//! the first `SAMPLE_BYTES`, the rest of the log goes into a new gzip member
+//! or zstd frame at the cheapest level; readers still see one .gz / .zst file.
+
+use crate::budget::fmt_size;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{self, Write};
+use std::sync::{Arc, OnceLock};
+
+/// Input looked at before deciding.
+const SAMPLE_BYTES: u64 = 64 * 1024;
+/// Compressed size, as a share of the input, from which compressing isn't worth it.
+const MIN_RATIO: f64 = 0.9;
+
+/// What a run's log writers decided, for the footer's `[compression]` line;
+/// unset while they compress as configured.
+pub type CompressionNote = Arc<OnceLock<String>>;
+
+// Counts what the encoder produced.
+struct Counted<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+enum Encoder<W: Write> {
+    Gz(GzEncoder<Counted<W>>),
+    Zstd(zstd::Encoder<'static, Counted<W>>),
+}
+
+impl<W: Write> Encoder<W> {
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Encoder::Gz(enc) => enc,
+            Encoder::Zstd(enc) => enc,
+        }
+    }
+
+    fn written(&self) -> u64 {
+        match self {
+            Encoder::Gz(enc) => enc.get_ref().written,
+            Encoder::Zstd(enc) => enc.get_ref().written,
+        }
+    }
+
+    fn finish(self) -> io::Result<Counted<W>> {
+        match self {
+            Encoder::Gz(enc) => enc.finish(),
+            Encoder::Zstd(enc) => enc.finish(),
+        }
+    }
+
+    // End the current member or frame and go on in a stored one.
+    fn stored(self) -> io::Result<Self> {
+        Ok(match self {
+            Encoder::Gz(enc) => Encoder::Gz(GzEncoder::new(enc.finish()?, Compression::none())),
+            Encoder::Zstd(enc) => {
+                let fastest = *zstd::compression_level_range().start();
+                Encoder::Zstd(zstd::Encoder::new(enc.finish()?, fastest)?)
+            }
+        })
+    }
+}
+
+/// A gzip or zstd encoder that gives up compressing when it doesn't pay.
+pub struct SkipIncompressible<W: Write> {
+    encoder: Option<Encoder<W>>,
+    /// Input bytes so far; None once decided.
+    sampled: Option<u64>,
+    note: CompressionNote,
+}
+
+impl<W: Write> SkipIncompressible<W> {
+    pub fn gz(inner: W, level: Compression, note: CompressionNote) -> Self {
+        let enc = GzEncoder::new(Counted { inner, written: 0 }, level);
+        Self::new(Encoder::Gz(enc), note)
+    }
+
+    pub fn zstd(inner: W, level: i32, note: CompressionNote) -> io::Result<Self> {
+        let enc = zstd::Encoder::new(Counted { inner, written: 0 }, level)?;
+        Ok(Self::new(Encoder::Zstd(enc), note))
+    }
+
+    fn new(encoder: Encoder<W>, note: CompressionNote) -> Self {
+        Self {
+            encoder: Some(encoder),
+            sampled: Some(0),
+            note,
+        }
+    }
+
+    fn encoder(&mut self) -> io::Result<&mut Encoder<W>> {
+        self.encoder
+            .as_mut()
+            .ok_or_else(|| io::Error::other("log encoder failed to restart"))
+    }
+
+    fn decide(&mut self, sampled: u64) -> io::Result<()> {
+        let enc = self.encoder()?;
+        enc.writer().flush()?;
+        let ratio = enc.written() as f64 / sampled as f64;
+        if ratio < MIN_RATIO {
+            return Ok(());
+        }
+        if let Some(enc) = self.encoder.take() {
+            self.encoder = Some(enc.stored()?);
+        }
+        let _ = self.note.set(format!(
+            "stored: the first {} of output only compressed to {:.0}%",
+            fmt_size(sampled as usize),
+            ratio * 100.0
+        ));
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for SkipIncompressible<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Decide right at the end of the sample.
+        let buf = match self.sampled {
+            Some(sampled) => &buf[..buf.len().min((SAMPLE_BYTES - sampled) as usize)],
+            None => buf,
+        };
+        let n = self.encoder()?.writer().write(buf)?;
+        if let Some(sampled) = self.sampled.as_mut() {
+            *sampled += n as u64;
+            if *sampled >= SAMPLE_BYTES {
+                let sampled = *sampled;
+                self.sampled = None;
+                self.decide(sampled)?;
+            }
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder()?.writer().flush()
+    }
+}
+
+impl<W: Write> Drop for SkipIncompressible<W> {
+    fn drop(&mut self) {
+        if let Some(enc) = self.encoder.take() {
+            let _ = enc.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+
+    // Bytes no compressor can shrink (xorshift).
+    fn noise(len: usize) -> Vec<u8> {
+        let mut x: u64 = 0x9E37_79B9_7F4A_7C15;
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn noise_is_stored_and_text_compressed() {
+        let text = "12:00:00 [STDOUT] Compiling serde v1.0.200\n".repeat(5000);
+        let noise = noise(256 * 1024);
+        for (input, skipped) in [(text.as_bytes(), false), (&noise[..], true)] {
+            let (note, zstd_note) = (CompressionNote::default(), CompressionNote::default());
+            let mut gz = Vec::new();
+            let mut zst = Vec::new();
+            {
+                let mut w = SkipIncompressible::gz(&mut gz, Compression::default(), note.clone());
+                w.write_all(input).unwrap();
+                let mut w = SkipIncompressible::zstd(&mut zst, 3, zstd_note.clone()).unwrap();
+                w.write_all(input).unwrap();
+            }
+            assert_eq!(note.get().is_some(), skipped);
+            assert_eq!(zstd_note.get().is_some(), skipped);
+            let mut plain = Vec::new();
+            MultiGzDecoder::new(&gz[..])
+                .read_to_end(&mut plain)
+                .unwrap();
+            assert_eq!(plain, input);
+            assert_eq!(zstd::decode_all(&zst[..]).unwrap(), input);
+            if skipped {
+                assert!(note
+                    .get()
+                    .unwrap()
+                    .starts_with("stored: the first 64.0 KiB"));
+                assert!(gz.len() < input.len() + 1024);
+            } else {
+                assert!(gz.len() < input.len() / 10);
+            }
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! pigz-style gzip: the stream is cut into fixed-size chunks that a pool of
+//! worker threads compress into independent gzip members, which a writer
+//! thread appends in order. Concatenated members form a valid gzip file
+//! (`zcat`, `gzip -d` and `MultiGzDecoder` read them transparently).
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+
+const CHUNK_SIZE: usize = 256 * 1024;
+
+// Sequence number plus raw (or compressed) bytes
+type Chunk = (u64, Vec<u8>);
+
+#[derive(Default)]
+struct Progress {
+    written: u64,
+    error: Option<io::Error>,
+}
+
+pub struct ParallelGzWriter {
+    current: Vec<u8>,
+    next_seq: u64,
+    jobs: Option<SyncSender<Chunk>>,
+    workers: Vec<JoinHandle<()>>,
+    writer: Option<JoinHandle<()>>,
+    progress: Arc<(Mutex<Progress>, Condvar)>,
+}
+
+/// Worst-case bytes held by the pipeline: the chunk being filled and, per
+/// worker, two queued inputs, one chunk in and out of the encoder, two
+/// finished members and the encoder state.
+pub fn max_buffered(threads: usize) -> usize {
+    let threads = threads.max(1);
+    CHUNK_SIZE * (1 + 6 * threads) + threads * super::GZ_STATE_BYTES
+}
+
+impl ParallelGzWriter {
+    pub fn new(sink: Box<dyn Write + Send>, threads: usize, level: Compression) -> Self {
+        let threads = threads.max(1);
+        // Bounded queues keep memory use at a few chunks per worker.
+        let (job_tx, job_rx) = mpsc::sync_channel::<Chunk>(threads * 2);
+        let (done_tx, done_rx) = mpsc::sync_channel::<Chunk>(threads * 2);
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let workers = (0..threads)
+            .map(|_| {
+                let job_rx = Arc::clone(&job_rx);
+                let done_tx = done_tx.clone();
+                std::thread::spawn(move || compress_worker(job_rx, done_tx, level))
+            })
+            .collect();
+        drop(done_tx);
+        let progress = Arc::new((Mutex::new(Progress::default()), Condvar::new()));
+        let writer_progress = Arc::clone(&progress);
+        let writer = std::thread::spawn(move || write_members(sink, done_rx, writer_progress));
+        Self {
+            current: Vec::with_capacity(CHUNK_SIZE),
+            next_seq: 0,
+            jobs: Some(job_tx),
+            workers,
+            writer: Some(writer),
+            progress,
+        }
+    }
+
+    fn dispatch(&mut self) -> io::Result<()> {
+        if self.current.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.current, Vec::with_capacity(CHUNK_SIZE));
+        let sent = self
+            .jobs
+            .as_ref()
+            .map(|jobs| jobs.send((self.next_seq, chunk)).is_ok())
+            .unwrap_or(false);
+        if !sent {
+            return Err(self.take_error());
+        }
+        self.next_seq += 1;
+        Ok(())
+    }
+
+    fn take_error(&self) -> io::Error {
+        let mut progress = self.progress.0.lock().unwrap();
+        progress
+            .error
+            .take()
+            .unwrap_or_else(|| io::Error::other("gzip pipeline stopped"))
+    }
+
+    // Block until every dispatched chunk has reached the sink.
+    fn wait_written(&self) -> io::Result<()> {
+        let (lock, cvar) = &*self.progress;
+        let mut progress = lock.lock().unwrap();
+        while progress.written < self.next_seq && progress.error.is_none() {
+            progress = cvar.wait(progress).unwrap();
+        }
+        match progress.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+fn compress_worker(jobs: Arc<Mutex<Receiver<Chunk>>>, done: SyncSender<Chunk>, level: Compression) {
+    loop {
+        let job = jobs.lock().unwrap().recv();
+        let Ok((seq, data)) = job else { return };
+        let mut enc = GzEncoder::new(Vec::with_capacity(data.len() / 2), level);
+        let member = enc.write_all(&data).and_then(|_| enc.finish());
+        // Writing into a Vec cannot fail; an empty member would still keep ordering intact.
+        if done.send((seq, member.unwrap_or_default())).is_err() {
+            return;
+        }
+    }
+}
+
+fn write_members(
+    mut sink: Box<dyn Write + Send>,
+    done: Receiver<Chunk>,
+    progress: Arc<(Mutex<Progress>, Condvar)>,
+) {
+    let mut pending = BTreeMap::new();
+    let mut next = 0u64;
+    for (seq, member) in done {
+        pending.insert(seq, member);
+        while let Some(member) = pending.remove(&next) {
+            let res = sink.write_all(&member).and_then(|_| sink.flush());
+            let (lock, cvar) = &*progress;
+            let mut state = lock.lock().unwrap();
+            match res {
+                Ok(()) => state.written = next + 1,
+                Err(err) => {
+                    state.error = Some(err);
+                    cvar.notify_all();
+                    return;
+                }
+            }
+            cvar.notify_all();
+            next += 1;
+        }
+    }
+}
+
+impl Write for ParallelGzWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.current.extend_from_slice(buf);
+        if self.current.len() >= CHUNK_SIZE {
+            self.dispatch()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.dispatch()?;
+        self.wait_written()
+    }
+}
+
+impl Drop for ParallelGzWriter {
+    fn drop(&mut self) {
+        let _ = self.dispatch();
+        // Closing the job queue lets workers, then the writer thread, drain and exit.
+        self.jobs.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! `io_backend = "uring"`: log file writes submitted through io_uring.
+
+use io_uring::{opcode, types, IoUring};
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+
+/// File writer that hands full buffers to io_uring and keeps filling a
+/// second buffer while the kernel writes the first one.
+pub struct UringFile {
+    ring: IoUring,
+    file: File,
+    offset: u64,
+    chunk: usize,
+    active: Vec<u8>,
+    // Buffer owned by the kernel until its completion is reaped
+    in_flight: Option<(Vec<u8>, u64)>,
+}
+
+impl UringFile {
+    pub fn new(file: File, chunk: usize) -> io::Result<Self> {
+        let chunk = chunk.max(4096);
+        // After what is there already, for `collision = "append"`.
+        let offset = file.metadata()?.len();
+        Ok(Self {
+            ring: IoUring::new(4)?,
+            file,
+            offset,
+            chunk,
+            active: Vec::with_capacity(chunk),
+            in_flight: None,
+        })
+    }
+
+    fn reap(&mut self) -> io::Result<()> {
+        let Some((buf, offset)) = self.in_flight.take() else {
+            return Ok(());
+        };
+        self.ring.submit_and_wait(1)?;
+        let res = self
+            .ring
+            .completion()
+            .next()
+            .map(|cqe| cqe.result())
+            .ok_or_else(|| io::Error::other("missing io_uring completion"))?;
+        if res < 0 {
+            return Err(io::Error::from_raw_os_error(-res));
+        }
+        // Short writes are rare on regular files; finish them synchronously.
+        let written = res as usize;
+        if written < buf.len() {
+            self.file
+                .write_all_at(&buf[written..], offset + written as u64)?;
+        }
+        Ok(())
+    }
+
+    fn submit_active(&mut self) -> io::Result<()> {
+        self.reap()?;
+        if self.active.is_empty() {
+            return Ok(());
+        }
+        let buf = std::mem::replace(&mut self.active, Vec::with_capacity(self.chunk));
+        let entry = opcode::Write::new(
+            types::Fd(self.file.as_raw_fd()),
+            buf.as_ptr(),
+            buf.len() as u32,
+        )
+        .offset(self.offset)
+        .build();
+        // SAFETY: the buffer is kept alive in `in_flight` until `reap` has
+        // observed its completion; moving the Vec does not move its heap data.
+        unsafe {
+            self.ring
+                .submission()
+                .push(&entry)
+                .map_err(|_| io::Error::other("io_uring queue full"))?;
+        }
+        self.ring.submit()?;
+        let offset = self.offset;
+        self.offset += buf.len() as u64;
+        self.in_flight = Some((buf, offset));
+        Ok(())
+    }
+}
+
+impl Write for UringFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.active.extend_from_slice(buf);
+        if self.active.len() >= self.chunk {
+            self.submit_active()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.submit_active()?;
+        self.reap()
+    }
+}
+
+impl Drop for UringFile {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
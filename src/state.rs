@@ -0,0 +1,601 @@
+//! lg's own state under `~/.local/share/lg`: environment baseline, running runs, newest log per directory, run groups, run baselines, `{seq}` counters, program digests.
+
+use crate::baseline::Baseline;
+use crate::config::simple_home_dir;
+use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Where lg keeps its own state: `$XDG_DATA_HOME/lg` or `~/.local/share/lg`.
+/// Tests point it at a temporary directory instead.
+pub struct StateDir(PathBuf);
+
+impl StateDir {
+    #[cfg(test)]
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self(root.into())
+    }
+
+    pub fn locate() -> Option<Self> {
+        Self::resolve(std::env::var_os("XDG_DATA_HOME"), simple_home_dir())
+    }
+
+    fn resolve(xdg_data_home: Option<OsString>, home: Option<PathBuf>) -> Option<Self> {
+        xdg_data_home
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home.map(|h| h.join(".local").join("share")))
+            .map(|d| Self(d.join("lg")))
+    }
+
+    pub fn env_baseline(&self) -> PathBuf {
+        self.0.join("env-baseline.json")
+    }
+
+    // Per-directory pointer to the newest log, keyed by a hash of the directory,
+    // so `lg last-path` finds it wherever output_dir points.
+    pub fn latest(&self, cwd: &Path) -> PathBuf {
+        self.0.join("latest").join(dir_key(cwd))
+    }
+
+    // Best effort, like the running registry.
+    pub fn record_latest(&self, cwd: &Path, log: &Path) {
+        let file = self.latest(cwd);
+        let log = fs::canonicalize(log).unwrap_or_else(|_| log.to_path_buf());
+        if let Some(dir) = file.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = replace_file(&file, format!("{}\n", log.display()).as_bytes());
+    }
+
+    /// The next `{seq}` of the output directory `dir`, from 1. The counter
+    /// is locked while it is bumped, so runs starting together get distinct
+    /// numbers.
+    pub fn next_seq(&self, dir: &Path) -> Result<u64> {
+        let path = self.0.join("seq").join(dir_key(dir));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("creating {:?}", parent))?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("opening {:?}", path))?;
+        // Released when the file is closed.
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+                return Err(io::Error::last_os_error())
+                    .with_context(|| format!("locking {:?}", path));
+            }
+        }
+        let mut last = String::new();
+        file.read_to_string(&mut last)
+            .with_context(|| format!("reading {:?}", path))?;
+        let seq = last.trim().parse::<u64>().unwrap_or(0) + 1;
+        file.seek(SeekFrom::Start(0))
+            .and_then(|_| file.set_len(0))
+            .and_then(|_| writeln!(file, "{}", seq))
+            .with_context(|| format!("writing {:?}", path))?;
+        Ok(seq)
+    }
+
+    // `digests/<hash of the path>`: the program's digest for the fingerprint,
+    // with the file's identity it was taken from.
+    pub fn program_digest(&self, program: &Path) -> PathBuf {
+        self.0.join("digests").join(dir_key(program))
+    }
+
+    pub fn index(&self) -> PathBuf {
+        self.0.join("index.sqlite")
+    }
+
+    // `baselines/<hash of the command line>.json`: a run reads only its own.
+    fn baseline(&self, cmd: &str, args: &str) -> PathBuf {
+        let digest = Sha256::digest(format!("{}\0{}", cmd, args));
+        let key: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+        self.0.join("baselines").join(format!("{}.json", key))
+    }
+
+    pub fn load_baseline(&self, cmd: &str, args: &str) -> Result<Option<Baseline>> {
+        let path = self.baseline(cmd, args);
+        match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map(Some)
+                .with_context(|| format!("parsing {:?}", path)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).with_context(|| format!("reading {:?}", path)),
+        }
+    }
+
+    /// Pin `baseline` for its command line, in place of any earlier one.
+    pub fn save_baseline(&self, baseline: &Baseline) -> Result<()> {
+        let path = self.baseline(&baseline.cmd, &baseline.args);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("creating {:?}", dir))?;
+        }
+        replace_file(&path, &serde_json::to_vec(baseline)?)
+            .with_context(|| format!("writing {:?}", path))
+    }
+
+    /// Whether there was a baseline to remove.
+    pub fn remove_baseline(&self, cmd: &str, args: &str) -> Result<bool> {
+        let path = self.baseline(cmd, args);
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err).with_context(|| format!("removing {:?}", path)),
+        }
+    }
+
+    /// Every pinned baseline, by command line.
+    pub fn baselines(&self) -> Result<Vec<Baseline>> {
+        let dir = self.0.join("baselines");
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err).with_context(|| format!("reading {:?}", dir)),
+        };
+        let mut baselines = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                let data = fs::read(&path).with_context(|| format!("reading {:?}", path))?;
+                let baseline: Baseline =
+                    serde_json::from_slice(&data).with_context(|| format!("parsing {:?}", path))?;
+                baselines.push(baseline);
+            }
+        }
+        baselines.sort_by_key(Baseline::command);
+        Ok(baselines)
+    }
+
+    fn running(&self) -> PathBuf {
+        self.0.join("running")
+    }
+
+    // `groups/<id>.json` describes a group, `groups/<id>.runs` lists its runs.
+    fn group(&self, id: &str) -> PathBuf {
+        self.0
+            .join("groups")
+            .join(format!("{}.json", file_safe(id)))
+    }
+
+    /// Start a group named `name`; its id is the name and the start time,
+    /// e.g. "nightly-20261016-020000".
+    pub fn start_group(
+        &self,
+        name: &str,
+        budget_secs: Option<u64>,
+        now: NaiveDateTime,
+    ) -> Result<Group> {
+        let base = format!("{}-{}", file_safe(name), now.format("%Y%m%d-%H%M%S"));
+        let mut n = 1;
+        loop {
+            let id = match n {
+                1 => base.clone(),
+                n => format!("{}-{}", base, n),
+            };
+            let path = self.group(&id);
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).with_context(|| format!("creating {:?}", dir))?;
+            }
+            // Two starts within a second get different ids.
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    let group = Group {
+                        id,
+                        name: name.to_string(),
+                        started: now,
+                        ended: None,
+                        budget_secs,
+                    };
+                    file.write_all(&serde_json::to_vec(&group)?)
+                        .with_context(|| format!("writing {:?}", path))?;
+                    return Ok(group);
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => n += 1,
+                Err(err) => return Err(err).with_context(|| format!("creating {:?}", path)),
+            }
+        }
+    }
+
+    pub fn end_group(&self, group: &mut Group, now: NaiveDateTime) -> Result<()> {
+        group.ended = Some(now);
+        let path = self.group(&group.id);
+        replace_file(&path, &serde_json::to_vec(group)?)
+            .with_context(|| format!("writing {:?}", path))
+    }
+
+    /// The group `id` and the runs it has had so far; no `Group` for an id
+    /// that was only ever set in `LG_GROUP`.
+    pub fn load_group(&self, id: &str) -> Result<(Option<Group>, Vec<GroupRun>)> {
+        let path = self.group(id);
+        let group = match fs::read(&path) {
+            Ok(data) => {
+                Some(serde_json::from_slice(&data).with_context(|| format!("parsing {:?}", path))?)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err).with_context(|| format!("reading {:?}", path)),
+        };
+        let runs = match fs::read_to_string(path.with_extension("runs")) {
+            // A line cut short by a crash is skipped.
+            Ok(data) => data
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        if group.is_none() && runs.is_empty() {
+            anyhow::bail!("no group {:?}", id);
+        }
+        Ok((group, runs))
+    }
+
+    // Best effort, like the running registry. One append per run, so runs
+    // ending at the same time don't mix their lines.
+    pub fn record_group_run(&self, id: &str, run: &GroupRun) {
+        let path = self.group(id).with_extension("runs");
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let Ok(mut line) = serde_json::to_vec(run) else {
+            return;
+        };
+        line.push(b'\n');
+        if let Ok(mut file) = OpenOptions::new().append(true).create(true).open(&path) {
+            let _ = file.write_all(&line);
+        }
+    }
+}
+
+// A directory as a file name: a hash of its path.
+fn dir_key(dir: &Path) -> String {
+    let digest = Sha256::digest(dir.as_os_str().as_encoded_bytes());
+    digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// A group id as a file name.
+fn file_safe(id: &str) -> String {
+    id.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// A group of runs (`lg group start`); runs join it through `LG_GROUP`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Group {
+    pub id: String,
+    pub name: String,
+    pub started: NaiveDateTime,
+    /// Set by `lg group end`.
+    #[serde(default)]
+    pub ended: Option<NaiveDateTime>,
+    /// Wall time the group's runs may add up to (`lg group start --budget`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget_secs: Option<u64>,
+}
+
+/// A finished run of a group, as recorded when it ended.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupRun {
+    pub cmd: String,
+    pub args: String,
+    pub started: NaiveDateTime,
+    pub duration_secs: Option<f64>,
+    pub exit_code: i32,
+    /// The log (stdout's of a split pair); None when `keep` discarded it.
+    pub log: Option<PathBuf>,
+}
+
+/// Environment of a fresh login shell, used as the reference for `env_diff`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnvBaseline {
+    pub captured: String,
+    pub shell: String,
+    pub vars: BTreeMap<String, String>,
+}
+
+pub fn env_baseline_path() -> Result<PathBuf> {
+    StateDir::locate()
+        .map(|d| d.env_baseline())
+        .context("cannot determine lg state directory (HOME unset)")
+}
+
+pub fn load_env_baseline() -> Result<EnvBaseline> {
+    let path = env_baseline_path()?;
+    let data = fs::read_to_string(&path).with_context(|| format!("reading {:?}", path))?;
+    serde_json::from_str(&data).with_context(|| format!("parsing {:?}", path))
+}
+
+/// A fresh name next to `path` for writing what then replaces it; unique
+/// across processes and threads.
+pub fn temp_path(path: &Path) -> PathBuf {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    path.with_file_name(format!(".{}.{}-{}.tmp", name, std::process::id(), n))
+}
+
+/// Write `data` to `path` in one step: other lg processes reading it see
+/// the old contents or the new, never a half-written file.
+pub fn replace_file(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = temp_path(path);
+    fs::write(&tmp, data)?;
+    let renamed = fs::rename(&tmp, path);
+    if renamed.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    renamed
+}
+
+pub fn latest_file(cwd: &Path) -> Option<PathBuf> {
+    StateDir::locate().map(|d| d.latest(cwd))
+}
+
+pub fn record_latest(cwd: &Path, log: &Path) {
+    if let Some(dir) = StateDir::locate() {
+        dir.record_latest(cwd, log);
+    }
+}
+
+pub fn record_group_run(id: &str, run: &GroupRun) {
+    if let Some(dir) = StateDir::locate() {
+        dir.record_group_run(id, run);
+    }
+}
+
+/// A running lg, announced in `<state dir>/running/<pid>.json` for `lg tail
+/// --all-running`. The file is removed when the run ends; entries left by a
+/// killed lg are ignored because their pid is gone.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunningEntry {
+    pub pid: u32,
+    /// See `crate::run_id`; missing in entries of older versions.
+    #[serde(default)]
+    pub run_id: Option<String>,
+    pub cmd: String,
+    args: String,
+    /// Directory the command runs in; missing in entries of older versions.
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+    pub logs: Vec<PathBuf>,
+}
+
+/// Removes the registry file, and any `lg kill` request left unread, on drop.
+pub struct RunningGuard(PathBuf);
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+        let _ = fs::remove_file(self.0.with_extension("kill"));
+    }
+}
+
+impl RunningEntry {
+    fn dir() -> Option<PathBuf> {
+        StateDir::locate().map(|d| d.running())
+    }
+
+    // Best effort: a read-only state dir must not stop the command from running.
+    pub fn register(
+        run_id: &str,
+        cmd: &str,
+        args: &str,
+        cwd: &Path,
+        logs: Vec<PathBuf>,
+    ) -> Option<RunningGuard> {
+        let dir = Self::dir()?;
+        fs::create_dir_all(&dir).ok()?;
+        let logs = logs
+            .into_iter()
+            .map(|p| fs::canonicalize(&p).unwrap_or(p))
+            .collect();
+        let entry = Self {
+            pid: std::process::id(),
+            run_id: Some(run_id.to_string()),
+            cmd: cmd.to_string(),
+            args: args.to_string(),
+            cwd: Some(cwd.to_path_buf()),
+            logs,
+        };
+        let path = dir.join(format!("{}.json", entry.pid));
+        replace_file(&path, &serde_json::to_vec(&entry).ok()?).ok()?;
+        Some(RunningGuard(path))
+    }
+
+    // `lg kill` leaves who asked in `<pid>.kill` before it sends the signal.
+    fn kill_request(pid: u32) -> Option<PathBuf> {
+        Self::dir().map(|d| d.join(format!("{}.kill", pid)))
+    }
+
+    /// Ask this run to stop: note who asked (`by`) for its log, then send
+    /// `signal` to its lg, which passes it on to the command.
+    #[cfg(unix)]
+    pub fn request_kill(&self, signal: i32, by: &str) -> Result<()> {
+        let request =
+            Self::kill_request(self.pid).context("cannot determine lg state directory")?;
+        replace_file(&request, by.as_bytes()).with_context(|| format!("writing {:?}", request))?;
+        // SAFETY: kill only sends a signal to the registered lg process.
+        if unsafe { libc::kill(self.pid as libc::pid_t, signal) } != 0 {
+            let err = io::Error::last_os_error();
+            let _ = fs::remove_file(&request);
+            return Err(err).with_context(|| format!("signalling lg pid {}", self.pid));
+        }
+        Ok(())
+    }
+
+    /// Who asked this lg to stop with `lg kill`, if anyone did; the request is used up.
+    pub fn take_kill_request() -> Option<String> {
+        let request = Self::kill_request(std::process::id())?;
+        let by = fs::read_to_string(&request).ok()?;
+        let _ = fs::remove_file(&request);
+        Some(by)
+    }
+
+    // Live entries, oldest registration first.
+    pub fn list() -> Vec<Self> {
+        let Some(entries) = Self::dir().and_then(|d| fs::read_dir(d).ok()) else {
+            return Vec::new();
+        };
+        let mut live: Vec<(std::time::SystemTime, Self)> = entries
+            .flatten()
+            .filter(|e| e.path().extension().is_some_and(|x| x == "json"))
+            .filter_map(|e| {
+                let registered = e.metadata().ok()?.modified().ok()?;
+                let entry: Self = serde_json::from_slice(&fs::read(e.path()).ok()?).ok()?;
+                Some((registered, entry))
+            })
+            .filter(|(_, e)| pid_alive(e.pid))
+            .collect();
+        live.sort_by_key(|(registered, _)| *registered);
+        live.into_iter().map(|(_, e)| e).collect()
+    }
+}
+
+fn pid_alive(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        // SAFETY: signal 0 only checks that the process exists.
+        let rc = unsafe { libc::kill(pid as libc::pid_t, 0) };
+        rc == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = pid;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_dir_prefers_xdg_data_home() {
+        let home = Some(PathBuf::from("/home/u"));
+        let dir = StateDir::resolve(Some("/data".into()), home.clone()).unwrap();
+        assert_eq!(dir.env_baseline(), Path::new("/data/lg/env-baseline.json"));
+        let dir = StateDir::resolve(Some("".into()), home).unwrap();
+        assert_eq!(
+            dir.env_baseline(),
+            Path::new("/home/u/.local/share/lg/env-baseline.json")
+        );
+        assert!(StateDir::resolve(None, None).is_none());
+    }
+
+    #[test]
+    fn latest_log_is_recorded_per_directory() {
+        let root = std::env::temp_dir().join(format!("lg-state-test-{}", std::process::id()));
+        let dir = StateDir::new(&root);
+        dir.record_latest(Path::new("/src/a"), Path::new("/logs/a.log"));
+        dir.record_latest(Path::new("/src/b"), Path::new("/logs/b.log"));
+        let read = |cwd: &str| fs::read_to_string(dir.latest(Path::new(cwd))).unwrap();
+        assert_eq!(read("/src/a"), "/logs/a.log\n");
+        assert_eq!(read("/src/b"), "/logs/b.log\n");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn latest_pointer_is_never_read_half_written() {
+        let root = std::env::temp_dir().join(format!("lg-state-race-test-{}", std::process::id()));
+        let dir = StateDir::new(&root);
+        let cwd = Path::new("/src");
+        let logs: Vec<String> = (0..4)
+            .map(|i| format!("/logs/{}/make.log", i.to_string().repeat(4096)))
+            .collect();
+        dir.record_latest(cwd, Path::new(&logs[0]));
+        std::thread::scope(|s| {
+            for log in &logs {
+                let dir = &dir;
+                s.spawn(move || {
+                    for _ in 0..50 {
+                        dir.record_latest(cwd, Path::new(log));
+                    }
+                });
+            }
+            for _ in 0..200 {
+                let read = fs::read_to_string(dir.latest(cwd)).unwrap();
+                assert!(logs.iter().any(|l| read == format!("{}\n", l)));
+            }
+        });
+        let left: Vec<_> = fs::read_dir(dir.latest(cwd).parent().unwrap())
+            .unwrap()
+            .collect();
+        assert_eq!(left.len(), 1, "temporary files left behind");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn seq_counts_runs_per_directory() {
+        let root = std::env::temp_dir().join(format!("lg-seq-test-{}", std::process::id()));
+        let dir = StateDir::new(&root);
+        let (a, b) = (Path::new("/logs/a"), Path::new("/logs/b"));
+        assert_eq!(dir.next_seq(a).unwrap(), 1);
+        assert_eq!(dir.next_seq(b).unwrap(), 1);
+        let mut taken: Vec<u64> = std::thread::scope(|s| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        (0..25)
+                            .map(|_| dir.next_seq(a).unwrap())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .flat_map(|t| t.join().unwrap())
+                .collect()
+        });
+        taken.sort_unstable();
+        assert_eq!(taken, (2..=101).collect::<Vec<u64>>());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn groups_collect_their_runs() {
+        let root = std::env::temp_dir().join(format!("lg-group-test-{}", std::process::id()));
+        let dir = StateDir::new(&root);
+        let at = |t: &str| NaiveDateTime::parse_from_str(t, "%Y-%m-%d %H:%M:%S").unwrap();
+        let now = at("2026-10-16 02:00:00");
+        let mut group = dir.start_group("nightly build", None, now).unwrap();
+        assert_eq!(group.id, "nightly_build-20261016-020000");
+        let again = dir.start_group("nightly build", None, now).unwrap();
+        assert_eq!(again.id, "nightly_build-20261016-020000-2");
+        let run = |cmd: &str, exit_code| GroupRun {
+            cmd: cmd.into(),
+            args: String::new(),
+            started: now,
+            duration_secs: Some(1.5),
+            exit_code,
+            log: None,
+        };
+        dir.record_group_run(&group.id, &run("make", 0));
+        dir.record_group_run(&group.id, &run("deploy", 1));
+        dir.end_group(&mut group, at("2026-10-16 02:10:00"))
+            .unwrap();
+        let (loaded, runs) = dir.load_group(&group.id).unwrap();
+        assert_eq!(loaded.unwrap().ended, Some(at("2026-10-16 02:10:00")));
+        let cmds: Vec<&str> = runs.iter().map(|r| r.cmd.as_str()).collect();
+        assert_eq!(cmds, ["make", "deploy"]);
+        // A group only named in LG_GROUP has runs but no start.
+        dir.record_group_run("adhoc", &run("make", 0));
+        assert!(dir.load_group("adhoc").unwrap().0.is_none());
+        assert!(dir.load_group("missing").is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
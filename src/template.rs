@@ -0,0 +1,420 @@
+//! File name templates and the recorded form of a command's arguments.
+
+use crate::git::GitInfo;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::ffi::OsString;
+
+// Any long flag containing one of these words is treated as sensitive too
+static SENSITIVE_FLAG_WORDS: &[&str] =
+    &["password", "passwd", "token", "secret", "apikey", "api-key"];
+
+static MASK: &str = "****";
+// Placeholders only known once the command exits; using one makes lg write to a temp file and rename
+pub static POST_RUN_PLACEHOLDERS: &[&str] =
+    &["{exit_code}", "{failure_class}", "{duration}", "{pid}"];
+
+// `{env.NAME}` or `{env.NAME:-fallback}` in templates
+static ENV_PLACEHOLDER: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\{env\.([A-Za-z_][A-Za-z0-9_]*)(?::-([^}]*))?\}").unwrap());
+
+// Replace values of sensitive flags: `--password X`, `--token=X`, `-p X`.
+pub fn mask_sensitive_args(args: &[OsString], flags: &[String]) -> Vec<OsString> {
+    let is_sensitive = |flag: &str| {
+        flags.iter().any(|f| f == flag)
+            || (flag.starts_with("--")
+                && SENSITIVE_FLAG_WORDS
+                    .iter()
+                    .any(|w| flag.to_ascii_lowercase().contains(w)))
+    };
+    let mut out = Vec::with_capacity(args.len());
+    let mut mask_next = false;
+    for arg in args {
+        let s = arg.to_string_lossy();
+        if mask_next {
+            out.push(OsString::from(MASK));
+            mask_next = false;
+            continue;
+        }
+        if s == "--" {
+            out.push(arg.clone());
+            continue;
+        }
+        if let Some((flag, _)) = s.split_once('=').filter(|(f, _)| f.starts_with('-')) {
+            if is_sensitive(flag) {
+                out.push(OsString::from(format!("{}={}", flag, MASK)));
+                continue;
+            }
+        } else if s.starts_with('-') && is_sensitive(&s) {
+            mask_next = true;
+        }
+        out.push(arg.clone());
+    }
+    out
+}
+
+// Digest of the exact argument list, so runs with identical args can still be matched.
+pub fn hash_args(args: &[OsString]) -> String {
+    if args.is_empty() {
+        return String::new();
+    }
+    let mut hasher = Sha256::new();
+    for a in args {
+        hasher.update(a.to_string_lossy().as_bytes());
+        hasher.update([0u8]);
+    }
+    let digest = hasher.finalize();
+    let hex: String = digest[..6].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256-{}", hex)
+}
+
+/// Shell-style glob: `*` is any run of characters, `?` any one.
+pub fn glob_match(glob: &str, name: &str) -> bool {
+    let (glob, name): (Vec<char>, Vec<char>) = (glob.chars().collect(), name.chars().collect());
+    // Where the last `*` was, and how much of `name` it has taken so far.
+    let (mut g, mut n, mut star) = (0, 0, None);
+    while n < name.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g, n));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                g += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sg, sn)) => {
+                    star = Some((sg, sn + 1));
+                    (g, n) = (sg + 1, sn + 1);
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|c| *c == '*')
+}
+
+pub fn join_args(args: &[OsString], include_full: bool) -> String {
+    let mut out = Vec::new();
+    for a in args {
+        let s = a.to_string_lossy().to_string();
+        if include_full {
+            out.push(s);
+        } else {
+            if !s.starts_with('-') {
+                out.push(s);
+            }
+        }
+    }
+    out.join(" ")
+}
+
+pub fn sanitize_component(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' || ch == '.' {
+            out.push(ch);
+        } else {
+            out.push('_');
+        }
+    }
+    while out.contains("__") {
+        out = out.replace("__", "_");
+    }
+    out.trim_matches('_').to_string()
+}
+
+fn maybe_sanitize_component<'a>(input: &'a str, sanitize: bool) -> Cow<'a, str> {
+    if sanitize {
+        Cow::Owned(sanitize_component(input))
+    } else {
+        Cow::Borrowed(input)
+    }
+}
+
+/// Values substituted into `filename_template`. Post-run values stay `None`
+/// (rendered as "NA") until the command has finished.
+#[derive(Clone, Copy)]
+pub struct TemplateVars<'a> {
+    pub cmd: &'a str,
+    pub args: &'a str,
+    pub date: &'a str,
+    pub time: &'a str,
+    pub ts: &'a str,
+    pub hostname: &'a str,
+    /// The user running lg.
+    pub user: &'a str,
+    pub cwd: &'a str,
+    pub ci_run: &'a str,
+    /// See `crate::run_id`.
+    pub run_id: &'a str,
+    /// The run of the lg this one runs under, if any.
+    pub parent_run_id: Option<&'a str>,
+    /// See `crate::fingerprint`; None with `fingerprint = false`.
+    pub fingerprint: Option<&'a str>,
+    /// None outside a git checkout, or with `git = false`.
+    pub git: Option<&'a GitInfo>,
+    /// The output directory's next run number; only taken when a template
+    /// uses `{seq}`.
+    pub seq: Option<u64>,
+    pub exit_code: Option<i32>,
+    pub failure_class: Option<&'a str>,
+    /// Whole seconds the command ran.
+    pub duration_secs: Option<u64>,
+    /// The command's process id; None when it could not be started.
+    pub pid: Option<u32>,
+}
+
+pub fn render_template(
+    tpl: &str,
+    vars: &TemplateVars,
+    sanitize: bool,
+    include_args_in_name: bool,
+) -> String {
+    let TemplateVars {
+        cmd,
+        args,
+        date,
+        time,
+        ts,
+        hostname,
+        user,
+        cwd,
+        ci_run,
+        run_id,
+        parent_run_id: _,
+        fingerprint,
+        git,
+        seq,
+        exit_code,
+        failure_class,
+        duration_secs,
+        pid,
+    } = *vars;
+    let mut args_used = if include_args_in_name {
+        args.to_string()
+    } else {
+        String::new()
+    };
+    if sanitize {
+        args_used = sanitize_component(&args_used);
+    }
+    let cmd_fragment = maybe_sanitize_component(cmd, sanitize);
+    let hostname_fragment = maybe_sanitize_component(hostname, sanitize);
+    let user_fragment = maybe_sanitize_component(user, sanitize);
+    let cwd_fragment = maybe_sanitize_component(cwd, sanitize);
+    let ci_run_fragment = maybe_sanitize_component(ci_run, sanitize);
+    let branch_fragment = maybe_sanitize_component(git.map_or("NA", |g| &g.branch), sanitize);
+    let mut s = tpl
+        .replace("{cmd}", cmd_fragment.as_ref())
+        .replace("{args}", &args_used)
+        .replace("{date}", date)
+        .replace("{time}", time)
+        .replace("{ts}", ts)
+        .replace("{hostname}", hostname_fragment.as_ref())
+        .replace("{user}", user_fragment.as_ref())
+        .replace("{cwd}", cwd_fragment.as_ref())
+        .replace("{ci_run}", ci_run_fragment.as_ref())
+        .replace("{run_id}", run_id)
+        .replace("{fingerprint}", fingerprint.unwrap_or("NA"))
+        .replace("{git_branch}", branch_fragment.as_ref())
+        .replace(
+            "{git_sha}",
+            git.and_then(GitInfo::short_sha).unwrap_or("NA"),
+        )
+        .replace("{seq}", &opt_num(seq));
+    if let Some(code) = exit_code {
+        s = s.replace("{exit_code}", &code.to_string());
+    } else {
+        s = s.replace("{exit_code}", "NA");
+    }
+    let duration = duration_secs.map_or_else(|| "NA".to_string(), |s| format!("{}s", s));
+    s = s.replace("{duration}", &duration);
+    s = s.replace("{pid}", &opt_num(pid));
+    let class_fragment = maybe_sanitize_component(failure_class.unwrap_or("NA"), sanitize);
+    s = s.replace("{failure_class}", class_fragment.as_ref());
+    s = expand_env(s, sanitize);
+    s = s.replace("..", ".");
+    while s.contains("__") {
+        s = s.replace("__", "_");
+    }
+    s.trim_matches(|c| c == '_' || c == '.').to_string()
+}
+
+/// `header_template` filled in: values as they are, post-run ones as "NA".
+pub fn render_header(tpl: &str, vars: &TemplateVars) -> String {
+    let s = tpl
+        .replace("{cmd}", vars.cmd)
+        .replace("{args}", vars.args)
+        .replace("{date}", vars.date)
+        .replace("{time}", vars.time)
+        .replace("{ts}", vars.ts)
+        .replace("{hostname}", vars.hostname)
+        .replace("{user}", vars.user)
+        .replace("{cwd}", vars.cwd)
+        .replace("{ci_run}", vars.ci_run)
+        .replace("{run_id}", vars.run_id)
+        .replace("{fingerprint}", vars.fingerprint.unwrap_or("NA"))
+        .replace("{git_branch}", vars.git.map_or("NA", |g| &g.branch))
+        .replace(
+            "{git_sha}",
+            vars.git.and_then(GitInfo::short_sha).unwrap_or("NA"),
+        )
+        .replace("{seq}", &opt_num(vars.seq));
+    let s = POST_RUN_PLACEHOLDERS
+        .iter()
+        .fold(s, |s, placeholder| s.replace(placeholder, "NA"));
+    expand_env(s, false)
+}
+
+fn opt_num(n: Option<impl ToString>) -> String {
+    n.map_or_else(|| "NA".to_string(), |n| n.to_string())
+}
+
+fn expand_env(s: String, sanitize: bool) -> String {
+    if !s.contains("{env.") {
+        return s;
+    }
+    ENV_PLACEHOLDER
+        .replace_all(&s, |caps: &regex::Captures| {
+            let value = std::env::var(&caps[1])
+                .ok()
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| caps.get(2).map_or("NA", |d| d.as_str()).to_string());
+            maybe_sanitize_component(&value, sanitize).into_owned()
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> TemplateVars<'static> {
+        TemplateVars {
+            cmd: "cargo",
+            args: "test --release",
+            date: "2025-01-02",
+            time: "03-04-05",
+            ts: "1735787045",
+            hostname: "build host",
+            user: "ci",
+            cwd: "/src/app",
+            ci_run: "NA",
+            run_id: "01M5170180ZZZZZZZZZZZZZZZZ",
+            parent_run_id: None,
+            fingerprint: None,
+            git: None,
+            seq: None,
+            exit_code: None,
+            failure_class: None,
+            duration_secs: None,
+            pid: None,
+        }
+    }
+
+    #[test]
+    fn renders_placeholders() {
+        let tpl = "{cmd}_{args}_{date}_{time}.log";
+        assert_eq!(
+            render_template(tpl, &vars(), true, true),
+            "cargo_test_--release_2025-01-02_03-04-05.log"
+        );
+        // Without include_args_in_name, {args} is empty and the separator collapses.
+        assert_eq!(
+            render_template(tpl, &vars(), true, false),
+            "cargo_2025-01-02_03-04-05.log"
+        );
+        assert_eq!(
+            render_template("{hostname}_{exit_code}.log", &vars(), true, false),
+            "build_host_NA.log"
+        );
+        let done = TemplateVars {
+            duration_secs: Some(83),
+            pid: Some(4242),
+            ..vars()
+        };
+        assert_eq!(
+            render_template("{cmd}_{duration}_{pid}.log", &done, true, false),
+            "cargo_83s_4242.log"
+        );
+        let numbered = TemplateVars {
+            seq: Some(17),
+            ..vars()
+        };
+        assert_eq!(
+            render_template("{user}_{cmd}_{seq}_{pid}.log", &numbered, true, false),
+            "ci_cargo_17_NA.log"
+        );
+        let git = GitInfo {
+            branch: "feature/login".into(),
+            sha: Some("3fa9c20b17de4a1f0c2b9e8d7a6f5e4d3c2b1a09".into()),
+            dirty: true,
+        };
+        let in_git = TemplateVars {
+            git: Some(&git),
+            ..vars()
+        };
+        assert_eq!(
+            render_template("{cmd}_{git_branch}_{git_sha}.log", &in_git, true, false),
+            "cargo_feature_login_3fa9c20b17de.log"
+        );
+        assert_eq!(
+            render_template("{cmd}_{git_branch}_{git_sha}.log", &vars(), true, false),
+            "cargo_NA_NA.log"
+        );
+    }
+
+    #[test]
+    fn header_template_keeps_values_as_they_are() {
+        let tpl = "# {cmd} {args} @ {hostname}:{cwd} ({exit_code})\n# {env.LG_TEST_UNSET_VAR:-dev}";
+        assert_eq!(
+            render_header(tpl, &vars()),
+            "# cargo test --release @ build host:/src/app (NA)\n# dev"
+        );
+    }
+
+    #[test]
+    fn env_placeholder_falls_back() {
+        let tpl = "{cmd}_{env.LG_TEST_UNSET_VAR:-dev}_{env.LG_TEST_UNSET_VAR}.log";
+        assert_eq!(
+            render_template(tpl, &vars(), true, false),
+            "cargo_dev_NA.log"
+        );
+    }
+
+    #[test]
+    fn masks_sensitive_flags() {
+        let args: Vec<OsString> = ["--token", "abc", "--db-password=x", "-p", "y", "--", "-p"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        let flags = vec!["-p".to_string()];
+        assert_eq!(
+            join_args(&mask_sensitive_args(&args, &flags), true),
+            "--token **** --db-password=**** -p **** -- -p"
+        );
+        // Short flags aren't masked unless configured.
+        let args: Vec<OsString> = ["-p", "a/b"].iter().map(OsString::from).collect();
+        let defaults = crate::config::Config::default().mask_arg_flags;
+        assert_eq!(
+            join_args(&mask_sensitive_args(&args, &defaults), true),
+            "-p a/b"
+        );
+    }
+
+    #[test]
+    fn args_forms() {
+        let args: Vec<OsString> = ["-v", "build", "--release"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        assert_eq!(join_args(&args, false), "build");
+        assert_eq!(hash_args(&args), hash_args(&args.clone()));
+        assert!(hash_args(&args).starts_with("sha256-"));
+        assert_eq!(hash_args(&[]), "");
+    }
+}
@@ -0,0 +1,214 @@
+//! Durations and points in time as accepted by `--since`, `--until`, `--timeout` and `lg clean`.
+
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use std::time::Duration;
+
+/// Parse an age/duration like `90s`, `30m`, `12h`, `3d`, `2w` or `1d12h`.
+/// A bare number means days.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let invalid = || format!("invalid duration {:?} (expected e.g. 30m, 12h, 3d)", s);
+    if s.is_empty() {
+        return Err(invalid());
+    }
+    let mut rest = s;
+    let mut secs = 0u64;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (num, tail) = rest.split_at(split);
+        let n: u64 = num.parse().map_err(|_| invalid())?;
+        let split = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(split);
+        let unit_secs = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            "d" | "" => 86_400,
+            "w" => 7 * 86_400,
+            _ => return Err(format!("unknown duration unit {:?} in {:?}", unit, s)),
+        };
+        secs = n
+            .checked_mul(unit_secs)
+            .and_then(|v| secs.checked_add(v))
+            .ok_or_else(invalid)?;
+        rest = tail;
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+/// Parse a point in time for the time-accepting flags: an age counted back
+/// from now (anything `parse_duration` takes), a date (`2024-05-01`, local
+/// midnight), a date and time (`2024-05-01 13:30`, `2024-05-01T13:30:00`),
+/// or `now`, `today`, `yesterday`.
+pub fn parse_time(s: &str) -> Result<NaiveDateTime, String> {
+    parse_time_at(s, Local::now().naive_local())
+}
+
+fn parse_time_at(s: &str, now: NaiveDateTime) -> Result<NaiveDateTime, String> {
+    let s = s.trim();
+    let midnight = |d: NaiveDate| d.and_time(NaiveTime::MIN);
+    match s.to_ascii_lowercase().as_str() {
+        "now" => return Ok(now),
+        "today" => return Ok(midnight(now.date())),
+        "yesterday" => return Ok(midnight(now.date() - chrono::Days::new(1))),
+        _ => {}
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(midnight(date));
+    }
+    for fmt in [
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+    ] {
+        if let Ok(t) = NaiveDateTime::parse_from_str(s, fmt) {
+            return Ok(t);
+        }
+    }
+    let age = parse_duration(s).map_err(|_| {
+        format!(
+            "invalid time {:?} (expected e.g. 2h, 3d, 2024-05-01, yesterday)",
+            s
+        )
+    })?;
+    chrono::Duration::from_std(age)
+        .ok()
+        .and_then(|age| now.checked_sub_signed(age))
+        .ok_or_else(|| format!("time {:?} is out of range", s))
+}
+
+/// Parse an instant: anything `parse_time` takes, in local time, or seconds
+/// since the epoch as `@1760580000`.
+pub fn parse_instant(s: &str) -> Result<DateTime<Local>, String> {
+    let s = s.trim();
+    if let Some(secs) = s.strip_prefix('@') {
+        return secs
+            .parse()
+            .ok()
+            .and_then(|secs| Local.timestamp_opt(secs, 0).single())
+            .ok_or_else(|| format!("invalid timestamp {:?} (expected e.g. @1760580000)", s));
+    }
+    let t = parse_time(s)?;
+    t.and_local_timezone(Local)
+        .earliest()
+        .ok_or_else(|| format!("time {:?} does not exist in the local time zone", s))
+}
+
+// Runs without a parsable start time never match a time filter.
+pub fn started_between(
+    started: Option<NaiveDateTime>,
+    since: Option<NaiveDateTime>,
+    until: Option<NaiveDateTime>,
+) -> bool {
+    if since.is_none() && until.is_none() {
+        return true;
+    }
+    started.is_some_and(|t| since.map_or(true, |s| t >= s) && until.map_or(true, |u| t <= u))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn duration_units() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * 3600)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * 86_400)));
+        assert_eq!(parse_duration(" 3 "), Ok(Duration::from_secs(3 * 86_400)));
+    }
+
+    #[test]
+    fn duration_compound() {
+        assert_eq!(parse_duration("1d12h"), Ok(Duration::from_secs(36 * 3600)));
+        assert_eq!(parse_duration("1h30m15s"), Ok(Duration::from_secs(5415)));
+    }
+
+    #[test]
+    fn duration_rejects_garbage() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration("99999999999999999999w").is_err());
+    }
+
+    #[test]
+    fn instants_from_epoch_or_local_time() {
+        assert_eq!(
+            parse_instant("@1760580000").unwrap().timestamp(),
+            1760580000
+        );
+        assert_eq!(
+            parse_instant("2024-05-01 13:30").unwrap().naive_local(),
+            at("2024-05-01 13:30:00")
+        );
+        assert!(parse_instant("@soon").is_err());
+    }
+
+    #[test]
+    fn time_from_age() {
+        let now = at("2024-05-10 12:00:00");
+        assert_eq!(parse_time_at("2h", now), Ok(at("2024-05-10 10:00:00")));
+        assert_eq!(parse_time_at("3d", now), Ok(at("2024-05-07 12:00:00")));
+    }
+
+    #[test]
+    fn time_from_date_and_datetime() {
+        let now = at("2024-05-10 12:00:00");
+        assert_eq!(
+            parse_time_at("2024-05-01", now),
+            Ok(at("2024-05-01 00:00:00"))
+        );
+        assert_eq!(
+            parse_time_at("2024-05-01 13:30", now),
+            Ok(at("2024-05-01 13:30:00"))
+        );
+        assert_eq!(
+            parse_time_at("2024-05-01T13:30:05", now),
+            Ok(at("2024-05-01 13:30:05"))
+        );
+    }
+
+    #[test]
+    fn time_keywords() {
+        let now = at("2024-05-10 12:34:56");
+        assert_eq!(parse_time_at("now", now), Ok(now));
+        assert_eq!(parse_time_at("today", now), Ok(at("2024-05-10 00:00:00")));
+        assert_eq!(
+            parse_time_at("Yesterday", now),
+            Ok(at("2024-05-09 00:00:00"))
+        );
+    }
+
+    #[test]
+    fn time_rejects_garbage() {
+        let now = at("2024-05-10 12:00:00");
+        assert!(parse_time_at("last tuesday", now).is_err());
+        assert!(parse_time_at("2024-13-01", now).is_err());
+    }
+
+    #[test]
+    fn started_between_bounds() {
+        let t = Some(at("2024-05-10 12:00:00"));
+        assert!(started_between(t, None, None));
+        assert!(started_between(None, None, None));
+        assert!(!started_between(
+            None,
+            Some(at("2024-05-01 00:00:00")),
+            None
+        ));
+        assert!(started_between(t, Some(at("2024-05-10 12:00:00")), None));
+        assert!(!started_between(t, None, Some(at("2024-05-10 11:59:59"))));
+    }
+}
@@ -0,0 +1,888 @@
+//! The `lg <tool>` subcommands and the log reading they share.
+
+mod baseline;
+mod checklist;
+mod clean;
+mod config_doc;
+mod du;
+mod grep;
+mod group;
+mod history;
+mod kill;
+mod last_path;
+mod open;
+mod replay;
+mod report;
+#[cfg(feature = "self-update")]
+mod self_update;
+mod stats;
+mod tail;
+mod trash;
+
+use crate::anomaly::Anomaly;
+use crate::cli::{ConfigCommand, EnvBaselineArgs, OutputFormat, Tool, ToolCli};
+use crate::config::{config_sources, load_config, selected_profile, Config};
+use crate::context::TTY;
+use crate::jsonl;
+use crate::matchers::Diagnostic;
+use crate::naming::output_dir_root;
+use crate::runner::parse_duration_secs;
+use crate::sinks::Stream;
+use crate::state::{env_baseline_path, load_env_baseline, EnvBaseline};
+use crate::tools::baseline::run_baseline;
+use crate::tools::clean::run_clean;
+use crate::tools::config_doc::run_config_doc;
+use crate::tools::du::run_du;
+use crate::tools::grep::run_grep;
+pub use crate::tools::group::group_budget;
+use crate::tools::group::run_group;
+use crate::tools::history::run_history;
+use crate::tools::kill::run_kill;
+use crate::tools::last_path::{run_last_path, tmux_hook};
+use crate::tools::open::run_open;
+use crate::tools::replay::run_replay;
+use crate::tools::report::run_report;
+use crate::tools::stats::{run_list, run_show, run_stats};
+use crate::tools::tail::{run_attach, run_tail};
+pub use crate::tools::trash::discard_run;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDateTime};
+use flate2::read::MultiGzDecoder;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+// Returns the process exit status; `lg grep` uses 1 for "no match" like grep.
+pub fn run_tool(cli: ToolCli) -> Result<i32> {
+    let sources = config_sources(cli.config.as_deref());
+    let profile = selected_profile(cli.profile.as_deref());
+    let cfg = load_config(&sources, profile.as_deref())?;
+    match cli.tool {
+        Tool::List(args) => run_list(&cfg, args)?,
+        Tool::History(args) => run_history(&cfg, args)?,
+        Tool::Show(args) => run_show(&cfg, args)?,
+        Tool::Replay(args) => run_replay(&cfg, args)?,
+        Tool::Tail(args) => run_tail(&cfg, args)?,
+        Tool::Attach(args) => run_attach(&cfg, args)?,
+        Tool::Kill(args) => run_kill(args)?,
+        Tool::LastPath(args) => run_last_path(args)?,
+        Tool::TmuxHook(args) => print!("{}", tmux_hook(&args.key)),
+        Tool::Open(args) => run_open(&cfg, args)?,
+        Tool::Stats(args) => run_stats(&cfg, args)?,
+        Tool::Du(args) => run_du(&cfg, args)?,
+        Tool::Grep(args) => return Ok(if run_grep(&cfg, args)? { 0 } else { 1 }),
+        Tool::Report(args) => run_report(&cfg, args)?,
+        Tool::Clean(args) => run_clean(&cfg, args)?,
+        Tool::EnvBaseline(args) => run_env_baseline(args)?,
+        Tool::Group(command) => return run_group(&cfg, command),
+        Tool::Baseline(command) => return run_baseline(&cfg, command),
+        Tool::Config(ConfigCommand::Schema { .. }) => {
+            let schema = schemars::schema_for!(Config);
+            println!("{}", serde_json::to_string_pretty(&schema)?);
+        }
+        Tool::Config(ConfigCommand::Doc { key, no_pager }) => {
+            run_config_doc(&cfg, &sources, profile.as_deref(), key.as_deref(), no_pager)?
+        }
+        #[cfg(feature = "self-update")]
+        Tool::SelfUpdate(args) => return self_update::run_self_update(args),
+        #[cfg(not(feature = "self-update"))]
+        Tool::SelfUpdate(_) => anyhow::bail!(
+            "built without the `self-update` feature; update lg the way it was installed"
+        ),
+    }
+    Ok(0)
+}
+
+fn run_env_baseline(args: EnvBaselineArgs) -> Result<()> {
+    let path = env_baseline_path()?;
+    if args.show {
+        let baseline = load_env_baseline()?;
+        println!("# captured {} from {}", baseline.captured, baseline.shell);
+        for (k, v) in &baseline.vars {
+            println!("{}={}", k, v);
+        }
+        return Ok(());
+    }
+    // Start a login shell from a nearly empty environment so it only sees what
+    // the profile scripts set up.
+    let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".into());
+    let mut command = std::process::Command::new(&shell);
+    command.args(["-l", "-c", "env -0"]).env_clear();
+    for key in ["HOME", "USER", "LOGNAME", "SHELL", "TERM"] {
+        if let Some(v) = std::env::var_os(key) {
+            command.env(key, v);
+        }
+    }
+    let output = command
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .with_context(|| format!("running login shell {}", shell))?;
+    if !output.status.success() {
+        anyhow::bail!("login shell {} exited with {}", shell, output.status);
+    }
+    let vars: BTreeMap<String, String> = output
+        .stdout
+        .split(|&b| b == 0)
+        .filter_map(|entry| {
+            let entry = String::from_utf8_lossy(entry);
+            let (k, v) = entry.split_once('=')?;
+            Some((k.to_string(), v.to_string()))
+        })
+        .collect();
+    let baseline = EnvBaseline {
+        captured: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        shell,
+        vars,
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("create state dir {:?}", parent))?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(&baseline)?)
+        .with_context(|| format!("writing {:?}", path))?;
+    println!(
+        "captured {} variables from {} into {}",
+        baseline.vars.len(),
+        baseline.shell,
+        path.display()
+    );
+    Ok(())
+}
+
+// Directory the log tools look in: explicit flag, then output_dir (up to its
+// first placeholder), then cwd.
+pub fn log_dir(cfg: &Config, dir: Option<PathBuf>) -> PathBuf {
+    dir.or_else(|| output_dir_root(cfg).map(|(root, _)| root))
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+// How many levels of subdirectories hold logs: those the placeholders of
+// output_dir add, and the run id directory of nested runs (`nested_subdir`).
+// Taken for a `--dir` too, which is likely another machine's directory of a
+// shared log root.
+fn log_depth(cfg: &Config) -> usize {
+    let depth = output_dir_root(cfg).map_or(0, |(_, depth)| depth);
+    depth + usize::from(cfg.nested_subdir)
+}
+
+/// The log of the most recent run in `dir`, of command `cmd` if given.
+pub fn latest_run(cfg: &Config, dir: Option<PathBuf>, cmd: Option<&str>) -> Result<PathBuf> {
+    let dir = log_dir(cfg, dir);
+    let runs = scan_logs(cfg, &dir)?;
+    let run = runs
+        .into_iter()
+        .rev()
+        .find(|r| cmd.map_or(true, |c| r.cmd == c))
+        .with_context(|| format!("no matching runs in {}", dir.display()))?;
+    Ok(run.path)
+}
+
+/// What lg can recover about a past run from its log file(s).
+#[derive(Debug, Clone, Serialize)]
+pub struct LogRecord {
+    path: PathBuf,
+    /// None in logs of lg versions before run ids.
+    run_id: Option<String>,
+    /// The run of the lg this one ran under.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_run_id: Option<String>,
+    cmd: String,
+    args: String,
+    /// Directory the command ran in.
+    cwd: Option<PathBuf>,
+    /// The `lg group` the run belongs to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    started: Option<NaiveDateTime>,
+    /// How long after its scheduled time the run started (`scheduled_at`).
+    start_delay_secs: Option<f64>,
+    /// From the header; see `crate::fingerprint`.
+    fingerprint: Option<String>,
+    /// Whether the fingerprint differs from the one of the previous run with
+    /// the same command and arguments.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    fingerprint_changed: bool,
+    /// From the `[duration]` footer line, or else from the header start time
+    /// to the log's last write.
+    duration_secs: Option<i64>,
+    exit_code: Option<i32>,
+    failure_class: Option<String>,
+    /// Kernel-reported reason for a kill, e.g. "oom-killed".
+    probable_cause: Option<String>,
+    /// From `[summary]` footer lines, written by `post_failure_summarizer`.
+    summary: Option<String>,
+    /// What the problem matchers found, from `[diagnostic]` footer lines.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    diagnostics: Vec<Diagnostic>,
+    /// How the run differed from its `lg baseline`, from `[baseline]` footer lines.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    baseline: Vec<String>,
+    /// How the run is off from the usual for its command line, by the run index;
+    /// filled in by `lg list`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    anomalies: Vec<Anomaly>,
+    /// The `output_artifact` beside the log, from the `[artifact]` footer line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    artifact: Option<PathBuf>,
+    lines: u64,
+    bytes: u64,
+}
+
+/// Read every lg log in `dir` (plain or gzip; split pairs are merged into one run).
+pub fn scan_logs(cfg: &Config, dir: &Path) -> Result<Vec<LogRecord>> {
+    let mut runs: BTreeMap<PathBuf, LogRecord> = BTreeMap::new();
+    for entry in log_dir_entries(cfg, dir)? {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let stem = match name
+            .strip_suffix(".gz")
+            .or_else(|| name.strip_suffix(".zst"))
+            .unwrap_or(&name)
+            .strip_suffix(".log")
+        {
+            Some(stem) if !name.starts_with('.') => stem,
+            _ => continue,
+        };
+        // A `latest_symlink` link is another name for a run already listed.
+        if entry.file_type().is_ok_and(|t| t.is_symlink()) {
+            continue;
+        }
+        let key = path.with_file_name(
+            stem.strip_suffix(".out")
+                .or_else(|| stem.strip_suffix(".err"))
+                .unwrap_or(stem),
+        );
+        let Ok(Some(record)) = parse_log(cfg, &path) else {
+            continue;
+        };
+        match runs.get_mut(&key) {
+            Some(run) => {
+                run.lines += record.lines;
+                run.bytes += record.bytes;
+                if stem.ends_with(".out") {
+                    run.path = record.path;
+                }
+            }
+            None => {
+                runs.insert(key, record);
+            }
+        }
+    }
+    let mut runs: Vec<LogRecord> = runs.into_values().collect();
+    runs.sort_by(|a, b| a.started.cmp(&b.started).then(a.path.cmp(&b.path)));
+    mark_fingerprint_changes(&mut runs);
+    Ok(runs)
+}
+
+// The entries of `dir`, and of the subdirectories a templated output_dir
+// ("logs/{cmd}/{date}") spreads logs over.
+fn log_dir_entries(cfg: &Config, dir: &Path) -> Result<Vec<fs::DirEntry>> {
+    let mut entries = Vec::new();
+    let mut level = vec![dir.to_path_buf()];
+    for depth in 0..=log_depth(cfg) {
+        let mut below = Vec::new();
+        for dir in &level {
+            let listing = match fs::read_dir(dir) {
+                Ok(listing) => listing,
+                Err(_) if depth > 0 => continue,
+                Err(err) => return Err(err).with_context(|| format!("reading log dir {:?}", dir)),
+            };
+            for entry in listing.flatten() {
+                let hidden = entry.file_name().to_string_lossy().starts_with('.');
+                match entry.file_type() {
+                    Ok(t) if t.is_dir() && !hidden => below.push(entry.path()),
+                    _ => entries.push(entry),
+                }
+            }
+        }
+        level = below;
+    }
+    Ok(entries)
+}
+
+/// Logs still at their hidden `.partial` name in `dir`: runs going on, or
+/// ones whose lg died before the rename.
+pub fn partial_logs(cfg: &Config, dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut logs: Vec<PathBuf> = log_dir_entries(cfg, dir)?
+        .into_iter()
+        .filter(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            let file = e.file_type().is_ok_and(|t| t.is_file());
+            file && name.starts_with('.') && name.contains(".partial")
+        })
+        .map(|e| e.path())
+        .collect();
+    logs.sort();
+    Ok(logs)
+}
+
+// Flag each run whose fingerprint differs from the previous run of the same command line.
+fn mark_fingerprint_changes(runs: &mut [LogRecord]) {
+    let mut last: BTreeMap<(String, String), String> = BTreeMap::new();
+    for run in runs {
+        let Some(fingerprint) = &run.fingerprint else {
+            continue;
+        };
+        let key = (run.cmd.clone(), run.args.clone());
+        if let Some(previous) = last.insert(key, fingerprint.clone()) {
+            run.fingerprint_changed = previous != *fingerprint;
+        }
+    }
+}
+
+// Plain, gzip or zstd log, by extension.
+pub fn open_log(path: &Path) -> Result<io::BufReader<Box<dyn Read>>> {
+    let file = File::open(path).with_context(|| format!("open {:?}", path))?;
+    Ok(io::BufReader::new(decoder(path, file)?))
+}
+
+/// The output lines of a log, numbered from the top of the file, without its
+/// header and footer. The records of a JSON Lines log come as a text log has
+/// them, less the timestamp: `[STDERR] message`.
+pub fn body_lines<R: BufRead>(reader: R) -> BodyLines<R> {
+    BodyLines {
+        lines: reader.split(b'\n').peekable(),
+        lineno: 0,
+        in_body: false,
+        jsonl: false,
+    }
+}
+
+pub struct BodyLines<R: BufRead> {
+    lines: std::iter::Peekable<io::Split<R>>,
+    lineno: usize,
+    in_body: bool,
+    jsonl: bool,
+}
+
+impl<R: BufRead> Iterator for BodyLines<R> {
+    type Item = Result<(usize, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        #[derive(Deserialize)]
+        struct LineRecord {
+            stream: String,
+            line: String,
+        }
+        loop {
+            let raw = match self.lines.next()? {
+                Ok(raw) => raw,
+                Err(err) => return Some(Err(err.into())),
+            };
+            self.lineno += 1;
+            let line = String::from_utf8_lossy(&raw);
+            let line = line.trim_end_matches('\r');
+            if self.lineno == 1 && jsonl::is_header(line) {
+                (self.jsonl, self.in_body) = (true, true);
+                continue;
+            }
+            if self.jsonl {
+                let Ok(record) = serde_json::from_str::<LineRecord>(line) else {
+                    continue;
+                };
+                let stream = record.stream.to_ascii_uppercase();
+                return Some(Ok((self.lineno, format!("[{}] {}", stream, record.line))));
+            }
+            if !self.in_body {
+                self.in_body = line == "----- BEGIN OUTPUT -----";
+                continue;
+            }
+            // The footer and the blank line that separates it from the output.
+            let next_is_footer =
+                matches!(self.lines.peek(), Some(Ok(next)) if is_footer_line(next));
+            if is_footer_line(&raw) || (line.is_empty() && next_is_footer) {
+                continue;
+            }
+            return Some(Ok((self.lineno, line.to_string())));
+        }
+    }
+}
+
+/// `file` decompressed as its name `path` says.
+pub fn decoder(path: &Path, file: File) -> Result<Box<dyn Read>> {
+    Ok(match path.extension().and_then(|e| e.to_str()) {
+        Some("gz") => Box::new(MultiGzDecoder::new(file)),
+        Some("zst") => Box::new(zstd::Decoder::new(file)?),
+        _ => Box::new(file),
+    })
+}
+
+// Parse header and footer of a single log; Ok(None) if it isn't an lg log.
+pub fn parse_log(cfg: &Config, path: &Path) -> Result<Option<LogRecord>> {
+    let meta = fs::metadata(path)?;
+    let mut reader = open_log(path)?;
+    let mut buf = Vec::new();
+    let mut first = true;
+    let mut in_body = false;
+    let mut is_jsonl = false;
+    let mut record = LogRecord {
+        path: path.to_path_buf(),
+        run_id: None,
+        parent_run_id: None,
+        cmd: String::new(),
+        args: String::new(),
+        cwd: None,
+        group: None,
+        started: None,
+        start_delay_secs: None,
+        fingerprint: None,
+        fingerprint_changed: false,
+        duration_secs: None,
+        exit_code: None,
+        failure_class: None,
+        probable_cause: None,
+        summary: None,
+        diagnostics: Vec::new(),
+        baseline: Vec::new(),
+        anomalies: Vec::new(),
+        artifact: None,
+        lines: 0,
+        bytes: meta.len(),
+    };
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim_end_matches(['\n', '\r']);
+        if first {
+            if jsonl::is_header(line) {
+                parse_jsonl(&mut record, line, &mut reader)?;
+                is_jsonl = true;
+                break;
+            }
+            if line != "# lg log" {
+                return Ok(None);
+            }
+            first = false;
+            continue;
+        }
+        if !in_body {
+            if line == "----- BEGIN OUTPUT -----" {
+                in_body = true;
+            } else if let Some(v) = line.strip_prefix("cmd: ") {
+                record.cmd = v.to_string();
+            } else if let Some(v) = line.strip_prefix("args: ") {
+                record.args = v.to_string();
+            } else if let Some(v) = line.strip_prefix("run_id: ") {
+                record.run_id = Some(v.to_string());
+            } else if let Some(v) = line.strip_prefix("parent_run_id: ") {
+                record.parent_run_id = Some(v.to_string());
+            } else if let Some(v) = line.strip_prefix("cwd: ") {
+                record.cwd = Some(PathBuf::from(v));
+            } else if let Some(v) = line.strip_prefix("group: ") {
+                record.group = Some(v.to_string());
+            } else if let Some(v) = line.strip_prefix("scheduled: ") {
+                record.start_delay_secs = v
+                    .rsplit_once("(start delay ")
+                    .and_then(|(_, delay)| delay.trim_end_matches("s)").parse().ok());
+            } else if let Some(v) = line.strip_prefix("fingerprint: ") {
+                record.fingerprint = Some(v.to_string());
+            } else if let Some(v) = line.strip_prefix("date: ") {
+                let fmt = format!("{} {}", cfg.date_format, cfg.time_format);
+                record.started = NaiveDateTime::parse_from_str(v, &fmt).ok();
+            }
+            continue;
+        }
+        if let Some(v) = line.strip_prefix("[exit_code] ") {
+            record.exit_code = v.trim().parse().ok();
+        } else if let Some(v) = line.strip_prefix("[failure_class] ") {
+            record.failure_class = Some(v.trim().to_string());
+        } else if let Some(v) = line.strip_prefix("[probable_cause] ") {
+            record.probable_cause = Some(v.trim().to_string());
+        } else if let Some(v) = line.strip_prefix("[duration] ") {
+            record.duration_secs = parse_duration_secs(v).map(|s| s.round() as i64);
+        } else if let Some(v) = line.strip_prefix("[summary] ") {
+            let summary = record.summary.get_or_insert_with(String::new);
+            if !summary.is_empty() {
+                summary.push('\n');
+            }
+            summary.push_str(v);
+        } else if let Some(v) = line.strip_prefix("[diagnostic] ") {
+            record.diagnostics.extend(Diagnostic::parse(v));
+        } else if let Some(v) = line.strip_prefix("[baseline] ") {
+            record.baseline.push(v.to_string());
+        } else if let Some(v) = line.strip_prefix("[artifact] ") {
+            record.artifact = Some(path.with_file_name(v));
+        } else if record.exit_code.is_some() && is_footer_line(line.as_bytes()) {
+            // The other footer lines ([signal], [timeout], [cpu], ...) are not output.
+        } else if !(line.is_empty() && record.exit_code.is_none()) || record.lines > 0 {
+            record.lines += 1;
+        }
+    }
+    // The blank separator before the footer is not output.
+    if !is_jsonl && record.exit_code.is_some() && record.lines > 0 {
+        record.lines -= 1;
+    }
+    // Logs from before lg recorded `[duration]`.
+    if let (None, Some(started), Ok(modified)) =
+        (record.duration_secs, record.started, meta.modified())
+    {
+        let ended: DateTime<Local> = modified.into();
+        record.duration_secs = Some((ended.naive_local() - started).num_seconds().max(0));
+    }
+    Ok(Some(record))
+}
+
+// Rest of a JSON Lines log after its header record: output lines up to the footer record.
+fn parse_jsonl(record: &mut LogRecord, header: &str, reader: impl BufRead) -> Result<()> {
+    let header: jsonl::Record = serde_json::from_str(header)?;
+    record.run_id = header.run_id;
+    record.parent_run_id = header.parent_run_id;
+    record.cmd = header.cmd;
+    record.args = header.args;
+    record.cwd = header.cwd;
+    record.group = header.group;
+    record.start_delay_secs = header.start_delay_secs;
+    record.fingerprint = header.fingerprint;
+    record.started = header
+        .ts
+        .and_then(|ts| DateTime::parse_from_rfc3339(&ts).ok())
+        .map(|ts| ts.with_timezone(&Local).naive_local());
+    for line in reader.split(b'\n') {
+        let line = line?;
+        if !jsonl::is_footer(&line) {
+            record.lines += 1;
+            continue;
+        }
+        let footer: jsonl::Record = serde_json::from_slice(&line)?;
+        record.exit_code = footer.exit_code;
+        record.failure_class = footer.failure_class;
+        record.probable_cause = footer.probable_cause;
+        record.duration_secs = footer
+            .usage
+            .map(|u| (u.duration_ms as f64 / 1000.0).round() as i64);
+        record.summary = footer.summary;
+        record.diagnostics = footer.diagnostics;
+        record.baseline = footer.baseline;
+        record.artifact = footer.artifact.map(|name| record.path.with_file_name(name));
+    }
+    Ok(())
+}
+
+// Send `f`'s output through the pager when stdout is a terminal, like git:
+// $LG_PAGER, then $PAGER, then `less`, with LESS=FRX unless LESS is set
+// (quit if one screen, keep colors, don't clear). "cat" or "" disables it.
+pub fn with_pager(no_pager: bool, f: impl FnOnce(&mut dyn Write) -> Result<()>) -> Result<()> {
+    let pager = std::env::var("LG_PAGER")
+        .or_else(|_| std::env::var("PAGER"))
+        .unwrap_or_else(|_| "less".to_string());
+    let wanted = cfg!(unix) && !no_pager && !matches!(pager.trim(), "" | "cat");
+    let child = if wanted && TTY.stdout {
+        let mut command = std::process::Command::new("sh");
+        command.arg("-c").arg(&pager).stdin(Stdio::piped());
+        if std::env::var_os("LESS").is_none() {
+            command.env("LESS", "FRX");
+        }
+        if std::env::var_os("LV").is_none() {
+            command.env("LV", "-c");
+        }
+        command.spawn().ok()
+    } else {
+        None
+    };
+    let Some(mut child) = child else {
+        let out = io::stdout();
+        let mut out = out.lock();
+        return ignore_broken_pipe(f(&mut out).and_then(|_| Ok(out.flush()?)));
+    };
+    let mut stdin = io::BufWriter::new(child.stdin.take().expect("piped stdin"));
+    let res = f(&mut stdin).and_then(|_| Ok(stdin.flush()?));
+    drop(stdin);
+    child.wait().context("waiting for pager")?;
+    ignore_broken_pipe(res)
+}
+
+// Quitting the pager (or `| head`) before the end is not an error.
+pub fn ignore_broken_pipe(res: Result<()>) -> Result<()> {
+    match res {
+        Err(err)
+            if err.chain().any(|e| {
+                e.downcast_ref::<io::Error>()
+                    .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+            }) =>
+        {
+            Ok(())
+        }
+        other => other,
+    }
+}
+
+// The files of a run: split pairs are recorded under their `.out` file.
+pub fn run_files(path: &Path) -> Vec<(PathBuf, Option<Stream>)> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    for suffix in [".out.log", ".out.log.gz", ".out.log.zst"] {
+        if let Some(stem) = name.strip_suffix(suffix) {
+            let err = path.with_file_name(format!("{}{}", stem, suffix.replacen("out", "err", 1)));
+            let mut files = vec![(path.to_path_buf(), Some(Stream::Stdout))];
+            if err.exists() {
+                files.push((err, Some(Stream::Stderr)));
+            }
+            return files;
+        }
+    }
+    vec![(path.to_path_buf(), None)]
+}
+
+// Split "[ts][STDOUT] msg" or "[STDOUT] msg" into its stream label and message.
+pub fn split_line_marker(line: &str) -> (Option<&str>, &str) {
+    let mut rest = line;
+    for _ in 0..2 {
+        let Some((label, after)) = rest.strip_prefix('[').and_then(|r| r.split_once(']')) else {
+            break;
+        };
+        if matches!(
+            label,
+            "STDOUT" | "STDERR" | "STDIN" | "EXPECT" | "LG" | "FOLD"
+        ) {
+            return (Some(label), after.strip_prefix(' ').unwrap_or(after));
+        }
+        rest = after;
+    }
+    (None, line)
+}
+
+/// A `[[fold]]` marker, by the fold's name.
+#[derive(Debug, PartialEq)]
+pub enum FoldMarker {
+    Start(String),
+    End(String),
+}
+
+/// The fold marker a log line holds: "[ts][FOLD] start deps" in a text log,
+/// a "fold" record in a JSON Lines one.
+pub fn fold_marker(line: &str) -> Option<FoldMarker> {
+    #[derive(Deserialize)]
+    struct FoldRecord {
+        line: String,
+    }
+    let message = match split_line_marker(line) {
+        (Some("FOLD"), message) => message.to_string(),
+        _ if line.starts_with('{') && line.contains(r#""stream":"fold""#) => {
+            serde_json::from_str::<FoldRecord>(line).ok()?.line
+        }
+        _ => return None,
+    };
+    if let Some(name) = message.strip_prefix("start ") {
+        return Some(FoldMarker::Start(name.to_string()));
+    }
+    // "end deps (120 lines)"
+    let rest = message.strip_prefix("end ")?;
+    let name = rest.rsplit_once(" (").map_or(rest, |(name, _)| name);
+    Some(FoldMarker::End(name.to_string()))
+}
+
+pub fn is_footer_line(line: &[u8]) -> bool {
+    [
+        &b"[exit_code] "[..],
+        b"[pid] ",
+        b"[failure_class] ",
+        b"[probable_cause] ",
+        b"[timeout] ",
+        b"[max_log_size] ",
+        b"[compression] ",
+        b"[artifact] ",
+        b"[duration] ",
+        b"[cpu] ",
+        b"[max_rss] ",
+        b"[signal] ",
+        b"[cancelled] ",
+        b"[plugin] ",
+        b"[diagnostic] ",
+        b"[summary] ",
+        b"[baseline] ",
+    ]
+    .iter()
+    .any(|p| line.starts_with(p))
+}
+
+// Like `du -h`: 1024-based, one decimal below 10.
+pub fn human_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["B", "K", "M", "G"] {
+        if size < 1024.0 {
+            return match unit {
+                "B" => format!("{}B", bytes),
+                _ if size < 10.0 => format!("{:.1}{}", size, unit),
+                _ => format!("{:.0}{}", size, unit),
+            };
+        }
+        size /= 1024.0;
+    }
+    format!("{:.1}T", size)
+}
+
+pub fn opt_str<T: std::fmt::Display>(v: Option<T>) -> String {
+    v.map(|v| v.to_string()).unwrap_or_default()
+}
+
+// SGR codes for terminal output.
+pub static BOLD: &str = "1";
+pub static BOLD_RED: &str = "1;31";
+pub static DIM_RED: &str = "2;31";
+static RED: &str = "31";
+pub static GREEN: &str = "32";
+pub static YELLOW: &str = "33";
+pub static MAGENTA: &str = "35";
+pub static CYAN: &str = "36";
+
+// Color only when stdout is a terminal and NO_COLOR (https://no-color.org) is unset.
+pub fn use_color() -> bool {
+    TTY.stdout && std::env::var_os("NO_COLOR").map_or(true, |v| v.is_empty())
+}
+
+pub fn paint<'a>(s: &'a str, sgr: &str, on: bool) -> Cow<'a, str> {
+    if on && !s.is_empty() {
+        Cow::Owned(format!("\x1b[{}m{}\x1b[0m", sgr, s))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+// Table cells worth a color: exit codes by outcome, failure details in yellow.
+fn cell_color(column: &str, value: &str) -> Option<&'static str> {
+    match column {
+        "exit_code" | "last_exit_code" if value == "0" => Some(GREEN),
+        "exit_code" | "last_exit_code" if !value.is_empty() => Some(RED),
+        "failures" if value != "0" => Some(YELLOW),
+        "failure_class" | "failure_classes" | "probable_cause" if !value.is_empty() => Some(YELLOW),
+        _ => None,
+    }
+}
+
+// The rendering shared by the log tools: CSV (RFC 4180 quoting), tab-separated
+// plain text, or an aligned table that is colored on a terminal. JSON is
+// written by the callers, which serialize their own records.
+pub fn write_table<W: Write>(
+    mut w: W,
+    header: &[&str],
+    rows: impl Iterator<Item = Vec<String>>,
+    format: OutputFormat,
+) -> Result<()> {
+    if format == OutputFormat::Plain {
+        for row in rows {
+            let fields: Vec<String> = row.iter().map(|f| f.replace(['\t', '\n'], " ")).collect();
+            writeln!(w, "{}", fields.join("\t"))?;
+        }
+        return Ok(());
+    }
+    if format == OutputFormat::Csv {
+        let quote = |f: &str| {
+            if f.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", f.replace('"', "\"\""))
+            } else {
+                f.to_string()
+            }
+        };
+        writeln!(w, "{}", header.join(","))?;
+        for row in rows {
+            let fields: Vec<String> = row.iter().map(|f| quote(f)).collect();
+            writeln!(w, "{}", fields.join(","))?;
+        }
+        return Ok(());
+    }
+    let rows: Vec<Vec<String>> = rows.collect();
+    let mut widths: Vec<usize> = header.iter().map(|h| h.len()).collect();
+    for row in &rows {
+        for (width, field) in widths.iter_mut().zip(row) {
+            *width = (*width).max(field.chars().count());
+        }
+    }
+    let color = use_color();
+    let pad = |f: &str, width: usize| format!("{:<width$}", f, width = width);
+    let line: Vec<String> = header
+        .iter()
+        .zip(&widths)
+        .map(|(h, width)| paint(&pad(h, *width), BOLD, color).into_owned())
+        .collect();
+    writeln!(w, "{}", line.join("  ").trim_end())?;
+    for row in &rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .zip(header)
+            .map(|((f, width), column)| match cell_color(column, f) {
+                Some(sgr) if color => paint(&pad(f, *width), sgr, true).into_owned(),
+                _ => pad(f, *width),
+            })
+            .collect();
+        writeln!(w, "{}", line.join("  ").trim_end())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_read_like_du() {
+        assert_eq!(human_size(512), "512B");
+        assert_eq!(human_size(1536), "1.5K");
+        assert_eq!(human_size(20 * 1024 * 1024), "20M");
+        assert_eq!(human_size(3 << 40), "3.0T");
+    }
+
+    #[test]
+    fn a_templated_output_dir_is_scanned_below_its_root() {
+        let root = std::env::temp_dir().join(format!("lg-scan-test-{}", std::process::id()));
+        let log = "# lg log\ncmd: make\nargs: \ndate: 2026-10-16 09-00-00\n\
+                   ----- BEGIN OUTPUT -----\n\n[exit_code] 0\n";
+        for day in ["2026-10-15", "2026-10-16"] {
+            fs::create_dir_all(root.join("make").join(day)).unwrap();
+            fs::write(root.join("make").join(day).join("make.log"), log).unwrap();
+        }
+        fs::create_dir_all(root.join(".lg-trash/b/make")).unwrap();
+        fs::write(root.join(".lg-trash/b/make/make.log"), log).unwrap();
+        // A run still going, or one whose lg was killed.
+        let partial = root.join("make/2026-10-16/.make.log.partial");
+        fs::write(&partial, log).unwrap();
+        let cfg = Config {
+            output_dir: Some(root.join("{cmd}/{date}")),
+            ..Config::default()
+        };
+        assert_eq!(log_dir(&cfg, None), root);
+        assert_eq!(scan_logs(&cfg, &root).unwrap().len(), 2);
+        assert_eq!(partial_logs(&cfg, &root).unwrap(), [partial]);
+        // Any other directory is scanned as it is.
+        assert_eq!(scan_logs(&Config::default(), &root).unwrap().len(), 0);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn nested_runs_are_scanned_in_their_parents_directory() {
+        let root = std::env::temp_dir().join(format!("lg-nested-test-{}", std::process::id()));
+        let log = |cmd: &str| {
+            format!(
+                "# lg log\ncmd: {}\nargs: \ndate: 2026-10-16 09-00-00\n\
+                 ----- BEGIN OUTPUT -----\n\n[exit_code] 0\n",
+                cmd
+            )
+        };
+        let nested = root.join("01M52D1TK2GDWCCXJKCP2JPT9V");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.join("sh.log"), log("sh")).unwrap();
+        fs::write(nested.join("echo.log"), log("echo")).unwrap();
+        let partial = nested.join(".make.log.partial");
+        fs::write(&partial, log("make")).unwrap();
+        let cfg = Config {
+            nested_subdir: true,
+            ..Config::default()
+        };
+        let cmds: Vec<String> = scan_logs(&cfg, &root)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.cmd)
+            .collect();
+        assert_eq!(cmds, ["echo", "sh"]);
+        assert_eq!(partial_logs(&cfg, &root).unwrap(), [partial]);
+        assert_eq!(scan_logs(&Config::default(), &root).unwrap().len(), 1);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
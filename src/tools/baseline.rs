@@ -0,0 +1,229 @@
+//! `lg baseline`: pin a known-good run per command line (see `crate::baseline`)
+//! and compare runs with it.
+
+use crate::baseline::{Baseline, BaselineCheck};
+use crate::cli::{BaselineCommand, BaselineRunArgs, OutputFormat};
+use crate::config::Config;
+use crate::index::RunIndex;
+use crate::sinks::Stream;
+use crate::state::StateDir;
+use crate::tools::replay::read_events;
+use crate::tools::{
+    latest_run, log_dir, open_log, opt_str, parse_log, run_files, scan_logs, write_table, LogRecord,
+};
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub fn run_baseline(cfg: &Config, command: BaselineCommand) -> Result<i32> {
+    let state = StateDir::locate().context("cannot determine lg state directory (HOME unset)")?;
+    match command {
+        BaselineCommand::Set(args) => {
+            let run = find_run(cfg, &args)?;
+            let mut baseline = Baseline {
+                cmd: run.cmd.clone(),
+                args: run.args.clone(),
+                run_id: run.run_id.clone(),
+                // Absolute, so `lg baseline list` points at it from anywhere.
+                log: fs::canonicalize(&run.path).unwrap_or_else(|_| run.path.clone()),
+                exit_code: run.exit_code,
+                duration_secs: run.duration_secs.map(|s| s as f64),
+                lines: 0,
+                errors: count(&run, "error"),
+                warnings: count(&run, "warning"),
+                kinds: BTreeSet::new(),
+            };
+            for_each_line(&run.path, |line| baseline.observe(line))?;
+            state.save_baseline(&baseline)?;
+            println!(
+                "baseline of {}: run {} (exit {}, {} lines)",
+                baseline.command(),
+                baseline.run(),
+                opt_str(baseline.exit_code),
+                baseline.lines
+            );
+        }
+        BaselineCommand::Check(args) => {
+            let run = find_run(cfg, &args)?;
+            let baseline = state
+                .load_baseline(&run.cmd, &run.args)?
+                .with_context(|| format!("no baseline for {}", command_line(&run)))?;
+            let mut check = BaselineCheck::with(baseline, cfg.baseline_factor);
+            for_each_line(&run.path, |line| check.observe(line))?;
+            let deviations = check.deviations(
+                run.exit_code,
+                run.duration_secs.map(|s| s as f64),
+                &run.diagnostics,
+            );
+            let this = run
+                .run_id
+                .clone()
+                .unwrap_or_else(|| run.path.display().to_string());
+            let base = check.baseline().run();
+            if deviations.is_empty() {
+                println!(
+                    "{}: run {} matches baseline {}",
+                    command_line(&run),
+                    this,
+                    base
+                );
+                return Ok(0);
+            }
+            println!(
+                "{}: run {} differs from baseline {}",
+                command_line(&run),
+                this,
+                base
+            );
+            for deviation in &deviations {
+                println!("  {}", deviation);
+            }
+            return Ok(1);
+        }
+        BaselineCommand::List => {
+            let baselines = state.baselines()?;
+            let header = [
+                "command",
+                "run",
+                "exit_code",
+                "duration_secs",
+                "lines",
+                "log",
+            ];
+            let rows = baselines.iter().map(|b| {
+                vec![
+                    b.command(),
+                    b.run_id.clone().unwrap_or_default(),
+                    opt_str(b.exit_code),
+                    opt_str(b.duration_secs),
+                    b.lines.to_string(),
+                    b.log.display().to_string(),
+                ]
+            });
+            write_table(io::stdout().lock(), &header, rows, OutputFormat::Table)?;
+        }
+        BaselineCommand::Unset { command } => {
+            let command = command.join(" ");
+            let baselines = state.baselines()?;
+            let Some(baseline) = baselines.iter().find(|b| b.command() == command) else {
+                bail!("no baseline for {}", command);
+            };
+            state.remove_baseline(&baseline.cmd, &baseline.args)?;
+            println!("removed the baseline of {}", command);
+        }
+    }
+    Ok(0)
+}
+
+// The run `args` names: a log file, a run id, or else the most recent run.
+fn find_run(cfg: &Config, args: &BaselineRunArgs) -> Result<LogRecord> {
+    let path = match &args.run {
+        None => latest_run(cfg, args.dir.clone(), args.cmd.as_deref())?,
+        Some(run) if Path::new(run).is_file() => PathBuf::from(run),
+        Some(id) => run_log(cfg, args.dir.clone(), id)?,
+    };
+    parse_log(cfg, &path)?.with_context(|| format!("{} is not an lg log", path.display()))
+}
+
+// The log of run `id`: from the run index, which covers every directory, or
+// else from the log directory.
+fn run_log(cfg: &Config, dir: Option<PathBuf>, id: &str) -> Result<PathBuf> {
+    if let Some(index) = RunIndex::existing()?.filter(|_| dir.is_none()) {
+        let runs = index.runs()?;
+        if let Some(run) = runs.into_iter().find(|r| r.run_id.as_deref() == Some(id)) {
+            return Ok(run.cwd.join(run.log_path));
+        }
+    }
+    let dir = log_dir(cfg, dir);
+    scan_logs(cfg, &dir)?
+        .into_iter()
+        .find(|r| r.run_id.as_deref() == Some(id))
+        .map(|r| r.path)
+        .with_context(|| format!("no run {} in {} or the run index", id, dir.display()))
+}
+
+// Each output line of the run, from both logs of a split pair.
+fn for_each_line(path: &Path, mut f: impl FnMut(&str)) -> Result<()> {
+    for (file, default_stream) in run_files(path) {
+        let events = read_events(open_log(&file)?, default_stream.unwrap_or(Stream::Stdout))?;
+        for event in events {
+            let text = String::from_utf8_lossy(&event.text);
+            text.lines().for_each(&mut f);
+        }
+    }
+    Ok(())
+}
+
+fn count(run: &LogRecord, severity: &str) -> usize {
+    run.diagnostics
+        .iter()
+        .filter(|d| d.severity == severity)
+        .count()
+}
+
+fn command_line(run: &LogRecord) -> String {
+    format!("{} {}", run.cmd, run.args).trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_run_is_compared_with_the_log_it_was_pinned_from() {
+        let dir = std::env::temp_dir().join(format!("lg-baseline-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = |name: &str, run_id: &str, body: &str, code: i32| {
+            let path = dir.join(name);
+            fs::write(
+                &path,
+                format!(
+                    "# lg log\ncmd: backup.sh\nargs: --full\ndate: 2026-10-16 02-00-00\n\
+                     run_id: {}\n----- BEGIN OUTPUT -----\n{}\n[exit_code] {}\n[duration] 40.2s\n",
+                    run_id, body, code
+                ),
+            )
+            .unwrap();
+            parse_log(&Config::default(), &path).unwrap().unwrap()
+        };
+        let good = log(
+            "good.log",
+            "01M5170180ZZZZZZZZZZZZZZZZ",
+            "[02:00:00.100][STDOUT] copied 120 files\n[02:00:40.200][LG] note\n",
+            0,
+        );
+        let mut baseline = Baseline {
+            cmd: good.cmd.clone(),
+            args: good.args.clone(),
+            run_id: good.run_id.clone(),
+            log: good.path.clone(),
+            exit_code: good.exit_code,
+            duration_secs: good.duration_secs.map(|s| s as f64),
+            lines: 0,
+            errors: 0,
+            warnings: 0,
+            kinds: BTreeSet::new(),
+        };
+        for_each_line(&good.path, |line| baseline.observe(line)).unwrap();
+        assert_eq!(baseline.lines, 1);
+
+        let bad = log(
+            "bad.log",
+            "01M5170190ZZZZZZZZZZZZZZZZ",
+            "[02:00:00.100][STDOUT] copied 7 files\n[02:00:01.000][STDERR] disk full\n",
+            1,
+        );
+        let mut check = BaselineCheck::with(baseline, 2.0);
+        for_each_line(&bad.path, |line| check.observe(line)).unwrap();
+        assert_eq!(
+            check.deviations(bad.exit_code, Some(40.0), &bad.diagnostics),
+            [
+                "exit code 1, baseline 0",
+                "1 new kind(s) of line, first: disk full"
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,245 @@
+//! A full-screen checklist on the terminal, for `lg clean --interactive`.
+
+use anyhow::Result;
+use std::io::Write;
+
+/// What a key press does in the checklist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    Toggle,
+    ToggleAll,
+    Confirm,
+    Cancel,
+    Other,
+}
+
+// One read from a raw terminal is one key; arrows arrive as escape sequences.
+fn decode(input: &[u8]) -> Key {
+    match input {
+        b"\x1b[A" | b"\x1bOA" | b"k" => Key::Up,
+        b"\x1b[B" | b"\x1bOB" | b"j" => Key::Down,
+        b" " | b"x" => Key::Toggle,
+        b"a" => Key::ToggleAll,
+        b"\r" | b"\n" => Key::Confirm,
+        // A lone Escape, q or Ctrl-C (signals are off in raw mode).
+        b"\x1b" | b"q" | b"\x03" => Key::Cancel,
+        _ => Key::Other,
+    }
+}
+
+/// Cursor, scroll position and ticks; independent of the terminal.
+struct Checklist {
+    checked: Vec<bool>,
+    cursor: usize,
+    top: usize,
+}
+
+impl Checklist {
+    fn new(len: usize) -> Self {
+        // Everything the filters matched starts ticked; untick what to keep.
+        Self {
+            checked: vec![true; len],
+            cursor: 0,
+            top: 0,
+        }
+    }
+
+    // Some(true) to confirm, Some(false) to cancel.
+    fn press(&mut self, key: Key) -> Option<bool> {
+        let len = self.checked.len();
+        match key {
+            Key::Up => self.cursor = self.cursor.saturating_sub(1),
+            Key::Down => self.cursor = (self.cursor + 1).min(len.saturating_sub(1)),
+            Key::Toggle if len > 0 => self.checked[self.cursor] ^= true,
+            Key::ToggleAll => {
+                let all = self.checked.iter().all(|c| *c);
+                self.checked.iter_mut().for_each(|c| *c = !all);
+            }
+            Key::Confirm => return Some(true),
+            Key::Cancel => return Some(false),
+            Key::Toggle | Key::Other => {}
+        }
+        None
+    }
+
+    fn selected(&self) -> Vec<usize> {
+        (0..self.checked.len())
+            .filter(|i| self.checked[*i])
+            .collect()
+    }
+
+    // Keep the cursor inside a window of `rows` lines.
+    fn scroll(&mut self, rows: usize) {
+        let rows = rows.max(1);
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + rows {
+            self.top = self.cursor + 1 - rows;
+        }
+    }
+
+    fn render(
+        &mut self,
+        out: &mut impl Write,
+        title: &str,
+        items: &[String],
+        status: &str,
+        (width, height): (usize, usize),
+    ) -> std::io::Result<()> {
+        // Title, blank line, items, blank line, status.
+        let rows = height.saturating_sub(4);
+        self.scroll(rows);
+        let clip = |s: &str| s.chars().take(width).collect::<String>();
+        write!(out, "\x1b[H\x1b[2J{}\r\n\r\n", clip(title))?;
+        for (i, item) in items.iter().enumerate().skip(self.top).take(rows.max(1)) {
+            let mark = if self.checked[i] { "[x]" } else { "[ ]" };
+            let line = clip(&format!("{} {}", mark, item));
+            if i == self.cursor {
+                write!(out, "\x1b[7m{}\x1b[0m\r\n", line)?;
+            } else {
+                write!(out, "{}\r\n", line)?;
+            }
+        }
+        write!(out, "\r\n{}", clip(status))?;
+        out.flush()
+    }
+}
+
+/// Let the user tick items on the controlling terminal; `status` describes a
+/// selection for the bottom line. Returns the ticked indices, or None when cancelled.
+#[cfg(unix)]
+pub fn pick(
+    title: &str,
+    items: &[String],
+    status: impl Fn(&[usize]) -> String,
+) -> Result<Option<Vec<usize>>> {
+    use anyhow::Context;
+    use std::io::Read;
+
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .context("--interactive needs a terminal")?;
+    let _raw = RawMode::enter(&tty)?;
+    write!(tty, "\x1b[?1049h\x1b[?25l")?;
+    let mut list = Checklist::new(items.len());
+    let mut buf = [0u8; 16];
+    let done = loop {
+        let selected = list.selected();
+        let help = "space toggle, a all, enter confirm, q cancel";
+        let status = format!("{}  ({})", status(&selected), help);
+        let size = window_size(&tty);
+        list.render(&mut tty, title, items, &status, size)?;
+        let n = tty.read(&mut buf)?;
+        if n == 0 {
+            break false;
+        }
+        if let Some(done) = list.press(decode(&buf[..n])) {
+            break done;
+        }
+    };
+    Ok(done.then(|| list.selected()))
+}
+
+#[cfg(not(unix))]
+pub fn pick(
+    _title: &str,
+    _items: &[String],
+    _status: impl Fn(&[usize]) -> String,
+) -> Result<Option<Vec<usize>>> {
+    anyhow::bail!("--interactive needs a Unix terminal")
+}
+
+#[cfg(unix)]
+fn window_size(tty: &std::fs::File) -> (usize, usize) {
+    use std::os::fd::AsRawFd;
+    // SAFETY: TIOCGWINSZ only writes into the provided struct.
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(tty.as_raw_fd(), libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_col > 0
+    {
+        (size.ws_col as usize, size.ws_row as usize)
+    } else {
+        (80, 24)
+    }
+}
+
+/// Unbuffered, unechoed input until dropped, which also leaves the
+/// alternate screen the checklist is drawn on.
+#[cfg(unix)]
+struct RawMode {
+    fd: std::os::fd::RawFd,
+    saved: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    fn enter(tty: &std::fs::File) -> std::io::Result<Self> {
+        use std::os::fd::AsRawFd;
+        let fd = tty.as_raw_fd();
+        // SAFETY: tcgetattr/tcsetattr only read and write the termios struct.
+        unsafe {
+            let mut saved: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut saved) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            let mut raw = saved;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(fd, libc::TCSANOW, &raw) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(Self { fd, saved })
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: restores the settings read in `enter` on the same descriptor.
+        unsafe {
+            let reset = b"\x1b[?25h\x1b[?1049l";
+            libc::write(self.fd, reset.as_ptr().cast(), reset.len());
+            libc::tcsetattr(self.fd, libc::TCSANOW, &self.saved);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_move_and_tick() {
+        let mut list = Checklist::new(3);
+        for key in [b"j".as_slice(), b" ", b"\x1b[B", b"\x1b[B", b"x"] {
+            assert_eq!(list.press(decode(key)), None);
+        }
+        assert_eq!(list.selected(), [0]);
+        list.press(decode(b"a"));
+        assert_eq!(list.selected(), [0, 1, 2]);
+        list.press(decode(b"a"));
+        assert!(list.selected().is_empty());
+        assert_eq!(list.press(decode(b"\r")), Some(true));
+        assert_eq!(list.press(decode(b"\x1b")), Some(false));
+    }
+
+    #[test]
+    fn window_follows_the_cursor() {
+        let items: Vec<String> = (0..10).map(|i| format!("run {}", i)).collect();
+        let mut list = Checklist::new(items.len());
+        (0..6).for_each(|_| {
+            list.press(Key::Down);
+        });
+        let mut screen = Vec::new();
+        list.render(&mut screen, "t", &items, "s", (80, 7)).unwrap();
+        let screen = String::from_utf8(screen).unwrap();
+        assert_eq!(list.top, 4);
+        assert!(screen.contains("[x] run 4") && screen.contains("\x1b[7m[x] run 6"));
+        assert!(!screen.contains("run 3") && !screen.contains("run 7"));
+    }
+}
@@ -0,0 +1,527 @@
+//! `lg clean`.
+
+use crate::cli::CleanArgs;
+use crate::config::Config;
+use crate::index::RunIndex;
+use crate::state::RunningEntry;
+use crate::timespec::started_between;
+use crate::tools::checklist;
+use crate::tools::trash::Trash;
+use crate::tools::{
+    human_size, log_dir, opt_str, parse_log, partial_logs, run_files, scan_logs, LogRecord,
+};
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDateTime, TimeZone};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub fn run_clean(cfg: &Config, args: CleanArgs) -> Result<()> {
+    let dir = log_dir(cfg, args.dir.clone());
+    let trash = Trash::in_dir(&dir);
+    if args.undo_last {
+        return undo_last(&trash, &dir, args.dry_run);
+    }
+    if !(args.delete || args.interactive || args.orphans) && args.compress_older_than.is_none() {
+        anyhow::bail!(
+            "nothing to do; pass --compress-older-than TIME, --delete, --interactive, --orphans or --undo-last"
+        );
+    }
+    if !args.dry_run {
+        let purged = trash.purge(cfg.clean_trash_days, Local::now().naive_local())?;
+        if purged > 0 {
+            println!(
+                "purged {} batch(es) older than {} days from {}",
+                purged,
+                cfg.clean_trash_days,
+                trash.root().display()
+            );
+        }
+    }
+    if args.orphans {
+        clean_orphans(cfg, &dir, &args)?;
+    } else if args.delete || args.interactive {
+        delete_runs(cfg, &dir, &args)?;
+    }
+    match args.compress_older_than {
+        Some(before) => compress_logs(cfg, &dir, before, args.dry_run),
+        None => Ok(()),
+    }
+}
+
+// The logs running lgs are writing, which are never offered.
+fn running_logs() -> Vec<PathBuf> {
+    RunningEntry::list()
+        .into_iter()
+        .flat_map(|e| e.logs)
+        .collect()
+}
+
+fn delete_runs(cfg: &Config, dir: &Path, args: &CleanArgs) -> Result<()> {
+    let running = running_logs();
+    let mut runs = scan_logs(cfg, dir)?;
+    let total: u64 = runs.iter().map(|r| r.bytes).sum();
+    let last_started = group_last_started(&runs);
+    runs.retain(|r| {
+        // A group is only as old as its newest run.
+        let started = match &r.group {
+            Some(group) => last_started[group],
+            None => r.started,
+        };
+        args.cmd.as_ref().map_or(true, |c| &r.cmd == c)
+            && args
+                .group
+                .as_ref()
+                .map_or(true, |g| r.group.as_ref() == Some(g))
+            && started_between(started, None, args.older_than)
+            && (!args.failed_only || r.exit_code.is_some_and(|c| c != 0))
+            && !fs::canonicalize(&r.path).is_ok_and(|p| running.contains(&p))
+    });
+    let matched = runs.clone();
+    if let Some(keep) = args.keep_last {
+        spare_newest(&mut runs, keep);
+    }
+    if let Some(max) = args.max_total_size {
+        trim_to_size(&mut runs, total, max as u64);
+    }
+    spare_groups(&mut runs, &matched);
+    if runs.is_empty() {
+        println!("no matching runs in {}", dir.display());
+        return Ok(());
+    }
+    if args.interactive {
+        let items: Vec<String> = runs.iter().map(run_label).collect();
+        let title = format!(
+            "Runs to delete from {} (unticked runs are kept)",
+            dir.display()
+        );
+        let picked = checklist::pick(&title, &items, |selected| {
+            let bytes: u64 = selected.iter().map(|i| runs[*i].bytes).sum();
+            format!(
+                "{} of {} runs, {}",
+                selected.len(),
+                runs.len(),
+                human_size(bytes)
+            )
+        })?;
+        let Some(picked) = picked else {
+            println!("cancelled; nothing deleted");
+            return Ok(());
+        };
+        runs = picked.into_iter().map(|i| runs[i].clone()).collect();
+    }
+    let batch = new_batch(cfg, dir, args)?;
+    let mut freed = 0;
+    let mut removed = Vec::new();
+    for run in &runs {
+        let files = run_files(&run.path).into_iter().map(|(file, _)| file);
+        let artifact = run.artifact.clone().filter(|a| a.exists());
+        for file in files.chain(artifact) {
+            if let Ok(path) = fs::canonicalize(&file) {
+                removed.push(path);
+            }
+            remove_log(&file, dir, batch.as_deref(), args.dry_run)?;
+        }
+        freed += run.bytes;
+    }
+    if !args.dry_run {
+        forget_in_index(&removed);
+    }
+    if batch.is_some() {
+        println!(
+            "moved {} in {} runs to {}; `lg clean --undo-last` puts them back",
+            human_size(freed),
+            runs.len(),
+            Trash::in_dir(dir).root().display()
+        );
+        return Ok(());
+    }
+    let verb = if args.dry_run { "would free" } else { "freed" };
+    println!("{} {} in {} runs", verb, human_size(freed), runs.len());
+    Ok(())
+}
+
+// `.partial` logs no running lg is writing: their lg was killed before it
+// could rename them. Listed, or with --delete deleted like runs.
+fn clean_orphans(cfg: &Config, dir: &Path, args: &CleanArgs) -> Result<()> {
+    let running = running_logs();
+    let orphans: Vec<(PathBuf, u64)> = partial_logs(cfg, dir)?
+        .into_iter()
+        .filter(|log| {
+            // The part of a log that `max_log_size_action = "rotate"` moved out.
+            let log = match log.to_string_lossy().strip_suffix(".1") {
+                Some(rotated) => PathBuf::from(rotated),
+                None => log.clone(),
+            };
+            !fs::canonicalize(log).is_ok_and(|p| running.contains(&p))
+        })
+        .map(|log| {
+            let size = fs::metadata(&log).map_or(0, |m| m.len());
+            (log, size)
+        })
+        .collect();
+    if orphans.is_empty() {
+        println!("no orphaned .partial logs in {}", dir.display());
+        return Ok(());
+    }
+    let freed: u64 = orphans.iter().map(|(_, size)| size).sum();
+    if !args.delete {
+        for (log, size) in &orphans {
+            let run = parse_log(cfg, log).ok().flatten();
+            let command = run.map(|r| format!("{} {}", r.cmd, r.args));
+            println!(
+                "{}  {:>5}  {}",
+                log.display(),
+                human_size(*size),
+                command.unwrap_or_default().trim_end()
+            );
+        }
+        println!(
+            "{} orphaned .partial log(s), {}; `lg clean --orphans --delete` removes them",
+            orphans.len(),
+            human_size(freed)
+        );
+        return Ok(());
+    }
+    let batch = new_batch(cfg, dir, args)?;
+    let mut removed = Vec::new();
+    for (log, _) in &orphans {
+        if let Ok(path) = fs::canonicalize(log) {
+            removed.push(path);
+        }
+        remove_log(log, dir, batch.as_deref(), args.dry_run)?;
+    }
+    if !args.dry_run {
+        forget_in_index(&removed);
+    }
+    if batch.is_some() {
+        println!(
+            "moved {} in {} orphaned log(s) to {}; `lg clean --undo-last` puts them back",
+            human_size(freed),
+            orphans.len(),
+            Trash::in_dir(dir).root().display()
+        );
+        return Ok(());
+    }
+    let verb = if args.dry_run { "would free" } else { "freed" };
+    println!(
+        "{} {} in {} orphaned log(s)",
+        verb,
+        human_size(freed),
+        orphans.len()
+    );
+    Ok(())
+}
+
+// The trash batch deleted logs go to, unless they are deleted for good.
+fn new_batch(cfg: &Config, dir: &Path, args: &CleanArgs) -> Result<Option<PathBuf>> {
+    match args.clean_trash(cfg) && !args.dry_run {
+        true => Ok(Some(
+            Trash::in_dir(dir).new_batch(Local::now().naive_local())?,
+        )),
+        false => Ok(None),
+    }
+}
+
+// Delete `file`, or move it to the trash `batch`.
+fn remove_log(file: &Path, dir: &Path, batch: Option<&Path>, dry_run: bool) -> Result<()> {
+    if dry_run {
+        println!("would delete {}", file.display());
+    } else if let Some(batch) = batch {
+        // Logs in subdirectories of a templated output_dir keep their place.
+        let relative = file
+            .strip_prefix(dir)
+            .unwrap_or_else(|_| Path::new(file.file_name().unwrap_or_default()));
+        let trashed = batch.join(relative);
+        if let Some(parent) = trashed.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("create {:?}", parent))?;
+        }
+        fs::rename(file, &trashed).with_context(|| format!("trashing {:?}", file))?;
+        println!("trashed {}", file.display());
+        remove_empty_dirs(file, dir);
+    } else {
+        fs::remove_file(file).with_context(|| format!("deleting {:?}", file))?;
+        println!("deleted {}", file.display());
+        remove_empty_dirs(file, dir);
+    }
+    Ok(())
+}
+
+// Take the `keep` newest runs of each command off the (oldest first) list.
+fn spare_newest(runs: &mut Vec<LogRecord>, keep: usize) {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut spared = vec![false; runs.len()];
+    for (i, run) in runs.iter().enumerate().rev() {
+        let n = seen.entry(run.cmd.clone()).or_default();
+        *n += 1;
+        spared[i] = *n <= keep;
+    }
+    let mut spared = spared.into_iter();
+    runs.retain(|_| !spared.next().unwrap_or(false));
+}
+
+// Keep only as many of the oldest runs as it takes to bring `total` bytes down to `max`.
+fn trim_to_size(runs: &mut Vec<LogRecord>, total: u64, max: u64) {
+    let mut over = total.saturating_sub(max);
+    let needed = runs
+        .iter()
+        .take_while(|r| {
+            let needed = over > 0;
+            over = over.saturating_sub(r.bytes);
+            needed
+        })
+        .count();
+    runs.truncate(needed);
+}
+
+// The start of the newest run of each group.
+fn group_last_started(runs: &[LogRecord]) -> HashMap<String, Option<NaiveDateTime>> {
+    let mut last: HashMap<String, Option<NaiveDateTime>> = HashMap::new();
+    for run in runs {
+        if let Some(group) = &run.group {
+            let started = last.entry(group.clone()).or_default();
+            *started = (*started).max(run.started);
+        }
+    }
+    last
+}
+
+// A run that `--keep-last` or `--max-total-size` spared spares the rest of its
+// group: a group's runs are kept or deleted together.
+fn spare_groups(runs: &mut Vec<LogRecord>, matched: &[LogRecord]) {
+    let left: HashSet<&Path> = runs.iter().map(|r| r.path.as_path()).collect();
+    let spared: HashSet<String> = matched
+        .iter()
+        .filter(|r| !left.contains(r.path.as_path()))
+        .filter_map(|r| r.group.clone())
+        .collect();
+    runs.retain(|r| r.group.as_ref().map_or(true, |g| !spared.contains(g)));
+}
+
+// Best effort: the logs are gone either way.
+fn forget_in_index(removed: &[PathBuf]) {
+    let forgotten = RunIndex::existing().and_then(|index| match index {
+        Some(index) => index.forget(removed),
+        None => Ok(0),
+    });
+    match forgotten {
+        Ok(0) => {}
+        Ok(n) => println!("removed {} run(s) from the run index", n),
+        Err(err) => eprintln!("lg: index: {:#}", err),
+    }
+}
+
+fn undo_last(trash: &Trash, dir: &Path, dry_run: bool) -> Result<()> {
+    let Some(done) = trash.restore_last(dir, dry_run)? else {
+        println!("nothing to undo; {} is empty", trash.root().display());
+        return Ok(());
+    };
+    let verb = if dry_run { "would restore" } else { "restored" };
+    for file in &done.restored {
+        println!("{} {}", verb, file.display());
+    }
+    for file in &done.kept {
+        println!(
+            "kept {} in the trash: a file of that name exists",
+            file.display()
+        );
+    }
+    Ok(())
+}
+
+// One checklist line: start, size, outcome and command.
+fn run_label(run: &LogRecord) -> String {
+    let status = match run.exit_code {
+        Some(0) => "ok".to_string(),
+        Some(code) => format!("exit {}", code),
+        None => "no exit".to_string(),
+    };
+    format!(
+        "{:<19}  {:>5}  {:<8}  {} {}",
+        opt_str(run.started.map(|t| t.format("%Y-%m-%d %H:%M:%S"))),
+        human_size(run.bytes),
+        status,
+        run.cmd,
+        run.args
+    )
+    .trim_end()
+    .to_string()
+}
+
+fn compress_logs(cfg: &Config, dir: &Path, before: NaiveDateTime, dry_run: bool) -> Result<()> {
+    let cutoff: std::time::SystemTime = Local
+        .from_local_datetime(&before)
+        .earliest()
+        .context("--compress-older-than falls into a skipped local time")?
+        .into();
+    let mut compressed = 0;
+    let mut moved = Vec::new();
+    let runs = scan_logs(cfg, dir)?;
+    for (path, _) in runs.iter().flat_map(|r| run_files(&r.path)) {
+        if !path.to_string_lossy().ends_with(".log") {
+            continue;
+        }
+        let modified = fs::metadata(&path)?.modified()?;
+        if modified > cutoff {
+            continue;
+        }
+        let target = PathBuf::from(format!("{}.gz", path.display()));
+        if dry_run {
+            println!("would compress {}", path.display());
+        } else {
+            let from = fs::canonicalize(&path);
+            gzip_file(&path, &target, modified)
+                .with_context(|| format!("compressing {:?}", path))?;
+            println!("compressed {}", target.display());
+            if let Ok(from) = from {
+                let to = PathBuf::from(format!("{}.gz", from.display()));
+                moved.push((from, to));
+            }
+        }
+        compressed += 1;
+    }
+    if !moved.is_empty() {
+        rename_in_index(&moved);
+    }
+    if compressed == 0 {
+        println!(
+            "no plain logs last written before {} in {}",
+            before.format("%Y-%m-%d %H:%M:%S"),
+            dir.display()
+        );
+    }
+    Ok(())
+}
+
+// Best effort, like `forget_in_index`: the logs are compressed either way.
+fn rename_in_index(moved: &[(PathBuf, PathBuf)]) {
+    let renamed = RunIndex::existing().and_then(|index| match index {
+        Some(index) => index.rename_logs(moved),
+        None => Ok(0),
+    });
+    if let Err(err) = renamed {
+        eprintln!("lg: index: {:#}", err);
+    }
+}
+
+// Remove the directories between `file` and `dir` that it leaves empty, such
+// as an old `{date}` directory of a templated output_dir.
+fn remove_empty_dirs(file: &Path, dir: &Path) {
+    for parent in file.ancestors().skip(1) {
+        if parent == dir || !parent.starts_with(dir) || fs::remove_dir(parent).is_err() {
+            break;
+        }
+    }
+}
+
+// Replace `src` by a gzip copy at `dst`, keeping the original modification time.
+fn gzip_file(src: &Path, dst: &Path, modified: std::time::SystemTime) -> Result<()> {
+    let mut input = File::open(src)?;
+    let tmp = dst.with_extension("gz.partial");
+    let mut enc = GzEncoder::new(File::create(&tmp)?, Compression::default());
+    io::copy(&mut input, &mut enc)?;
+    let file = enc.finish()?;
+    file.set_modified(modified)?;
+    drop(file);
+    fs::rename(&tmp, dst)?;
+    fs::remove_file(src)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn run(cmd: &str, bytes: u64) -> LogRecord {
+        LogRecord {
+            path: PathBuf::from(format!("{}.log", cmd)),
+            run_id: None,
+            parent_run_id: None,
+            cmd: cmd.to_string(),
+            args: String::new(),
+            cwd: None,
+            group: None,
+            started: None,
+            start_delay_secs: None,
+            fingerprint: None,
+            fingerprint_changed: false,
+            duration_secs: None,
+            exit_code: Some(0),
+            failure_class: None,
+            probable_cause: None,
+            summary: None,
+            diagnostics: Vec::new(),
+            baseline: Vec::new(),
+            anomalies: Vec::new(),
+            artifact: None,
+            lines: 0,
+            bytes,
+        }
+    }
+
+    fn cmds(runs: &[LogRecord]) -> Vec<(&str, u64)> {
+        runs.iter().map(|r| (r.cmd.as_str(), r.bytes)).collect()
+    }
+
+    #[test]
+    fn keep_last_and_max_total_size_pick_the_oldest() {
+        // Oldest first, as scan_logs returns them.
+        let all = vec![
+            run("make", 1),
+            run("backup", 2),
+            run("make", 3),
+            run("make", 4),
+            run("backup", 5),
+        ];
+        let mut runs = all.clone();
+        spare_newest(&mut runs, 2);
+        assert_eq!(cmds(&runs), [("make", 1)]);
+        let mut runs = all.clone();
+        spare_newest(&mut runs, 1);
+        assert_eq!(cmds(&runs), [("make", 1), ("backup", 2), ("make", 3)]);
+
+        // 15 bytes in all; getting down to 10 takes the three oldest.
+        let mut runs = all.clone();
+        trim_to_size(&mut runs, 15, 10);
+        assert_eq!(cmds(&runs), [("make", 1), ("backup", 2), ("make", 3)]);
+        let mut runs = all;
+        trim_to_size(&mut runs, 15, 15);
+        assert!(runs.is_empty());
+    }
+
+    #[test]
+    fn groups_are_kept_or_deleted_whole() {
+        let at = |day: u32| NaiveDate::from_ymd_opt(2026, 10, day)?.and_hms_opt(2, 0, 0);
+        let mut all = vec![
+            run("make", 1),
+            run("test", 2),
+            run("make", 3),
+            run("make", 4),
+        ];
+        for (i, run) in all.iter_mut().enumerate() {
+            run.path = PathBuf::from(format!("{}.log", i));
+            run.started = at(i as u32 + 1);
+        }
+        all[1].group = Some("nightly".into());
+        all[2].group = Some("nightly".into());
+        assert_eq!(group_last_started(&all)["nightly"], at(3));
+        // Sparing the only test run spares the make run of its group.
+        let mut runs = all.clone();
+        spare_newest(&mut runs, 1);
+        spare_groups(&mut runs, &all);
+        assert_eq!(cmds(&runs), [("make", 1)]);
+        // Down to 7 of 10 bytes would split the group; down to 4 takes all of it.
+        let mut runs = all.clone();
+        trim_to_size(&mut runs, 10, 7);
+        spare_groups(&mut runs, &all);
+        assert_eq!(cmds(&runs), [("make", 1)]);
+        let mut runs = all.clone();
+        trim_to_size(&mut runs, 10, 4);
+        spare_groups(&mut runs, &all);
+        assert_eq!(cmds(&runs), [("make", 1), ("test", 2), ("make", 3)]);
+    }
+}
@@ -0,0 +1,192 @@
+//! `lg config doc`: the config keys described from the schema derived from `Config`.
+
+use crate::config::{env_keys, profiles, read_config_table, Config};
+use crate::tools::with_pager;
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// What `lg config doc` says about one key.
+#[derive(Debug)]
+struct KeyDoc {
+    key: String,
+    description: String,
+    ty: String,
+    default: Value,
+    value: Value,
+    /// The config file, profile or `LG_<KEY>` variable that sets the key (the
+    /// one that wins, if several do), else "default".
+    source: String,
+}
+
+pub fn run_config_doc(
+    cfg: &Config,
+    sources: &[PathBuf],
+    profile: Option<&str>,
+    key: Option<&str>,
+    no_pager: bool,
+) -> Result<()> {
+    let mut file_keys = BTreeMap::new();
+    for path in sources {
+        for key in read_config_table(path)?.keys() {
+            file_keys.insert(key.clone(), path.display().to_string());
+        }
+    }
+    // The profile's keys win over every file's own.
+    for path in sources.iter().filter(|_| profile.is_some()) {
+        let table = read_config_table(path)?;
+        let keys = profiles(&table).filter(|(name, _)| Some(name.as_str()) == profile);
+        for key in keys.flat_map(|(_, keys)| keys.keys()) {
+            let source = format!(
+                "{} [profile.{}]",
+                path.display(),
+                profile.unwrap_or_default()
+            );
+            file_keys.insert(key.clone(), source);
+        }
+    }
+    // Then the environment's.
+    for (key, var, _) in env_keys(std::env::vars_os()) {
+        file_keys.insert(key, var);
+    }
+    let mut docs = key_docs(cfg, &file_keys)?;
+    if let Some(key) = key {
+        docs.retain(|d| d.key == key);
+        if docs.is_empty() {
+            anyhow::bail!(
+                "unknown config key '{}'; `lg config doc` lists them all",
+                key
+            );
+        }
+    }
+    with_pager(no_pager, |out| {
+        for (i, doc) in docs.iter().enumerate() {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            writeln!(out, "{}", doc.key)?;
+            for line in doc.description.lines() {
+                writeln!(out, "    {}", line)?;
+            }
+            writeln!(out, "    type:    {}", doc.ty)?;
+            writeln!(out, "    default: {}", render(&doc.default))?;
+            writeln!(out, "    value:   {} ({})", render(&doc.value), doc.source)?;
+        }
+        Ok(())
+    })
+}
+
+// One entry per top-level key, alphabetically.
+fn key_docs(cfg: &Config, file_keys: &BTreeMap<String, String>) -> Result<Vec<KeyDoc>> {
+    let schema = serde_json::to_value(schemars::schema_for!(Config))?;
+    let values = serde_json::to_value(cfg)?;
+    let defs = &schema["definitions"];
+    let Some(properties) = schema["properties"].as_object() else {
+        return Ok(Vec::new());
+    };
+    Ok(properties
+        .iter()
+        .map(|(key, prop)| KeyDoc {
+            key: key.clone(),
+            description: prop["description"].as_str().unwrap_or_default().to_string(),
+            ty: type_name(prop, defs),
+            default: prop["default"].clone(),
+            value: values[key].clone(),
+            source: file_keys
+                .get(key)
+                .map_or_else(|| "default".to_string(), String::clone),
+        })
+        .collect())
+}
+
+// A short type for humans: `bool`, `integer (optional)`, `"none" | "gz" | "zstd"`.
+fn type_name(schema: &Value, defs: &Value) -> String {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.rsplit('/').next().unwrap_or(reference);
+        let def = &defs[name];
+        if def["properties"].is_object() {
+            return "table".into();
+        }
+        return type_name(def, defs);
+    }
+    if let Some(values) = schema["enum"].as_array() {
+        return join_alternatives(values.iter().map(render));
+    }
+    for combinator in ["allOf", "anyOf", "oneOf"] {
+        if let Some(parts) = schema[combinator].as_array() {
+            let optional = parts.iter().any(|p| p["type"] == "null");
+            let alternatives = parts
+                .iter()
+                .filter(|p| p["type"] != "null")
+                .map(|p| type_name(p, defs));
+            return optional_if(join_alternatives(alternatives), optional);
+        }
+    }
+    let types: Vec<&str> = match &schema["type"] {
+        Value::String(t) => vec![t.as_str()],
+        Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+        _ => return "any".into(),
+    };
+    let optional = types.contains(&"null");
+    let names = types.iter().filter(|t| **t != "null").map(|t| match *t {
+        "boolean" => "bool".to_string(),
+        "object" => "table".to_string(),
+        "array" => format!("array of {}", type_name(&schema["items"], defs)),
+        other => other.to_string(),
+    });
+    optional_if(join_alternatives(names), optional)
+}
+
+fn join_alternatives(parts: impl Iterator<Item = String>) -> String {
+    parts.collect::<Vec<_>>().join(" | ")
+}
+
+fn optional_if(ty: String, optional: bool) -> String {
+    if optional {
+        format!("{} (optional)", ty)
+    } else {
+        ty
+    }
+}
+
+// Values in TOML-like notation; unset optional keys show as such.
+fn render(value: &Value) -> String {
+    match value {
+        Value::Null => "unset".into(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_carry_type_default_and_source() {
+        let cfg = Config {
+            compress_level: Some(9),
+            ..Config::default()
+        };
+        let file_keys = BTreeMap::from([("compress_level".to_string(), "/home/u/.lg".to_string())]);
+        let docs = key_docs(&cfg, &file_keys).unwrap();
+        let doc = |key: &str| docs.iter().find(|d| d.key == key).unwrap();
+
+        let compress = doc("compress");
+        assert_eq!(compress.ty, r#""none" | "gz" | "zstd""#);
+        assert_eq!(
+            (render(&compress.default), compress.source.as_str()),
+            (r#""none""#.to_string(), "default")
+        );
+        let level = doc("compress_level");
+        assert_eq!(level.ty, "integer (optional)");
+        assert_eq!(
+            (render(&level.value), level.source.as_str()),
+            ("9".to_string(), "/home/u/.lg")
+        );
+        assert!(level.description.starts_with("Compression level"));
+        assert_eq!(doc("never_log").ty, "array of string");
+        assert_eq!(doc("tee").ty, r#"bool | "auto""#);
+        assert_eq!(doc("post_failure_summarizer").ty, "table (optional)");
+    }
+}
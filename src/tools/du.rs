@@ -0,0 +1,195 @@
+//! `lg du`.
+
+use crate::cli::{DuArgs, DuGroup, OutputFormat};
+use crate::config::Config;
+use crate::index::RunIndex;
+use crate::tools::{human_size, log_dir, opt_str, run_files, scan_logs, write_table};
+use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// A run as far as disk usage goes.
+struct RunSize {
+    cmd: String,
+    args: String,
+    cwd: Option<PathBuf>,
+    started: Option<NaiveDateTime>,
+    bytes: u64,
+}
+
+/// One line of `lg du`.
+#[derive(Debug, Serialize)]
+struct Usage {
+    group: String,
+    runs: u64,
+    bytes: u64,
+    oldest: Option<NaiveDateTime>,
+    newest: Option<NaiveDateTime>,
+}
+
+pub fn run_du(cfg: &Config, args: DuArgs) -> Result<()> {
+    let runs = if args.index {
+        indexed_runs()?
+    } else {
+        let dir = log_dir(cfg, args.dir);
+        scan_logs(cfg, &dir)?
+            .into_iter()
+            .map(|r| RunSize {
+                cmd: r.cmd,
+                args: r.args,
+                cwd: r.cwd,
+                started: r.started,
+                bytes: r.bytes,
+            })
+            .collect()
+    };
+    let usage = group_usage(&runs, args.by);
+    let out = io::stdout();
+    let mut out = out.lock();
+    if args.format == OutputFormat::Json {
+        serde_json::to_writer_pretty(&mut out, &usage)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+    let group = match args.by {
+        DuGroup::Cmd => "cmd",
+        DuGroup::Args => "invocation",
+        DuGroup::Cwd => "cwd",
+    };
+    // Machine-readable formats get exact byte counts, the table a short size and a total.
+    let table = args.format == OutputFormat::Table;
+    let header = [
+        group,
+        "runs",
+        if table { "size" } else { "bytes" },
+        "oldest",
+        "newest",
+    ];
+    let date = |t: Option<NaiveDateTime>| opt_str(t.map(|t| t.format("%Y-%m-%d %H:%M:%S")));
+    let size = |bytes: u64| {
+        if table {
+            human_size(bytes)
+        } else {
+            bytes.to_string()
+        }
+    };
+    let mut rows: Vec<Vec<String>> = usage
+        .iter()
+        .map(|u| {
+            vec![
+                u.group.clone(),
+                u.runs.to_string(),
+                size(u.bytes),
+                date(u.oldest),
+                date(u.newest),
+            ]
+        })
+        .collect();
+    if table && usage.len() > 1 {
+        rows.push(vec![
+            "total".into(),
+            usage.iter().map(|u| u.runs).sum::<u64>().to_string(),
+            size(usage.iter().map(|u| u.bytes).sum()),
+            date(usage.iter().filter_map(|u| u.oldest).min()),
+            date(usage.iter().filter_map(|u| u.newest).max()),
+        ]);
+    }
+    write_table(&mut out, &header, rows.into_iter(), args.format)
+}
+
+// Runs of the index whose logs still exist, wherever they were written. A log
+// overwritten by a later run with the same name is counted for that run only.
+fn indexed_runs() -> Result<Vec<RunSize>> {
+    let index = RunIndex::existing()?.context("no run index; set index = true to record runs")?;
+    let mut latest = BTreeMap::new();
+    for run in index.runs()? {
+        latest.insert(run.log_path.clone(), run);
+    }
+    Ok(latest
+        .into_values()
+        .filter_map(|run| {
+            let bytes = run_files(&run.log_path)
+                .iter()
+                .filter_map(|(file, _)| fs::metadata(file).ok())
+                .map(|m| m.len())
+                .reduce(|a, b| a + b)?;
+            Some(RunSize {
+                cmd: run.cmd,
+                args: run.args,
+                cwd: Some(run.cwd),
+                started: run.started,
+                bytes,
+            })
+        })
+        .collect())
+}
+
+// Largest group first.
+fn group_usage(runs: &[RunSize], by: DuGroup) -> Vec<Usage> {
+    let mut groups: BTreeMap<String, Usage> = BTreeMap::new();
+    for run in runs {
+        let key = match by {
+            DuGroup::Cmd => run.cmd.clone(),
+            DuGroup::Args if run.args.is_empty() => run.cmd.clone(),
+            DuGroup::Args => format!("{} {}", run.cmd, run.args),
+            DuGroup::Cwd => opt_str(run.cwd.as_ref().map(|d| d.display())),
+        };
+        let usage = groups.entry(key.clone()).or_insert(Usage {
+            group: key,
+            runs: 0,
+            bytes: 0,
+            oldest: None,
+            newest: None,
+        });
+        usage.runs += 1;
+        usage.bytes += run.bytes;
+        if let Some(started) = run.started {
+            usage.oldest = Some(usage.oldest.map_or(started, |t| t.min(started)));
+            usage.newest = Some(usage.newest.map_or(started, |t| t.max(started)));
+        }
+    }
+    let mut usage: Vec<Usage> = groups.into_values().collect();
+    usage.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.group.cmp(&b.group)));
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(cmd: &str, args: &str, started: &str, bytes: u64) -> RunSize {
+        RunSize {
+            cmd: cmd.into(),
+            args: args.into(),
+            cwd: Some(PathBuf::from("/src")),
+            started: NaiveDateTime::parse_from_str(started, "%Y-%m-%d %H:%M:%S").ok(),
+            bytes,
+        }
+    }
+
+    #[test]
+    fn usage_is_grouped_largest_first() {
+        let runs = [
+            run("make", "test", "2024-03-05 10:00:00", 100),
+            run("cargo", "build", "2024-03-01 09:00:00", 5000),
+            run("make", "", "2024-03-07 10:00:00", 300),
+        ];
+        let usage = group_usage(&runs, DuGroup::Cmd);
+        let summary: Vec<(&str, u64, u64)> = usage
+            .iter()
+            .map(|u| (u.group.as_str(), u.runs, u.bytes))
+            .collect();
+        assert_eq!(summary, [("cargo", 1, 5000), ("make", 2, 400)]);
+        let make = &usage[1];
+        assert_eq!(make.oldest, runs[0].started);
+        assert_eq!(make.newest, runs[2].started);
+
+        let by_args = group_usage(&runs, DuGroup::Args);
+        let groups: Vec<&str> = by_args.iter().map(|u| u.group.as_str()).collect();
+        assert_eq!(groups, ["cargo build", "make", "make test"]);
+    }
+}
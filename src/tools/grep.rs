@@ -0,0 +1,239 @@
+//! `lg grep`.
+
+use crate::cli::GrepArgs;
+use crate::config::Config;
+use crate::context::TTY;
+use crate::sinks::Stream;
+use crate::timespec::started_between;
+use crate::tools::{
+    body_lines, log_dir, open_log, paint, run_files, scan_logs, split_line_marker, use_color,
+    LogRecord, BOLD, BOLD_RED, CYAN, GREEN, MAGENTA,
+};
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Search the body of every selected run; returns whether anything matched.
+pub fn run_grep(cfg: &Config, args: GrepArgs) -> Result<bool> {
+    let dir = log_dir(cfg, args.dir);
+    let re = regex::RegexBuilder::new(&args.pattern)
+        .case_insensitive(args.ignore_case)
+        .build()
+        .context("invalid pattern")?;
+    let mut runs = scan_logs(cfg, &dir)?;
+    runs.retain(|r| {
+        args.cmd.as_ref().map_or(true, |c| &r.cmd == c)
+            && (!args.failed_only || r.exit_code.is_some_and(|c| c != 0))
+            && started_between(r.started, args.since, args.until)
+    });
+
+    let heading = args.heading || (TTY.stdout && !args.no_heading);
+    let mut search = GrepState {
+        before: args.before_context.or(args.context).unwrap_or(0),
+        after: args.after_context.or(args.context).unwrap_or(0),
+        out: io::BufWriter::new(io::stdout().lock()),
+        color: use_color(),
+        last_printed: None,
+        heading: None,
+        found: false,
+    };
+    for run in &runs {
+        search.heading = heading.then(|| run_heading(run));
+        for (path, default_stream) in run_files(&run.path) {
+            search.file(&path, default_stream, &re, args.stream)?;
+        }
+    }
+    search.out.flush()?;
+    Ok(search.found)
+}
+
+// "make -j8 (2026-10-16 11:48:45, exit 2)"
+fn run_heading(run: &LogRecord) -> String {
+    let mut line = run.cmd.clone();
+    if !run.args.is_empty() {
+        line.push(' ');
+        line.push_str(&run.args);
+    }
+    let started = run.started.map_or("start unknown".into(), |t| {
+        t.format("%Y-%m-%d %H:%M:%S").to_string()
+    });
+    let exit = run
+        .exit_code
+        .map_or("no exit code".into(), |c| format!("exit {}", c));
+    format!("{} ({}, {})", line, started, exit)
+}
+
+/// Context bookkeeping for `lg grep`, carried across files.
+struct GrepState<W: Write> {
+    before: usize,
+    after: usize,
+    out: W,
+    color: bool,
+    /// File and line number of the last line printed, to place `--` separators.
+    last_printed: Option<(PathBuf, usize)>,
+    /// The current run's heading, until its first line is printed.
+    heading: Option<String>,
+    found: bool,
+}
+
+impl<W: Write> GrepState<W> {
+    fn file(
+        &mut self,
+        path: &Path,
+        default_stream: Option<Stream>,
+        re: &Regex,
+        stream: Option<Stream>,
+    ) -> Result<()> {
+        let Ok(reader) = open_log(path) else {
+            return Ok(());
+        };
+        let mut pending: VecDeque<(usize, String)> = VecDeque::with_capacity(self.before);
+        let mut after_left = 0;
+        for body_line in body_lines(reader) {
+            let (lineno, line) = body_line?;
+            let line = line.as_str();
+            let (label, message) = split_line_marker(line);
+            let label = label.or(default_stream.map(Stream::label));
+            if stream.is_some_and(|s| label != Some(s.label())) {
+                continue;
+            }
+            if re.is_match(message) {
+                self.found = true;
+                for (n, l) in pending.drain(..) {
+                    self.print(path, n, &l, '-', None)?;
+                }
+                let start = line.len() - message.len();
+                self.print(path, lineno, line, ':', Some((start, re)))?;
+                after_left = self.after;
+            } else if after_left > 0 {
+                after_left -= 1;
+                self.print(path, lineno, line, '-', None)?;
+            } else if self.before > 0 {
+                if pending.len() == self.before {
+                    pending.pop_front();
+                }
+                pending.push_back((lineno, line.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    // grep-style `path:N:line` for matches and `path-N-line` for context;
+    // `highlight` marks the matches after the line's markers.
+    fn print(
+        &mut self,
+        path: &Path,
+        lineno: usize,
+        line: &str,
+        sep: char,
+        highlight: Option<(usize, &Regex)>,
+    ) -> Result<()> {
+        if let Some(heading) = self.heading.take() {
+            if self.last_printed.take().is_some() {
+                writeln!(self.out)?;
+            }
+            writeln!(self.out, "{}", paint(&heading, BOLD, self.color))?;
+        }
+        let with_context = self.before > 0 || self.after > 0;
+        if let Some((last_path, last_no)) = &self.last_printed {
+            if with_context && (last_path != path || *last_no + 1 != lineno) {
+                writeln!(self.out, "{}", paint("--", CYAN, self.color))?;
+            }
+        }
+        let text = match highlight {
+            Some((start, re)) if self.color => {
+                let (markers, message) = line.split_at(start);
+                let message = re.replace_all(message, |m: &regex::Captures| {
+                    paint(&m[0], BOLD_RED, true).into_owned()
+                });
+                Cow::Owned(format!("{}{}", markers, message))
+            }
+            _ => Cow::Borrowed(line),
+        };
+        let sep = paint(&sep.to_string(), CYAN, self.color).into_owned();
+        writeln!(
+            self.out,
+            "{}{}{}{}{}",
+            paint(&path.display().to_string(), MAGENTA, self.color),
+            sep,
+            paint(&lineno.to_string(), GREEN, self.color),
+            sep,
+            text
+        )?;
+        self.last_printed = Some((path.to_path_buf(), lineno));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::parse_log;
+    use std::fs;
+
+    #[test]
+    fn matches_are_headed_by_their_run() {
+        let dir = std::env::temp_dir().join(format!("lg-grep-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("make_1.log");
+        fs::write(
+            &path,
+            "# lg log\ncmd: make\nargs: -j8\ndate: 2026-10-16 11-48-45\n\
+             ----- BEGIN OUTPUT -----\n[STDOUT] ok\n[STDERR] error: boom\n\n[exit_code] 2\n",
+        )
+        .unwrap();
+        let run = parse_log(&Config::default(), &path).unwrap().unwrap();
+        let mut search = GrepState {
+            before: 0,
+            after: 0,
+            out: Vec::new(),
+            color: false,
+            last_printed: None,
+            heading: Some(run_heading(&run)),
+            found: false,
+        };
+        let re = Regex::new("error").unwrap();
+        search.file(&path, None, &re, None).unwrap();
+        let out = String::from_utf8(search.out).unwrap();
+        assert_eq!(
+            out,
+            format!(
+                "make -j8 (2026-10-16 11:48:45, exit 2)\n{}:7:[STDERR] error: boom\n",
+                path.display()
+            )
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn jsonl_logs_are_searched_by_their_lines() {
+        let dir = std::env::temp_dir().join(format!("lg-grep-jsonl-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("make_1.log");
+        fs::write(
+            &path,
+            "{\"type\":\"header\",\"cmd\":\"make\",\"args\":\"\"}\n\
+             {\"stream\":\"stdout\",\"line\":\"ok\"}\n\
+             {\"ts\":\"2026-10-16T11:48:45.000+02:00\",\"stream\":\"stderr\",\"line\":\"error: boom\"}\n\
+             {\"type\":\"footer\",\"exit_code\":2,\"summary\":\"error: boom\"}\n",
+        )
+        .unwrap();
+        let mut search = GrepState {
+            before: 1,
+            after: 0,
+            out: Vec::new(),
+            color: false,
+            last_printed: None,
+            heading: None,
+            found: false,
+        };
+        let re = Regex::new("error").unwrap();
+        search.file(&path, None, &re, Some(Stream::Stderr)).unwrap();
+        let out = String::from_utf8(search.out).unwrap();
+        assert_eq!(out, format!("{}:3:[STDERR] error: boom\n", path.display()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+//! `lg group`: runs of one script linked under a group id, which `lg group
+//! start` hands to them through `LG_GROUP`.
+
+use crate::cli::{GroupCommand, OutputFormat};
+use crate::config::{Config, GroupBudgetAction};
+use crate::state::{Group, GroupRun, StateDir};
+use crate::timespec::parse_duration;
+use crate::tools::report::fmt_secs;
+use crate::tools::{opt_str, write_table};
+use anyhow::{Context, Result};
+use chrono::{Local, SubsecRound};
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, Write};
+
+/// A group and its runs, as `lg group show --format json` prints them.
+#[derive(Serialize)]
+struct Summary<'a> {
+    id: &'a str,
+    /// None for a group only ever named in `LG_GROUP`.
+    group: Option<&'a Group>,
+    budget: Option<Budget>,
+    runs: Vec<Step<'a>>,
+}
+
+/// A run of the group and its share of the budget, or of the group's total
+/// time without one.
+#[derive(Serialize)]
+struct Step<'a> {
+    #[serde(flatten)]
+    run: &'a GroupRun,
+    share: Option<f64>,
+}
+
+/// How much of its time budget a group's runs have used.
+#[derive(Serialize, Clone, Copy)]
+pub struct Budget {
+    pub used_secs: f64,
+    pub budget_secs: u64,
+}
+
+impl Budget {
+    // The group's own budget, else `group_budget`; None without either.
+    fn of(cfg: &Config, group: Option<&Group>, runs: &[GroupRun]) -> Result<Option<Self>> {
+        let budget_secs = match group.and_then(|g| g.budget_secs) {
+            Some(secs) => secs,
+            None => match cfg.group_budget()? {
+                Some(budget) => budget.as_secs().max(1),
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(Self {
+            used_secs: total_secs(runs),
+            budget_secs,
+        }))
+    }
+
+    pub fn over(&self) -> bool {
+        self.used_secs > self.budget_secs as f64
+    }
+}
+
+// "31m02s used, over its 30m00s budget"
+impl fmt::Display for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let used = fmt_secs(self.used_secs.round() as i64);
+        let budget = fmt_secs(self.budget_secs as i64);
+        match self.over() {
+            true => write!(f, "{} used, over its {} budget", used, budget),
+            false => write!(f, "{} used of its {} budget", used, budget),
+        }
+    }
+}
+
+/// The budget of group `id` and how much of it its runs have used so far;
+/// None when it has no budget.
+pub fn group_budget(cfg: &Config, id: &str) -> Result<Option<Budget>> {
+    let state = StateDir::locate().context("cannot determine lg state directory (HOME unset)")?;
+    match state.load_group(id) {
+        Ok((group, runs)) => Budget::of(cfg, group.as_ref(), &runs),
+        // The first run of a group only named in `LG_GROUP`.
+        Err(_) => Budget::of(cfg, None, &[]),
+    }
+}
+
+fn total_secs(runs: &[GroupRun]) -> f64 {
+    runs.iter().filter_map(|r| r.duration_secs).sum()
+}
+
+// Returns the exit status: `lg group end` fails when a run of the group did,
+// or the group went over its budget with `group_budget_action = "fail"`.
+pub fn run_group(cfg: &Config, command: GroupCommand) -> Result<i32> {
+    let state = StateDir::locate().context("cannot determine lg state directory (HOME unset)")?;
+    let now = Local::now().naive_local().trunc_subsecs(0);
+    let (args, end) = match command {
+        GroupCommand::Start { name, budget } => {
+            if name.trim().is_empty() {
+                anyhow::bail!("a group needs a name");
+            }
+            let budget = match budget {
+                Some(spec) => Some(
+                    parse_duration(&spec)
+                        .map_err(anyhow::Error::msg)
+                        .context("--budget")?,
+                )
+                .filter(|d| !d.is_zero()),
+                None => cfg.group_budget()?,
+            };
+            let budget_secs = budget.map(|d| d.as_secs().max(1));
+            let group = state.start_group(&name, budget_secs, now)?;
+            println!("export LG_GROUP={}", group.id);
+            return Ok(0);
+        }
+        GroupCommand::Show(args) => (args, false),
+        GroupCommand::End(args) => (args, true),
+    };
+    let id = args
+        .id
+        .or_else(|| cfg.group.clone())
+        .context("no group id given and LG_GROUP is unset")?;
+    let (mut group, runs) = state.load_group(&id)?;
+    if end {
+        if let Some(group) = group.as_mut() {
+            state.end_group(group, now)?;
+        }
+    }
+    let budget = Budget::of(cfg, group.as_ref(), &runs)?;
+    let summary = Summary::new(&id, group.as_ref(), budget, &runs);
+    let mut out = io::stdout().lock();
+    summary.write(&mut out, args.format)?;
+    out.flush()?;
+    let failed = runs.iter().any(|r| r.exit_code != 0);
+    let over =
+        budget.is_some_and(|b| b.over()) && cfg.group_budget_action == GroupBudgetAction::Fail;
+    Ok(if end && (failed || over) { 1 } else { 0 })
+}
+
+impl<'a> Summary<'a> {
+    fn new(
+        id: &'a str,
+        group: Option<&'a Group>,
+        budget: Option<Budget>,
+        runs: &'a [GroupRun],
+    ) -> Self {
+        let whole = budget.map_or_else(|| total_secs(runs), |b| b.budget_secs as f64);
+        let runs = runs
+            .iter()
+            .map(|run| Step {
+                run,
+                share: run
+                    .duration_secs
+                    .filter(|_| whole > 0.0)
+                    .map(|secs| secs / whole),
+            })
+            .collect();
+        Self {
+            id,
+            group,
+            budget,
+            runs,
+        }
+    }
+
+    // "3 runs: 2 succeeded, 1 failed, 12m04s", then the budget if there is one.
+    fn counts(&self) -> String {
+        let failed = self.runs.iter().filter(|s| s.run.exit_code != 0).count();
+        let time = match self.budget {
+            Some(budget) => budget.to_string(),
+            None => {
+                let secs: f64 = self.runs.iter().filter_map(|s| s.run.duration_secs).sum();
+                fmt_secs(secs.round() as i64)
+            }
+        };
+        format!(
+            "{} runs: {} succeeded, {} failed, {}",
+            self.runs.len(),
+            self.runs.len() - failed,
+            failed,
+            time
+        )
+    }
+
+    fn write(&self, w: &mut dyn Write, format: OutputFormat) -> Result<()> {
+        if format == OutputFormat::Json {
+            serde_json::to_writer_pretty(&mut *w, self)?;
+            writeln!(w)?;
+            return Ok(());
+        }
+        if format == OutputFormat::Table {
+            let fmt = "%Y-%m-%d %H:%M:%S";
+            let span = match self.group {
+                Some(Group {
+                    started,
+                    ended: Some(ended),
+                    ..
+                }) => format!(" ({} to {})", started.format(fmt), ended.format(fmt)),
+                Some(group) => format!(" (started {})", group.started.format(fmt)),
+                None => String::new(),
+            };
+            writeln!(w, "group {}{}", self.id, span)?;
+            writeln!(w, "{}\n", self.counts())?;
+        }
+        let header = [
+            "started",
+            "cmd",
+            "args",
+            "exit_code",
+            "duration_secs",
+            "share",
+            "log",
+        ];
+        let rows = self.runs.iter().map(|step| {
+            let r = step.run;
+            vec![
+                r.started.format("%Y-%m-%d %H:%M:%S").to_string(),
+                r.cmd.clone(),
+                r.args.clone(),
+                r.exit_code.to_string(),
+                opt_str(r.duration_secs.map(|d| format!("{:.1}", d))),
+                opt_str(step.share.map(|s| format!("{:.0}%", s * 100.0))),
+                r.log
+                    .as_ref()
+                    .map_or("(discarded)".into(), |p| p.display().to_string()),
+            ]
+        });
+        write_table(w, &header, rows, format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    #[test]
+    fn runs_get_their_share_of_the_budget() {
+        let run = |cmd: &str, secs| GroupRun {
+            cmd: cmd.into(),
+            args: String::new(),
+            started: NaiveDateTime::default(),
+            duration_secs: Some(secs),
+            exit_code: 0,
+            log: None,
+        };
+        let runs = [run("make", 1200.0), run("test", 660.0)];
+        let shares = |summary: &Summary| -> Vec<Option<f64>> {
+            summary.runs.iter().map(|s| s.share).collect()
+        };
+        let cfg = Config {
+            group_budget: Some("30m".into()),
+            ..Config::default()
+        };
+        let budget = Budget::of(&cfg, None, &runs).unwrap().unwrap();
+        assert!(budget.over());
+        assert_eq!(budget.to_string(), "31m00s used, over its 30m00s budget");
+        let summary = Summary::new("nightly", None, Some(budget), &runs);
+        assert_eq!(
+            shares(&summary),
+            [Some(1200.0 / 1800.0), Some(660.0 / 1800.0)]
+        );
+        // The group's own budget comes first; without any, shares are of the total.
+        let group = Group {
+            id: "nightly".into(),
+            name: "nightly".into(),
+            started: NaiveDateTime::default(),
+            ended: None,
+            budget_secs: Some(3600),
+        };
+        let budget = Budget::of(&cfg, Some(&group), &runs).unwrap().unwrap();
+        assert!(!budget.over());
+        assert!(Budget::of(&Config::default(), None, &runs)
+            .unwrap()
+            .is_none());
+        let summary = Summary::new("nightly", None, None, &runs);
+        assert_eq!(
+            shares(&summary),
+            [Some(1200.0 / 1860.0), Some(660.0 / 1860.0)]
+        );
+    }
+}
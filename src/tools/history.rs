@@ -0,0 +1,91 @@
+//! `lg history`.
+
+use crate::cli::{HistoryArgs, OutputFormat};
+use crate::config::Config;
+use crate::index::RunIndex;
+use crate::timespec::started_between;
+use crate::tools::{log_dir, opt_str, scan_logs, with_pager, write_table};
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// One run in `lg history`.
+#[derive(Debug, Serialize)]
+struct HistoryEntry {
+    started: Option<NaiveDateTime>,
+    cmd: String,
+    args: String,
+    /// None while the run is going, or when lg was killed before it ended.
+    exit_code: Option<i32>,
+    duration_secs: Option<f64>,
+    path: PathBuf,
+}
+
+pub fn run_history(cfg: &Config, args: HistoryArgs) -> Result<()> {
+    // The index covers every directory; without one, fall back to the output directory.
+    let index = match args.dir {
+        Some(_) => None,
+        None => RunIndex::existing()?,
+    };
+    let mut runs: Vec<HistoryEntry> = match index {
+        Some(index) => index
+            .runs()?
+            .into_iter()
+            .map(|r| HistoryEntry {
+                started: r.started,
+                cmd: r.cmd,
+                args: r.args,
+                exit_code: r.exit_code,
+                duration_secs: r.duration_ms.map(|ms| ms as f64 / 1000.0),
+                path: r.log_path,
+            })
+            .collect(),
+        None => scan_logs(cfg, &log_dir(cfg, args.dir))?
+            .into_iter()
+            .map(|r| HistoryEntry {
+                started: r.started,
+                cmd: r.cmd,
+                args: r.args,
+                exit_code: r.exit_code,
+                duration_secs: r.duration_secs.map(|s| s as f64),
+                path: r.path,
+            })
+            .collect(),
+    };
+    runs.retain(|r| {
+        args.cmd.as_ref().map_or(true, |c| &r.cmd == c)
+            && (!args.failed || r.exit_code.is_some_and(|c| c != 0))
+            && started_between(r.started, args.since, args.until)
+    });
+    runs.drain(..runs.len().saturating_sub(args.limit));
+    with_pager(args.no_pager, |out| write_history(out, &runs, args.format))
+}
+
+fn write_history(out: &mut dyn Write, runs: &[HistoryEntry], format: OutputFormat) -> Result<()> {
+    if format == OutputFormat::Json {
+        serde_json::to_writer_pretty(&mut *out, runs)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+    let header = [
+        "started",
+        "cmd",
+        "args",
+        "exit_code",
+        "duration_secs",
+        "path",
+    ];
+    let rows = runs.iter().map(|r| {
+        vec![
+            opt_str(r.started.map(|t| t.format("%Y-%m-%d %H:%M:%S"))),
+            r.cmd.clone(),
+            r.args.clone(),
+            opt_str(r.exit_code),
+            opt_str(r.duration_secs.map(|d| format!("{:.1}", d))),
+            r.path.display().to_string(),
+        ]
+    });
+    write_table(out, &header, rows, format)
+}
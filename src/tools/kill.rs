@@ -0,0 +1,47 @@
+//! `lg kill`: stop a run from outside its terminal.
+
+use crate::cli::{KillArgs, KillSignal};
+use crate::context::HOSTNAME;
+use crate::state::RunningEntry;
+use crate::tools::tail::{running_run, running_run_id};
+use anyhow::Result;
+
+fn picked_run(args: &KillArgs) -> Result<RunningEntry> {
+    match &args.run_id {
+        Some(run_id) => running_run_id(run_id),
+        None => running_run(args.pid),
+    }
+}
+
+#[cfg(unix)]
+pub fn run_kill(args: KillArgs) -> Result<()> {
+    let entry = picked_run(&args)?;
+    let signal = match args.signal {
+        KillSignal::Term => libc::SIGTERM,
+        KillSignal::Int => libc::SIGINT,
+        KillSignal::Hup => libc::SIGHUP,
+    };
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .unwrap_or_else(|_| "?".into());
+    let by = format!(
+        "lg kill from {}@{}, pid {}",
+        user,
+        *HOSTNAME,
+        std::process::id()
+    );
+    entry.request_kill(signal, &by)?;
+    eprintln!(
+        "lg: sent {} to {}[{}]",
+        crate::runner::signal_name(signal),
+        entry.cmd,
+        entry.pid
+    );
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn run_kill(args: KillArgs) -> Result<()> {
+    let _ = (picked_run(&args)?, args.signal, &*HOSTNAME);
+    anyhow::bail!("lg kill needs Unix signals")
+}
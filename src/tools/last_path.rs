@@ -0,0 +1,54 @@
+//! `lg last-path` and `lg tmux-hook`.
+
+use crate::cli::LastPathArgs;
+use crate::state::latest_file;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+
+pub fn run_last_path(args: LastPathArgs) -> Result<()> {
+    let dir = if args.pane {
+        let out = std::process::Command::new("tmux")
+            .args(["display-message", "-p", "#{pane_current_path}"])
+            .output()
+            .context("running tmux")?;
+        anyhow::ensure!(
+            out.status.success(),
+            "tmux display-message failed (not inside tmux?)"
+        );
+        PathBuf::from(String::from_utf8_lossy(&out.stdout).trim())
+    } else {
+        match args.cwd {
+            Some(dir) => fs::canonicalize(&dir).with_context(|| format!("{:?}", dir))?,
+            None => std::env::current_dir()?,
+        }
+    };
+    let recorded = latest_file(&dir)
+        .and_then(|f| fs::read_to_string(f).ok())
+        .with_context(|| format!("no lg run recorded for {}", dir.display()))?;
+    let path = PathBuf::from(recorded.trim_end());
+    // `lg clean` may have compressed it since.
+    let gz = PathBuf::from(format!("{}.gz", path.display()));
+    let path = if !path.exists() && gz.exists() {
+        gz
+    } else {
+        path
+    };
+    anyhow::ensure!(
+        path.exists(),
+        "newest log {} no longer exists",
+        path.display()
+    );
+    println!("{}", path.display());
+    Ok(())
+}
+
+pub fn tmux_hook(key: &str) -> String {
+    format!(
+        "# lg: prefix + {key} opens the newest lg log of the current pane's directory\n\
+         # in a split. Add to ~/.tmux.conf, e.g. `lg tmux-hook >> ~/.tmux.conf`.\n\
+         bind-key {key} run-shell -b 'f=$(lg last-path --pane) \
+         && tmux split-window -h \"LESS=R lg show \\\"$f\\\"\" \
+         || tmux display-message \"lg: no log for this directory\"'\n"
+    )
+}
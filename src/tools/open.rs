@@ -0,0 +1,176 @@
+//! `lg open`.
+
+use crate::cli::OpenArgs;
+use crate::config::Config;
+use crate::matchers::Diagnostic;
+use crate::state::latest_file;
+use crate::tools::{
+    body_lines, log_dir, open_log, parse_log, run_files, scan_logs, split_line_marker, LogRecord,
+};
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// `path:line[:col]` as printed by compilers, linters and test runners
+// (`src/main.rs:10:5`, `--> src/lib.rs:3:1`, `app.py:12: error`).
+static LOCATION: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?:^|[\s(\['"])(?P<file>[\w./~+-]*\w\.\w+):(?P<line>\d+)(?::(?P<col>\d+))?"#)
+        .unwrap()
+});
+
+// Locations in the output of a run that point at files that exist, relative
+// to the run's directory, in order of appearance and without duplicates.
+fn extract_locations(run: &LogRecord) -> Result<Vec<Diagnostic>> {
+    let base = run.cwd.clone().unwrap_or_else(|| PathBuf::from("."));
+    let mut found: Vec<Diagnostic> = Vec::new();
+    for (path, _) in run_files(&run.path) {
+        for body_line in body_lines(open_log(&path)?) {
+            let (_, line) = body_line?;
+            let (_, message) = split_line_marker(&line);
+            for caps in LOCATION.captures_iter(message) {
+                let file = base.join(&caps["file"]);
+                if !file.is_file() {
+                    continue;
+                }
+                let diag = Diagnostic {
+                    severity: "error".to_string(),
+                    file,
+                    line: caps["line"].parse().unwrap_or(1),
+                    col: caps.name("col").and_then(|c| c.as_str().parse().ok()),
+                    message: message.trim().to_string(),
+                };
+                if !found.iter().any(|d| d.same_place(&diag)) {
+                    found.push(diag);
+                }
+            }
+        }
+    }
+    Ok(found)
+}
+
+pub fn run_open(cfg: &Config, args: OpenArgs) -> Result<()> {
+    let recorded = std::env::current_dir()
+        .ok()
+        .and_then(|cwd| latest_file(&cwd))
+        .and_then(|f| fs::read_to_string(f).ok())
+        .map(|p| PathBuf::from(p.trim_end()))
+        .filter(|p| p.exists());
+    let path = match args.path.or(recorded) {
+        Some(path) => path,
+        None => {
+            let dir = log_dir(cfg, args.dir);
+            scan_logs(cfg, &dir)?
+                .pop()
+                .with_context(|| format!("no runs in {}", dir.display()))?
+                .path
+        }
+    };
+    if !args.errors {
+        return launch_editor(&[path.as_os_str().to_owned()]);
+    }
+    let run = parse_log(cfg, &path)?.with_context(|| format!("{:?} is not an lg log", path))?;
+    // Prefer what the problem matchers recorded; scan the output for
+    // anything location-shaped when they found nothing.
+    let base = run.cwd.clone().unwrap_or_else(|| PathBuf::from("."));
+    let mut found: Vec<Diagnostic> = run
+        .diagnostics
+        .iter()
+        .filter(|d| d.severity != "note" && d.severity != "help")
+        .map(|d| Diagnostic {
+            file: base.join(&d.file),
+            message: format!("{}: {}", d.severity, d.message),
+            ..d.clone()
+        })
+        .filter(|d| d.file.is_file())
+        .collect();
+    if found.is_empty() {
+        found = extract_locations(&run)?;
+    }
+    anyhow::ensure!(
+        !found.is_empty(),
+        "no file:line locations in {}",
+        path.display()
+    );
+
+    let quickfix = |w: &mut dyn Write| -> Result<()> {
+        for d in &found {
+            writeln!(
+                w,
+                "{}:{}:{}: {}",
+                d.file.display(),
+                d.line,
+                d.col.unwrap_or(1),
+                d.message
+            )?;
+        }
+        Ok(())
+    };
+    match args.quickfix.as_deref() {
+        Some(p) if p == Path::new("-") => quickfix(&mut io::stdout().lock()),
+        Some(p) => {
+            let mut file = File::create(p).with_context(|| format!("create {:?}", p))?;
+            quickfix(&mut file)
+        }
+        None if editor_is(&["vim", "nvim", "vi", "gvim"]) => {
+            // Hand vim the whole list; :cnext walks through it.
+            let list = std::env::temp_dir().join(format!("lg-quickfix-{}.txt", std::process::id()));
+            quickfix(&mut File::create(&list)?)?;
+            let res = launch_editor(&["-q".into(), list.clone().into_os_string()]);
+            let _ = fs::remove_file(&list);
+            res
+        }
+        None => {
+            let first = &found[0];
+            if editor_is(&["code", "codium", "code-insiders"]) {
+                let target = format!(
+                    "{}:{}:{}",
+                    first.file.display(),
+                    first.line,
+                    first.col.unwrap_or(1)
+                );
+                launch_editor(&["-g".into(), target.into()])
+            } else {
+                // `+N file` is understood by vi, nano, emacs, micro, kak and friends.
+                let line = format!("+{}", first.line);
+                launch_editor(&[line.into(), first.file.clone().into_os_string()])
+            }
+        }
+    }
+}
+
+// $VISUAL, then $EDITOR, then vi; may carry its own flags ("code --wait").
+pub fn editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .find_map(|k| std::env::var(k).ok().filter(|v| !v.trim().is_empty()))
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+fn editor_is(names: &[&str]) -> bool {
+    let editor = editor();
+    let program = editor.split_whitespace().next().unwrap_or_default();
+    let base = Path::new(program).file_name().unwrap_or_default();
+    names.iter().any(|n| base == *n)
+}
+
+fn launch_editor(args: &[OsString]) -> Result<()> {
+    let editor = editor();
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg("editor")
+        .args(args)
+        .status()
+        .with_context(|| format!("starting editor {:?}", editor))?;
+    anyhow::ensure!(
+        status.success(),
+        "editor {:?} exited with {}",
+        editor,
+        status
+    );
+    Ok(())
+}
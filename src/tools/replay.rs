@@ -0,0 +1,249 @@
+//! `lg replay`: a past run's output printed again at the pace it was written,
+//! like scriptreplay(1). The pauses come from the per-line timestamps: the
+//! `[10:59:20.300]` of text logs (the `[time]` lines of `raw` ones) or the
+//! `ts` of JSON Lines records.
+
+use crate::cli::ReplayArgs;
+use crate::config::Config;
+use crate::jsonl;
+use crate::sinks::Stream;
+use crate::tools::{
+    ignore_broken_pipe, is_footer_line, latest_run, open_log, run_files, split_line_marker,
+};
+use anyhow::Result;
+use chrono::{DateTime, NaiveTime, Timelike};
+use serde::Deserialize;
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
+
+const DAY_SECS: f64 = 86_400.0;
+
+/// A piece of output and when it was written, in seconds on the log's own
+/// clock; None before the first timestamp.
+#[derive(Debug, PartialEq)]
+pub struct Event {
+    at: Option<f64>,
+    stream: Stream,
+    pub text: Vec<u8>,
+}
+
+/// An output record of a JSON Lines log.
+#[derive(Deserialize)]
+struct JsonLine {
+    #[serde(rename = "type")]
+    kind: Option<jsonl::Kind>,
+    ts: Option<String>,
+    #[serde(default)]
+    stream: String,
+    #[serde(default)]
+    line: String,
+}
+
+pub fn run_replay(cfg: &Config, args: ReplayArgs) -> Result<()> {
+    let path = match args.path {
+        Some(path) => path,
+        None => latest_run(cfg, args.dir, args.cmd.as_deref())?,
+    };
+    // Split pairs play as one, in the order their lines were written.
+    let files = run_files(&path);
+    let mut events = Vec::new();
+    for (file, default_stream) in &files {
+        let reader = open_log(file)?;
+        events.extend(read_events(
+            reader,
+            default_stream.unwrap_or(Stream::Stdout),
+        )?);
+    }
+    if files.len() > 1 {
+        events.sort_by(|a, b| a.at.unwrap_or(0.0).total_cmp(&b.at.unwrap_or(0.0)));
+    }
+    if events.iter().all(|e| e.at.is_none()) {
+        eprintln!(
+            "lg: replay: {} has no line timestamps (timestamp_each_line = false or \
+             plain_lines); printing it at once",
+            path.display()
+        );
+    }
+    ignore_broken_pipe(play(&events, args.speed, args.max_delay))
+}
+
+fn play(events: &[Event], speed: f64, max_delay: Option<f64>) -> Result<()> {
+    let (mut out, mut err) = (io::stdout().lock(), io::stderr().lock());
+    let mut last: Option<f64> = None;
+    for event in events {
+        if let (Some(at), Some(last)) = (event.at, last) {
+            let pause = ((at - last) / speed).max(0.0);
+            let pause = max_delay.map_or(pause, |max| pause.min(max.max(0.0)));
+            std::thread::sleep(Duration::from_secs_f64(pause));
+        }
+        last = event.at.or(last);
+        let w: &mut dyn Write = match event.stream {
+            Stream::Stdout => &mut out,
+            Stream::Stderr => &mut err,
+        };
+        w.write_all(&event.text)?;
+        w.flush()?;
+    }
+    Ok(())
+}
+
+// The output of one log file; lines without a timestamp of their own get
+// the one before them.
+pub fn read_events(reader: impl BufRead, default_stream: Stream) -> Result<Vec<Event>> {
+    let mut events = Vec::new();
+    let mut lines = reader.split(b'\n').peekable();
+    let mut first = true;
+    let mut in_body = false;
+    let mut clock = DayClock::default();
+    let mut at = None;
+    while let Some(raw) = lines.next() {
+        let mut raw = raw?;
+        if first && jsonl::is_header(&String::from_utf8_lossy(&raw)) {
+            return read_jsonl(lines.map(|l| l.map_err(Into::into)));
+        }
+        first = false;
+        if !in_body {
+            in_body = raw == b"----- BEGIN OUTPUT -----";
+            continue;
+        }
+        // The footer and the blank line that separates it from the output.
+        let next_is_footer = matches!(lines.peek(), Some(Ok(next)) if is_footer_line(next));
+        if is_footer_line(&raw) || (raw.is_empty() && next_is_footer) {
+            break;
+        }
+        let line = String::from_utf8_lossy(&raw).into_owned();
+        // A `raw` log's `[time]` line stamps the output after it.
+        if let Some(t) = line
+            .strip_prefix('[')
+            .and_then(|l| l.strip_suffix(']'))
+            .and_then(parse_stamp)
+        {
+            at = Some(clock.secs(t));
+            continue;
+        }
+        let (label, message) = split_line_marker(&line);
+        let stream = match label {
+            Some("STDOUT") => Stream::Stdout,
+            Some("STDERR") => Stream::Stderr,
+            // Input and lg's own notes were never output.
+            Some(_) => continue,
+            None => default_stream,
+        };
+        if label.is_some() {
+            let stamp = line[1..].split_once(']').and_then(|(t, _)| parse_stamp(t));
+            if let Some(t) = stamp {
+                at = Some(clock.secs(t));
+            }
+            raw = message.as_bytes().to_vec();
+        }
+        raw.push(b'\n');
+        events.push(Event {
+            at,
+            stream,
+            text: raw,
+        });
+    }
+    Ok(events)
+}
+
+fn read_jsonl(lines: impl Iterator<Item = Result<Vec<u8>>>) -> Result<Vec<Event>> {
+    let mut events = Vec::new();
+    let mut at = None;
+    for line in lines {
+        let line = line?;
+        if jsonl::is_footer(&line) {
+            break;
+        }
+        let Ok(record) = serde_json::from_slice::<JsonLine>(&line) else {
+            continue;
+        };
+        let stream = match record.stream.as_str() {
+            "stdout" => Stream::Stdout,
+            "stderr" => Stream::Stderr,
+            _ => continue,
+        };
+        if record.kind.is_some() {
+            continue;
+        }
+        if let Some(ts) = record
+            .ts
+            .and_then(|ts| DateTime::parse_from_rfc3339(&ts).ok())
+        {
+            at = Some(ts.timestamp_micros() as f64 / 1e6);
+        }
+        let mut text = record.line.into_bytes();
+        text.push(b'\n');
+        events.push(Event { at, stream, text });
+    }
+    Ok(events)
+}
+
+// "10:59:20.300", the time of day text logs stamp lines with.
+fn parse_stamp(s: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(s, "%H:%M:%S%.f").ok()
+}
+
+/// Text log stamps as seconds since the first day's midnight: a stamp well
+/// before the last one means the run went past midnight.
+#[derive(Default)]
+struct DayClock {
+    days: f64,
+    last: Option<f64>,
+}
+
+impl DayClock {
+    fn secs(&mut self, t: NaiveTime) -> f64 {
+        let of_day = t.num_seconds_from_midnight() as f64 + t.nanosecond() as f64 / 1e9;
+        if self.last.is_some_and(|last| of_day + DAY_SECS / 2.0 < last) {
+            self.days += 1.0;
+        }
+        self.last = Some(of_day);
+        self.days * DAY_SECS + of_day
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(at: f64, stream: Stream, text: &str) -> Event {
+        Event {
+            at: Some(at),
+            stream,
+            text: text.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn output_lines_keep_their_times() {
+        let log = "# lg log\ncmd: make\n----- BEGIN OUTPUT -----\n\
+                   [23:59:59.500][STDOUT] building\n\
+                   [23:59:59.750][STDIN] y\n\
+                   [00:00:01.000][STDERR] warning: late\n\
+                   \n[exit_code] 0\n[duration] 1.5s\n";
+        let events = read_events(log.as_bytes(), Stream::Stdout).unwrap();
+        assert_eq!(
+            events,
+            [
+                event(86_399.5, Stream::Stdout, "building\n"),
+                event(86_401.0, Stream::Stderr, "warning: late\n"),
+            ]
+        );
+        let raw = "# lg log\n----- BEGIN OUTPUT -----\n[10:00:00.000]\n 50%\r100%\n\
+                   [10:00:02.000]\ndone\n";
+        let events = read_events(raw.as_bytes(), Stream::Stderr).unwrap();
+        assert_eq!(
+            events,
+            [
+                event(36_000.0, Stream::Stderr, " 50%\r100%\n"),
+                event(36_002.0, Stream::Stderr, "done\n"),
+            ]
+        );
+        let jsonl = "{\"type\":\"header\",\"cmd\":\"make\"}\n\
+                     {\"ts\":\"2026-10-16T02:00:00.250+00:00\",\"stream\":\"stdout\",\"line\":\"a\"}\n\
+                     {\"ts\":\"2026-10-16T02:00:01.000+00:00\",\"stream\":\"stdin\",\"line\":\"b\"}\n\
+                     {\"type\":\"footer\",\"exit_code\":0}\n";
+        let events = read_events(jsonl.as_bytes(), Stream::Stdout).unwrap();
+        assert_eq!(events, [event(1_792_116_000.25, Stream::Stdout, "a\n")]);
+    }
+}
@@ -0,0 +1,758 @@
+//! `lg report`: a digest of the runs in a time window, as text, Markdown or HTML,
+//! with a timeline of each command's runs per day.
+
+use crate::cli::{OutputFormat, ReportArgs, ReportFormat};
+use crate::config::Config;
+use crate::state::StateDir;
+use crate::timespec::started_between;
+use crate::tools::{
+    body_lines, fold_marker, human_size, log_dir, open_log, opt_str, parse_log, run_files,
+    scan_logs, split_line_marker, write_table, FoldMarker, LogRecord,
+};
+use anyhow::{Context, Result};
+use chrono::{NaiveDate, NaiveDateTime};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+
+// Lines that usually start the story of a failure.
+static ERROR_LINE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(?:error|fatal|panic(?:ked)?|exception|traceback|failed|failure)\b").unwrap()
+});
+
+static BY_CMD_HEADER: [&str; 5] = ["cmd", "runs", "failures", "duration", "storage"];
+
+static HTML_STYLE: &str = "body{font-family:sans-serif;margin:2em}\
+     pre{background:#f4f4f4;padding:.5em;overflow-x:auto}\
+     .failed{color:#b00}td,th{padding:.2em .8em;text-align:left}\
+     .timeline td{padding:0;min-width:1.8em;text-align:center;border:1px solid #fff}\
+     .timeline a{color:inherit;text-decoration:none;display:block}\
+     .ok{background:#c6e9c0}.fail{background:#f2aaaa}.none{background:#eee}\
+     summary{cursor:pointer;color:#555}";
+
+/// The runs of one command in the window.
+#[derive(Default)]
+struct CommandTotals {
+    runs: u64,
+    failures: u64,
+    duration_secs: i64,
+    bytes: u64,
+}
+
+/// A failed run, with its output from the first error on.
+struct Failure<'a> {
+    run: &'a LogRecord,
+    excerpt: Vec<String>,
+    /// The `[[fold]]` sections among the excerpt's lines.
+    folds: Vec<ExcerptFold>,
+}
+
+struct ExcerptFold {
+    name: String,
+    lines: Range<usize>,
+}
+
+/// Runs per command and day, for the heatmap.
+struct Timeline<'a> {
+    first_day: NaiveDate,
+    days: usize,
+    rows: BTreeMap<&'a str, Vec<DayCell<'a>>>,
+}
+
+/// The runs of one command on one day.
+#[derive(Default, Clone, Copy)]
+struct DayCell<'a> {
+    runs: u32,
+    failures: u32,
+    /// Index in `Report::failures` of the day's first failure.
+    first_failure: Option<usize>,
+    last_run: Option<&'a LogRecord>,
+}
+
+struct Report<'a> {
+    dir: &'a Path,
+    /// Set for the report of an `lg group`, which has no window.
+    group: Option<&'a str>,
+    since: NaiveDateTime,
+    until: Option<NaiveDateTime>,
+    runs: usize,
+    succeeded: usize,
+    /// Runs without an exit code: still running, or lg itself was killed.
+    unfinished: usize,
+    duration_secs: i64,
+    bytes: u64,
+    by_cmd: BTreeMap<&'a str, CommandTotals>,
+    failures: Vec<Failure<'a>>,
+    /// None when no run has a start time.
+    timeline: Option<Timeline<'a>>,
+}
+
+pub fn run_report(cfg: &Config, args: ReportArgs) -> Result<()> {
+    let dir = log_dir(cfg, args.dir);
+    let mut runs = match &args.group {
+        Some(id) => group_logs(cfg, id)?,
+        None => scan_logs(cfg, &dir)?,
+    };
+    runs.retain(|r| {
+        args.cmd.as_ref().map_or(true, |c| &r.cmd == c)
+            && (args.group.is_some() || started_between(r.started, Some(args.since), args.until))
+    });
+    let mut report = Report::build(&dir, args.since, args.until, &runs, args.excerpt_lines)?;
+    report.group = args.group.as_deref();
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    report.write(&mut out, args.format)?;
+    out.flush()?;
+    Ok(())
+}
+
+impl<'a> Report<'a> {
+    fn build(
+        dir: &'a Path,
+        since: NaiveDateTime,
+        until: Option<NaiveDateTime>,
+        runs: &'a [LogRecord],
+        excerpt_lines: usize,
+    ) -> Result<Self> {
+        let mut by_cmd: BTreeMap<&str, CommandTotals> = BTreeMap::new();
+        let mut failures = Vec::new();
+        for run in runs {
+            let totals = by_cmd.entry(run.cmd.as_str()).or_default();
+            totals.runs += 1;
+            totals.duration_secs += run.duration_secs.unwrap_or(0);
+            totals.bytes += run.bytes;
+            if run.exit_code.is_some_and(|c| c != 0) {
+                totals.failures += 1;
+                let (excerpt, folds) = excerpt(run, excerpt_lines)?;
+                failures.push(Failure {
+                    run,
+                    excerpt,
+                    folds,
+                });
+            }
+        }
+        Ok(Self {
+            dir,
+            group: None,
+            since,
+            until,
+            runs: runs.len(),
+            succeeded: runs.iter().filter(|r| r.exit_code == Some(0)).count(),
+            unfinished: runs.iter().filter(|r| r.exit_code.is_none()).count(),
+            duration_secs: runs.iter().filter_map(|r| r.duration_secs).sum(),
+            bytes: runs.iter().map(|r| r.bytes).sum(),
+            by_cmd,
+            failures,
+            timeline: Timeline::build(runs),
+        })
+    }
+
+    fn write(&self, w: &mut dyn Write, format: ReportFormat) -> Result<()> {
+        match format {
+            ReportFormat::Text => self.write_text(w),
+            ReportFormat::Markdown => self.write_markdown(w),
+            ReportFormat::Html => self.write_html(w),
+        }
+    }
+
+    fn title(&self) -> String {
+        if let Some(group) = self.group {
+            return format!("lg report: group {}", group);
+        }
+        let fmt = "%Y-%m-%d %H:%M";
+        let until = self
+            .until
+            .map_or("now".into(), |t| t.format(fmt).to_string());
+        format!("lg report: {} to {}", self.since.format(fmt), until)
+    }
+
+    // "12 runs: 10 succeeded, 2 failed"
+    fn counts(&self) -> String {
+        let mut counts = format!(
+            "{} runs: {} succeeded, {} failed",
+            self.runs,
+            self.succeeded,
+            self.failures.len()
+        );
+        if self.unfinished > 0 {
+            counts.push_str(&format!(", {} unfinished", self.unfinished));
+        }
+        counts
+    }
+
+    fn by_cmd_rows(&self) -> impl Iterator<Item = Vec<String>> + '_ {
+        self.by_cmd.iter().map(|(cmd, t)| {
+            vec![
+                cmd.to_string(),
+                t.runs.to_string(),
+                t.failures.to_string(),
+                fmt_secs(t.duration_secs),
+                human_size(t.bytes),
+            ]
+        })
+    }
+
+    fn write_text(&self, w: &mut dyn Write) -> Result<()> {
+        writeln!(w, "{}", self.title())?;
+        if self.group.is_none() {
+            writeln!(w, "directory: {}", self.dir.display())?;
+        }
+        writeln!(w, "{}", self.counts())?;
+        writeln!(w, "total duration: {}", fmt_secs(self.duration_secs))?;
+        writeln!(w, "storage: {}", human_size(self.bytes))?;
+        if let Some(timeline) = &self.timeline {
+            writeln!(w, "\n{}", timeline.caption())?;
+            for line in timeline.grid() {
+                writeln!(w, "  {}", line)?;
+            }
+        }
+        if !self.failures.is_empty() {
+            writeln!(w, "\nFailures")?;
+        }
+        for f in &self.failures {
+            writeln!(w, "\n  {}", f.heading())?;
+            writeln!(w, "  {}", f.details())?;
+            for line in f.summary() {
+                writeln!(w, "  summary: {}", line)?;
+            }
+            for line in &f.excerpt {
+                writeln!(w, "    | {}", line)?;
+            }
+        }
+        if !self.by_cmd.is_empty() {
+            writeln!(w, "\nBy command")?;
+            write_table(
+                &mut *w,
+                &BY_CMD_HEADER,
+                self.by_cmd_rows(),
+                OutputFormat::Table,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_markdown(&self, w: &mut dyn Write) -> Result<()> {
+        writeln!(w, "# {}\n", self.title())?;
+        if self.group.is_none() {
+            writeln!(w, "- directory: `{}`", self.dir.display())?;
+        }
+        writeln!(w, "- {}", self.counts())?;
+        writeln!(w, "- total duration: {}", fmt_secs(self.duration_secs))?;
+        writeln!(w, "- storage: {}", human_size(self.bytes))?;
+        if let Some(timeline) = &self.timeline {
+            writeln!(w, "\n## Timeline\n\n{}\n", md_cell(&timeline.caption()))?;
+            for line in timeline.grid() {
+                writeln!(w, "    {}", line)?;
+            }
+        }
+        if !self.failures.is_empty() {
+            writeln!(w, "\n## Failures")?;
+        }
+        for f in &self.failures {
+            writeln!(w, "\n### {}\n", md_cell(&f.heading()))?;
+            writeln!(w, "{}", md_cell(&f.details()))?;
+            for line in f.summary() {
+                writeln!(w, "\n> {}", md_cell(line))?;
+            }
+            if !f.excerpt.is_empty() {
+                writeln!(w)?;
+            }
+            // An indented code block: nothing in the output can close it.
+            for line in &f.excerpt {
+                writeln!(w, "    {}", line)?;
+            }
+        }
+        if !self.by_cmd.is_empty() {
+            writeln!(w, "\n## By command\n")?;
+            writeln!(w, "| {} |", BY_CMD_HEADER.join(" | "))?;
+            writeln!(w, "|---|---:|---:|---:|---:|")?;
+            for row in self.by_cmd_rows() {
+                let cells: Vec<String> = row.iter().map(|c| md_cell(c)).collect();
+                writeln!(w, "| {} |", cells.join(" | "))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_html(&self, w: &mut dyn Write) -> Result<()> {
+        let title = html_escape(&self.title());
+        writeln!(w, "<!DOCTYPE html>")?;
+        writeln!(
+            w,
+            "<html><head><meta charset=\"utf-8\"><title>{}</title>",
+            title
+        )?;
+        writeln!(w, "<style>{}</style></head><body>", HTML_STYLE)?;
+        writeln!(w, "<h1>{}</h1>\n<ul>", title)?;
+        if self.group.is_none() {
+            writeln!(
+                w,
+                "<li>directory: <code>{}</code></li>",
+                html_escape(&self.dir.display().to_string())
+            )?;
+        }
+        writeln!(w, "<li>{}</li>", html_escape(&self.counts()))?;
+        writeln!(
+            w,
+            "<li>total duration: {}</li>",
+            fmt_secs(self.duration_secs)
+        )?;
+        writeln!(w, "<li>storage: {}</li>\n</ul>", human_size(self.bytes))?;
+        if let Some(timeline) = &self.timeline {
+            timeline.write_html(w)?;
+        }
+        if !self.failures.is_empty() {
+            writeln!(w, "<h2>Failures</h2>")?;
+        }
+        for (i, f) in self.failures.iter().enumerate() {
+            writeln!(
+                w,
+                "<h3 id=\"failure-{}\" class=\"failed\">{}</h3>",
+                i,
+                html_escape(&f.heading())
+            )?;
+            writeln!(w, "<p>{}</p>", html_escape(&f.details()))?;
+            for line in f.summary() {
+                writeln!(w, "<blockquote>{}</blockquote>", html_escape(line))?;
+            }
+            if !f.excerpt.is_empty() {
+                writeln!(w, "{}", html_excerpt(&f.excerpt, &f.folds))?;
+            }
+        }
+        if !self.by_cmd.is_empty() {
+            writeln!(w, "<h2>By command</h2>\n<table>")?;
+            let header: Vec<String> = BY_CMD_HEADER
+                .iter()
+                .map(|h| format!("<th>{}</th>", h))
+                .collect();
+            writeln!(w, "<tr>{}</tr>", header.concat())?;
+            for row in self.by_cmd_rows() {
+                let cells: Vec<String> = row
+                    .iter()
+                    .map(|c| format!("<td>{}</td>", html_escape(c)))
+                    .collect();
+                writeln!(w, "<tr>{}</tr>", cells.concat())?;
+            }
+            writeln!(w, "</table>")?;
+        }
+        writeln!(w, "</body></html>")?;
+        Ok(())
+    }
+}
+
+// The kept logs of the runs of group `id`, wherever they were written.
+fn group_logs(cfg: &Config, id: &str) -> Result<Vec<LogRecord>> {
+    let state = StateDir::locate().context("cannot determine lg state directory (HOME unset)")?;
+    let (_, runs) = state.load_group(id)?;
+    Ok(runs
+        .iter()
+        .filter_map(|run| parse_log(cfg, run.log.as_ref()?).ok().flatten())
+        .collect())
+}
+
+impl<'a> Timeline<'a> {
+    fn build(runs: &'a [LogRecord]) -> Option<Self> {
+        let dates = runs.iter().filter_map(|r| r.started.map(|t| t.date()));
+        let (first_day, last_day) = (dates.clone().min()?, dates.max()?);
+        let days = (last_day - first_day).num_days() as usize + 1;
+        let mut rows: BTreeMap<&str, Vec<DayCell>> = BTreeMap::new();
+        // Failures are numbered in run order, as `Report::build` lists them.
+        let mut failure = 0;
+        for run in runs {
+            let failed = run.exit_code.is_some_and(|c| c != 0);
+            if let Some(started) = run.started {
+                let cells = rows
+                    .entry(run.cmd.as_str())
+                    .or_insert_with(|| vec![DayCell::default(); days]);
+                let cell = &mut cells[(started.date() - first_day).num_days() as usize];
+                cell.runs += 1;
+                cell.last_run = Some(run);
+                if failed {
+                    cell.failures += 1;
+                    cell.first_failure.get_or_insert(failure);
+                }
+            }
+            failure += usize::from(failed);
+        }
+        Some(Self {
+            first_day,
+            days,
+            rows,
+        })
+    }
+
+    fn day(&self, i: usize) -> NaiveDate {
+        self.first_day + chrono::Days::new(i as u64)
+    }
+
+    fn caption(&self) -> String {
+        format!(
+            "Timeline, {} to {}: a column a day; . no runs, o all succeeded, X a failure",
+            self.first_day,
+            self.day(self.days - 1)
+        )
+    }
+
+    // "make  oo.oXo"
+    fn grid(&self) -> Vec<String> {
+        let width = self
+            .rows
+            .keys()
+            .map(|c| c.chars().count())
+            .max()
+            .unwrap_or(0);
+        self.rows
+            .iter()
+            .map(|(cmd, cells)| {
+                let marks: String = cells
+                    .iter()
+                    .map(|c| match c {
+                        c if c.failures > 0 => 'X',
+                        c if c.runs > 0 => 'o',
+                        _ => '.',
+                    })
+                    .collect();
+                format!("{:<width$}  {}", cmd, marks, width = width)
+            })
+            .collect()
+    }
+
+    // A table with a cell a day, linking to the day's first failure or else to
+    // its last log.
+    fn write_html(&self, w: &mut dyn Write) -> Result<()> {
+        writeln!(w, "<h2>Timeline</h2>\n<table class=\"timeline\">")?;
+        let days: Vec<String> = (0..self.days)
+            .map(|i| {
+                let day = self.day(i);
+                format!("<th title=\"{}\">{}</th>", day, day.format("%d"))
+            })
+            .collect();
+        writeln!(w, "<tr><th></th>{}</tr>", days.concat())?;
+        for (cmd, cells) in &self.rows {
+            let tds: Vec<String> = cells
+                .iter()
+                .enumerate()
+                .map(|(i, cell)| {
+                    let Some(last) = cell.last_run else {
+                        return "<td class=\"none\"></td>".to_string();
+                    };
+                    let title = format!(
+                        "{}: {} run(s), {} failed",
+                        self.day(i),
+                        cell.runs,
+                        cell.failures
+                    );
+                    let (class, href) = match cell.first_failure {
+                        Some(n) => ("fail", format!("#failure-{}", n)),
+                        None => ("ok", file_url(&last.path)),
+                    };
+                    format!(
+                        "<td class=\"{}\" title=\"{}\"><a href=\"{}\">{}</a></td>",
+                        class,
+                        html_escape(&title),
+                        html_escape(&href),
+                        cell.runs
+                    )
+                })
+                .collect();
+            writeln!(w, "<tr><th>{}</th>{}</tr>", html_escape(cmd), tds.concat())?;
+        }
+        writeln!(w, "</table>")?;
+        Ok(())
+    }
+}
+
+impl Failure<'_> {
+    // "make test: exit 2 (build-error, oom-killed)"
+    fn heading(&self) -> String {
+        let run = self.run;
+        let cmd = format!("{} {}", run.cmd, run.args);
+        let mut heading = format!("{}: exit {}", cmd.trim_end(), opt_str(run.exit_code));
+        let why: Vec<&str> = [&run.failure_class, &run.probable_cause]
+            .into_iter()
+            .filter_map(|s| s.as_deref())
+            .collect();
+        if !why.is_empty() {
+            heading.push_str(&format!(" ({})", why.join(", ")));
+        }
+        heading
+    }
+
+    // "started 2026-10-16 09:12:03, took 42s, log /var/log/lg/make_....log"
+    fn details(&self) -> String {
+        let run = self.run;
+        let started = run.started.map(|t| t.format("%Y-%m-%d %H:%M:%S"));
+        format!(
+            "started {}, took {}, log {}",
+            opt_str(started),
+            run.duration_secs.map_or("?".into(), fmt_secs),
+            run.path.display()
+        )
+    }
+
+    fn summary(&self) -> impl Iterator<Item = &str> {
+        self.run.summary.iter().flat_map(|s| s.lines())
+    }
+}
+
+// The output of a failed run from its first error-looking line on, or else
+// its last lines, and the folds among them; stderr goes first when the
+// streams were split.
+fn excerpt(run: &LogRecord, max_lines: usize) -> Result<(Vec<String>, Vec<ExcerptFold>)> {
+    if max_lines == 0 {
+        return Ok((Vec::new(), Vec::new()));
+    }
+    let mut files = run_files(&run.path);
+    files.reverse();
+    let mut tail: VecDeque<String> = VecDeque::with_capacity(max_lines);
+    let mut tail_folds = Vec::new();
+    for (i, (path, _)) in files.iter().enumerate() {
+        let Ok(reader) = open_log(path) else {
+            continue;
+        };
+        let mut found = Vec::new();
+        // Output lines read, the first of `found`, and the folds among them
+        // (an end of None is a fold still open).
+        let (mut n, mut found_at) = (0, 0);
+        let mut folds: Vec<(String, usize, Option<usize>)> = Vec::new();
+        for body_line in body_lines(reader) {
+            let (_, line) = body_line?;
+            let line = line.as_str();
+            if let Some(marker) = fold_marker(line) {
+                if let Some(open) = folds.last_mut().filter(|f| f.2.is_none()) {
+                    open.2 = Some(n);
+                }
+                if let FoldMarker::Start(name) = marker {
+                    folds.push((name, n, None));
+                }
+                continue;
+            }
+            let (label, message) = split_line_marker(line);
+            if matches!(label, Some("LG" | "EXPECT" | "STDIN")) {
+                continue;
+            }
+            let index = n;
+            n += 1;
+            if !found.is_empty() || ERROR_LINE.is_match(message) {
+                if found.is_empty() {
+                    found_at = index;
+                }
+                found.push(message.to_string());
+                if found.len() == max_lines {
+                    break;
+                }
+            } else if i == 0 {
+                if tail.len() == max_lines {
+                    tail.pop_front();
+                }
+                tail.push_back(message.to_string());
+            }
+        }
+        if !found.is_empty() {
+            let folds = clip_folds(&folds, found_at..found_at + found.len());
+            return Ok((found, folds));
+        }
+        if i == 0 {
+            tail_folds = clip_folds(&folds, n - tail.len()..n);
+        }
+    }
+    Ok((tail.into(), tail_folds))
+}
+
+// The parts of `folds` within output lines `window`, counted from its start.
+fn clip_folds(folds: &[(String, usize, Option<usize>)], window: Range<usize>) -> Vec<ExcerptFold> {
+    folds
+        .iter()
+        .filter_map(|(name, start, end)| {
+            let start = (*start).max(window.start);
+            let end = end.unwrap_or(window.end).min(window.end);
+            (start < end).then(|| ExcerptFold {
+                name: name.clone(),
+                lines: start - window.start..end - window.start,
+            })
+        })
+        .collect()
+}
+
+// The excerpt as `<pre>` blocks, each fold collapsed in a `<details>`.
+fn html_excerpt(lines: &[String], folds: &[ExcerptFold]) -> String {
+    let pre = |lines: &[String]| format!("<pre>{}</pre>", html_escape(&lines.join("\n")));
+    let mut out = String::new();
+    let mut at = 0;
+    for fold in folds {
+        if fold.lines.start > at {
+            out.push_str(&pre(&lines[at..fold.lines.start]));
+        }
+        let unit = if fold.lines.len() == 1 {
+            "line"
+        } else {
+            "lines"
+        };
+        out.push_str(&format!(
+            "<details><summary>{} ({} {})</summary>{}</details>",
+            html_escape(&fold.name),
+            fold.lines.len(),
+            unit,
+            pre(&lines[fold.lines.clone()])
+        ));
+        at = fold.lines.end;
+    }
+    if at < lines.len() {
+        out.push_str(&pre(&lines[at..]));
+    }
+    out
+}
+
+// "1h02m03s", "4m05s", "12s"
+pub fn fmt_secs(secs: i64) -> String {
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    match (h, m) {
+        (0, 0) => format!("{}s", s),
+        (0, _) => format!("{}m{:02}s", m, s),
+        _ => format!("{}h{:02}m{:02}s", h, m, s),
+    }
+}
+
+fn file_url(path: &Path) -> String {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    format!("file://{}", path.display()).replace(' ', "%20")
+}
+
+fn md_cell(s: &str) -> String {
+    s.replace('|', "\\|")
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn log(cmd: &str, date: &str, output: &[&str], code: i32) -> String {
+        let mut log = format!(
+            "# lg log\ncmd: {}\nargs: \ndate: {}\ncwd: /src\n----- BEGIN OUTPUT -----\n",
+            cmd, date
+        );
+        let time = &date[11..];
+        for line in output {
+            log.push_str(&format!("[{}][STDOUT] {}\n", time.replace('-', ":"), line));
+        }
+        log.push_str(&format!("\n[exit_code] {}\n[duration] 75.000s\n", code));
+        log
+    }
+
+    #[test]
+    fn failures_carry_their_first_error_and_days_their_runs() {
+        let dir = std::env::temp_dir().join(format!("lg-report-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let build = [
+            "Compiling app",
+            "error[E0425]: cannot find value `x`",
+            "  --> src/main.rs:3:5",
+            "aborting",
+        ];
+        fs::write(
+            dir.join("make_1.log"),
+            log("make", "2026-10-16 09-00-00", &build, 101),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("make_0.log"),
+            log("make", "2026-10-14 22-00-00", &["ok"], 0),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("make_2.log"),
+            log("make", "2026-10-16 10-00-00", &["ok"], 0),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("sync_1.log"),
+            log("sync", "2026-10-16 11-00-00", &["a", "b <c>", "d"], 1),
+        )
+        .unwrap();
+
+        let cfg = Config::default();
+        let runs = scan_logs(&cfg, &dir).unwrap();
+        let since = NaiveDateTime::parse_from_str("2026-10-16 00:00", "%Y-%m-%d %H:%M").unwrap();
+        let report = Report::build(&dir, since, None, &runs, 2).unwrap();
+        assert_eq!(report.counts(), "4 runs: 2 succeeded, 2 failed");
+        assert_eq!(fmt_secs(report.duration_secs), "5m00s");
+        assert_eq!(
+            report.failures[0].excerpt,
+            [
+                "error[E0425]: cannot find value `x`",
+                "  --> src/main.rs:3:5"
+            ]
+        );
+        // No error line: the last lines instead.
+        assert_eq!(report.failures[1].excerpt, ["b <c>", "d"]);
+
+        let render = |format| {
+            let mut out = Vec::new();
+            report.write(&mut out, format).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let text = render(ReportFormat::Text);
+        assert!(text.starts_with("lg report: 2026-10-16 00:00 to now\n"));
+        assert!(text.contains("\n  make: exit 101\n"));
+        assert!(text.contains("    | error[E0425]"));
+        let markdown = render(ReportFormat::Markdown);
+        assert!(markdown.contains("\n### sync: exit 1\n"));
+        assert!(markdown.contains("| make | 3 | 1 | 3m45s |"));
+        assert!(markdown.contains("\n    make  o.X\n    sync  ..X\n"));
+        let html = render(ReportFormat::Html);
+        assert!(html.contains("<th title=\"2026-10-15\">15</th>"));
+        assert!(html.contains(
+            "<td class=\"fail\" title=\"2026-10-16: 2 run(s), 1 failed\"><a href=\"#failure-0\">2</a>"
+        ));
+        assert!(html.contains("<h3 id=\"failure-1\" class=\"failed\">sync: exit 1</h3>"));
+        assert!(html.contains("<pre>b &lt;c&gt;\nd</pre>"));
+        assert!(html.ends_with("</body></html>\n"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn folds_in_an_excerpt_collapse_in_html() {
+        let dir = std::env::temp_dir().join(format!("lg-report-fold-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cargo_1.log");
+        fs::write(
+            &path,
+            "# lg log\ncmd: cargo\nargs: test\ndate: 2026-10-16 09-00-00\n\
+             ----- BEGIN OUTPUT -----\n\
+             [FOLD] start downloads\n\
+             [STDOUT]   Downloaded a v1\n\
+             [STDOUT]   Downloaded b v1\n\
+             [FOLD] end downloads (2 lines)\n\
+             [STDOUT] running 3 tests\n\
+             [FOLD] start tests\n\
+             [STDOUT] test a ... ok\n\
+             [STDOUT] test b ... ok\n\
+             [FOLD] end tests (2 lines)\n\
+             [STDOUT] 1 of 3 tests left\n\
+             \n[exit_code] 101\n",
+        )
+        .unwrap();
+        let run = parse_log(&Config::default(), &path).unwrap().unwrap();
+        // No error line: the last lines, of which the first is in a fold.
+        let (lines, folds) = excerpt(&run, 5).unwrap();
+        assert_eq!(lines[0], "  Downloaded b v1");
+        assert_eq!(
+            html_excerpt(&lines, &folds),
+            "<details><summary>downloads (1 line)</summary><pre>  Downloaded b v1</pre></details>\
+             <pre>running 3 tests</pre>\
+             <details><summary>tests (2 lines)</summary><pre>test a ... ok\ntest b ... ok</pre></details>\
+             <pre>1 of 3 tests left</pre>"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
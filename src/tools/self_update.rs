@@ -0,0 +1,170 @@
+//! `lg self-update`: replace the running binary with a GitHub release of lg.
+//! A release carries one binary per platform, `lg-<arch>-<os>` (such as
+//! `lg-x86_64-linux`), and a `SHA256SUMS` file listing their digests; a
+//! download is only installed when its digest matches. curl(1) fetches.
+
+use crate::cli::SelfUpdateArgs;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const REPO: &str = "Batyan45/lg";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Result<&Asset> {
+        self.assets
+            .iter()
+            .find(|a| a.name == name)
+            .with_context(|| format!("release {} has no {}", self.tag_name, name))
+    }
+}
+
+/// Returns the exit status: with `--check`, 1 when a newer release is out.
+pub fn run_self_update(args: SelfUpdateArgs) -> Result<i32> {
+    let url = match &args.tag {
+        Some(tag) => format!(
+            "https://api.github.com/repos/{}/releases/tags/{}",
+            REPO, tag
+        ),
+        None => format!("https://api.github.com/repos/{}/releases/latest", REPO),
+    };
+    let release: Release =
+        serde_json::from_slice(&fetch(&url)?).with_context(|| format!("reading {}", url))?;
+    let current = env!("CARGO_PKG_VERSION");
+    let version = release.tag_name.trim_start_matches('v');
+    let newer = is_newer(version, current);
+    if args.check {
+        match newer {
+            true => println!("lg {} is out (this is {})", version, current),
+            false => println!("lg {} is up to date", current),
+        }
+        return Ok(i32::from(newer));
+    }
+    if !newer && args.tag.is_none() {
+        println!("lg {} is up to date", current);
+        return Ok(0);
+    }
+
+    let exe = std::env::current_exe().context("locating the lg binary")?;
+    let exe = fs::canonicalize(&exe).unwrap_or(exe);
+    if exe.starts_with("/usr") && !exe.starts_with("/usr/local") {
+        bail!(
+            "{} comes from a system package; update lg with the package manager",
+            exe.display()
+        );
+    }
+    let name = format!("lg-{}-{}", std::env::consts::ARCH, std::env::consts::OS);
+    let sums = fetch(&release.asset("SHA256SUMS")?.browser_download_url)?;
+    let expected = listed_digest(&String::from_utf8_lossy(&sums), &name)
+        .with_context(|| format!("SHA256SUMS of {} does not list {}", release.tag_name, name))?;
+    let binary = fetch(&release.asset(&name)?.browser_download_url)?;
+    let digest: String = Sha256::digest(&binary)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if digest != expected {
+        bail!(
+            "{} of {} does not match SHA256SUMS ({} instead of {}); nothing was installed",
+            name,
+            release.tag_name,
+            digest,
+            expected
+        );
+    }
+    install(&exe, &binary)?;
+    println!("lg {} -> {} ({})", current, version, exe.display());
+    Ok(0)
+}
+
+fn fetch(url: &str) -> Result<Vec<u8>> {
+    let output = Command::new("curl")
+        .args(["-fsSL", "--proto", "=https"])
+        .args(["-H", "Accept: application/vnd.github+json"])
+        .arg(url)
+        .output()
+        .context("running curl")?;
+    if !output.status.success() {
+        bail!(
+            "fetching {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+// Write the new binary beside the old one, then rename it over: a failure
+// halfway leaves the old binary as it was.
+fn install(exe: &Path, binary: &[u8]) -> Result<()> {
+    let name = exe.file_name().unwrap_or_default().to_string_lossy();
+    let staged = exe.with_file_name(format!(".{}.update", name));
+    fs::write(&staged, binary).with_context(|| format!("writing {:?}", staged))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+    fs::rename(&staged, exe).with_context(|| {
+        let _ = fs::remove_file(&staged);
+        format!("replacing {:?}", exe)
+    })
+}
+
+// "1.10.0" is newer than "1.9.2"; a pre-release suffix ("-rc1") is ignored.
+fn is_newer(version: &str, current: &str) -> bool {
+    let parts = |v: &str| -> Vec<u64> {
+        v.split('-')
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|p| p.parse().unwrap_or(0))
+            .collect()
+    };
+    parts(version) > parts(current)
+}
+
+// The digest `sums` (sha256sum(1) output) lists for `name`.
+fn listed_digest(sums: &str, name: &str) -> Option<String> {
+    sums.lines().find_map(|line| {
+        let (digest, file) = line.split_once(char::is_whitespace)?;
+        let file = file.trim_start().trim_start_matches('*');
+        (file == name).then(|| digest.to_ascii_lowercase())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn releases_are_compared_and_checked_by_their_sums() {
+        assert!(is_newer("1.10.0", "1.9.2"));
+        assert!(!is_newer("1.1.1", "1.1.1"));
+        assert!(!is_newer("1.2.0-rc1", "1.2.0"));
+        let sums = "AB12  lg-aarch64-linux\ncd34 *lg-x86_64-linux\n";
+        assert_eq!(
+            listed_digest(sums, "lg-x86_64-linux").as_deref(),
+            Some("cd34")
+        );
+        assert_eq!(
+            listed_digest(sums, "lg-aarch64-linux").as_deref(),
+            Some("ab12")
+        );
+        assert_eq!(listed_digest(sums, "lg-x86_64-macos"), None);
+    }
+}
@@ -0,0 +1,464 @@
+//! `lg stats`, `lg list` and `lg show`.
+
+use crate::anomaly::{anomalies, earlier_runs, Anomaly};
+use crate::cli::{ListArgs, OutputFormat, ShowArgs, StatsArgs};
+use crate::config::Config;
+use crate::index::{IndexedRun, RunIndex};
+use crate::timespec::started_between;
+use crate::tools::{
+    fold_marker, latest_run, log_dir, open_log, opt_str, run_files, scan_logs, with_pager,
+    write_table, FoldMarker, LogRecord,
+};
+use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+/// Per-command aggregate used by `lg stats`.
+#[derive(Debug, Serialize)]
+struct CommandStats {
+    cmd: String,
+    runs: u64,
+    failures: u64,
+    avg_duration_secs: Option<f64>,
+    max_duration_secs: Option<i64>,
+    /// Over the scheduled runs: how late they started, see `scheduled_at`.
+    avg_start_delay_secs: Option<f64>,
+    max_start_delay_secs: Option<f64>,
+    avg_lines: f64,
+    last_run: Option<NaiveDateTime>,
+    last_exit_code: Option<i32>,
+    failure_classes: BTreeMap<String, u64>,
+}
+
+fn aggregate_stats(runs: &[LogRecord]) -> Vec<CommandStats> {
+    let mut by_cmd: BTreeMap<&str, Vec<&LogRecord>> = BTreeMap::new();
+    for run in runs {
+        by_cmd.entry(run.cmd.as_str()).or_default().push(run);
+    }
+    by_cmd
+        .into_iter()
+        .map(|(cmd, runs)| {
+            let durations: Vec<i64> = runs.iter().filter_map(|r| r.duration_secs).collect();
+            let delays: Vec<f64> = runs.iter().filter_map(|r| r.start_delay_secs).collect();
+            let mut failure_classes = BTreeMap::new();
+            for class in runs.iter().filter_map(|r| r.failure_class.as_ref()) {
+                *failure_classes.entry(class.clone()).or_insert(0) += 1;
+            }
+            let last = runs.last().copied();
+            CommandStats {
+                cmd: cmd.to_string(),
+                runs: runs.len() as u64,
+                failures: runs.iter().filter(|r| r.exit_code != Some(0)).count() as u64,
+                avg_duration_secs: (!durations.is_empty())
+                    .then(|| durations.iter().sum::<i64>() as f64 / durations.len() as f64),
+                max_duration_secs: durations.iter().max().copied(),
+                avg_start_delay_secs: (!delays.is_empty())
+                    .then(|| delays.iter().sum::<f64>() / delays.len() as f64),
+                max_start_delay_secs: delays.iter().copied().reduce(f64::max),
+                avg_lines: runs.iter().map(|r| r.lines).sum::<u64>() as f64 / runs.len() as f64,
+                last_run: last.and_then(|r| r.started),
+                last_exit_code: last.and_then(|r| r.exit_code),
+                failure_classes,
+            }
+        })
+        .collect()
+}
+
+/// What lg itself cost for a command's runs, as `lg stats --self` reports it.
+#[derive(Debug, PartialEq, Serialize)]
+struct SelfStats {
+    cmd: String,
+    runs: u64,
+    /// From lg starting to the command being spawned.
+    avg_startup_ms: f64,
+    max_startup_ms: i64,
+    /// Output lg read from the commands.
+    bytes: i64,
+    lg_cpu_ms: Option<i64>,
+    lg_cpu_ms_per_mib: Option<f64>,
+}
+
+// One row per command, then one over all of them when there are several.
+// Runs from before lg recorded its overhead are left out.
+fn aggregate_self(runs: &[IndexedRun]) -> Vec<SelfStats> {
+    let mut by_cmd: BTreeMap<&str, Vec<&IndexedRun>> = BTreeMap::new();
+    for run in runs.iter().filter(|r| r.startup_ms.is_some()) {
+        by_cmd.entry(run.cmd.as_str()).or_default().push(run);
+    }
+    let all: Vec<&IndexedRun> = by_cmd.values().flatten().copied().collect();
+    let mut stats: Vec<SelfStats> = by_cmd
+        .iter()
+        .map(|(cmd, runs)| self_stats(cmd, runs))
+        .collect();
+    if stats.len() > 1 {
+        stats.push(self_stats("(all)", &all));
+    }
+    stats
+}
+
+fn self_stats(cmd: &str, runs: &[&IndexedRun]) -> SelfStats {
+    let startups: Vec<i64> = runs.iter().filter_map(|r| r.startup_ms).collect();
+    let timed: Vec<&&IndexedRun> = runs.iter().filter(|r| r.lg_cpu_ms.is_some()).collect();
+    let cpu_ms: i64 = timed.iter().filter_map(|r| r.lg_cpu_ms).sum();
+    let cpu_bytes: i64 = timed.iter().filter_map(|r| r.bytes).sum();
+    SelfStats {
+        cmd: cmd.to_string(),
+        runs: runs.len() as u64,
+        avg_startup_ms: startups.iter().sum::<i64>() as f64 / startups.len().max(1) as f64,
+        max_startup_ms: startups.iter().max().copied().unwrap_or(0),
+        bytes: runs.iter().filter_map(|r| r.bytes).sum(),
+        lg_cpu_ms: (!timed.is_empty()).then_some(cpu_ms),
+        lg_cpu_ms_per_mib: (cpu_bytes > 0)
+            .then(|| cpu_ms as f64 / (cpu_bytes as f64 / (1024.0 * 1024.0))),
+    }
+}
+
+fn write_self_stats(out: &mut dyn Write, args: &StatsArgs, format: OutputFormat) -> Result<()> {
+    let index =
+        RunIndex::existing()?.context("no run index; set index = true to record what lg costs")?;
+    let mut runs = index.runs()?;
+    runs.retain(|r| {
+        args.cmd.as_ref().map_or(true, |c| &r.cmd == c)
+            && started_between(r.started, args.since, args.until)
+    });
+    let stats = aggregate_self(&runs);
+    if format == OutputFormat::Json {
+        serde_json::to_writer_pretty(&mut *out, &stats)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+    let header = [
+        "cmd",
+        "runs",
+        "avg_startup_ms",
+        "max_startup_ms",
+        "bytes",
+        "lg_cpu_ms",
+        "lg_cpu_ms_per_mib",
+    ];
+    let rows = stats.iter().map(|s| {
+        vec![
+            s.cmd.clone(),
+            s.runs.to_string(),
+            format!("{:.1}", s.avg_startup_ms),
+            s.max_startup_ms.to_string(),
+            s.bytes.to_string(),
+            opt_str(s.lg_cpu_ms),
+            opt_str(s.lg_cpu_ms_per_mib.map(|ms| format!("{:.1}", ms))),
+        ]
+    });
+    write_table(out, &header, rows, format)
+}
+
+pub fn run_stats(cfg: &Config, args: StatsArgs) -> Result<()> {
+    let format = args.format.unwrap_or(if args.csv {
+        OutputFormat::Csv
+    } else if args.json {
+        OutputFormat::Json
+    } else {
+        OutputFormat::Table
+    });
+    let out = io::stdout();
+    let mut out = out.lock();
+    if args.lg_self {
+        return write_self_stats(&mut out, &args, format);
+    }
+    let dir = log_dir(cfg, args.dir);
+    let mut runs = scan_logs(cfg, &dir)?;
+    runs.retain(|r| {
+        args.cmd.as_ref().map_or(true, |c| &r.cmd == c)
+            && started_between(r.started, args.since, args.until)
+    });
+    if args.runs {
+        if format == OutputFormat::Json {
+            serde_json::to_writer_pretty(&mut out, &runs)?;
+            writeln!(out)?;
+            return Ok(());
+        }
+        let header = [
+            "started",
+            "cmd",
+            "exit_code",
+            "failure_class",
+            "probable_cause",
+            "duration_secs",
+            "start_delay_secs",
+            "lines",
+            "bytes",
+            "path",
+        ];
+        let rows = runs.iter().map(|r| {
+            vec![
+                opt_str(r.started.map(|t| t.format("%Y-%m-%d %H:%M:%S"))),
+                r.cmd.clone(),
+                opt_str(r.exit_code),
+                r.failure_class.clone().unwrap_or_default(),
+                r.probable_cause.clone().unwrap_or_default(),
+                opt_str(r.duration_secs),
+                opt_str(r.start_delay_secs.map(|d| format!("{:.1}", d))),
+                r.lines.to_string(),
+                r.bytes.to_string(),
+                r.path.display().to_string(),
+            ]
+        });
+        return write_table(&mut out, &header, rows, format);
+    }
+
+    let stats = aggregate_stats(&runs);
+    if format == OutputFormat::Json {
+        serde_json::to_writer_pretty(&mut out, &stats)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+    let header = [
+        "cmd",
+        "runs",
+        "failures",
+        "avg_duration_secs",
+        "max_duration_secs",
+        "avg_start_delay_secs",
+        "max_start_delay_secs",
+        "avg_lines",
+        "last_run",
+        "last_exit_code",
+        "failure_classes",
+    ];
+    let rows = stats.iter().map(|s| {
+        vec![
+            s.cmd.clone(),
+            s.runs.to_string(),
+            s.failures.to_string(),
+            opt_str(s.avg_duration_secs.map(|d| format!("{:.1}", d))),
+            opt_str(s.max_duration_secs),
+            opt_str(s.avg_start_delay_secs.map(|d| format!("{:.1}", d))),
+            opt_str(s.max_start_delay_secs.map(|d| format!("{:.1}", d))),
+            format!("{:.1}", s.avg_lines),
+            opt_str(s.last_run.map(|t| t.format("%Y-%m-%d %H:%M:%S"))),
+            opt_str(s.last_exit_code),
+            s.failure_classes
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join(" "),
+        ]
+    });
+    write_table(&mut out, &header, rows, format)
+}
+
+pub fn run_list(cfg: &Config, args: ListArgs) -> Result<()> {
+    let dir = log_dir(cfg, args.dir);
+    let mut runs = scan_logs(cfg, &dir)?;
+    runs.retain(|r| {
+        args.cmd.as_ref().map_or(true, |c| &r.cmd == c)
+            && (!args.failed_only || r.exit_code.is_some_and(|c| c != 0))
+            && started_between(r.started, args.since, args.until)
+    });
+    if let Some(limit) = args.limit {
+        runs.drain(..runs.len().saturating_sub(limit));
+    }
+    mark_anomalies(cfg, &mut runs);
+    with_pager(args.no_pager, |out| write_runs(out, &runs, args.format))
+}
+
+// Flag the runs that are off from the usual for their command line, as the
+// run index has it. Best effort: without an index nothing is flagged.
+fn mark_anomalies(cfg: &Config, runs: &mut [LogRecord]) {
+    if cfg.anomaly_factor <= 1.0 {
+        return;
+    }
+    let indexed = match RunIndex::existing().and_then(|i| i.map(|i| i.runs()).transpose()) {
+        Ok(Some(indexed)) => indexed,
+        Ok(None) => return,
+        Err(err) => {
+            eprintln!("lg: index: {:#}", err);
+            return;
+        }
+    };
+    for run in runs {
+        let Some(row) = indexed
+            .iter()
+            .find(|r| r.run_id.is_some() && r.run_id == run.run_id)
+        else {
+            continue;
+        };
+        let earlier = earlier_runs(&indexed, row);
+        run.anomalies = anomalies(&earlier, row, cfg.anomaly_factor);
+    }
+}
+
+fn write_runs(out: &mut dyn Write, runs: &[LogRecord], format: OutputFormat) -> Result<()> {
+    if format == OutputFormat::Json {
+        serde_json::to_writer_pretty(&mut *out, runs)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+    let header = [
+        "started",
+        "cmd",
+        "args",
+        "exit_code",
+        "failure_class",
+        "duration_secs",
+        "lines",
+        "anomaly",
+        "fingerprint",
+        "path",
+    ];
+    let rows = runs.iter().map(|r| {
+        // "*" marks a run whose program, environment or cwd changed since the last one.
+        let fingerprint = r.fingerprint.as_deref().map_or_else(String::new, |f| {
+            let short = f.strip_prefix("sha256-").unwrap_or(f);
+            format!("{}{}", short, if r.fingerprint_changed { " *" } else { "" })
+        });
+        vec![
+            opt_str(r.started.map(|t| t.format("%Y-%m-%d %H:%M:%S"))),
+            r.cmd.clone(),
+            r.args.clone(),
+            opt_str(r.exit_code),
+            r.failure_class.clone().unwrap_or_default(),
+            opt_str(r.duration_secs),
+            r.lines.to_string(),
+            r.anomalies
+                .iter()
+                .map(Anomaly::short)
+                .collect::<Vec<_>>()
+                .join(", "),
+            fingerprint,
+            r.path.display().to_string(),
+        ]
+    });
+    write_table(out, &header, rows, format)
+}
+
+pub fn run_show(cfg: &Config, args: ShowArgs) -> Result<()> {
+    let path = match args.path {
+        Some(path) => path,
+        None => latest_run(cfg, args.dir, args.cmd.as_deref())?,
+    };
+    let files = run_files(&path);
+    with_pager(args.no_pager, |out| {
+        for (i, (file, _)) in files.iter().enumerate() {
+            if files.len() > 1 {
+                let gap = if i > 0 { "\n" } else { "" };
+                writeln!(out, "{}==> {} <==", gap, file.display())?;
+            }
+            match args.folded {
+                true => write_folded(open_log(file)?, out)?,
+                false => {
+                    io::copy(&mut open_log(file)?, out)?;
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+// The log with each fold collapsed into a line that names it.
+fn write_folded(log: impl BufRead, out: &mut dyn Write) -> Result<()> {
+    let mut fold: Option<(String, u64)> = None;
+    let summary = |out: &mut dyn Write, name: &str, lines: u64| {
+        let unit = if lines == 1 { "line" } else { "lines" };
+        writeln!(out, "[FOLD] {} ({} {} folded)", name, lines, unit)
+    };
+    for raw in log.split(b'\n') {
+        let raw = raw?;
+        match (fold_marker(&String::from_utf8_lossy(&raw)), &mut fold) {
+            (Some(FoldMarker::Start(name)), None) => fold = Some((name, 0)),
+            (Some(FoldMarker::End(_)), Some(_)) => {
+                let (name, lines) = fold.take().unwrap();
+                summary(out, &name, lines)?;
+            }
+            (_, Some((_, lines))) => *lines += 1,
+            (_, None) => {
+                out.write_all(&raw)?;
+                out.write_all(b"\n")?;
+            }
+        }
+    }
+    // A log cut short inside a fold.
+    if let Some((name, lines)) = fold {
+        summary(out, &name, lines)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn indexed(cmd: &str, overhead: Option<(i64, i64, Option<i64>)>) -> IndexedRun {
+        IndexedRun {
+            run_id: None,
+            cmd: cmd.into(),
+            args: String::new(),
+            cwd: PathBuf::from("/src"),
+            started: None,
+            exit_code: Some(0),
+            duration_ms: Some(1000),
+            log_path: PathBuf::from("/src/run.log"),
+            startup_ms: overhead.map(|o| o.0),
+            bytes: overhead.map(|o| o.1),
+            lg_cpu_ms: overhead.and_then(|o| o.2),
+        }
+    }
+
+    #[test]
+    fn folds_collapse_to_one_line() {
+        let show = |log: &str| {
+            let mut out = Vec::new();
+            write_folded(log.as_bytes(), &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let text = "----- BEGIN OUTPUT -----\n\
+                    [10:00:00.000][FOLD] start downloads\n\
+                    [10:00:00.000][STDOUT]   Downloaded serde v1.0\n\
+                    [10:00:00.100][STDOUT]   Downloaded regex v1.10\n\
+                    [10:00:00.100][FOLD] end downloads (2 lines)\n\
+                    [10:00:01.000][STDERR] error: linking failed\n\
+                    [10:00:01.000][FOLD] start tests\n\
+                    [10:00:01.000][STDOUT] running 40 tests\n";
+        assert_eq!(
+            show(text),
+            "----- BEGIN OUTPUT -----\n\
+             [FOLD] downloads (2 lines folded)\n\
+             [10:00:01.000][STDERR] error: linking failed\n\
+             [FOLD] tests (1 line folded)\n"
+        );
+        let jsonl = "{\"stream\":\"fold\",\"line\":\"start tests\"}\n\
+                     {\"stream\":\"stdout\",\"line\":\"test a ... ok\"}\n\
+                     {\"stream\":\"fold\",\"line\":\"end tests (1 line)\"}\n\
+                     {\"type\":\"footer\",\"exit_code\":0}\n";
+        assert_eq!(
+            show(jsonl),
+            "[FOLD] tests (1 line folded)\n{\"type\":\"footer\",\"exit_code\":0}\n"
+        );
+    }
+
+    #[test]
+    fn self_stats_cover_the_runs_that_recorded_them() {
+        let mib = 1024 * 1024;
+        let runs = [
+            indexed("make", Some((4, 2 * mib, Some(30)))),
+            indexed("make", Some((6, 0, None))),
+            // Recorded before lg kept its overhead.
+            indexed("make", None),
+            indexed("cargo", Some((2, mib, Some(5)))),
+        ];
+        let stats = aggregate_self(&runs);
+        assert_eq!(
+            stats.iter().map(|s| s.cmd.as_str()).collect::<Vec<_>>(),
+            ["cargo", "make", "(all)"]
+        );
+        let make = &stats[1];
+        assert_eq!((make.runs, make.max_startup_ms), (2, 6));
+        assert_eq!(make.avg_startup_ms, 5.0);
+        assert_eq!((make.bytes, make.lg_cpu_ms), (2 * mib, Some(30)));
+        assert_eq!(make.lg_cpu_ms_per_mib, Some(15.0));
+        let all = &stats[2];
+        assert_eq!((all.runs, all.lg_cpu_ms), (3, Some(35)));
+        assert_eq!(aggregate_self(&runs[..2]).len(), 1);
+    }
+}
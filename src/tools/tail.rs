@@ -0,0 +1,409 @@
+//! `lg tail` and `lg attach`.
+
+use crate::cli::{AttachArgs, TailArgs};
+use crate::config::Config;
+use crate::jsonl;
+use crate::state::RunningEntry;
+use crate::tools::{
+    decoder, ignore_broken_pipe, log_dir, paint, parse_log, partial_logs, scan_logs, use_color,
+    LogRecord, BOLD, CYAN, GREEN, MAGENTA, YELLOW,
+};
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// How often `lg tail` checks logs for new output.
+static TAIL_POLL: Duration = Duration::from_millis(200);
+
+/// Reads what has been appended to one log since the last poll.
+struct Follower {
+    source: Source,
+    prefix: String,
+    partial: Vec<u8>,
+    /// The footer has been printed; nothing more will be written.
+    finished: bool,
+}
+
+/// Where a follower's output comes from.
+enum Source {
+    Plain(File),
+    /// A compressed log, decoded again from the top whenever it grows (the
+    /// encoder flushes whole lines); `seen` bytes of it were read before.
+    Compressed {
+        file: File,
+        path: PathBuf,
+        len: u64,
+        seen: usize,
+    },
+}
+
+impl Source {
+    fn read_new(&mut self, buf: &mut Vec<u8>) -> Result<()> {
+        match self {
+            Source::Plain(file) => {
+                file.read_to_end(buf)?;
+            }
+            Source::Compressed {
+                file,
+                path,
+                len,
+                seen,
+            } => {
+                let now = file.metadata()?.len();
+                if now == *len {
+                    return Ok(());
+                }
+                *len = now;
+                file.seek(SeekFrom::Start(0))?;
+                let mut data = Vec::new();
+                // The stream ends early while the run is going; keep what came before.
+                let _ = decoder(path, file.try_clone()?)?.read_to_end(&mut data);
+                if data.len() > *seen {
+                    buf.extend_from_slice(&data[*seen..]);
+                    *seen = data.len();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Follower {
+    // Compressed logs are read from the top whatever `start` says.
+    fn open(path: &Path, prefix: String, start: SeekFrom) -> Result<Self> {
+        let mut file = File::open(path).with_context(|| format!("open {:?}", path))?;
+        let source = if is_compressed(path) {
+            Source::Compressed {
+                file,
+                path: path.to_path_buf(),
+                len: 0,
+                seen: 0,
+            }
+        } else {
+            file.seek(start)?;
+            Source::Plain(file)
+        };
+        Ok(Self {
+            source,
+            prefix,
+            partial: Vec::new(),
+            finished: false,
+        })
+    }
+
+    // Print complete new lines; returns whether anything was read.
+    fn poll(&mut self, out: &mut dyn Write) -> Result<bool> {
+        let before = self.partial.len();
+        self.source.read_new(&mut self.partial)?;
+        if self.partial.len() == before {
+            return Ok(false);
+        }
+        let end = match self.partial.iter().rposition(|&b| b == b'\n') {
+            Some(pos) => pos + 1,
+            None => return Ok(true),
+        };
+        for line in self.partial[..end - 1].split(|&b| b == b'\n') {
+            let line = String::from_utf8_lossy(line);
+            if line.starts_with("[exit_code] ") || jsonl::is_footer(line.as_bytes()) {
+                self.finished = true;
+            }
+            writeln!(out, "{}{}", self.prefix, line)?;
+        }
+        self.partial.drain(..end);
+        out.flush()?;
+        Ok(true)
+    }
+}
+
+pub fn run_tail(cfg: &Config, args: TailArgs) -> Result<()> {
+    let out = io::stdout();
+    let mut out = out.lock();
+    if args.all_running {
+        return ignore_broken_pipe(tail_running(&mut out));
+    }
+    let path = match args.path {
+        Some(path) => path,
+        None => newest_log(cfg, args.dir, args.cmd.as_deref())?,
+    };
+    ignore_broken_pipe(tail_file(&mut out, &path))
+}
+
+// The log of the newest running lg (of `cmd`), else the newest one on disk,
+// counting the `.partial` logs of runs lg has no registry entry for.
+fn newest_log(cfg: &Config, dir: Option<PathBuf>, cmd: Option<&str>) -> Result<PathBuf> {
+    let matches = |c: &str| cmd.map_or(true, |cmd| c == cmd);
+    let running = RunningEntry::list()
+        .into_iter()
+        .rev()
+        .filter(|e| matches(&e.cmd))
+        .find_map(|e| e.logs.into_iter().next());
+    if let Some(path) = running {
+        return Ok(path);
+    }
+    let dir = log_dir(cfg, dir);
+    let mut runs = scan_logs(cfg, &dir)?;
+    runs.extend(partial_runs(cfg, &dir));
+    let run = runs
+        .into_iter()
+        .filter(|r| matches(&r.cmd))
+        .max_by(|a, b| a.started.cmp(&b.started).then(a.path.cmp(&b.path)))
+        .with_context(|| match cmd {
+            Some(cmd) => format!("no runs of {} in {}", cmd, dir.display()),
+            None => format!("no runs in {}", dir.display()),
+        })?;
+    Ok(run.path)
+}
+
+// The runs of the `.partial` logs in `dir`.
+fn partial_runs(cfg: &Config, dir: &Path) -> Vec<LogRecord> {
+    let logs = partial_logs(cfg, dir).unwrap_or_default();
+    logs.iter()
+        .filter_map(|log| parse_log(cfg, log).ok().flatten())
+        .collect()
+}
+
+// Follow one log from the top until its footer, or until it has been idle
+// for a while and no running lg is writing it.
+fn tail_file(out: &mut dyn Write, path: &Path) -> Result<()> {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut follower = Follower::open(&path, String::new(), SeekFrom::Start(0))?;
+    let mut idle = 0;
+    while !follower.finished {
+        if follower.poll(out)? {
+            idle = 0;
+            continue;
+        }
+        idle += 1;
+        if idle >= 10 && !RunningEntry::list().iter().any(|e| e.logs.contains(&path)) {
+            break;
+        }
+        std::thread::sleep(TAIL_POLL);
+    }
+    Ok(())
+}
+
+// Multiplex every running lg until none is left. Runs already going are
+// joined live; runs that start later are shown from their first line.
+fn tail_running(out: &mut dyn Write) -> Result<()> {
+    let color = use_color();
+    let palette = [GREEN, YELLOW, MAGENTA, CYAN, BOLD];
+    let mut followers: BTreeMap<(u32, PathBuf), Follower> = BTreeMap::new();
+    let mut first_scan = true;
+    loop {
+        let running = RunningEntry::list();
+        if first_scan && running.is_empty() {
+            eprintln!("lg: no running lg processes");
+            return Ok(());
+        }
+        for entry in &running {
+            for log in &entry.logs {
+                let key = (entry.pid, log.clone());
+                if followers.contains_key(&key) {
+                    continue;
+                }
+                let label = format!("{}[{}]", entry.cmd, entry.pid);
+                let sgr = palette[followers.len() % palette.len()];
+                let prefix = format!("{} ", paint(&label, sgr, color));
+                let start = if first_scan {
+                    SeekFrom::End(0)
+                } else {
+                    SeekFrom::Start(0)
+                };
+                let Ok(mut follower) = Follower::open(log, prefix, start) else {
+                    continue;
+                };
+                // A compressed stream can't be read while the encoder still holds its tail.
+                if is_compressed(log) {
+                    eprintln!("lg: not following compressed log {}", log.display());
+                    follower.finished = true;
+                }
+                followers.insert(key, follower);
+            }
+        }
+        first_scan = false;
+        let mut active = false;
+        for ((pid, _), follower) in followers.iter_mut().filter(|(_, f)| !f.finished) {
+            let read = follower.poll(out)?;
+            if !read && !running.iter().any(|e| e.pid == *pid) {
+                follower.finished = true;
+            }
+            active |= !follower.finished;
+        }
+        if !active && running.is_empty() {
+            return Ok(());
+        }
+        std::thread::sleep(TAIL_POLL);
+    }
+}
+
+/// The run of lg `pid`, or the most recently started one in the current directory.
+pub fn running_run(pid: Option<u32>) -> Result<RunningEntry> {
+    let running = RunningEntry::list();
+    match pid {
+        Some(pid) => running
+            .into_iter()
+            .find(|e| e.pid == pid)
+            .with_context(|| format!("no running lg with pid {}", pid)),
+        None => {
+            let cwd = std::env::current_dir()?;
+            let cwd = fs::canonicalize(&cwd).unwrap_or(cwd);
+            running
+                .into_iter()
+                .rev()
+                .find(|e| e.cwd.as_ref().is_some_and(|d| *d == cwd))
+                .with_context(|| {
+                    format!(
+                        "no running lg started in {} (`lg tail --all-running` shows all)",
+                        cwd.display()
+                    )
+                })
+        }
+    }
+}
+
+/// The running run with id `run_id`.
+pub fn running_run_id(run_id: &str) -> Result<RunningEntry> {
+    RunningEntry::list()
+        .into_iter()
+        .find(|e| e.run_id.as_deref() == Some(run_id))
+        .with_context(|| format!("no running lg with run id {}", run_id))
+}
+
+// Join a run that is still going: replay its last lines, then follow it live
+// until it ends.
+pub fn run_attach(cfg: &Config, args: AttachArgs) -> Result<()> {
+    let entry = running_run(args.pid)?;
+    let backlog = args.backlog.unwrap_or(cfg.attach_backlog);
+    let mut followers = Vec::new();
+    for log in &entry.logs {
+        // A compressed stream can't be read while the encoder still holds its tail.
+        if is_compressed(log) {
+            anyhow::bail!("cannot attach to compressed log {}", log.display());
+        }
+        let start = if args.from_start {
+            0
+        } else {
+            backlog_start(log, backlog)?
+        };
+        followers.push(Follower::open(log, String::new(), SeekFrom::Start(start))?);
+    }
+    eprintln!("lg: attached to {}[{}]", entry.cmd, entry.pid);
+    let out = io::stdout();
+    let mut out = out.lock();
+    ignore_broken_pipe(follow_run(&mut out, entry.pid, &mut followers))
+}
+
+fn is_compressed(log: &Path) -> bool {
+    log.extension().is_some_and(|e| e == "gz" || e == "zst")
+}
+
+fn follow_run(out: &mut dyn Write, pid: u32, followers: &mut [Follower]) -> Result<()> {
+    loop {
+        let mut read = false;
+        for follower in followers.iter_mut().filter(|f| !f.finished) {
+            read |= follower.poll(out)?;
+        }
+        if followers.iter().all(|f| f.finished) {
+            return Ok(());
+        }
+        if !read && !RunningEntry::list().iter().any(|e| e.pid == pid) {
+            // Killed before writing its footer; print what it managed to write.
+            for follower in followers.iter_mut() {
+                follower.poll(out)?;
+            }
+            return Ok(());
+        }
+        if !read {
+            std::thread::sleep(TAIL_POLL);
+        }
+    }
+}
+
+// Offset of the `lines`-th complete output line from the end of `path`, never
+// before the first output line.
+fn backlog_start(path: &Path, lines: usize) -> Result<u64> {
+    let data = fs::read(path).with_context(|| format!("reading {:?}", path))?;
+    Ok(backlog_offset(&data, lines) as u64)
+}
+
+fn backlog_offset(data: &[u8], lines: usize) -> usize {
+    let marker = b"----- BEGIN OUTPUT -----\n";
+    let body = if data.starts_with(b"# lg log") {
+        data.windows(marker.len())
+            .position(|w| w == marker)
+            .map_or(data.len(), |p| p + marker.len())
+    } else {
+        // JSON Lines: everything after the header record
+        data.iter()
+            .position(|&b| b == b'\n')
+            .map_or(data.len(), |p| p + 1)
+    };
+    // Only complete lines count; an unterminated tail is printed once it ends.
+    let complete = data.iter().rposition(|&b| b == b'\n').map_or(0, |p| p + 1);
+    if lines == 0 || complete <= body {
+        return complete.max(body);
+    }
+    data[body..complete - 1]
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, &b)| b == b'\n')
+        .nth(lines - 1)
+        .map_or(body, |(i, _)| body + i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backlog_stays_inside_the_output() {
+        let log =
+            b"# lg log\ncmd: x\n----- BEGIN OUTPUT -----\n[STDOUT] a\n[STDOUT] b\n[STDOUT] c\n[ST";
+        let at = |n| String::from_utf8_lossy(&log[backlog_offset(log, n)..]).into_owned();
+        assert_eq!(at(0), "[ST");
+        assert_eq!(at(2), "[STDOUT] b\n[STDOUT] c\n[ST");
+        assert_eq!(at(10), "[STDOUT] a\n[STDOUT] b\n[STDOUT] c\n[ST");
+        let json = b"{\"type\":\"header\"}\n{\"line\":\"a\"}\n";
+        assert_eq!(backlog_offset(json, 5), 18);
+    }
+
+    #[test]
+    fn newest_log_counts_partial_and_compressed_logs() {
+        let dir = std::env::temp_dir().join(format!("lg-tail-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = |date: &str| {
+            format!(
+                "# lg log\ncmd: lg-tail-test\ndate: {}\n----- BEGIN OUTPUT -----\n[STDOUT] a\n",
+                date
+            )
+        };
+        fs::write(dir.join("old.log"), log("2026-10-16 10-00-00")).unwrap();
+        let partial = dir.join(".lg-tail-test_NA.log.partial");
+        fs::write(&partial, log("2026-10-16 11-00-00")).unwrap();
+        let cfg = Config::default();
+        let newest = |cmd| newest_log(&cfg, Some(dir.clone()), Some(cmd));
+        assert_eq!(newest("lg-tail-test").unwrap(), partial);
+        assert!(newest("make").is_err());
+
+        // A gzip log whose run is still going: only its flushed part decodes.
+        let gz = dir.join("new.log.gz");
+        let mut enc = flate2::write::GzEncoder::new(
+            File::create(&gz).unwrap(),
+            flate2::Compression::default(),
+        );
+        enc.write_all(b"one\n").unwrap();
+        enc.flush().unwrap();
+        let mut follower = Follower::open(&gz, String::new(), SeekFrom::End(0)).unwrap();
+        let mut out = Vec::new();
+        assert!(follower.poll(&mut out).unwrap());
+        enc.write_all(b"two\n").unwrap();
+        enc.finish().unwrap();
+        assert!(follower.poll(&mut out).unwrap());
+        assert_eq!(out, b"one\ntwo\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
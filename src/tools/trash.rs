@@ -0,0 +1,215 @@
+//! `.lg-trash/`: where `lg clean` (and `keep`) move deleted logs when `clean_trash` is on.
+
+use crate::config::Config;
+use crate::runner::rotated_path;
+use crate::tools::run_files;
+use anyhow::{Context, Result};
+use chrono::{Duration, Local, NaiveDateTime};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+static TRASH_DIR: &str = ".lg-trash";
+// Each `lg clean` fills one batch directory, named after when it ran.
+static BATCH_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+
+/// The trash of one log directory.
+pub struct Trash {
+    root: PathBuf,
+}
+
+/// What `restore_last` did with the files of a batch.
+#[derive(Debug, Default)]
+pub struct Restored {
+    pub restored: Vec<PathBuf>,
+    /// Left in the trash because a file of that name exists again.
+    pub kept: Vec<PathBuf>,
+}
+
+impl Trash {
+    pub fn in_dir(dir: &Path) -> Self {
+        Self {
+            root: dir.join(TRASH_DIR),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Create an empty batch for a clean running at `now`.
+    pub fn new_batch(&self, now: NaiveDateTime) -> Result<PathBuf> {
+        let name = now.format(BATCH_FORMAT).to_string();
+        fs::create_dir_all(&self.root).with_context(|| format!("create {:?}", self.root))?;
+        // create_dir claims a name atomically, so runs discarding their logs
+        // in the same second each get a batch of their own.
+        let mut batch = self.root.join(&name);
+        let mut n = 1;
+        loop {
+            match fs::create_dir(&batch) {
+                Ok(()) => return Ok(batch),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    n += 1;
+                    batch = self.root.join(format!("{}-{}", name, n));
+                }
+                Err(err) => return Err(err).with_context(|| format!("create {:?}", batch)),
+            }
+        }
+    }
+
+    // Batches oldest first; anything else in the trash is left alone.
+    fn batches(&self) -> Vec<(NaiveDateTime, PathBuf)> {
+        let Ok(entries) = fs::read_dir(&self.root) else {
+            return Vec::new();
+        };
+        let mut batches: Vec<(NaiveDateTime, PathBuf)> = entries
+            .flatten()
+            .filter_map(|e| {
+                let name = e.file_name().to_string_lossy().to_string();
+                let stamp = name.get(..19)?;
+                let at = NaiveDateTime::parse_from_str(stamp, BATCH_FORMAT).ok()?;
+                Some((at, e.path()))
+            })
+            .collect();
+        batches.sort();
+        batches
+    }
+
+    /// Delete the batches older than `days` for good; returns how many.
+    pub fn purge(&self, days: u64, now: NaiveDateTime) -> Result<usize> {
+        let cutoff = now - Duration::days(days.min(i32::MAX as u64) as i64);
+        let mut purged = 0;
+        for (at, batch) in self.batches() {
+            if at >= cutoff {
+                break;
+            }
+            fs::remove_dir_all(&batch).with_context(|| format!("purging {:?}", batch))?;
+            purged += 1;
+        }
+        // An empty trash disappears.
+        let _ = fs::remove_dir(&self.root);
+        Ok(purged)
+    }
+
+    /// Move the files of the newest batch back into `dir`, subdirectories
+    /// included; None if the trash is empty.
+    pub fn restore_last(&self, dir: &Path, dry_run: bool) -> Result<Option<Restored>> {
+        let Some((_, batch)) = self.batches().pop() else {
+            return Ok(None);
+        };
+        let mut files = Vec::new();
+        batch_files(&batch, &mut files)?;
+        files.sort();
+        let mut done = Restored::default();
+        for file in files {
+            let target = dir.join(file.strip_prefix(&batch).unwrap_or(&file));
+            if target.exists() {
+                done.kept.push(file);
+                continue;
+            }
+            if !dry_run {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent).with_context(|| format!("create {:?}", parent))?;
+                }
+                fs::rename(&file, &target).with_context(|| format!("restoring {:?}", target))?;
+            }
+            done.restored.push(target);
+        }
+        if !dry_run && done.kept.is_empty() {
+            // Only the emptied subdirectories are left.
+            fs::remove_dir_all(&batch).with_context(|| format!("removing {:?}", batch))?;
+            let _ = fs::remove_dir(&self.root);
+        }
+        Ok(Some(done))
+    }
+}
+
+// The files in `dir` and its subdirectories.
+fn batch_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("reading {:?}", dir))? {
+        let entry = entry?;
+        match entry.file_type()?.is_dir() {
+            true => batch_files(&entry.path(), files)?,
+            false => files.push(entry.path()),
+        }
+    }
+    Ok(())
+}
+
+/// Delete the files of the run logged at `log` (the other half of a split
+/// run, rotated parts, its `artifact`), or move them into a batch of their
+/// own with `clean_trash`; returns where they went, None when deleted.
+pub fn discard_run(cfg: &Config, log: &Path, artifact: Option<&Path>) -> Result<Option<PathBuf>> {
+    let mut files: Vec<PathBuf> = artifact.into_iter().map(Path::to_path_buf).collect();
+    for (file, _) in run_files(log) {
+        files.push(rotated_path(&file));
+        files.push(file);
+    }
+    files.retain(|f| f.exists());
+    if !cfg.clean_trash {
+        for file in &files {
+            fs::remove_file(file).with_context(|| format!("deleting {:?}", file))?;
+        }
+        return Ok(None);
+    }
+    let dir = log.parent().unwrap_or(Path::new("."));
+    let batch = Trash::in_dir(dir).new_batch(Local::now().naive_local())?;
+    for file in &files {
+        let trashed = batch.join(file.file_name().unwrap_or_default());
+        fs::rename(file, &trashed).with_context(|| format!("trashing {:?}", file))?;
+    }
+    Ok(Some(batch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn last_batch_is_restored_and_old_ones_purged() {
+        let dir = std::env::temp_dir().join(format!("lg-trash-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let trash = Trash::in_dir(&dir);
+        let old = trash.new_batch(at("2024-03-01 10:00:00")).unwrap();
+        fs::write(old.join("make_1.log"), "old").unwrap();
+        let last = trash.new_batch(at("2024-03-09 10:00:00")).unwrap();
+        for name in ["make_2.log", "make_3.log"] {
+            fs::write(last.join(name), name).unwrap();
+        }
+        // A newer run took this name since; it stays in the trash.
+        fs::write(dir.join("make_3.log"), "new").unwrap();
+
+        let done = trash.restore_last(&dir, false).unwrap().unwrap();
+        assert_eq!(done.restored, [dir.join("make_2.log")]);
+        assert_eq!(done.kept, [last.join("make_3.log")]);
+        assert_eq!(
+            fs::read_to_string(dir.join("make_2.log")).unwrap(),
+            "make_2.log"
+        );
+
+        assert_eq!(trash.purge(7, at("2024-03-10 12:00:00")).unwrap(), 1);
+        assert!(!old.exists() && last.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn batches_started_together_are_distinct() {
+        let dir = std::env::temp_dir().join(format!("lg-trash-race-test-{}", std::process::id()));
+        let now = at("2024-03-09 10:00:00");
+        let batches: Vec<PathBuf> = std::thread::scope(|s| {
+            let workers: Vec<_> = (0..8)
+                .map(|_| s.spawn(|| Trash::in_dir(&dir).new_batch(now).unwrap()))
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+        let mut unique = batches.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), batches.len());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}